@@ -0,0 +1,246 @@
+//! Which streams of a file a tool looks at, from `--include`, `--exclude` and
+//! `--max-stream-size`.
+//!
+//! Patterns are matched against the path of a stream, its storage names and its own name
+//! separated by `/`, and like stream names they ignore case. In a pattern
+//!
+//! - `*` matches any part of a name and `?` one character of it
+//! - `**` as a whole component matches any number of storages, so `**/\x01Ole10Native` is that
+//!   stream anywhere in the file
+//! - `\xNN` is the character with that hex code, for the control characters that start some
+//!   stream names, and a backslash before anything else takes it literally
+use clap::{Arg, ArgMatches, Command};
+use ole::prelude::{DirectoryEntry, EntryPath, ObjectType, OleFile};
+use std::str::FromStr;
+
+/// A parsed `--include` or `--exclude` pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glob(Vec<Component>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Component {
+    /// `**`
+    AnyStorages,
+    Name(Vec<Token>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    AnyChars,
+    AnyChar,
+    Char(char),
+}
+
+impl FromStr for Glob {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let mut components = vec![];
+        let mut tokens = vec![];
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '/' => push_component(&mut components, std::mem::take(&mut tokens)),
+                '*' => tokens.push(Token::AnyChars),
+                '?' => tokens.push(Token::AnyChar),
+                '\\' => match chars.next() {
+                    Some('x') => {
+                        let hex = chars.by_ref().take(2).collect::<String>();
+                        let code = u8::from_str_radix(&hex, 16)
+                            .map_err(|_| format!("invalid escape \\x{} in {}", hex, pattern))?;
+                        tokens.push(Token::Char(code as char));
+                    }
+                    Some(c) => tokens.push(Token::Char(c)),
+                    None => return Err(format!("dangling backslash at the end of {}", pattern)),
+                },
+                c => tokens.push(Token::Char(c)),
+            }
+        }
+        push_component(&mut components, tokens);
+        if components.is_empty() {
+            return Err("empty pattern".to_string());
+        }
+        Ok(Glob(components))
+    }
+}
+
+fn push_component(components: &mut Vec<Component>, tokens: Vec<Token>) {
+    // like stream paths, leading, trailing and repeated slashes don't count
+    if tokens.is_empty() {
+        return;
+    }
+    components.push(if tokens == [Token::AnyChars, Token::AnyChars] {
+        Component::AnyStorages
+    } else {
+        Component::Name(tokens)
+    });
+}
+
+impl Glob {
+    /// Whether the stream at `path` matches the pattern.
+    pub fn matches<S: AsRef<str>>(&self, path: &[S]) -> bool {
+        let names = path
+            .iter()
+            .map(|name| name.as_ref().to_uppercase().chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        matches_components(&self.0, &names)
+    }
+}
+
+fn matches_components(pattern: &[Component], names: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => names.is_empty(),
+        Some((Component::AnyStorages, rest)) => {
+            (0..=names.len()).any(|skip| matches_components(rest, &names[skip..]))
+        }
+        Some((Component::Name(tokens), rest)) => match names.split_first() {
+            Some((name, names)) => matches_name(tokens, name) && matches_components(rest, names),
+            None => false,
+        },
+    }
+}
+
+fn matches_name(tokens: &[Token], name: &[char]) -> bool {
+    match tokens.split_first() {
+        None => name.is_empty(),
+        Some((Token::AnyChars, rest)) => {
+            (0..=name.len()).any(|skip| matches_name(rest, &name[skip..]))
+        }
+        Some((Token::AnyChar, rest)) => !name.is_empty() && matches_name(rest, &name[1..]),
+        Some((Token::Char(c), rest)) => match name.split_first() {
+            Some((first, name)) => {
+                c.to_uppercase().eq(std::iter::once(*first)) && matches_name(rest, name)
+            }
+            None => false,
+        },
+    }
+}
+
+/// The stream filters, as parsed from the command line.
+#[derive(Clone, Debug, Default)]
+pub struct StreamFilter {
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+    pub max_stream_size: Option<u64>,
+}
+
+/// Attach the `--include`, `--exclude` and `--max-stream-size` flags, for the tools that go
+/// through the streams of their inputs.
+pub fn stream_filter_args(command: Command<'static>) -> Command<'static> {
+    command
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .help("Only look at the streams whose path matches this pattern, e.g. **/\\x01Ole10Native. Can be given more than once.")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|value| Glob::from_str(value).map(|_| ())),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .help("Skip the streams whose path matches this pattern. Can be given more than once.")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|value| Glob::from_str(value).map(|_| ())),
+        )
+        .arg(
+            Arg::new("max-stream-size")
+                .long("max-stream-size")
+                .help("Skip streams larger than this many bytes.")
+                .takes_value(true)
+                .validator(|value| value.parse::<u64>().map(|_| ())),
+        )
+}
+
+impl StreamFilter {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        // the unwraps are safe, the values were checked by the validators
+        let globs = |name| {
+            matches
+                .values_of(name)
+                .into_iter()
+                .flatten()
+                .map(|value| Glob::from_str(value).unwrap())
+                .collect()
+        };
+        StreamFilter {
+            include: globs("include"),
+            exclude: globs("exclude"),
+            max_stream_size: matches
+                .value_of("max-stream-size")
+                .map(|value| value.parse().unwrap()),
+        }
+    }
+
+    /// Whether to look at the stream at `path`, which is `size` bytes long.
+    pub fn allows(&self, path: &EntryPath, size: u64) -> bool {
+        let path = path.components();
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.matches(path)))
+            && !self.exclude.iter().any(|glob| glob.matches(path))
+            && self.max_stream_size.map_or(true, |max| size <= max)
+    }
+
+    /// The streams of `ole` the filter allows, with their paths.
+    pub fn streams<'a>(&self, ole: &'a OleFile) -> Vec<(EntryPath, &'a DirectoryEntry)> {
+        ole.walk()
+            .filter(|(path, entry)| {
+                entry.object_type() == ObjectType::Stream && self.allows(path, entry.stream_size())
+            })
+            .collect()
+    }
+
+    /// The names of the streams of `ole` the filter allows, as [`OleFile::list_streams`] gives
+    /// them.
+    pub fn stream_names(&self, ole: &OleFile) -> Vec<String> {
+        let allowed = self
+            .streams(ole)
+            .into_iter()
+            .map(|(_, entry)| entry.index())
+            .collect::<Vec<_>>();
+        ole.entries()
+            .filter(|entry| allowed.contains(&entry.index()))
+            .map(|entry| entry.name().to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        Glob::from_str(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_glob() {
+        let native = ["ObjectPool", "_1234", "\x01Ole10Native"];
+        assert!(glob(r"**/\x01Ole10Native").matches(&native));
+        assert!(glob(r"**/\x01ole10native").matches(&["\x01Ole10Native"]));
+        assert!(glob("ObjectPool/_*/*").matches(&native));
+        assert!(!glob("ObjectPool/*").matches(&native));
+        assert!(glob("Macros/**").matches(&["Macros", "VBA", "dir"]));
+        assert!(glob("?ord*").matches(&["WordDocument"]));
+        assert!(!glob("?ord*").matches(&["1Table"]));
+        assert!(Glob::from_str(r"\xZZ").is_err());
+        assert!(Glob::from_str("a\\").is_err());
+        assert!(Glob::from_str("/").is_err());
+    }
+
+    #[test]
+    fn test_stream_filter() {
+        let filter = StreamFilter {
+            include: vec![glob(r"**/\x01Ole10Native"), glob("Macros/**")],
+            exclude: vec![glob("Macros/VBA/_*")],
+            max_stream_size: Some(100),
+        };
+        let path = |path: &str| EntryPath::from(ole::util::split_stream_path(path));
+        assert!(filter.allows(&path("ObjectPool/_1/\x01Ole10Native"), 10));
+        assert!(!filter.allows(&path("ObjectPool/_1/\x01Ole10Native"), 1000));
+        assert!(filter.allows(&path("Macros/VBA/dir"), 10));
+        assert!(!filter.allows(&path("Macros/VBA/_VBA_PROJECT"), 10));
+        assert!(!filter.allows(&path("Data"), 10));
+        assert!(StreamFilter::default().allows(&path("Data"), u64::MAX));
+    }
+}
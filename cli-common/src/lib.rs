@@ -0,0 +1,566 @@
+//! Shared command line plumbing for the ole tools.
+//!
+//! Every tool builds its `clap::Command` through [`command`], which adds the standard flags:
+//!
+//! - `--file`/`-f` and positional paths: the inputs to process, any number of them, `-` for
+//!   stdin
+//! - `--json`: machine readable output
+//! - `--log-level`: verbosity of the logger (error, warn, info, debug, trace)
+//! - `--recursive`/`-r`: descend into directories given as inputs
+//! - `--max-size`: skip inputs larger than this many bytes
+//! - `--password`: the password to decrypt encrypted inputs with
+//! - `--max-depth`/`--max-artifacts`: bounds for unwrapping files nested in the inputs
+//!
+//! and then calls [`CommonArgs::from_matches`] and [`init_logging`]. Results are printed per
+//! file, under `--json` as a [`Report`], and the process exits with [`exit_code`].
+//!
+//! Tools that go through the streams of their inputs also attach [`stream_filter_args`] and
+//! check each stream against the [`StreamFilter`]. With the `schema` feature they attach
+//! `schema_arg` too, and `print_schema` prints the JSON Schema of their reports.
+mod filter;
+#[cfg(feature = "schema")]
+mod schema;
+
+pub use filter::{stream_filter_args, Glob, StreamFilter};
+#[cfg(feature = "schema")]
+pub use schema::{print_schema, report_schema, schema_arg};
+
+use clap::{Arg, ArgMatches, Command};
+use log::{warn, Level};
+use ole::{
+    prelude::{EncryptionScheme, OleFile, UnwrapLimits},
+    DecryptedFormat,
+};
+use serde::Serialize;
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+/// The path that stands for stdin.
+pub const STDIN: &str = "-";
+/// How much is read from stdin when `--max-size` isn't given.
+pub const DEFAULT_STDIN_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// The standard flags, as parsed from the command line.
+#[derive(Clone, Debug)]
+pub struct CommonArgs {
+    pub paths: Vec<PathBuf>,
+    pub json: bool,
+    pub log_level: Level,
+    pub recursive: bool,
+    pub max_size: Option<u64>,
+    pub password: Option<String>,
+    pub unwrap_limits: UnwrapLimits,
+}
+
+/// Create a `Command` for a tool, with the standard flags already attached.
+pub fn command(name: &'static str, about: &'static str) -> Command<'static> {
+    Command::new(name)
+        .about(about)
+        .arg(
+            Arg::new("file")
+                .long("file")
+                .short('f')
+                .help("The path to the file to be processed, - for stdin. Can be given more than once.")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("paths")
+                .help("More files (or directories, with --recursive) to process.")
+                .takes_value(true)
+                .multiple_values(true),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print the results as JSON."),
+        )
+        .arg(
+            Arg::new("log-level")
+                .long("log-level")
+                .help("One of error, warn, info, debug or trace.")
+                .takes_value(true)
+                .default_value(DEFAULT_LOG_LEVEL)
+                .validator(|value| Level::from_str(value).map(|_| ())),
+        )
+        .arg(
+            Arg::new("recursive")
+                .long("recursive")
+                .short('r')
+                .help("Process the files inside directories given as paths, recursively."),
+        )
+        .arg(
+            Arg::new("max-size")
+                .long("max-size")
+                .help("Skip files larger than this many bytes.")
+                .takes_value(true)
+                .validator(|value| value.parse::<u64>().map(|_| ())),
+        )
+        .arg(
+            Arg::new("password")
+                .long("password")
+                .help("The password to decrypt encrypted files with.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("max-depth")
+                .long("max-depth")
+                .help("How deep to unwrap files nested in each other.")
+                .takes_value(true)
+                .validator(|value| value.parse::<usize>().map(|_| ())),
+        )
+        .arg(
+            Arg::new("max-artifacts")
+                .long("max-artifacts")
+                .help("How many nested files to unwrap from one input at most.")
+                .takes_value(true)
+                .validator(|value| value.parse::<usize>().map(|_| ())),
+        )
+}
+
+impl CommonArgs {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        let paths = matches
+            .values_of("file")
+            .into_iter()
+            .flatten()
+            .chain(matches.values_of("paths").into_iter().flatten())
+            .map(PathBuf::from)
+            .collect();
+        CommonArgs {
+            paths,
+            json: matches.is_present("json"),
+            // both unwraps are safe, the values were checked by the validators
+            log_level: Level::from_str(matches.value_of("log-level").unwrap()).unwrap(),
+            recursive: matches.is_present("recursive"),
+            max_size: matches
+                .value_of("max-size")
+                .map(|value| value.parse().unwrap()),
+            password: matches.value_of("password").map(String::from),
+            unwrap_limits: {
+                let defaults = UnwrapLimits::default();
+                UnwrapLimits {
+                    max_depth: matches
+                        .value_of("max-depth")
+                        .map_or(defaults.max_depth, |value| value.parse().unwrap()),
+                    max_artifacts: matches
+                        .value_of("max-artifacts")
+                        .map_or(defaults.max_artifacts, |value| value.parse().unwrap()),
+                }
+            },
+        }
+    }
+
+    /// The files to process: the given paths, with directories expanded when `--recursive`
+    /// was passed, and anything over `--max-size` left out.
+    pub fn input_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for path in &self.paths {
+            self.collect(path, &mut files);
+        }
+        files
+    }
+
+    /// Parse the given input, which is read from stdin if it is `-`.
+    ///
+    /// The parse is lenient, damaged and tampered files are what the tools are for: what it got
+    /// past goes to `warnings`, see [`parse_warnings`]. With `--password` an encrypted binary
+    /// Word or Excel input is decrypted, and the decrypted copy is what is returned. When the
+    /// content of an encrypted input can't be looked at, `warnings` says why.
+    pub fn open(&self, path: &Path, warnings: &mut Vec<String>) -> ole::Result<Input> {
+        let data = if is_stdin(path) {
+            read_stdin(self.max_size.unwrap_or(DEFAULT_STDIN_LIMIT))?
+        } else if self.password.is_some() {
+            // decrypting works on the whole file
+            fs::read(path)?
+        } else {
+            let ole = OleFile::parse_lenient_sync(io::BufReader::new(fs::File::open(path)?))?;
+            warnings.extend(parse_warnings(&ole));
+            warnings.extend(self.encrypted_content_notice(&ole));
+            return Ok(Input::new(ole));
+        };
+        let ole = OleFile::parse_lenient_sync(&data[..])?;
+        warnings.extend(parse_warnings(&ole));
+        Ok(self.decrypt(ole, &data, warnings))
+    }
+
+    /// `ole` decrypted with `--password`, or `ole` itself when it isn't encrypted or can't be
+    /// decrypted, which `warnings` is told about.
+    fn decrypt(&self, ole: OleFile, data: &[u8], warnings: &mut Vec<String>) -> Input {
+        let (password, scheme) = match (&self.password, ole.encryption_scheme()) {
+            (Some(password), Some(scheme)) => (password, scheme),
+            _ => {
+                warnings.extend(self.encrypted_content_notice(&ole));
+                return Input::new(ole);
+            }
+        };
+        let reason = match ole::decrypt(data, password) {
+            Ok(decrypted) if decrypted.format() == DecryptedFormat::Compound => {
+                match OleFile::parse_lenient_sync(decrypted.data()) {
+                    Ok(decrypted) => {
+                        return Input {
+                            ole: decrypted,
+                            decrypted_from: Some(scheme),
+                        }
+                    }
+                    Err(err) => format!("the decrypted file could not be parsed: {}", err),
+                }
+            }
+            Ok(_) => "it decrypts to an OOXML package, which the tools don't look into".to_string(),
+            Err(ole::error::Error::WrongPassword) => "the --password given is wrong".to_string(),
+            Err(err) => format!("it could not be decrypted: {}", err),
+        };
+        warnings.push(format!(
+            "content unavailable: encrypted ({}); {}",
+            scheme, reason
+        ));
+        Input::new(ole)
+    }
+
+    /// What to tell the user when the content of `ole` can't be looked at because it is
+    /// encrypted and no password was given, `None` when it isn't encrypted.
+    fn encrypted_content_notice(&self, ole: &OleFile) -> Option<String> {
+        let scheme = ole.encryption_scheme()?;
+        // only binary files decrypt to something the tools look into
+        Some(if scheme == EncryptionScheme::Rc4CryptoApi {
+            format!(
+                "content unavailable: encrypted ({}); supply --password",
+                scheme
+            )
+        } else {
+            format!("content unavailable: encrypted ({})", scheme)
+        })
+    }
+
+    fn collect(&self, path: &Path, files: &mut Vec<PathBuf>) {
+        if is_stdin(path) {
+            // there is only one stdin to read
+            if files.iter().any(|file| is_stdin(file)) {
+                warn!("Skipping {}, stdin is already an input.", STDIN);
+            } else {
+                files.push(path.to_path_buf());
+            }
+        } else if path.is_dir() {
+            if !self.recursive {
+                warn!(
+                    "Skipping directory {} (use --recursive to process it).",
+                    path.display()
+                );
+                return;
+            }
+            let mut entries = match fs::read_dir(path) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .collect::<Vec<_>>(),
+                Err(err) => {
+                    warn!("Could not read directory {}: {}", path.display(), err);
+                    return;
+                }
+            };
+            entries.sort();
+            for entry in entries {
+                self.collect(&entry, files);
+            }
+        } else if self.within_max_size(path) {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    fn within_max_size(&self, path: &Path) -> bool {
+        match (self.max_size, fs::metadata(path)) {
+            (Some(max_size), Ok(metadata)) if metadata.len() > max_size => {
+                warn!(
+                    "Skipping {} ({} bytes is over the limit of {} bytes).",
+                    path.display(),
+                    metadata.len(),
+                    max_size
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// An input as [`CommonArgs::open`] parsed it.
+#[derive(Debug)]
+pub struct Input {
+    /// the parsed file, or its decrypted copy when it was decrypted with `--password`
+    pub ole: OleFile,
+    /// the scheme the input is encrypted with, when `ole` is its decrypted copy
+    pub decrypted_from: Option<EncryptionScheme>,
+}
+
+impl Input {
+    fn new(ole: OleFile) -> Self {
+        Self {
+            ole,
+            decrypted_from: None,
+        }
+    }
+}
+
+/// What parsing `ole` got past, for the warnings of its report.
+pub fn parse_warnings(ole: &OleFile) -> Vec<String> {
+    ole.warnings()
+        .iter()
+        .map(|warning| format!("parse: {}", warning))
+        .collect()
+}
+
+/// Whether the path stands for stdin.
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new(STDIN)
+}
+
+/// Read all of stdin, failing if there is more than `limit` bytes of it.
+pub fn read_stdin(limit: u64) -> io::Result<Vec<u8>> {
+    read_limited(io::stdin().lock(), limit)
+}
+
+fn read_limited<R: Read>(reader: R, limit: u64) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    reader.take(limit + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("stdin is larger than {} bytes", limit),
+        ));
+    }
+    Ok(data)
+}
+
+/// Set up logging at the requested level. Logs go to stderr so they never mix with the results.
+pub fn init_logging(level: Level) {
+    simple_logger::init_with_level(level).unwrap();
+}
+
+/// Exit status when every input was processed.
+pub const EXIT_OK: i32 = 0;
+/// Exit status when the command line was unusable, e.g. no input files.
+pub const EXIT_USAGE: i32 = 1;
+/// Exit status when at least one input could not be processed.
+pub const EXIT_FAILED_INPUT: i32 = 2;
+
+/// Whether a tool managed to process a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Error,
+}
+
+/// The result of running a tool on one file, as printed with `--json` (one report per line).
+///
+/// Every tool uses the same envelope, only `findings` differs: the indicators for oleid,
+/// the extracted objects for oleobj, the stream names for olecli. On error `findings` is
+/// null and `errors` says what went wrong. `warnings` are about findings that may be
+/// incomplete, e.g. because the content is encrypted.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Report<T: Serialize> {
+    pub tool: &'static str,
+    pub version: &'static str,
+    pub file: String,
+    pub status: Status,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    pub findings: Option<T>,
+}
+
+impl<T: Serialize> Report<T> {
+    /// Build the report for `file` from the outcome of processing it.
+    pub fn new<E: Display>(
+        tool: &'static str,
+        version: &'static str,
+        file: &Path,
+        result: Result<T, E>,
+    ) -> Self {
+        let (status, errors, findings) = match result {
+            Ok(findings) => (Status::Ok, vec![], Some(findings)),
+            Err(err) => (Status::Error, vec![err.to_string()], None),
+        };
+        Report {
+            tool,
+            version,
+            file: file.display().to_string(),
+            status,
+            errors,
+            warnings: vec![],
+            findings,
+        }
+    }
+
+    /// Attach warnings to the report, they are also logged.
+    pub fn with_warnings(mut self, warnings: Vec<String>) -> Self {
+        for warning in &warnings {
+            warn!("{}: {}", self.file, warning);
+        }
+        self.warnings.extend(warnings);
+        self
+    }
+
+    /// Print the report as a single line of JSON.
+    pub fn print_json(&self) {
+        println!("{}", serde_json::to_string(self).unwrap());
+    }
+}
+
+/// The exit status for a run that produced the given statuses.
+pub fn exit_code(statuses: &[Status]) -> i32 {
+    if statuses.contains(&Status::Error) {
+        EXIT_FAILED_INPUT
+    } else {
+        EXIT_OK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_envelope() {
+        let report = Report::new(
+            "tool",
+            "0.1.0",
+            Path::new("a.doc"),
+            Ok::<_, String>(vec!["finding"]),
+        );
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "tool": "tool",
+                "version": "0.1.0",
+                "file": "a.doc",
+                "status": "ok",
+                "errors": [],
+                "warnings": [],
+                "findings": ["finding"],
+            })
+        );
+
+        let report = Report::<Vec<String>>::new("tool", "0.1.0", Path::new("b.doc"), Err("bad"));
+        assert_eq!(report.status, Status::Error);
+        assert_eq!(report.errors, vec!["bad".to_string()]);
+        assert!(report.findings.is_none());
+        assert_eq!(exit_code(&[Status::Ok, report.status]), EXIT_FAILED_INPUT);
+        assert_eq!(exit_code(&[Status::Ok]), EXIT_OK);
+    }
+
+    #[test]
+    fn test_stdin_input() {
+        let args = CommonArgs {
+            paths: vec![PathBuf::from("-"), PathBuf::from("-")],
+            json: false,
+            log_level: Level::Info,
+            recursive: false,
+            max_size: Some(1),
+            password: None,
+            unwrap_limits: UnwrapLimits::default(),
+        };
+        assert_eq!(args.input_files(), vec![PathBuf::from("-")]);
+        assert_eq!(read_limited(&b"abcd"[..], 4).unwrap(), b"abcd");
+        assert!(read_limited(&b"abcde"[..], 4).is_err());
+    }
+
+    /// The parse warnings of `oledoc1.doc_` with `patch` applied, opened like a tool does.
+    fn warnings_of_patched(name: &str, patch: impl FnOnce(&mut Vec<u8>)) -> Vec<String> {
+        let mut data = fs::read("../data/oledoc1.doc_").unwrap();
+        patch(&mut data);
+        let path = std::env::temp_dir().join(format!(
+            "ole-cli-common-{}-{}.doc",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, &data).unwrap();
+        let args = CommonArgs {
+            paths: vec![path.clone()],
+            json: false,
+            log_level: Level::Info,
+            recursive: false,
+            max_size: None,
+            password: None,
+            unwrap_limits: UnwrapLimits::default(),
+        };
+        let mut warnings = vec![];
+        let opened = args.open(&path, &mut warnings);
+        fs::remove_file(&path).unwrap();
+        assert!(opened.unwrap().decrypted_from.is_none());
+        warnings
+    }
+
+    #[test]
+    fn test_open_is_lenient() {
+        // declare one FAT sector more than the file has
+        let warnings = warnings_of_patched("counts", |data| {
+            let count = u32::from_le_bytes(data[0x2C..0x30].try_into().unwrap());
+            data[0x2C..0x30].copy_from_slice(&(count + 1).to_le_bytes());
+        });
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("parse: header:"));
+
+        // rename the Data stream to D:ta, which the spec forbids
+        let warnings = warnings_of_patched("names", |data| {
+            let name = [b'D', 0, b'a', 0, b't', 0, b'a', 0, 0, 0];
+            let at = data.windows(name.len()).position(|w| w == name).unwrap();
+            data[at + 2] = b':';
+        });
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("D:ta"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn test_open_decrypts() {
+        let open = |file: &str, password: Option<&str>| {
+            let args = CommonArgs {
+                paths: vec![],
+                json: false,
+                log_level: Level::Info,
+                recursive: false,
+                max_size: None,
+                password: password.map(String::from),
+                unwrap_limits: UnwrapLimits::default(),
+            };
+            let mut warnings = vec![];
+            let path = Path::new("../data/encryption/encrypted").join(file);
+            let input = args.open(&path, &mut warnings).unwrap();
+            (input, warnings)
+        };
+
+        let (input, warnings) = open("rc4cryptoapi_password.doc", Some("Password1234_"));
+        assert_eq!(input.decrypted_from, Some(EncryptionScheme::Rc4CryptoApi));
+        assert!(!input.ole.is_encrypted());
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        let (input, warnings) = open("rc4cryptoapi_password.xls", Some("password"));
+        assert!(input.decrypted_from.is_none());
+        assert!(input.ole.is_encrypted());
+        assert_eq!(
+            warnings,
+            ["content unavailable: encrypted (RC4 CryptoAPI); the --password given is wrong"]
+        );
+
+        let (_, warnings) = open("rc4cryptoapi_password.xls", None);
+        assert_eq!(
+            warnings,
+            ["content unavailable: encrypted (RC4 CryptoAPI); supply --password"]
+        );
+
+        let (input, warnings) = open("example_password.xlsx", Some("Password1234_"));
+        assert!(input.decrypted_from.is_none());
+        assert!(warnings[0].contains("OOXML package"), "{:?}", warnings);
+        let (_, warnings) = open("example_password.xlsx", None);
+        assert_eq!(
+            warnings,
+            ["content unavailable: encrypted (ECMA-376 agile)"]
+        );
+    }
+}
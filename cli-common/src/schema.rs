@@ -0,0 +1,55 @@
+//! JSON Schemas of the `--json` output, generated from the types that are serialized so they
+//! can't drift from what the tools print.
+use crate::Report;
+use clap::{Arg, ArgMatches, Command};
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::Serialize;
+
+/// Attach `--schema` to a tool's command.
+pub fn schema_arg(command: Command<'static>) -> Command<'static> {
+    command.arg(
+        Arg::new("schema")
+            .long("schema")
+            .help("Print the JSON Schema of the --json output and exit."),
+    )
+}
+
+/// The schema of the reports `tool` prints with findings of type `T`. It describes the output
+/// of one release, so its title names the version, as the `version` of every report does.
+pub fn report_schema<T: Serialize + JsonSchema>(tool: &str, version: &str) -> RootSchema {
+    let mut schema = schema_for!(Report<T>);
+    schema.schema.metadata().title = Some(format!("{} {} report", tool, version));
+    schema
+}
+
+/// Print the schema of the reports of `tool` if `--schema` was given, and say whether it was.
+pub fn print_schema<T: Serialize + JsonSchema>(
+    matches: &ArgMatches,
+    tool: &str,
+    version: &str,
+) -> bool {
+    if !matches.is_present("schema") {
+        return false;
+    }
+    let schema = report_schema::<T>(tool, version);
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_schema() {
+        let schema = serde_json::to_value(report_schema::<Vec<String>>("tool", "0.1.0")).unwrap();
+        assert_eq!(schema["title"], "tool 0.1.0 report");
+        let properties = schema["properties"].as_object().unwrap();
+        for field in [
+            "tool", "version", "file", "status", "errors", "warnings", "findings",
+        ] {
+            assert!(properties.contains_key(field), "{} is missing", field);
+        }
+        assert_eq!(schema["definitions"]["Status"]["enum"][0], "ok");
+    }
+}
@@ -0,0 +1,31 @@
+//! Reading whole streams into memory, the path most consumers take.
+//!
+//! Run with `cargo bench -p ole --bench open_stream`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use ole::OleFile;
+
+fn open_stream(c: &mut Criterion) {
+    let mut group = c.benchmark_group("open_stream");
+    for file in ["oledoc1.doc_", "file-sample_1MB.doc"] {
+        let data = std::fs::read(format!("../data/{}", file)).unwrap();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let streams = ole.list_streams_with_path();
+        let total = streams
+            .iter()
+            .filter_map(|path| ole.find_stream_path(path))
+            .map(|entry| entry.stream_size())
+            .sum::<u64>();
+        group.throughput(Throughput::Bytes(total));
+        group.bench_function(file, |b| {
+            b.iter(|| {
+                for path in &streams {
+                    black_box(ole.open_stream_path(path).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, open_stream);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+//! Any input, however malformed, is an `Err` or a file to look at, never a panic.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ole::{OleFile, OleParseOptions};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ole::analyze(data);
+    if let Ok(ole) = OleFile::parse_sync_with_options(data, &OleParseOptions::lenient()) {
+        for (path, _) in ole.walk() {
+            let _ = ole.open_stream(&path.as_strs());
+        }
+        let _ = (ole.orphaned_entries(), ole.unused_sectors(), ole.sector_map());
+        let _ = (ole.tree_violations(), ole.embedded_object_entries());
+        let _ = (ole.vba_autoexec(), ole.deobfuscate_vba());
+    }
+    let _ = ole::decrypt(data, "password");
+});
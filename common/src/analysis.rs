@@ -0,0 +1,441 @@
+//! One call summaries of a compound file, for tools that scan many files.
+//!
+//! The types here are a stable view of a parsed file. They copy what they need out of the
+//! parser's own structures, so those can change shape without breaking anyone holding a
+//! report. They are `#[non_exhaustive]`: fields may be added in any release, and
+//! [`REPORT_VERSION`] is bumped when an existing field changes meaning.
+use crate::{
+    constants,
+    directory::{DirectoryEntry, NodeColor, ObjectType},
+    ftype::OleFileType,
+    header::OleHeader,
+    AutoExec, EncryptionScheme, ExternalConnection, OleFile, OleObject, OleParseOptions, Result,
+    UnwrapLimits,
+};
+
+/// Version of the report types, see the module docs.
+pub const REPORT_VERSION: u32 = 1;
+
+/// The layout figures from a compound file header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HeaderInfo {
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub sector_size: u32,
+    pub mini_sector_size: u32,
+    /// streams smaller than this live in the mini stream
+    pub mini_stream_cutoff: u32,
+    pub directory_sectors: u32,
+    pub fat_sectors: u32,
+    pub mini_fat_sectors: u32,
+    pub difat_sectors: u32,
+}
+
+impl From<&OleHeader> for HeaderInfo {
+    fn from(header: &OleHeader) -> Self {
+        Self {
+            major_version: header.major_version,
+            minor_version: header.minor_version,
+            sector_size: header.sector_size as u32,
+            mini_sector_size: header.mini_sector_size as u32,
+            mini_stream_cutoff: header.standard_stream_min_size,
+            directory_sectors: header.directory_sectors_len,
+            fat_sectors: header.sector_allocation_table_len,
+            mini_fat_sectors: header.short_sector_allocation_table_len,
+            difat_sectors: header.master_sector_allocation_table_len,
+        }
+    }
+}
+
+/// A stream and where it lives in the file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StreamStat {
+    /// storage names leading to the stream, then the stream's own name
+    pub path: Vec<String>,
+    pub size: u64,
+    /// which allocation table chains the data, decided by the size against the cutoff
+    pub location: StreamLocation,
+    /// first sector of the chain, counted in mini sectors for [`StreamLocation::MiniFat`],
+    /// `None` for an empty stream
+    pub start_sector: Option<u32>,
+}
+
+/// Where the sectors of a stream are allocated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamLocation {
+    /// regular sectors, chained in the FAT
+    Fat,
+    /// 64 byte sectors within the mini stream, chained in the mini-FAT
+    MiniFat,
+}
+
+impl StreamStat {
+    /// The last component of the path.
+    pub fn name(&self) -> &str {
+        self.path.last().map(String::as_str).unwrap_or_default()
+    }
+}
+
+/// The shape of the red-black tree holding the children of one storage.
+///
+/// MS-CFB asks writers for balanced red-black trees. Handcrafted files often link entries into
+/// long lists instead, which makes lookups slow and tells something about the tool that built
+/// the file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TreeStats {
+    /// path of the storage, empty for the root storage
+    pub storage: Vec<String>,
+    /// number of children in the tree
+    pub entries: usize,
+    /// longest path from the tree root down to a child, 0 for an empty tree
+    pub depth: usize,
+    /// depth of a perfectly balanced tree with the same number of entries
+    pub optimal_depth: usize,
+    /// red nodes with a red child, a red tree root, and nodes whose subtrees differ in black
+    /// height. Office itself does not always keep the colors consistent, so on their own these
+    /// are weak evidence.
+    pub color_violations: usize,
+}
+
+/// What [`analyze`] found out about a file.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct AnalysisReport {
+    /// [`REPORT_VERSION`] at the time the report was made
+    pub version: u32,
+    pub header: HeaderInfo,
+    pub file_type: OleFileType,
+    pub encrypted: bool,
+    pub encryption_scheme: Option<EncryptionScheme>,
+    /// all the streams, in directory order
+    pub streams: Vec<StreamStat>,
+    /// how many compound files are nested in this one, at any depth
+    pub nested_files: usize,
+    /// one per storage, starting with the root
+    pub trees: Vec<TreeStats>,
+    /// the connections of a workbook to external data, empty when they can't be read
+    pub external_connections: Vec<ExternalConnection>,
+    /// whether there is a VBA project with at least one module
+    pub macros: bool,
+    /// the VBA procedures that run by themselves, empty when the projects can't be read
+    pub autoexec: Vec<AutoExec>,
+    /// the embedded and linked OLE objects, empty when they can't be read
+    pub ole_objects: Vec<OleObject>,
+}
+
+/// Parse `data` as a compound file and summarize it.
+///
+/// ```rust
+/// let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+/// let report = ole::analyze(&data).unwrap();
+/// assert!(report.streams.iter().any(|stream| stream.name() == "WordDocument"));
+/// ```
+pub fn analyze(data: &[u8]) -> Result<AnalysisReport> {
+    analyze_with_options(data, &OleParseOptions::strict())
+}
+
+/// [`analyze`] with the parse following `options`, e.g. lenient and with a `max_memory` for
+/// files from untrusted sources.
+///
+/// ```rust
+/// use ole::OleParseOptions;
+///
+/// let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+/// let options = OleParseOptions {
+///     max_memory: Some(data.len()),
+///     ..OleParseOptions::lenient()
+/// };
+/// let report = ole::analyze_with_options(&data, &options).unwrap();
+/// assert!(report.streams.iter().any(|stream| stream.name() == "WordDocument"));
+/// ```
+pub fn analyze_with_options(data: &[u8], options: &OleParseOptions) -> Result<AnalysisReport> {
+    let ole = OleFile::parse_sync_with_options(data, options)?;
+    Ok(AnalysisReport {
+        version: REPORT_VERSION,
+        header: ole.header_info(),
+        file_type: ole.file_type,
+        encrypted: ole.encrypted,
+        encryption_scheme: ole.encryption_scheme,
+        streams: ole.stream_stats(),
+        nested_files: ole.unwrap_nested(&UnwrapLimits::default()).files.len(),
+        trees: ole.tree_stats(),
+        external_connections: ole.external_connections().unwrap_or_default(),
+        macros: ole
+            .vba_projects()
+            .is_ok_and(|projects| projects.iter().any(|project| !project.modules.is_empty())),
+        autoexec: ole.vba_autoexec().unwrap_or_default(),
+        ole_objects: ole.ole_objects().unwrap_or_default(),
+    })
+}
+
+/// Every stream with its full path, in directory order.
+pub(crate) fn stream_stats(ole: &OleFile) -> Vec<StreamStat> {
+    let mini_stream_cutoff = ole.header_info().mini_stream_cutoff as u64;
+    entries_with_paths(ole)
+        .into_iter()
+        .filter(|(_, entry)| entry.object_type == ObjectType::Stream)
+        .map(|(path, entry)| StreamStat {
+            path,
+            size: entry.stream_size,
+            location: if entry.stream_size < mini_stream_cutoff {
+                StreamLocation::MiniFat
+            } else {
+                StreamLocation::Fat
+            },
+            start_sector: entry
+                .starting_sector_location
+                .filter(|start| *start != constants::CHAIN_END && entry.stream_size > 0),
+        })
+        .collect()
+}
+
+/// Tree shape of the root storage and then of every storage below it.
+pub(crate) fn tree_stats(ole: &OleFile) -> Vec<TreeStats> {
+    let storages = entries_with_paths(ole)
+        .into_iter()
+        .filter(|(_, entry)| entry.object_type == ObjectType::Storage);
+    let root = Some((vec![], ole.root()));
+    // entries shared between trees, or in a cycle, are counted in the first tree reaching them
+    let mut visited = vec![false; ole.id_count()];
+    root.into_iter()
+        .chain(storages)
+        .map(|(storage, entry)| tree_shape(ole, storage, entry.child_id, &mut visited))
+        .collect()
+}
+
+fn tree_shape(
+    ole: &OleFile,
+    storage: Vec<String>,
+    tree_root: Option<u32>,
+    visited: &mut [bool],
+) -> TreeStats {
+    let color = |id: u32| {
+        ole.entry_by_id(id)
+            .map_or(NodeColor::Black, |entry| entry.color)
+    };
+    // nodes in depth first order with their depth and parent, walked without recursion so a
+    // degenerate tree can't exhaust the stack
+    let mut nodes: Vec<(u32, usize, Option<u32>)> = vec![];
+    let mut pending = tree_root
+        .map(|id| (id, 1, None))
+        .into_iter()
+        .collect::<Vec<_>>();
+    while let Some((id, depth, parent)) = pending.pop() {
+        match visited.get_mut(id as usize) {
+            Some(seen) if !*seen => *seen = true,
+            _ => continue,
+        }
+        let entry = match ole.entry_by_id(id) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        nodes.push((id, depth, parent));
+        for child in [entry.left_sibling_id, entry.right_sibling_id]
+            .into_iter()
+            .flatten()
+        {
+            pending.push((child, depth + 1, Some(id)));
+        }
+    }
+    let in_tree = |id: Option<u32>| id.filter(|id| nodes.iter().any(|(node, _, _)| node == id));
+
+    let mut color_violations = nodes
+        .iter()
+        .filter(|(id, _, parent)| match parent {
+            Some(parent) => color(*id) == NodeColor::Red && color(*parent) == NodeColor::Red,
+            None => color(*id) == NodeColor::Red,
+        })
+        .count();
+    // black heights, children before parents, missing children count as black leaves
+    let mut black_heights = vec![1usize; ole.id_count()];
+    for (id, _, _) in nodes.iter().rev() {
+        let entry = match ole.entry_by_id(*id) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let height = |child: Option<u32>| in_tree(child).map_or(1, |c| black_heights[c as usize]);
+        let (left, right) = (
+            height(entry.left_sibling_id),
+            height(entry.right_sibling_id),
+        );
+        if left != right {
+            color_violations += 1;
+        }
+        black_heights[*id as usize] = left.max(right) + usize::from(color(*id) == NodeColor::Black);
+    }
+
+    TreeStats {
+        storage,
+        entries: nodes.len(),
+        depth: nodes.iter().map(|(_, depth, _)| *depth).max().unwrap_or(0),
+        optimal_depth: (usize::BITS - nodes.len().leading_zeros()) as usize,
+        color_violations,
+    }
+}
+
+/// Walks the directory tree from the root, giving every storage and stream below it its full
+/// path, in directory order: by stream ID, the order of the entries in the directory stream.
+pub(crate) fn entries_with_paths(ole: &OleFile) -> Vec<(Vec<String>, &DirectoryEntry)> {
+    let mut entries = vec![];
+    // a malformed tree can link entries in a cycle, so every entry is visited once at most
+    let mut visited = vec![false; ole.id_count()];
+    let mut pending = vec![(0u32, vec![])];
+    while let Some((id, parent_path)) = pending.pop() {
+        let entry = match ole.entry_by_id(id) {
+            Some(entry) if !visited[id as usize] => entry,
+            _ => continue,
+        };
+        visited[id as usize] = true;
+        // siblings share the parent's path, pushed first so children come out before them
+        for sibling in [entry.right_sibling_id, entry.left_sibling_id]
+            .into_iter()
+            .flatten()
+        {
+            pending.push((sibling, parent_path.clone()));
+        }
+        if entry.object_type == ObjectType::RootStorage {
+            if let Some(child) = entry.child_id {
+                pending.push((child, vec![]));
+            }
+            continue;
+        }
+        let mut path = parent_path;
+        path.push(entry.name.clone());
+        if let Some(child) = entry.child_id {
+            pending.push((child, path.clone()));
+        }
+        entries.push((path, entry));
+    }
+    // the traversal order follows the shape of the trees, which writers are free to choose
+    entries.sort_by_key(|(_, entry)| entry.index);
+    entries
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::{error::Error, test_support::CfbBuilder, OleParseOptions};
+
+    #[test]
+    fn test_report_of_synthesized_file() {
+        let data = CfbBuilder::new()
+            .stream("Small", &[1; 10])
+            .stream("Storage/Large", &[2; 5000])
+            .build();
+        let report = analyze(&data).unwrap();
+        assert_eq!(report.version, REPORT_VERSION);
+        assert_eq!(report.header.major_version, 3);
+        assert_eq!(report.header.sector_size, 512);
+        assert_eq!(report.header.mini_stream_cutoff, 4096);
+
+        let small = report.streams.iter().find(|s| s.name() == "Small").unwrap();
+        assert_eq!(small.path, vec!["Small"]);
+        assert_eq!(small.size, 10);
+        assert_eq!(small.location, StreamLocation::MiniFat);
+        assert_eq!(small.start_sector, Some(0));
+        let large = report.streams.iter().find(|s| s.name() == "Large").unwrap();
+        assert_eq!(large.path, vec!["Storage", "Large"]);
+        assert_eq!(large.size, 5000);
+        assert_eq!(large.location, StreamLocation::Fat);
+        assert_eq!(large.start_sector, Some(0));
+    }
+
+    /// Everything a caller can do with a file that only reads it.
+    fn exercise(data: &[u8]) {
+        let _ = analyze(data);
+        if let Ok(ole) = OleFile::parse_sync_with_options(data, &OleParseOptions::lenient()) {
+            for (path, _) in ole.walk() {
+                let _ = ole.open_stream(&path.as_strs());
+            }
+            let _ = (
+                ole.orphaned_entries(),
+                ole.unused_sectors(),
+                ole.sector_map(),
+            );
+            let _ = (ole.tree_violations(), ole.embedded_object_entries());
+            let _ = (ole.vba_autoexec(), ole.deobfuscate_vba());
+        }
+        #[cfg(feature = "decrypt")]
+        let _ = crate::decrypt(data, "password");
+    }
+
+    #[test]
+    fn test_malformed_input_does_not_panic() {
+        let data = CfbBuilder::new()
+            .stream("WordDocument", &[0xA5; 600])
+            .stream("1Table", &[7; 40])
+            .stream("Macros/VBA/dir", &[1; 30])
+            .stream("ObjectPool/_1/\x01Ole10Native", &[3; 90])
+            .build();
+        for len in (0..data.len()).step_by(61) {
+            exercise(&data[..len]);
+        }
+        for position in 0..data.len() {
+            for value in [0x00, 0x01, 0x7F, 0xFF] {
+                let mut corrupted = data.clone();
+                corrupted[position] = value;
+                exercise(&corrupted);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unbounded_input_is_refused() {
+        // a DIFAT sector naming itself as the next one, with no end to the count
+        let mut data = CfbBuilder::new().stream("A", b"a").build();
+        let difat = (data.len() / 512 - 1) as u32;
+        data.extend(std::iter::repeat(0).take(508));
+        data.extend_from_slice(&difat.to_le_bytes());
+        data[0x44..0x48].copy_from_slice(&difat.to_le_bytes());
+        data[0x48..0x4C].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(analyze(&data), Err(Error::OleInvalidChain { .. })));
+        exercise(&data);
+
+        // expressions nested deeper than any stack
+        for nested in ["(", "-", "Chr("] {
+            let source = format!("x = {}1", nested.repeat(100_000));
+            assert!(crate::deobfuscate(&source).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_tree_stats() {
+        let data = CfbBuilder::new()
+            .stream("A", b"a")
+            .stream("B", b"b")
+            .stream("C", b"c")
+            .stream("D", b"d")
+            .stream("Storage/E", b"e")
+            .build();
+        let mut ole = OleFile::parse_sync(&data[..]).unwrap();
+        // the builder chains siblings to the right, a degenerate all black tree
+        let stats = ole.tree_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].storage, Vec::<String>::new());
+        assert_eq!(stats[0].entries, 5);
+        assert_eq!(stats[0].depth, 5);
+        assert_eq!(stats[0].optimal_depth, 3);
+        assert_eq!(stats[0].color_violations, 4);
+        assert_eq!(stats[1].storage, vec!["Storage"]);
+        assert_eq!((stats[1].entries, stats[1].depth), (1, 1));
+        assert_eq!(stats[1].color_violations, 0);
+
+        // a red root with a red child
+        let a = ole
+            .directory_entries
+            .iter()
+            .position(|e| e.name == "A")
+            .unwrap();
+        let b = ole
+            .directory_entries
+            .iter()
+            .position(|e| e.name == "B")
+            .unwrap();
+        ole.directory_entries[a].color = NodeColor::Red;
+        ole.directory_entries[b].color = NodeColor::Red;
+        assert_eq!(ole.tree_stats()[0].color_violations, 6);
+    }
+}
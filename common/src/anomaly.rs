@@ -0,0 +1,341 @@
+//! Checks for structure that parses but that writers don't produce.
+//!
+//! [`OleFile::warnings`] lists what parsing had to get past. The checks here look further, at
+//! what parsing never needs to touch: FAT entries leading out of the file, timestamps that hold
+//! no date, lie after the time of the check or are set where the spec wants them zeroed, streams
+//! whose chains are longer or shorter than their size, and streams carrying a class, which only
+//! storages have. None of it stops Office from opening a
+//! file, which is why crafted files get away with it.
+//!
+//! [`OleFile::warnings`]: crate::OleFile::warnings
+use crate::{
+    constants,
+    directory::{DirectoryEntry, ObjectType},
+    error::Location,
+    follow_chain, OleFile,
+};
+use chrono::{NaiveDateTime, Utc};
+
+/// Suspicious structure found by [`crate::OleFile::anomalies`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    /// what was found, for people
+    pub message: String,
+    /// where in the file it was found, as far as known
+    pub location: Location,
+}
+
+/// What kind of structure an [`Anomaly`] is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum AnomalyKind {
+    /// a FAT entry links to a sector past the end of the file
+    FatEntryOutOfRange,
+    /// a mini FAT entry links to a mini sector past the end of the mini stream
+    MiniFatEntryOutOfRange,
+    /// a directory entry was created or modified after the time of the check
+    FutureTimestamp,
+    /// a creation or modification time holds no date, such as one before 1601
+    InvalidTimestamp,
+    /// a stream has a creation or modification time, or the root a creation time, which the
+    /// spec wants zeroed
+    UnexpectedTimestamp,
+    /// the chain of a stream has more or fewer sectors than its size needs
+    StreamSizeMismatch,
+    /// the chain of a stream loops or leaves its table
+    BrokenChain,
+    /// a stream has a class ID, which the spec reserves for storages
+    StreamClassId,
+}
+
+impl Anomaly {
+    /// Attribute the anomaly to `entry`, keeping the sector and offset already known.
+    fn at(mut self, entry: &DirectoryEntry) -> Self {
+        self.location.entry = Some(entry.index);
+        self.location.name = Some(entry.name.clone());
+        self
+    }
+}
+
+impl std::fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Every anomaly in `ole`: the FAT and the mini FAT first, then the directory entries in
+/// directory order.
+pub(crate) fn anomalies(ole: &OleFile) -> Vec<Anomaly> {
+    anomalies_at(ole, Utc::now().naive_utc())
+}
+
+/// [`anomalies`] with `now` as the time of the check.
+fn anomalies_at(ole: &OleFile, now: NaiveDateTime) -> Vec<Anomaly> {
+    let mut found = vec![];
+    let sector_size = ole.header.sector_size as u64;
+    let fat_sectors = ole
+        .sector_allocation_table_sector_indices()
+        .unwrap_or_default();
+    let per_sector = sector_size / 4;
+    let sectors = ole.sectors.len();
+    for (index, next) in ole.sector_allocation_table.iter().enumerate() {
+        if *next <= constants::MAX_REG_STREAM_ID_VALUE && *next as usize >= sectors {
+            let offset = fat_sectors
+                .get(index / per_sector as usize)
+                .map(|fat| (*fat as u64 + 1) * sector_size + (index as u64 % per_sector) * 4);
+            found.push(Anomaly {
+                kind: AnomalyKind::FatEntryOutOfRange,
+                message: format!(
+                    "FAT entry of sector {} links to sector {}, the file has {}",
+                    index, next, sectors
+                ),
+                location: Location {
+                    sector: Some(index as u32),
+                    offset,
+                    ..Location::default()
+                },
+            });
+        }
+    }
+    // the mini FAT usually maps more mini sectors than the mini stream holds, all free
+    let mini_sectors = ole
+        .mini_stream_bytes()
+        .len()
+        .div_ceil(constants::MINI_SECTOR_SIZE as usize);
+    for (index, next) in ole.short_sector_allocation_table.iter().enumerate() {
+        if *next <= constants::MAX_REG_STREAM_ID_VALUE && *next as usize >= mini_sectors {
+            found.push(Anomaly {
+                kind: AnomalyKind::MiniFatEntryOutOfRange,
+                message: format!(
+                    "mini FAT entry of mini sector {} links to mini sector {}, the mini stream has {}",
+                    index, next, mini_sectors
+                ),
+                location: Location {
+                    sector: Some(index as u32),
+                    ..Location::default()
+                },
+            });
+        }
+    }
+
+    for entry in ole.entries() {
+        for (what, raw, time) in [
+            ("created", entry.raw_creation_time, entry.creation_time),
+            (
+                "modified",
+                entry.raw_modification_time,
+                entry.modification_time,
+            ),
+        ] {
+            if raw == 0 {
+                continue;
+            }
+            let (kind, message) = match time {
+                None => (
+                    AnomalyKind::InvalidTimestamp,
+                    format!(
+                        "{:?} was {} at FILETIME {:#018x}, which is no date",
+                        entry.name, what, raw
+                    ),
+                ),
+                Some(time) if time > now => (
+                    AnomalyKind::FutureTimestamp,
+                    format!("{:?} was {} in the future, on {}", entry.name, what, time),
+                ),
+                Some(time)
+                    if entry.object_type == ObjectType::Stream
+                        || (entry.object_type == ObjectType::RootStorage && what == "created") =>
+                {
+                    (
+                        AnomalyKind::UnexpectedTimestamp,
+                        format!(
+                            "{:?} was {} on {}, the spec wants the time zeroed",
+                            entry.name, what, time
+                        ),
+                    )
+                }
+                Some(_) => continue,
+            };
+            found.push(Anomaly {
+                kind,
+                message,
+                location: entry.location(),
+            });
+        }
+        if entry.object_type == ObjectType::Stream {
+            if let Some(class_id) = &entry.class_id {
+                found.push(Anomaly {
+                    kind: AnomalyKind::StreamClassId,
+                    message: format!("stream {:?} has the class ID {}", entry.name, class_id),
+                    location: entry.location(),
+                });
+            }
+        }
+        found.extend(chain_anomaly(ole, entry));
+    }
+    found
+}
+
+/// Whether the chain of `entry`, a stream or the root with its mini stream, fits its size.
+fn chain_anomaly(ole: &OleFile, entry: &DirectoryEntry) -> Option<Anomaly> {
+    if entry.object_type == ObjectType::Storage || entry.stream_size == 0 {
+        return None;
+    }
+    let start = entry.starting_sector_location?;
+    let in_mini_stream = entry.object_type == ObjectType::Stream
+        && entry.stream_size < ole.header.standard_stream_min_size as u64;
+    let (table, unit, sector_size) = if in_mini_stream {
+        (
+            &ole.short_sector_allocation_table,
+            "mini sector",
+            constants::MINI_SECTOR_SIZE,
+        )
+    } else {
+        (
+            &ole.sector_allocation_table,
+            "sector",
+            ole.header.sector_size as u64,
+        )
+    };
+    let chain = match follow_chain(table, start, unit) {
+        Ok(chain) => chain,
+        Err(err) => {
+            return Some(
+                Anomaly {
+                    kind: AnomalyKind::BrokenChain,
+                    message: format!("the chain of {:?} is broken: {}", entry.name, err),
+                    location: err.location().cloned().unwrap_or_default(),
+                }
+                .at(entry),
+            )
+        }
+    };
+    let needed = entry.stream_size.div_ceil(sector_size) as usize;
+    (chain.len() != needed).then(|| Anomaly {
+        kind: AnomalyKind::StreamSizeMismatch,
+        message: format!(
+            "{:?} is {} bytes, which need {} {}s, its chain has {}",
+            entry.name,
+            entry.stream_size,
+            needed,
+            unit,
+            chain.len()
+        ),
+        location: Location {
+            sector: Some(start),
+            ..entry.location()
+        },
+    })
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    #[test]
+    fn test_anomalies() {
+        let data = CfbBuilder::new()
+            .stream("Small", &[1; 100])
+            .stream("Large", &[2; 5000])
+            .build();
+        let mut ole = OleFile::parse_sync(&data[..]).unwrap();
+        assert_eq!(ole.anomalies(), vec![]);
+
+        // a chain two sectors longer than the size, and a class on a stream
+        let large = ole.find_stream_path("Large").unwrap().index;
+        ole.directory_entries[large].stream_size = 4000;
+        ole.directory_entries[large].class_id = Some("00020906-0000-0000-c000-000000000046".into());
+        let kinds = |ole: &OleFile| {
+            ole.anomalies()
+                .into_iter()
+                .map(|anomaly| (anomaly.kind, anomaly.location.entry))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds(&ole),
+            vec![
+                (AnomalyKind::StreamClassId, Some(large)),
+                (AnomalyKind::StreamSizeMismatch, Some(large)),
+            ]
+        );
+
+        // the last sector of Large links past the end of the file
+        ole.directory_entries[large].stream_size = 5000;
+        let start = ole.directory_entries[large]
+            .starting_sector_location
+            .unwrap();
+        let last = follow_chain(&ole.sector_allocation_table, start, "sector")
+            .unwrap()
+            .pop()
+            .unwrap();
+        ole.sector_allocation_table[last as usize] = 1000;
+        let anomalies = ole.anomalies();
+        assert_eq!(anomalies[0].kind, AnomalyKind::FatEntryOutOfRange);
+        assert_eq!(anomalies[0].location.sector, Some(last));
+        assert!(anomalies[0].location.offset.is_some());
+        assert!(anomalies
+            .iter()
+            .any(|anomaly| anomaly.kind == AnomalyKind::BrokenChain
+                && anomaly.location.entry == Some(large)
+                && anomaly.location.sector == Some(1000)));
+    }
+
+    #[test]
+    fn test_timestamp_anomalies() {
+        let data = CfbBuilder::new().stream("Storage/Data", &[1; 100]).build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let offset = |path: &str, field: u64| {
+            let index = ole.find_stream_path(path).map_or(0, |entry| entry.index);
+            (ole.directory_entry_location(index, None).offset.unwrap() + field) as usize
+        };
+        let filetime = |year: i32| {
+            let time = chrono::NaiveDate::from_ymd_opt(year, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap();
+            ((time.and_utc().timestamp() + 11_644_473_600) * 10_000_000) as u64
+        };
+        let mut patched = data.clone();
+        for (at, value) in [
+            // storages may have both times, the root a modification time
+            (offset("Storage", 100), filetime(2001)),
+            (offset("Storage", 108), filetime(2002)),
+            (offset("", 108), filetime(2003)),
+            // but not a creation time, and streams neither
+            (offset("", 100), filetime(2004)),
+            (offset("Storage/Data", 108), filetime(2005)),
+            // before 1601 when read as signed, and in 2100
+            (offset("Storage", 108), u64::MAX),
+            (offset("Storage/Data", 100), filetime(2100)),
+        ] {
+            patched[at..at + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        let ole = OleFile::parse_sync(&patched[..]).unwrap();
+        let storage = ole.find_stream_path("Storage").unwrap();
+        assert_eq!(
+            storage.creation_time().map(|time| time.to_rfc3339()),
+            Some("2001-01-01T00:00:00+00:00".to_string())
+        );
+        assert_eq!(storage.modification_time(), None);
+        let now = chrono::NaiveDate::from_ymd_opt(2026, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let kinds = anomalies_at(&ole, now)
+            .into_iter()
+            .map(|anomaly| (anomaly.kind, anomaly.location.name.unwrap()))
+            .collect::<Vec<_>>();
+        let root = ole.root().name().to_string();
+        assert_eq!(
+            kinds,
+            [
+                (AnomalyKind::UnexpectedTimestamp, root),
+                (AnomalyKind::InvalidTimestamp, "Storage".to_string()),
+                (AnomalyKind::FutureTimestamp, "Data".to_string()),
+                (AnomalyKind::UnexpectedTimestamp, "Data".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//! Archives carried inside documents, told by their magic and, with the `archive` feature, listed
+//! without extracting them.
+//!
+//! Zip archives are read through their central directory. RAR 4 and RAR 5 archives keep a header
+//! in front of every member, which is walked here; with encrypted headers there is nothing to
+//! list. 7z archives normally compress their header, so they are only recognised.
+
+/// The kind of an archive, from the magic at its start.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    SevenZip,
+    Rar,
+}
+
+/// A file or directory in an archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveMember {
+    /// path within the archive, as stored
+    pub name: String,
+    /// unpacked size in bytes
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+/// what an empty zip archive starts with, the end of central directory record
+const ZIP_EMPTY_MAGIC: &[u8] = b"PK\x05\x06";
+const SEVEN_ZIP_MAGIC: &[u8] = b"7z\xBC\xAF\x27\x1C";
+const RAR4_MAGIC: &[u8] = b"Rar!\x1A\x07\x00";
+#[cfg_attr(not(feature = "archive"), allow(dead_code))]
+const RAR5_MAGIC: &[u8] = b"Rar!\x1A\x07\x01\x00";
+
+pub fn archive_format(data: &[u8]) -> Option<ArchiveFormat> {
+    //! The format of the archive `data` holds, `None` if it doesn't start like one
+    //!
+    //! ## Example usage
+    //! ```rust
+    //! use ole::{archive_format, ArchiveFormat};
+    //!
+    //! assert_eq!(archive_format(b"PK\x03\x04\x14\x00"), Some(ArchiveFormat::Zip));
+    //! assert_eq!(archive_format(b"MZ\x90\x00"), None);
+    //! ```
+    if data.starts_with(ZIP_MAGIC) || data.starts_with(ZIP_EMPTY_MAGIC) {
+        Some(ArchiveFormat::Zip)
+    } else if data.starts_with(SEVEN_ZIP_MAGIC) {
+        Some(ArchiveFormat::SevenZip)
+    } else if data.starts_with(&RAR4_MAGIC[..6]) {
+        Some(ArchiveFormat::Rar)
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "archive")]
+pub fn archive_members(data: &[u8]) -> crate::Result<Option<Vec<ArchiveMember>>> {
+    //! The members of the archive `data` holds, `None` if it isn't an archive or its members
+    //! can't be listed without unpacking it. A RAR archive cut short gives the members before
+    //! the cut.
+    //!
+    //! ## Example usage
+    //! ```rust
+    //! use ole::archive_members;
+    //!
+    //! // a 7z archive is recognised, not listed
+    //! assert_eq!(archive_members(b"7z\xBC\xAF\x27\x1C\x00\x04").unwrap(), None);
+    //! ```
+    match archive_format(data) {
+        Some(ArchiveFormat::Zip) => zip_members(data).map(Some),
+        Some(ArchiveFormat::Rar) if data.starts_with(RAR5_MAGIC) => {
+            Ok(rar5_members(&data[RAR5_MAGIC.len()..]))
+        }
+        Some(ArchiveFormat::Rar) if data.starts_with(RAR4_MAGIC) => {
+            Ok(rar4_members(&data[RAR4_MAGIC.len()..]))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(feature = "archive")]
+fn zip_members(data: &[u8]) -> crate::Result<Vec<ArchiveMember>> {
+    let invalid = |error| {
+        crate::error::Error::StdIo(std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    };
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).map_err(invalid)?;
+    (0..archive.len())
+        .map(|index| {
+            // reads the central directory entry, not the data
+            let member = archive.by_index_raw(index).map_err(invalid)?;
+            Ok(ArchiveMember {
+                name: member.name().to_string(),
+                size: member.size(),
+                is_dir: member.is_dir(),
+            })
+        })
+        .collect()
+}
+
+/// Walk the blocks of a RAR 4 archive (the RAR 4.x technote), `data` starting after the
+/// marker block.
+#[cfg(feature = "archive")]
+fn rar4_members(data: &[u8]) -> Option<Vec<ArchiveMember>> {
+    const ARCHIVE_HEADER: u8 = 0x73;
+    const FILE_HEADER: u8 = 0x74;
+    const END_OF_ARCHIVE: u8 = 0x7B;
+    /// archive header flag: every block header after it is encrypted
+    const ENCRYPTED_HEADERS: u16 = 0x0080;
+    /// block flag: a 4-byte data size follows the header size
+    const HAS_DATA: u16 = 0x8000;
+    /// file header flags: 64-bit sizes, and the bits that all set mark a directory
+    const LARGE_FILE: u16 = 0x0100;
+    const DIRECTORY: u16 = 0x00E0;
+
+    let mut members = vec![];
+    let mut offset = 0usize;
+    // CRC, type, flags and size
+    while let Some(block) = data.get(offset..offset + 7) {
+        let block_type = block[2];
+        let flags = u16::from_le_bytes([block[3], block[4]]);
+        let header_size = u16::from_le_bytes([block[5], block[6]]) as usize;
+        let header = match data.get(offset..offset + header_size) {
+            Some(header) if header_size >= 7 => header,
+            _ => break,
+        };
+        let mut data_size = match (flags & HAS_DATA != 0, block_type) {
+            (true, _) | (_, FILE_HEADER) => le_u32(header, 7)? as u64,
+            _ => 0,
+        };
+        match block_type {
+            ARCHIVE_HEADER if flags & ENCRYPTED_HEADERS != 0 => return None,
+            FILE_HEADER => {
+                let mut size = le_u32(header, 11)? as u64;
+                let name_len = u16::from_le_bytes([*header.get(26)?, *header.get(27)?]) as usize;
+                let mut name_offset = 32;
+                if flags & LARGE_FILE != 0 {
+                    data_size |= (le_u32(header, 32)? as u64) << 32;
+                    size |= (le_u32(header, 36)? as u64) << 32;
+                    name_offset += 8;
+                }
+                let name = header.get(name_offset..name_offset + name_len)?;
+                // a Unicode name comes after the ASCII one and a zero byte
+                let name = name.split(|byte| *byte == 0).next().unwrap_or_default();
+                members.push(ArchiveMember {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    size,
+                    is_dir: flags & DIRECTORY == DIRECTORY,
+                });
+            }
+            END_OF_ARCHIVE => break,
+            _ => {}
+        }
+        offset = offset
+            .checked_add(header_size)?
+            .checked_add(usize::try_from(data_size).ok()?)?;
+    }
+    Some(members)
+}
+
+/// Walk the blocks of a RAR 5 archive (the RAR 5.0 technote), `data` starting after the
+/// signature.
+#[cfg(feature = "archive")]
+fn rar5_members(data: &[u8]) -> Option<Vec<ArchiveMember>> {
+    const FILE_HEADER: u64 = 2;
+    const ENCRYPTION_HEADER: u64 = 4;
+    const END_OF_ARCHIVE: u64 = 5;
+    /// header flags: an extra area size and a data size follow the flags
+    const HAS_EXTRA: u64 = 0x0001;
+    const HAS_DATA: u64 = 0x0002;
+    /// file flags: a directory, and the optional fields before the name
+    const DIRECTORY: u64 = 0x0001;
+    const HAS_MTIME: u64 = 0x0002;
+    const HAS_CRC: u64 = 0x0004;
+
+    let mut members = vec![];
+    let mut offset = 0usize;
+    while offset < data.len() {
+        // the CRC, then the size of everything from the type to the end of the header
+        let mut reader = match data.get(offset + 4..) {
+            Some(rest) => Vints(rest),
+            None => break,
+        };
+        let header_size = reader.next()? as usize;
+        let header_start = data.len() - reader.0.len();
+        let header = match data.get(header_start..header_start.saturating_add(header_size)) {
+            Some(header) => header,
+            None => break,
+        };
+        let mut reader = Vints(header);
+        let header_type = reader.next()?;
+        let flags = reader.next()?;
+        if flags & HAS_EXTRA != 0 {
+            reader.next()?;
+        }
+        let data_size = if flags & HAS_DATA != 0 {
+            reader.next()?
+        } else {
+            0
+        };
+        match header_type {
+            FILE_HEADER => {
+                let file_flags = reader.next()?;
+                let size = reader.next()?;
+                // attributes
+                reader.next()?;
+                if file_flags & HAS_MTIME != 0 {
+                    reader.skip(4)?;
+                }
+                if file_flags & HAS_CRC != 0 {
+                    reader.skip(4)?;
+                }
+                // compression information and host OS
+                reader.next()?;
+                reader.next()?;
+                let name_len = reader.next()? as usize;
+                let name = reader.0.get(..name_len)?;
+                members.push(ArchiveMember {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    size,
+                    is_dir: file_flags & DIRECTORY != 0,
+                });
+            }
+            ENCRYPTION_HEADER => return None,
+            END_OF_ARCHIVE => break,
+            _ => {}
+        }
+        offset = (header_start + header.len()).checked_add(usize::try_from(data_size).ok()?)?;
+    }
+    Some(members)
+}
+
+#[cfg(feature = "archive")]
+fn le_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads the variable length integers of RAR 5: seven bits a byte, low bits first, the top bit
+/// set on all but the last byte.
+#[cfg(feature = "archive")]
+struct Vints<'a>(&'a [u8]);
+
+#[cfg(feature = "archive")]
+impl Vints<'_> {
+    fn next(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for (position, byte) in self.0.iter().enumerate().take(10) {
+            value |= ((byte & 0x7F) as u64) << (7 * position);
+            if byte & 0x80 == 0 {
+                self.0 = &self.0[position + 1..];
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.0 = self.0.get(len..)?;
+        Some(())
+    }
+}
+
+#[cfg(all(test, feature = "archive"))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn member(name: &str, size: u64, is_dir: bool) -> ArchiveMember {
+        ArchiveMember {
+            name: name.to_string(),
+            size,
+            is_dir,
+        }
+    }
+
+    #[test]
+    fn test_zip_members() {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options = zip::write::FileOptions::default();
+        zip.add_directory("docs", options).unwrap();
+        zip.start_file("docs/invoice.exe", options).unwrap();
+        zip.write_all(&[0x90; 1000]).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        assert_eq!(archive_format(&data), Some(ArchiveFormat::Zip));
+        assert_eq!(
+            archive_members(&data).unwrap().unwrap(),
+            [
+                member("docs/", 0, true),
+                member("docs/invoice.exe", 1000, false)
+            ]
+        );
+        assert!(archive_members(&data[..data.len() - 30]).is_err());
+    }
+
+    /// A RAR 4 block with the given type, flags and the fields after the size.
+    fn rar4_block(block_type: u8, flags: u16, fields: &[u8]) -> Vec<u8> {
+        let mut block = vec![0, 0, block_type];
+        block.extend(flags.to_le_bytes());
+        block.extend((7 + fields.len() as u16).to_le_bytes());
+        block.extend(fields);
+        block
+    }
+
+    fn rar4_file(name: &str, packed: &[u8], size: u32, flags: u16) -> Vec<u8> {
+        let mut fields = (packed.len() as u32).to_le_bytes().to_vec();
+        fields.extend(size.to_le_bytes());
+        // host OS, CRC, time, version, method
+        fields.extend([0u8; 11]);
+        fields.extend((name.len() as u16).to_le_bytes());
+        // attributes
+        fields.extend([0u8; 4]);
+        fields.extend(name.as_bytes());
+        let mut block = rar4_block(0x74, flags | 0x8000, &fields);
+        block.extend(packed);
+        block
+    }
+
+    #[test]
+    fn test_rar4_members() {
+        let mut data = RAR4_MAGIC.to_vec();
+        data.extend(rar4_block(0x73, 0, &[0u8; 6]));
+        data.extend(rar4_file("payload", &[], 0, 0x00E0));
+        data.extend(rar4_file("payload\\run.js", b"packed data", 4096, 0));
+        data.extend(rar4_block(0x7B, 0x4000, &[]));
+
+        assert_eq!(archive_format(&data), Some(ArchiveFormat::Rar));
+        let members = [
+            member("payload", 0, true),
+            member("payload\\run.js", 4096, false),
+        ];
+        assert_eq!(archive_members(&data).unwrap().unwrap(), members);
+        // cut in the middle of the data of the second member
+        assert_eq!(
+            archive_members(&data[..data.len() - 14]).unwrap().unwrap(),
+            members
+        );
+
+        let mut encrypted = RAR4_MAGIC.to_vec();
+        encrypted.extend(rar4_block(0x73, 0x0080, &[0u8; 6]));
+        assert_eq!(archive_members(&encrypted).unwrap(), None);
+    }
+
+    /// A RAR 5 block: CRC, header size, then `header`, then `packed`.
+    fn rar5_block(header: &[u8], packed: &[u8]) -> Vec<u8> {
+        let mut block = vec![0u8; 4];
+        block.push(header.len() as u8);
+        block.extend(header);
+        block.extend(packed);
+        block
+    }
+
+    #[test]
+    fn test_rar5_members() {
+        let mut data = RAR5_MAGIC.to_vec();
+        // main archive header: type 1, no flags, no archive flags
+        data.extend(rar5_block(&[1, 0, 0], &[]));
+        let name = b"invoice.pdf.exe";
+        // file header: type 2, data follows (12 bytes), file flags with CRC, 300 bytes
+        // unpacked (0xAC 0x02), attributes, CRC, compression, host, name
+        let mut header = vec![2, 0x02, 12, 0x04, 0xAC, 0x02, 0x20, 1, 2, 3, 4, 0, 1];
+        header.push(name.len() as u8);
+        header.extend(name);
+        data.extend(rar5_block(&header, &[0x55; 12]));
+        data.extend(rar5_block(&[5, 0, 0], &[]));
+
+        assert_eq!(archive_format(&data), Some(ArchiveFormat::Rar));
+        assert_eq!(
+            archive_members(&data).unwrap().unwrap(),
+            [member("invoice.pdf.exe", 300, false)]
+        );
+
+        let mut encrypted = RAR5_MAGIC.to_vec();
+        encrypted.extend(rar5_block(&[4, 0, 0, 0], &[]));
+        assert_eq!(archive_members(&encrypted).unwrap(), None);
+    }
+}
@@ -0,0 +1,69 @@
+//! Which optional parts this build of the crate has, for services running differently built
+//! instances side by side that have to send each file to one able to handle it.
+use crate::EncryptionScheme;
+
+/// The Cargo features the crate can be built with, in the order [`Capabilities::features`]
+/// lists them.
+const FEATURES: [(&str, bool); 9] = [
+    ("async", cfg!(feature = "async")),
+    ("blocking", cfg!(feature = "blocking")),
+    ("mmap", cfg!(feature = "mmap")),
+    ("archive", cfg!(feature = "archive")),
+    ("decrypt", cfg!(feature = "decrypt")),
+    ("ingest", cfg!(feature = "ingest")),
+    ("manifest", cfg!(feature = "manifest")),
+    ("parallel", cfg!(feature = "parallel")),
+    ("zeroize", cfg!(feature = "zeroize")),
+];
+
+/// What this build of the crate can do, see [`capabilities`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// the version of the crate
+    pub version: &'static str,
+    /// the Cargo features compiled in, by their names in the manifest
+    pub features: Vec<&'static str>,
+    /// the encryption schemes whose content can be decrypted, with the `decrypt` feature;
+    /// which scheme a file uses is detected in every build
+    pub decryption: Vec<EncryptionScheme>,
+}
+
+impl Capabilities {
+    /// Whether the Cargo feature `feature` is compiled in.
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+/// What this build of the crate can do.
+///
+/// ```rust
+/// let capabilities = ole::capabilities();
+/// if !capabilities.has_feature("archive") {
+///     println!("archives in OLE objects are only recognized, not listed");
+/// }
+/// assert_eq!(
+///     capabilities.decryption.is_empty(),
+///     !capabilities.has_feature("decrypt")
+/// );
+/// ```
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect(),
+        decryption: if cfg!(feature = "decrypt") {
+            vec![
+                EncryptionScheme::Rc4CryptoApi,
+                EncryptionScheme::Standard,
+                EncryptionScheme::Agile,
+            ]
+        } else {
+            vec![]
+        },
+    }
+}
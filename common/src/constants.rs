@@ -1,22 +1,42 @@
-use std::{collections::HashMap, marker::Unpin};
-use tokio::io::AsyncRead;
+use std::collections::HashMap;
 
-pub trait Readable: Unpin + AsyncRead {}
-impl Readable for tokio::fs::File {}
+/// Anything the async parser can read a file from: files, buffers, sockets, ...
+#[cfg(feature = "async")]
+pub trait Readable: Unpin + tokio::io::AsyncRead {}
+#[cfg(feature = "async")]
+impl<T: Unpin + tokio::io::AsyncRead> Readable for T {}
 
 pub const HEADER_LENGTH: usize = 512;
 pub const MAGIC_BYTES: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
-pub const _CORRECT_MINOR_VERSION: [u8; 2] = [0x3E, 0x00];
+pub const CORRECT_MINOR_VERSION: [u8; 2] = [0x3E, 0x00];
 pub const MAJOR_VERSION_3_VALUE: u16 = 3;
 pub const MAJOR_VERSION_3: [u8; 2] = [0x03, 0x00];
 pub const MAJOR_VERSION_4: [u8; 2] = [0x04, 0x00];
 pub const SECTOR_SIZE_VERSION_3: [u8; 2] = [0x09, 0x00];
 pub const SECTOR_SIZE_VERSION_4: [u8; 2] = [0x0C, 0x00];
 pub const CORRECT_STANDARD_STREAM_MIN_SIZE: [u8; 4] = [0x00, 0x10, 0x00, 0x00];
+/// size of a sector in the mini stream, fixed for both major versions
+pub const MINI_SECTOR_SIZE: u64 = 64;
 
+/// number of sectors `OleFile::open_lazy` keeps in memory
+pub const DEFAULT_SECTOR_CACHE: usize = 256;
+
+/// most sectors `OleFile::open_bounded` keeps in memory, whatever the budget: extraction reads
+/// each sector once, a larger cache only holds on to memory
+pub const MAX_BOUNDED_SECTOR_CACHE: usize = 4096;
+
+/// first byte of the range that file locking uses, the sector holding it is the range lock
+/// sector, which no structure may use
+pub const RANGE_LOCK_OFFSET: u64 = 0x7FFFFF00;
+
+pub const DIFAT_SECTOR: u32 = 0xFFFFFFFC;
+pub const FAT_SECTOR: u32 = 0xFFFFFFFD;
 pub const CHAIN_END: u32 = 0xFFFFFFFE;
 pub const UNALLOCATED_SECTOR: u32 = 0xFFFFFFFF;
 
+/// number of FAT sector locations stored in the header itself, the rest live in DIFAT sectors
+pub const HEADER_DIFAT_ENTRIES: usize = 109;
+
 pub const SIZE_OF_DIRECTORY_ENTRY: usize = 128;
 
 pub const NODE_COLOR_RED: [u8; 1] = [0x00];
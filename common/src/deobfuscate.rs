@@ -0,0 +1,939 @@
+//! Strings that VBA code only builds at run time, recovered by evaluating the constant parts of
+//! its expressions.
+//!
+//! Nothing is executed: expressions made only of literals and a few string functions are
+//! evaluated, everything depending on a variable is left alone. Numeric arrays decoded with a
+//! `Xor` against a constant are the one pattern followed across statements.
+use crate::{vba, OleFile, Result, VbaModule};
+use std::collections::HashMap;
+
+/// characters a recovered string has at least, shorter ones are rarely worth a look
+const MIN_STRING_LEN: usize = 4;
+/// how deep parentheses, calls and signs may be nested before an expression is given up on
+const MAX_NESTING: usize = 64;
+
+/// How a recovered string was hidden.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Obfuscation {
+    /// literals joined with `&` or `+`
+    Concatenation,
+    /// characters given by code, `Chr`, `ChrW` and `ChrB`
+    Chr,
+    StrReverse,
+    /// `Replace`, `Split` and `Join` on literals
+    StringFunction,
+    /// character codes in an `Array` or a list that is split
+    NumericArray,
+    /// character codes combined with a constant key through `Xor`
+    Xor,
+}
+
+/// A string recovered from VBA source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecoveredString {
+    /// the line of the source the expression starts on, from 1
+    pub line: usize,
+    /// the expression as written
+    pub expression: String,
+    pub value: String,
+    /// the constructs the expression used, in the order they were met
+    pub obfuscations: Vec<Obfuscation>,
+}
+
+/// The source of a VBA module with the strings recovered from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeobfuscatedModule {
+    /// the storage of the project the module is in
+    pub storage: Vec<String>,
+    pub module: VbaModule,
+    pub source: String,
+    pub recovered: Vec<RecoveredString>,
+}
+
+pub(crate) fn deobfuscate_vba(ole: &OleFile) -> Result<Vec<DeobfuscatedModule>> {
+    let mut modules = vec![];
+    for project in vba::vba_projects(ole)? {
+        for module in &project.modules {
+            let source = vba::module_source(ole, &project, module)?;
+            modules.push(DeobfuscatedModule {
+                storage: project.storage.clone(),
+                module: module.clone(),
+                recovered: deobfuscate(&source),
+                source,
+            });
+        }
+    }
+    Ok(modules)
+}
+
+/// Recover the strings the constant expressions of VBA `source` evaluate to, in the order they
+/// appear. Plain literals are left out, they can be read in the source as they are.
+///
+/// ```rust
+/// let source = "Sub AutoOpen()\r\n    url = StrReverse(\"exe.d/moc.elpmaxe//:ptth\")\r\nEnd Sub";
+/// let recovered = ole::deobfuscate(source);
+/// assert_eq!(recovered[0].value, "http://example.com/d.exe");
+/// ```
+pub fn deobfuscate(source: &str) -> Vec<RecoveredString> {
+    let tokens = tokenize(source);
+    let statements = tokens
+        .split(|token| token.kind == Kind::End)
+        .filter(|statement| !statement.is_empty())
+        .collect::<Vec<_>>();
+    let xor_keys = xor_keys(&statements);
+    let mut scan = Scan {
+        source,
+        recovered: vec![],
+    };
+    for statement in statements {
+        let mut position = 0;
+        // the array assigned by `name = Array(...)`, which a later loop may decode with a key
+        let key = match statement {
+            [Token {
+                kind: Kind::Ident(name),
+                ..
+            }, Token {
+                kind: Kind::Op('='),
+                ..
+            }, ..] => {
+                position = 2;
+                xor_keys.get(&name.to_ascii_lowercase()).copied()
+            }
+            _ => None,
+        };
+        while position < statement.len() {
+            let mut parser = Parser {
+                tokens: statement,
+                position,
+                depth: 0,
+                scan: &mut scan,
+            };
+            match parser.expression() {
+                Some(found) => {
+                    position = parser.position;
+                    match key.filter(|_| position == statement.len()) {
+                        Some(key) => scan.settle_xor(found, key),
+                        None => scan.settle(found),
+                    }
+                }
+                // carry on after what the failed parse got through rather than at the next
+                // token, which would parse the rest of the statement again and again
+                None => position = parser.position.max(position + 1),
+            }
+        }
+    }
+    scan.recovered
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    Str(String),
+    Num(i64),
+    Ident(String),
+    Op(char),
+    /// the end of a statement: a line break or a colon
+    End,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: Kind,
+    start: usize,
+    end: usize,
+    line: usize,
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let chars = source.char_indices().collect::<Vec<_>>();
+    let offset = |index: usize| chars.get(index).map_or(source.len(), |(offset, _)| *offset);
+    let mut tokens: Vec<Token> = vec![];
+    let mut line = 1;
+    let mut index = 0;
+    while index < chars.len() {
+        let (start, c) = chars[index];
+        let begin = index;
+        let kind = match c {
+            '\n' => {
+                line += 1;
+                index += 1;
+                Kind::End
+            }
+            ':' => {
+                index += 1;
+                Kind::End
+            }
+            '\'' => {
+                while index < chars.len() && chars[index].1 != '\n' {
+                    index += 1;
+                }
+                continue;
+            }
+            '"' => {
+                let mut text = String::new();
+                index += 1;
+                while index < chars.len() && chars[index].1 != '\n' {
+                    if chars[index].1 == '"' {
+                        if chars.get(index + 1).map(|(_, c)| *c) != Some('"') {
+                            index += 1;
+                            break;
+                        }
+                        index += 1;
+                    }
+                    text.push(chars[index].1);
+                    index += 1;
+                }
+                Kind::Str(text)
+            }
+            '&' if matches!(chars.get(index + 1), Some((_, 'H' | 'h' | 'O' | 'o'))) => {
+                let radix = if chars[index + 1].1.eq_ignore_ascii_case(&'h') {
+                    16
+                } else {
+                    8
+                };
+                index += 2;
+                let digits = take_while(&chars, &mut index, |c| c.is_digit(radix));
+                // type suffixes, as in &HFF&
+                take_while(&chars, &mut index, |c| matches!(c, '&' | '%'));
+                match i64::from_str_radix(&digits, radix) {
+                    Ok(value) => Kind::Num(value),
+                    Err(_) => Kind::Op('?'),
+                }
+            }
+            '0'..='9' => {
+                let digits = take_while(&chars, &mut index, |c| c.is_ascii_digit());
+                if chars.get(index).map(|(_, c)| *c) == Some('.') {
+                    // floating point numbers aren't evaluated
+                    index += 1;
+                    take_while(&chars, &mut index, |c| c.is_ascii_digit());
+                    Kind::Op('?')
+                } else {
+                    take_while(&chars, &mut index, |c| {
+                        matches!(c, '&' | '%' | '#' | '!' | '@')
+                    });
+                    digits.parse().map_or(Kind::Op('?'), Kind::Num)
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let name = take_while(&chars, &mut index, |c| c.is_alphanumeric() || c == '_');
+                if name == "_" {
+                    // a line continuation, up to and including the line break
+                    while index < chars.len() && chars[index].1 != '\n' {
+                        index += 1;
+                    }
+                    index += 1;
+                    line += 1;
+                    continue;
+                }
+                let at_statement_start = tokens.last().map_or(true, |last| last.kind == Kind::End);
+                if at_statement_start && name.eq_ignore_ascii_case("Rem") {
+                    while index < chars.len() && chars[index].1 != '\n' {
+                        index += 1;
+                    }
+                    continue;
+                }
+                let mut name = name;
+                if chars.get(index).map(|(_, c)| *c) == Some('$') {
+                    name.push('$');
+                    index += 1;
+                }
+                Kind::Ident(name)
+            }
+            c if c.is_whitespace() => {
+                index += 1;
+                continue;
+            }
+            c => {
+                index += 1;
+                Kind::Op(c)
+            }
+        };
+        tokens.push(Token {
+            kind,
+            start,
+            end: offset(index.max(begin + 1)),
+            line: if chars[begin].1 == '\n' {
+                line - 1
+            } else {
+                line
+            },
+        });
+    }
+    tokens
+}
+
+fn take_while(chars: &[(usize, char)], index: &mut usize, keep: impl Fn(char) -> bool) -> String {
+    let mut taken = String::new();
+    while let Some((_, c)) = chars.get(*index).filter(|(_, c)| keep(*c)) {
+        taken.push(*c);
+        *index += 1;
+    }
+    taken
+}
+
+/// The keys arrays are decoded with: a statement indexing `name(...)` and combining it through
+/// `Xor` with a number, or with a name assigned a number, gives the key of array `name`.
+fn xor_keys(statements: &[&[Token]]) -> HashMap<String, i64> {
+    let mut constants = HashMap::new();
+    for statement in statements {
+        let statement = match statement {
+            [Token {
+                kind: Kind::Ident(keyword),
+                ..
+            }, rest @ ..]
+                if keyword.eq_ignore_ascii_case("Const") =>
+            {
+                rest
+            }
+            _ => statement,
+        };
+        if let [Token {
+            kind: Kind::Ident(name),
+            ..
+        }, Token {
+            kind: Kind::Op('='),
+            ..
+        }, Token {
+            kind: Kind::Num(value),
+            ..
+        }] = statement
+        {
+            constants.insert(name.to_ascii_lowercase(), *value);
+        }
+    }
+    let mut keys = HashMap::new();
+    for statement in statements {
+        for (index, token) in statement.iter().enumerate() {
+            if !matches!(&token.kind, Kind::Ident(name) if name.eq_ignore_ascii_case("Xor")) {
+                continue;
+            }
+            let operand = |index: Option<usize>| match index.and_then(|index| statement.get(index))
+            {
+                Some(Token {
+                    kind: Kind::Num(value),
+                    ..
+                }) => Some(*value),
+                Some(Token {
+                    kind: Kind::Ident(name),
+                    ..
+                }) => constants.get(&name.to_ascii_lowercase()).copied(),
+                _ => None,
+            };
+            let key = match operand(Some(index + 1)).or(operand(index.checked_sub(1))) {
+                Some(key) => key,
+                None => continue,
+            };
+            for pair in statement.windows(2) {
+                if let [Token {
+                    kind: Kind::Ident(name),
+                    ..
+                }, Token {
+                    kind: Kind::Op('('),
+                    ..
+                }] = pair
+                {
+                    keys.entry(name.to_ascii_lowercase()).or_insert(key);
+                }
+            }
+        }
+    }
+    keys
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Num(i64),
+    Array(Vec<Value>),
+    /// depends on something only known at run time
+    Unknown,
+}
+
+/// An evaluated expression and where it is in the source.
+#[derive(Clone, Debug)]
+struct Evaluated {
+    value: Value,
+    start: usize,
+    end: usize,
+    line: usize,
+    obfuscations: Vec<Obfuscation>,
+}
+
+impl Evaluated {
+    fn unknown(start: &Token, end: &Token) -> Self {
+        Self {
+            value: Value::Unknown,
+            start: start.start,
+            end: end.end,
+            line: start.line,
+            obfuscations: vec![],
+        }
+    }
+
+    fn uses(&mut self, obfuscation: Obfuscation) {
+        if !self.obfuscations.contains(&obfuscation) {
+            self.obfuscations.push(obfuscation);
+        }
+    }
+
+    fn merge(&mut self, other: &Evaluated) {
+        self.end = other.end;
+        for obfuscation in &other.obfuscations {
+            self.uses(*obfuscation);
+        }
+    }
+}
+
+/// What has been recovered so far.
+struct Scan<'a> {
+    source: &'a str,
+    recovered: Vec<RecoveredString>,
+}
+
+impl Scan<'_> {
+    /// Record `found` if it is a constant worth reporting.
+    fn settle(&mut self, mut found: Evaluated) {
+        let value = match &found.value {
+            Value::Str(value) if !found.obfuscations.is_empty() => value.clone(),
+            Value::Array(items) => match characters(items, 0) {
+                Some(value) => {
+                    found.uses(Obfuscation::NumericArray);
+                    value
+                }
+                None => return,
+            },
+            _ => return,
+        };
+        self.record(found, value);
+    }
+
+    /// Record the array `found` decoded with `key`, or as it is when that doesn't give text.
+    fn settle_xor(&mut self, mut found: Evaluated, key: i64) {
+        let decoded = match &found.value {
+            Value::Array(items) => characters(items, key),
+            _ => None,
+        };
+        match decoded {
+            Some(value) => {
+                found.uses(Obfuscation::NumericArray);
+                found.uses(Obfuscation::Xor);
+                self.record(found, value);
+            }
+            None => self.settle(found),
+        }
+    }
+
+    fn record(&mut self, found: Evaluated, value: String) {
+        if value.chars().count() < MIN_STRING_LEN {
+            return;
+        }
+        self.recovered.push(RecoveredString {
+            line: found.line,
+            expression: self.source[found.start..found.end].to_string(),
+            value,
+            obfuscations: found.obfuscations,
+        });
+    }
+}
+
+/// The text an array of character codes spells once combined with `key`, if every code gives a
+/// printable character.
+fn characters(items: &[Value], key: i64) -> Option<String> {
+    if items.len() < MIN_STRING_LEN {
+        return None;
+    }
+    items
+        .iter()
+        .map(|item| {
+            let code = match item {
+                Value::Num(code) => *code,
+                Value::Str(text) => text.trim().parse().ok()?,
+                _ => return None,
+            } ^ key;
+            let c = char::from_u32(u32::try_from(code).ok()?)?;
+            (c == '\t' || c == '\r' || c == '\n' || (' '..='~').contains(&c)).then_some(c)
+        })
+        .collect()
+}
+
+/// Evaluates one expression of a statement, following VBA's operator precedence.
+struct Parser<'a, 'b> {
+    tokens: &'a [Token],
+    position: usize,
+    /// how many `unary` calls are under way
+    depth: usize,
+    scan: &'a mut Scan<'b>,
+}
+
+impl Parser<'_, '_> {
+    fn peek(&self) -> Option<&Kind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn peek_op(&self, op: char) -> bool {
+        self.peek() == Some(&Kind::Op(op))
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Kind::Ident(name)) if name.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expression(&mut self) -> Option<Evaluated> {
+        let mut left = self.concatenation()?;
+        while self.peek_keyword("Xor") {
+            self.position += 1;
+            let right = self.concatenation()?;
+            left = self.binary(left, right, |left, right| match (left, right) {
+                (Value::Num(left), Value::Num(right)) => Value::Num(left ^ right),
+                _ => Value::Unknown,
+            });
+            if left.value != Value::Unknown {
+                left.uses(Obfuscation::Xor);
+            }
+        }
+        Some(left)
+    }
+
+    /// Operands joined with `&`. When some aren't constant, each run of constant ones is
+    /// settled on its own, that's how a URL built around a variable still comes out.
+    fn concatenation(&mut self) -> Option<Evaluated> {
+        let mut operands = vec![self.additive()?];
+        while self.peek_op('&') {
+            self.position += 1;
+            operands.push(self.additive()?);
+        }
+        if operands.len() == 1 {
+            return operands.pop();
+        }
+        let text = |value: &Value| match value {
+            Value::Str(text) => Some(text.clone()),
+            Value::Num(number) => Some(number.to_string()),
+            _ => None,
+        };
+        let mut runs: Vec<Evaluated> = vec![];
+        for operand in operands {
+            let joined = match runs.last().map(|run| &run.value).and_then(text) {
+                Some(left) => text(&operand.value).map(|right| left + &right),
+                None => None,
+            };
+            match (joined, runs.last_mut()) {
+                (Some(joined), Some(run)) => {
+                    run.value = Value::Str(joined);
+                    run.merge(&operand);
+                    run.uses(Obfuscation::Concatenation);
+                }
+                _ => runs.push(operand),
+            }
+        }
+        if runs.len() == 1 {
+            return runs.pop();
+        }
+        let unknown = Evaluated {
+            value: Value::Unknown,
+            start: runs[0].start,
+            end: runs[runs.len() - 1].end,
+            line: runs[0].line,
+            obfuscations: vec![],
+        };
+        for run in runs {
+            self.scan.settle(run);
+        }
+        Some(unknown)
+    }
+
+    fn additive(&mut self) -> Option<Evaluated> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Kind::Op(op @ ('+' | '-'))) => *op,
+                _ => return Some(left),
+            };
+            self.position += 1;
+            let right = self.multiplicative()?;
+            let joins = matches!((&left.value, &right.value), (Value::Str(_), Value::Str(_)));
+            left = self.binary(left, right, |left, right| match (op, left, right) {
+                ('+', Value::Str(left), Value::Str(right)) => Value::Str(left + &right),
+                ('+', Value::Num(left), Value::Num(right)) => {
+                    left.checked_add(right).map_or(Value::Unknown, Value::Num)
+                }
+                ('-', Value::Num(left), Value::Num(right)) => {
+                    left.checked_sub(right).map_or(Value::Unknown, Value::Num)
+                }
+                _ => Value::Unknown,
+            });
+            if joins {
+                left.uses(Obfuscation::Concatenation);
+            }
+        }
+    }
+
+    fn multiplicative(&mut self) -> Option<Evaluated> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Kind::Op(op @ ('*' | '/' | '\\'))) => *op,
+                _ if self.peek_keyword("Mod") => '%',
+                _ => return Some(left),
+            };
+            self.position += 1;
+            let right = self.unary()?;
+            left = self.binary(left, right, |left, right| {
+                let (left, right) = match (left, right) {
+                    (Value::Num(left), Value::Num(right)) => (left, right),
+                    _ => return Value::Unknown,
+                };
+                let result = match op {
+                    '*' => left.checked_mul(right),
+                    // `/` gives a fraction unless the division is exact
+                    '/' if right != 0 && left % right != 0 => None,
+                    '/' | '\\' => left.checked_div(right),
+                    _ => left.checked_rem(right),
+                };
+                result.map_or(Value::Unknown, Value::Num)
+            });
+        }
+    }
+
+    /// Every nesting goes through here, so this is where it is bounded.
+    fn unary(&mut self) -> Option<Evaluated> {
+        if self.depth == MAX_NESTING {
+            return None;
+        }
+        self.depth += 1;
+        let found = self.negation();
+        self.depth -= 1;
+        found
+    }
+
+    fn negation(&mut self) -> Option<Evaluated> {
+        if self.peek_op('-') {
+            let start = self.tokens[self.position].clone();
+            self.position += 1;
+            let mut operand = self.unary()?;
+            operand.start = start.start;
+            operand.line = start.line;
+            operand.value = match operand.value {
+                Value::Num(value) => value.checked_neg().map_or(Value::Unknown, Value::Num),
+                _ => Value::Unknown,
+            };
+            return Some(operand);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Option<Evaluated> {
+        let token = self.tokens.get(self.position)?.clone();
+        self.position += 1;
+        let value = match &token.kind {
+            Kind::Str(text) => Value::Str(text.clone()),
+            Kind::Num(number) => Value::Num(*number),
+            Kind::Op('(') => {
+                let mut inner = self.expression()?;
+                if !self.peek_op(')') {
+                    return None;
+                }
+                inner.start = token.start;
+                inner.end = self.tokens[self.position].end;
+                self.position += 1;
+                return Some(inner);
+            }
+            Kind::Ident(name) => return self.call(&token, name),
+            _ => return None,
+        };
+        Some(Evaluated {
+            value,
+            start: token.start,
+            end: token.end,
+            line: token.line,
+            obfuscations: vec![],
+        })
+    }
+
+    /// A function call, or any other name. The arguments of functions that aren't evaluated
+    /// are settled on their own.
+    fn call(&mut self, token: &Token, name: &str) -> Option<Evaluated> {
+        if ["Xor", "Mod", "Then", "To", "Step", "And", "Or", "Not"]
+            .iter()
+            .any(|keyword| name.eq_ignore_ascii_case(keyword))
+        {
+            return None;
+        }
+        let mut arguments = vec![];
+        let mut end = token;
+        if self.peek_op('(') {
+            self.position += 1;
+            if !self.peek_op(')') {
+                loop {
+                    arguments.push(self.expression()?);
+                    if !self.peek_op(',') {
+                        break;
+                    }
+                    self.position += 1;
+                }
+            }
+            if !self.peek_op(')') {
+                return None;
+            }
+            end = &self.tokens[self.position];
+            self.position += 1;
+        }
+        let mut found = Evaluated::unknown(token, end);
+        for argument in &arguments {
+            for obfuscation in &argument.obfuscations {
+                found.uses(*obfuscation);
+            }
+        }
+        let values = arguments
+            .iter()
+            .map(|argument| argument.value.clone())
+            .collect::<Vec<_>>();
+        let function = name.trim_end_matches('$').to_ascii_lowercase();
+        let (value, obfuscation) = evaluate(&function, values);
+        if value == Value::Unknown {
+            for argument in arguments {
+                self.scan.settle(argument);
+            }
+            found.obfuscations.clear();
+            return Some(found);
+        }
+        if let Some(obfuscation) = obfuscation {
+            found.uses(obfuscation);
+        }
+        found.value = value;
+        Some(found)
+    }
+
+    fn binary(
+        &mut self,
+        mut left: Evaluated,
+        right: Evaluated,
+        op: impl FnOnce(Value, Value) -> Value,
+    ) -> Evaluated {
+        let value = op(left.value.clone(), right.value.clone());
+        if value == Value::Unknown {
+            let mut unknown = left.clone();
+            unknown.end = right.end;
+            unknown.obfuscations.clear();
+            unknown.value = Value::Unknown;
+            self.scan.settle(left);
+            self.scan.settle(right);
+            return unknown;
+        }
+        left.merge(&right);
+        left.value = value;
+        left
+    }
+}
+
+/// The value of the known function `name`, `$` suffix removed and in lower case, on constant
+/// `arguments`, with the construct it counts as.
+fn evaluate(name: &str, arguments: Vec<Value>) -> (Value, Option<Obfuscation>) {
+    let number = |value: &Value| match value {
+        Value::Num(number) => Some(*number),
+        Value::Str(text) => text.trim().parse().ok(),
+        _ => None,
+    };
+    let text = |value: &Value| match value {
+        Value::Str(text) => Some(text.clone()),
+        Value::Num(number) => Some(number.to_string()),
+        _ => None,
+    };
+    let evaluated = match (name, arguments.as_slice()) {
+        ("chr" | "chrb", [code]) => number(code)
+            .and_then(|code| u8::try_from(code).ok())
+            .map(|code| Value::Str((code as char).to_string()))
+            .map(|value| (value, Obfuscation::Chr)),
+        ("chrw", [code]) => number(code)
+            .filter(|code| (-32768..=65535).contains(code))
+            .and_then(|code| char::from_u32(code.rem_euclid(65536) as u32))
+            .map(|c| (Value::Str(c.to_string()), Obfuscation::Chr)),
+        ("strreverse", [value]) => text(value).map(|text| {
+            (
+                Value::Str(text.chars().rev().collect()),
+                Obfuscation::StrReverse,
+            )
+        }),
+        ("asc" | "ascw", [value]) => text(value)
+            .and_then(|text| text.chars().next())
+            .map(|c| (Value::Num(c as i64), Obfuscation::Chr)),
+        ("array", items) if items.iter().all(|item| *item != Value::Unknown) => {
+            Some((Value::Array(items.to_vec()), Obfuscation::NumericArray))
+        }
+        ("replace", [value, find, replacement]) => {
+            match (text(value), text(find), text(replacement)) {
+                (Some(value), Some(find), Some(replacement)) if !find.is_empty() => Some((
+                    Value::Str(value.replace(&find, &replacement)),
+                    Obfuscation::StringFunction,
+                )),
+                _ => None,
+            }
+        }
+        ("split", [value]) | ("split", [value, _]) => {
+            let separator = match arguments.get(1) {
+                Some(separator) => text(separator),
+                None => Some(" ".to_string()),
+            };
+            match (text(value), separator) {
+                (Some(value), Some(separator)) if !separator.is_empty() => Some((
+                    Value::Array(
+                        value
+                            .split(separator.as_str())
+                            .map(|part| Value::Str(part.to_string()))
+                            .collect(),
+                    ),
+                    Obfuscation::StringFunction,
+                )),
+                _ => None,
+            }
+        }
+        ("join", [Value::Array(items)]) | ("join", [Value::Array(items), _]) => {
+            let separator = match arguments.get(1) {
+                Some(separator) => text(separator),
+                None => Some(" ".to_string()),
+            };
+            let parts = items.iter().map(text).collect::<Option<Vec<_>>>();
+            match (parts, separator) {
+                (Some(parts), Some(separator)) => Some((
+                    Value::Str(parts.join(&separator)),
+                    Obfuscation::StringFunction,
+                )),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    match evaluated {
+        Some((value, obfuscation)) => (value, Some(obfuscation)),
+        None => (Value::Unknown, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(source: &str) -> Vec<String> {
+        deobfuscate(source)
+            .into_iter()
+            .map(|recovered| recovered.value)
+            .collect()
+    }
+
+    #[test]
+    fn test_chr_concatenation() {
+        let source = "Sub Document_Open()\r\n\
+            ' Chr(65) & Chr(66) in a comment\r\n\
+            Set o = CreateObject(Chr(87) & Chr(83) & \"cript.\" & ChrW(&H53) & Chr$(104) & _\r\n\
+            \"ell\")\r\n\
+            url = \"http://\" & host & Chr(47) & \"p\" & \"ay\" & \"load\"\r\n\
+            End Sub\r\n";
+        let recovered = deobfuscate(source);
+        assert_eq!(
+            recovered
+                .iter()
+                .map(|recovered| (recovered.line, recovered.value.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(3, "WScript.Shell"), (5, "/payload")]
+        );
+        assert_eq!(
+            recovered[0].expression,
+            "Chr(87) & Chr(83) & \"cript.\" & ChrW(&H53) & Chr$(104) & _\r\n\"ell\""
+        );
+        assert_eq!(
+            recovered[0].obfuscations,
+            vec![Obfuscation::Chr, Obfuscation::Concatenation]
+        );
+        assert_eq!(
+            recovered[1].expression,
+            "Chr(47) & \"p\" & \"ay\" & \"load\""
+        );
+        // plain literals are left out
+        assert!(values("x = \"http://example.com\"").is_empty());
+    }
+
+    #[test]
+    fn test_string_functions() {
+        assert_eq!(
+            values("Shell StrReverse(\"exe.clac\"), 0"),
+            vec!["calc.exe"]
+        );
+        assert_eq!(
+            values("p = Replace(\"poXXwerXXshell\", \"XX\", \"\") + \" -enc\""),
+            vec!["powershell -enc"]
+        );
+        assert_eq!(
+            values("s = Join(Split(\"c m d\"), \"\") & \".exe\""),
+            vec!["cmd.exe"]
+        );
+        assert_eq!(values("c = Chr(Asc(\"a\") - 32) + \"BCD\""), vec!["ABCD"]);
+        // depending on a variable, nothing can be said
+        assert!(values("s = StrReverse(x) & Chr(y)").is_empty());
+    }
+
+    #[test]
+    fn test_numeric_arrays() {
+        assert_eq!(
+            values("a = Array(104, 116, &H74, &H70, 58, 47, 47)"),
+            vec!["http://"]
+        );
+        assert_eq!(
+            values("a = Split(\"99,109,100\", \",\"): b = Array(1, 2, 300, 4)"),
+            Vec::<String>::new()
+        );
+        assert_eq!(values("a = Split(\"99,109,100,46\", \",\")"), vec!["cmd."]);
+    }
+
+    #[test]
+    fn test_xor_loop() {
+        // "calc.exe" with every code Xor 7
+        let source = "Const KEY = 7\n\
+            data = Array(100, 102, 107, 100, 41, 98, 127, 98)\n\
+            For i = 0 To UBound(data)\n\
+            \ts = s & Chr(data(i) Xor KEY)\n\
+            Next\n\
+            t = Chr(104 Xor 0) & Chr(105 Xor 0) & \"!!\"";
+        let recovered = deobfuscate(source);
+        assert_eq!(recovered.len(), 2);
+        assert_eq!(recovered[0].value, "calc.exe");
+        assert_eq!(recovered[0].line, 2);
+        assert_eq!(
+            recovered[0].obfuscations,
+            vec![Obfuscation::NumericArray, Obfuscation::Xor]
+        );
+        assert_eq!(recovered[1].value, "hi!!");
+        assert!(recovered[1].obfuscations.contains(&Obfuscation::Xor));
+    }
+
+    #[test]
+    fn test_malformed_source() {
+        for source in [
+            "x = Chr(",
+            "x = Chr(65, Chr(66)",
+            "x = \"unterminated & Chr(65)",
+            "x = Chr(99999) & ChrW(-99999) & Chr(\"x\")",
+            "x = 99999999999999999999 * 2 \\ 0",
+            "Rem Chr(65) & Chr(66) & Chr(67) & Chr(68)",
+            "_",
+            "&H",
+        ] {
+            assert!(values(source).is_empty(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let parentheses = format!("x = {}1", "(".repeat(5000));
+        assert!(values(&parentheses).is_empty());
+        let signs = format!("x = {}1", "-".repeat(100_000));
+        assert!(values(&signs).is_empty());
+        // unbalanced, each failed parse used to be retried from the next token
+        let unbalanced = format!("x = {}Chr(65) & \"BCD\"", "(".repeat(100_000));
+        assert!(values(&unbalanced).is_empty());
+
+        let nested = format!(
+            "x = {}Chr(65) & \"BCD\"{}",
+            "(".repeat(MAX_NESTING / 2),
+            ")".repeat(MAX_NESTING / 2)
+        );
+        assert_eq!(values(&nested), vec!["ABCD"]);
+    }
+}
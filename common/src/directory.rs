@@ -1,9 +1,60 @@
-use crate::{constants, error::Error, header::OleHeader, Result};
-use chrono::NaiveDateTime;
+use crate::{
+    constants,
+    error::{Error, Location},
+    header::OleHeader,
+    OleFile, OleParseOptions, ParseWarning, ParseWarningKind, Result,
+};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 use derivative::Derivative;
+use log::{trace, warn};
 use std::array::TryFromSliceError;
 
-#[derive(Clone, Derivative, Copy, PartialEq)]
+/// Where an entry sits in the storage hierarchy: the names of the storages leading to it, then
+/// its own name.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntryPath(Vec<String>);
+
+impl EntryPath {
+    pub fn components(&self) -> &[String] {
+        &self.0
+    }
+
+    /// The last component, empty for the root storage.
+    pub fn name(&self) -> &str {
+        self.0.last().map(String::as_str).unwrap_or_default()
+    }
+
+    /// How many storages deep the entry is, 0 for the children of the root.
+    pub fn depth(&self) -> usize {
+        self.0.len().saturating_sub(1)
+    }
+
+    /// The components as the slice [`crate::OleFile::open_stream`] takes.
+    pub fn as_strs(&self) -> Vec<&str> {
+        self.0.iter().map(String::as_str).collect()
+    }
+}
+
+impl From<Vec<String>> for EntryPath {
+    fn from(components: Vec<String>) -> Self {
+        Self(components)
+    }
+}
+
+impl From<EntryPath> for Vec<String> {
+    fn from(path: EntryPath) -> Self {
+        path.0
+    }
+}
+
+/// Slash separated, with the escaping of [`crate::util::join_stream_path`].
+impl std::fmt::Display for EntryPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&crate::util::join_stream_path(&self.0))
+    }
+}
+
+#[derive(Clone, Derivative, Copy, PartialEq, Eq)]
 #[derivative(Debug)]
 pub enum ObjectType {
     Storage,
@@ -11,7 +62,7 @@ pub enum ObjectType {
     RootStorage,
 }
 
-#[derive(Clone, Derivative, Copy)]
+#[derive(Clone, Derivative, Copy, PartialEq, Eq)]
 #[derivative(Debug)]
 pub enum NodeColor {
     Red,
@@ -146,84 +197,77 @@ pub struct DirectoryEntryRaw {
 }
 
 impl DirectoryEntryRaw {
+    /// The name, decoded as well as it can be whatever is wrong with it.
+    pub(crate) fn name(&self) -> String {
+        parse_name(&self.name, u16::from_le_bytes(self.name_len)).0
+    }
+
     pub fn parse(unparsed_entry: &[u8]) -> Result<Self> {
-        let name: [u8; 64] =
-            unparsed_entry[0..64]
-                .try_into()
-                .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("name", err.to_string())
-                })?;
-        let name_len: [u8; 2] =
-            unparsed_entry[64..66]
-                .try_into()
-                .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("name_len", err.to_string())
-                })?;
+        let name: [u8; 64] = unparsed_entry[0..64]
+            .try_into()
+            .map_err(|err: TryFromSliceError| Error::invalid_entry("name", err.to_string()))?;
+        let name_len: [u8; 2] = unparsed_entry[64..66]
+            .try_into()
+            .map_err(|err: TryFromSliceError| Error::invalid_entry("name_len", err.to_string()))?;
         let object_type: [u8; 1] =
             unparsed_entry[66..67]
                 .try_into()
                 .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("object_type", err.to_string())
+                    Error::invalid_entry("object_type", err.to_string())
                 })?;
         let color_flag: [u8; 1] =
             unparsed_entry[67..68]
                 .try_into()
                 .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("color_flag", err.to_string())
+                    Error::invalid_entry("color_flag", err.to_string())
                 })?;
         let left_sibling_id: [u8; 4] =
             unparsed_entry[68..72]
                 .try_into()
                 .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("left_sibling_id", err.to_string())
+                    Error::invalid_entry("left_sibling_id", err.to_string())
                 })?;
         let right_sibling_id: [u8; 4] =
             unparsed_entry[72..76]
                 .try_into()
                 .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("right_sibling_id", err.to_string())
-                })?;
-        let child_id: [u8; 4] =
-            unparsed_entry[76..80]
-                .try_into()
-                .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("child_id", err.to_string())
-                })?;
-        let class_id: [u8; 16] =
-            unparsed_entry[80..96]
-                .try_into()
-                .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("class_id", err.to_string())
+                    Error::invalid_entry("right_sibling_id", err.to_string())
                 })?;
+        let child_id: [u8; 4] = unparsed_entry[76..80]
+            .try_into()
+            .map_err(|err: TryFromSliceError| Error::invalid_entry("child_id", err.to_string()))?;
+        let class_id: [u8; 16] = unparsed_entry[80..96]
+            .try_into()
+            .map_err(|err: TryFromSliceError| Error::invalid_entry("class_id", err.to_string()))?;
         let state_bits: [u8; 4] =
             unparsed_entry[96..100]
                 .try_into()
                 .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("state_bits", err.to_string())
+                    Error::invalid_entry("state_bits", err.to_string())
                 })?;
         let creation_time: [u8; 8] =
             unparsed_entry[100..108]
                 .try_into()
                 .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("creation_time", err.to_string())
+                    Error::invalid_entry("creation_time", err.to_string())
                 })?;
         let modification_time: [u8; 8] =
             unparsed_entry[108..116]
                 .try_into()
                 .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("modification_time", err.to_string())
+                    Error::invalid_entry("modification_time", err.to_string())
                 })?;
         let starting_sector_location: [u8; 4] =
             unparsed_entry[116..120]
                 .try_into()
                 .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("starting_sector_location", err.to_string())
+                    Error::invalid_entry("starting_sector_location", err.to_string())
                 })?;
         let stream_size: [u8; 8] =
             unparsed_entry[120..128]
                 .try_into()
                 .map_err(|err: TryFromSliceError| {
-                    Error::OleInvalidDirectoryEntry("stream_size", err.to_string())
+                    Error::invalid_entry("stream_size", err.to_string())
                 })?;
 
         Ok(DirectoryEntryRaw {
@@ -247,32 +291,115 @@ impl DirectoryEntryRaw {
 #[derive(Clone, Derivative)]
 #[derivative(Debug)]
 pub struct DirectoryEntry {
-    index: usize,
+    pub(crate) index: usize,
     //the index in the directory array
     pub(crate) object_type: ObjectType,
     pub(crate) name: String,
-    color: NodeColor,
+    pub(crate) color: NodeColor,
     pub(crate) left_sibling_id: Option<u32>,
     pub(crate) right_sibling_id: Option<u32>,
     pub(crate) child_id: Option<u32>,
 
     pub(crate) class_id: Option<String>,
 
+    #[derivative(Debug = "ignore")]
+    raw_name: [u8; 64],
+
     //TODO: do we need this?
     #[derivative(Debug = "ignore")]
     _state_bits: [u8; 4],
 
-    creation_time: Option<NaiveDateTime>,
-    modification_time: Option<NaiveDateTime>,
+    pub(crate) creation_time: Option<NaiveDateTime>,
+    pub(crate) modification_time: Option<NaiveDateTime>,
+    /// the FILETIMEs as stored, for the checks the decoded times can't express
+    #[derivative(Debug = "ignore")]
+    pub(crate) raw_creation_time: u64,
+    #[derivative(Debug = "ignore")]
+    pub(crate) raw_modification_time: u64,
     pub(crate) starting_sector_location: Option<u32>,
     pub(crate) stream_size: u64,
 }
 
+/// Characters MS-CFB forbids in names, they separate paths in the APIs of many implementations.
+const ILLEGAL_NAME_CHARACTERS: [char; 4] = ['/', '\\', ':', '!'];
+
+/// Decode the name field, giving the name and, if the field breaks the rules of MS-CFB, what is
+/// wrong with it. The name is decoded as well as it can be either way: the length is cut to the
+/// field and to whole code units, and unpaired surrogates are replaced.
+fn parse_name(field: &[u8; 64], name_len: u16) -> (String, Option<String>) {
+    let len = (name_len as usize).min(field.len()) & !1;
+    let mut units = field[..len]
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+    let terminated = units.last() == Some(&0);
+    if terminated {
+        units.pop();
+    }
+    let name = String::from_utf16_lossy(&units);
+
+    let problem = if len != name_len as usize {
+        Some(format!("has a name length of {} bytes", name_len))
+    } else if name_len > 0 && !terminated {
+        Some("has a name without a null terminator".to_string())
+    } else if String::from_utf16(&units).is_err() {
+        Some("has a name that isn't valid UTF-16".to_string())
+    } else if let Some(illegal) = name.chars().find(|c| ILLEGAL_NAME_CHARACTERS.contains(c)) {
+        Some(format!("has {:?} in its name", illegal))
+    } else {
+        // names such as `\x01CompObj` start with a control character, anywhere else it hides
+        // something
+        name.chars()
+            .skip(1)
+            .find(|c| c.is_control())
+            .map(|control| format!("has {:?} inside its name", control))
+    };
+    (name, problem)
+}
+
+/// A CLSID in its usual uppercase text form, `None` when it is all zeroes.
+pub(crate) fn format_class_id(bytes: [u8; 16]) -> Option<String> {
+    if bytes == [0x00; 16] {
+        return None;
+    }
+    let a = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let b = i16::from_le_bytes([bytes[4], bytes[5]]);
+    let c = i16::from_le_bytes([bytes[6], bytes[7]]);
+
+    Some(
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+            a,
+            b,
+            c,
+            bytes[8],
+            bytes[9],
+            bytes[10],
+            bytes[11],
+            bytes[12],
+            bytes[13],
+            bytes[14],
+            bytes[15]
+        )
+        .to_uppercase(),
+    )
+}
+
+/// Whether two entry names are the same under the MS-CFB rule (2.6.4), which compares names after
+/// mapping them to uppercase, so `worddocument` names the `WordDocument` stream.
+pub fn names_equal(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_uppercase)
+        .eq(b.chars().flat_map(char::to_uppercase))
+}
+
 impl DirectoryEntry {
     pub(crate) fn from_raw(
         ole_file_header: &OleHeader,
         raw_directory_entry: DirectoryEntryRaw,
         index: usize,
+        options: &OleParseOptions,
+        warnings: &mut Vec<ParseWarning>,
     ) -> Result<Self> {
         // first, check to see if the directory entry is even allocated...
         let object_type = match raw_directory_entry.object_type {
@@ -282,24 +409,35 @@ impl DirectoryEntry {
             constants::OBJECT_TYPE_ROOT_STORAGE => Ok(ObjectType::RootStorage),
             constants::OBJECT_TYPE_STORAGE => Ok(ObjectType::Storage),
             constants::OBJECT_TYPE_STREAM => Ok(ObjectType::Stream),
-            anything_else => Err(Error::OleInvalidDirectoryEntry(
+            anything_else => Err(Error::invalid_entry(
                 "object_type",
                 format!("invalid value: {:x?}", anything_else),
             )),
         }?;
 
         let name_len = u16::from_le_bytes(raw_directory_entry.name_len);
-        let name_raw = &raw_directory_entry.name[0..(name_len as usize)]
-            .chunks(2)
-            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
-            .collect::<Vec<_>>();
-        let mut name = String::from_utf16(&name_raw[..])?;
-        //drop the null terminator
-        let _ = name.pop();
+        let (name, problem) = parse_name(&raw_directory_entry.name, name_len);
+        if let Some(problem) = problem {
+            if options.strict_names {
+                return Err(Error::invalid_entry(
+                    "name",
+                    format!("entry {} {}", index, problem),
+                ));
+            }
+            let warning = format!("directory: entry {} {:?} {}", index, name, problem);
+            warn!("{}", warning);
+            warnings.push(
+                ParseWarning::new(ParseWarningKind::IllegalName, warning).at(Location {
+                    entry: Some(index),
+                    name: Some(name.clone()),
+                    ..Location::default()
+                }),
+            );
+        }
         let color = match raw_directory_entry.color_flag {
             constants::NODE_COLOR_RED => Ok(NodeColor::Red),
             constants::NODE_COLOR_BLACK => Ok(NodeColor::Black),
-            anything_else => Err(Error::OleInvalidDirectoryEntry(
+            anything_else => Err(Error::invalid_entry(
                 "node_color",
                 format!("invalid value: {:x?}", anything_else),
             )),
@@ -310,7 +448,7 @@ impl DirectoryEntry {
             potential_value => {
                 let potential_value = u32::from_le_bytes(potential_value);
                 if potential_value > constants::MAX_REG_STREAM_ID_VALUE {
-                    Err(Error::OleInvalidDirectoryEntry(
+                    Err(Error::invalid_entry(
                         "left_sibling_id",
                         format!("invalid value: {:x?}", potential_value),
                     ))
@@ -324,7 +462,7 @@ impl DirectoryEntry {
             potential_value => {
                 let potential_value = u32::from_le_bytes(potential_value);
                 if potential_value > constants::MAX_REG_STREAM_ID_VALUE {
-                    Err(Error::OleInvalidDirectoryEntry(
+                    Err(Error::invalid_entry(
                         "right_sibling_id",
                         format!("invalid value: {:x?}", potential_value),
                     ))
@@ -338,7 +476,7 @@ impl DirectoryEntry {
             potential_value => {
                 let potential_value = u32::from_le_bytes(potential_value);
                 if potential_value > constants::MAX_REG_STREAM_ID_VALUE {
-                    Err(Error::OleInvalidDirectoryEntry(
+                    Err(Error::invalid_entry(
                         "child_id",
                         format!("invalid value: {:x?}", potential_value),
                     ))
@@ -347,15 +485,12 @@ impl DirectoryEntry {
                 }
             }
         }?;
-        //TODO: the spec says there are some validations we should carry out on these times, but I'm passing them on unmodified.
-        let creation_time = match i64::from_le_bytes(raw_directory_entry.creation_time) {
-            0 => None,
-            time => epochs::windows_file(time),
-        };
-        let modification_time = match i64::from_le_bytes(raw_directory_entry.modification_time) {
-            0 => None,
-            time => epochs::windows_file(time),
-        };
+        // Streams MUST have zeroed times and the root a zeroed creation time. Nothing depends on
+        // them, so they are kept as they are and the checks are left to OleFile::anomalies.
+        let raw_creation_time = u64::from_le_bytes(raw_directory_entry.creation_time);
+        let raw_modification_time = u64::from_le_bytes(raw_directory_entry.modification_time);
+        let creation_time = crate::util::filetime(raw_creation_time);
+        let modification_time = crate::util::filetime(raw_modification_time);
 
         // This field contains the first sector location if this is a stream
         // object. For a root storage object, this field MUST contain the first sector of the mini stream, if the
@@ -383,6 +518,12 @@ impl DirectoryEntry {
             example, a parser whose purpose is to verify the correctness of a compound file).
              */
             let mut stream_size_modified = raw_directory_entry.stream_size;
+            if stream_size_modified[4..] != [0x00; 4] {
+                trace!(
+                    "ignoring non-zero high bits of stream size for entry {} in a version 3 file",
+                    index
+                );
+            }
             stream_size_modified[4] = 0x00;
             stream_size_modified[5] = 0x00;
             stream_size_modified[6] = 0x00;
@@ -394,43 +535,13 @@ impl DirectoryEntry {
         };
         let stream_size = u64::from_le_bytes(stream_size);
         if stream_size != 0 && object_type == ObjectType::Storage {
-            return Err(Error::OleInvalidDirectoryEntry(
+            return Err(Error::invalid_entry(
                 "stream_size",
                 "storage object type has non-zero stream size".to_string(),
             ));
-        } else if object_type == ObjectType::RootStorage && stream_size % 64 != 0 {
-            return Err(Error::OleInvalidDirectoryEntry(
-                "stream_size",
-                "root storage object type must have stream size % 64 === 0".to_string(),
-            ));
         }
 
-        let class_id = match raw_directory_entry.class_id {
-            empty if empty == [0x00; 16] => None,
-            bytes => {
-                let a = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-                let b = i16::from_le_bytes([bytes[4], bytes[5]]);
-                let c = i16::from_le_bytes([bytes[6], bytes[7]]);
-
-                Some(
-                    format!(
-                        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
-                        a,
-                        b,
-                        c,
-                        bytes[8],
-                        bytes[9],
-                        bytes[10],
-                        bytes[11],
-                        bytes[12],
-                        bytes[13],
-                        bytes[14],
-                        bytes[15]
-                    )
-                    .to_uppercase(),
-                )
-            }
-        };
+        let class_id = format_class_id(raw_directory_entry.class_id);
 
         Ok(Self {
             index,
@@ -442,10 +553,105 @@ impl DirectoryEntry {
             child_id,
             class_id,
             _state_bits: raw_directory_entry.state_bits,
+            raw_name: raw_directory_entry.name,
             creation_time,
             modification_time,
+            raw_creation_time,
+            raw_modification_time,
             starting_sector_location,
             stream_size,
         })
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name field as stored: 64 bytes of UTF-16, whatever the length field says and
+    /// including anything after the terminator.
+    pub fn raw_name(&self) -> &[u8; 64] {
+        &self.raw_name
+    }
+
+    pub fn object_type(&self) -> ObjectType {
+        self.object_type
+    }
+
+    pub fn color(&self) -> NodeColor {
+        self.color
+    }
+
+    /// Size of the stream data in bytes, 0 for storages.
+    pub fn stream_size(&self) -> u64 {
+        self.stream_size
+    }
+
+    /// Position of this entry in the directory, the id siblings and children refer to.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// This entry as the location of an error about it.
+    pub(crate) fn location(&self) -> Location {
+        Location {
+            entry: Some(self.index),
+            name: Some(self.name.clone()),
+            ..Location::default()
+        }
+    }
+
+    pub fn left_sibling_id(&self) -> Option<u32> {
+        self.left_sibling_id
+    }
+
+    pub fn right_sibling_id(&self) -> Option<u32> {
+        self.right_sibling_id
+    }
+
+    /// Root of the tree of children, for storages.
+    pub fn child_id(&self) -> Option<u32> {
+        self.child_id
+    }
+
+    /// The left sibling in the tree of `ole`, the file this entry was read from.
+    pub fn left_sibling<'a>(&self, ole: &'a OleFile) -> Option<&'a DirectoryEntry> {
+        ole.entry_by_id(self.left_sibling_id?)
+    }
+
+    /// The right sibling in the tree of `ole`, the file this entry was read from.
+    pub fn right_sibling<'a>(&self, ole: &'a OleFile) -> Option<&'a DirectoryEntry> {
+        ole.entry_by_id(self.right_sibling_id?)
+    }
+
+    /// The root of the tree of children in `ole`, the file this entry was read from.
+    pub fn child<'a>(&self, ole: &'a OleFile) -> Option<&'a DirectoryEntry> {
+        ole.entry_by_id(self.child_id?)
+    }
+
+    /// The children of this storage in `ole`, the file this entry was read from, in the order
+    /// of their tree, see [`OleFile::children`]. Empty for streams.
+    pub fn children<'a>(&self, ole: &'a OleFile) -> impl Iterator<Item = &'a DirectoryEntry> {
+        let children = match self.object_type {
+            ObjectType::Stream => vec![],
+            _ => ole.child_entries(self),
+        };
+        children.into_iter()
+    }
+
+    pub fn class_id(&self) -> Option<&str> {
+        self.class_id.as_deref()
+    }
+
+    /// When the storage was created, `None` when the field is zeroed, as the spec wants for
+    /// streams and the root, or holds no date, see [`OleFile::anomalies`].
+    pub fn creation_time(&self) -> Option<DateTime<Utc>> {
+        self.creation_time.map(|time| Utc.from_utc_datetime(&time))
+    }
+
+    /// When the storage was last modified, `None` when the field is zeroed, as the spec wants
+    /// for streams, or holds no date, see [`OleFile::anomalies`].
+    pub fn modification_time(&self) -> Option<DateTime<Utc>> {
+        self.modification_time
+            .map(|time| Utc.from_utc_datetime(&time))
+    }
 }
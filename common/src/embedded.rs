@@ -0,0 +1,405 @@
+//! OLE objects embedded in or linked from a compound file (MS-OLEDS), told apart by the
+//! streams of the storages that hold them.
+use crate::{
+    directory::{DirectoryEntry, EntryPath, ObjectType},
+    error::Error,
+    OleFile, Result,
+};
+
+/// `\x01Ole`, the OLEStream every embedded or linked object has (MS-OLEDS 2.3.3)
+const OLE_STREAM: &str = "\x01Ole";
+/// `\x01Ole10Native`, the data of an OLE 1.0 object such as a Package (MS-OLEDS 2.3.6)
+const OLE10_NATIVE_STREAM: &str = "\x01Ole10Native";
+/// `\x01CompObj`, naming the class of the object (MS-OLEDS 2.3.8)
+const COMP_OBJ_STREAM: &str = "\x01CompObj";
+/// `Contents`, where many OLE 2 objects keep their native data
+const CONTENTS_STREAM: &str = "Contents";
+/// bit of the OLEStream flags set for a linked object
+const OLE_STREAM_LINKED: u32 = 0x0000_0001;
+/// size of the CompObjHeader in front of the user type
+const COMP_OBJ_HEADER_LEN: usize = 28;
+
+/// How an OLE object keeps its data, which decides what opening it does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OleObjectKind {
+    /// an OLE 2 object, its data in the storage next to its class: a picture, a chart, a
+    /// workbook in a document
+    Storage,
+    /// an OLE 1.0 object in `\x01Ole10Native`, usually the Packager wrapping an arbitrary file
+    Packager,
+    /// no data of its own, only a link to a source outside the file
+    Linked,
+}
+
+/// A storage holding an OLE object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OleObject {
+    /// path of the storage, empty when the whole file is the object
+    pub path: EntryPath,
+    pub kind: OleObjectKind,
+    /// CLSID of the storage, `None` when it is all zeroes
+    pub class_id: Option<String>,
+    /// the ProgID from `\x01CompObj`, such as `Package` or `Excel.Sheet.8`
+    pub prog_id: Option<String>,
+}
+
+/// What marks a storage as holding an OLE object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjectEvidence {
+    /// an `\x01Ole` stream, below the root
+    OleStream,
+    /// an `\x01Ole10Native` stream
+    Ole10Native,
+    /// a `\x01CompObj` stream, below the root
+    CompObj,
+    /// a CLSID, below the root, next to a `\x01CompObj`, `Contents` or `\x01Ole10Native`
+    /// stream
+    ClassId,
+    /// named like the objects Word keeps in `ObjectPool`, `_` and a number
+    ObjectPoolName,
+    /// named like the objects Excel embeds, `MBD` and eight hex digits
+    MbdName,
+}
+
+/// A storage that holds an OLE object, with everything that says so.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectEntry {
+    /// path of the storage, empty when the whole file is the object
+    pub path: EntryPath,
+    /// stream ID of the storage
+    pub index: usize,
+    /// CLSID of the storage, `None` when it is all zeroes
+    pub class_id: Option<String>,
+    /// never empty
+    pub evidence: Vec<ObjectEvidence>,
+}
+
+/// Find the storages holding OLE objects: those with an `\x01Ole10Native` stream, and below the
+/// root those with an `\x01Ole` or `\x01CompObj` stream, a CLSID next to the data of an object,
+/// or the name Word or Excel give their objects. The root of every Office document has a
+/// CompObj and a CLSID, and Word puts an `\x01Ole` there too, so at the root they don't count.
+/// Other storages have a CLSID of their own as well, such as the recipients and attachments of
+/// an Outlook message, so a CLSID alone doesn't count either.
+pub(crate) fn object_entries(ole: &OleFile) -> Vec<ObjectEntry> {
+    let storages = std::iter::once((EntryPath::default(), ole.root())).chain(
+        ole.walk()
+            .filter(|(_, entry)| entry.object_type() == ObjectType::Storage),
+    );
+    let mut entries = vec![];
+    for (path, storage) in storages {
+        let children = ole.child_entries(storage);
+        let has = |name: &str| {
+            children.iter().any(|child| {
+                child.object_type() == ObjectType::Stream && ole.names_match(child.name(), name)
+            })
+        };
+        let is_root = storage.object_type() == ObjectType::RootStorage;
+        let in_object_pool = path.depth() == 1 && path.components()[0] == "ObjectPool";
+        let native = has(OLE10_NATIVE_STREAM);
+        let comp_obj = has(COMP_OBJ_STREAM);
+        let evidence = [
+            (!is_root && has(OLE_STREAM), ObjectEvidence::OleStream),
+            (native, ObjectEvidence::Ole10Native),
+            (!is_root && comp_obj, ObjectEvidence::CompObj),
+            (
+                !is_root
+                    && storage.class_id().is_some()
+                    && (comp_obj || native || has(CONTENTS_STREAM)),
+                ObjectEvidence::ClassId,
+            ),
+            (
+                in_object_pool && is_object_pool_name(storage.name()),
+                ObjectEvidence::ObjectPoolName,
+            ),
+            (is_mbd_name(storage.name()), ObjectEvidence::MbdName),
+        ]
+        .into_iter()
+        .filter_map(|(found, evidence)| found.then_some(evidence))
+        .collect::<Vec<_>>();
+        if evidence.is_empty() {
+            continue;
+        }
+        entries.push(ObjectEntry {
+            path,
+            index: storage.index(),
+            class_id: storage.class_id().map(str::to_string),
+            evidence,
+        });
+    }
+    entries
+}
+
+/// `_` and a number, such as `_1549162656`, the names of the objects in a Word `ObjectPool`.
+fn is_object_pool_name(name: &str) -> bool {
+    match name.strip_prefix('_') {
+        Some(number) => !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// `MBD` and eight hex digits, such as `MBD0049DB15`, the names of the objects in a workbook.
+fn is_mbd_name(name: &str) -> bool {
+    match name.strip_prefix("MBD") {
+        Some(number) => number.len() == 8 && number.bytes().all(|byte| byte.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+/// Classify the storages [`object_entries`] finds.
+pub(crate) fn ole_objects(ole: &OleFile) -> Result<Vec<OleObject>> {
+    let mut objects = vec![];
+    for entry in object_entries(ole) {
+        let storage = match ole.entry_by_id(entry.index as u32) {
+            Some(storage) => storage,
+            None => continue,
+        };
+        let kind = if entry.evidence.contains(&ObjectEvidence::Ole10Native) {
+            OleObjectKind::Packager
+        } else if entry.evidence.contains(&ObjectEvidence::OleStream)
+            && is_linked(ole, &entry.path)?
+        {
+            OleObjectKind::Linked
+        } else {
+            OleObjectKind::Storage
+        };
+        objects.push(OleObject {
+            prog_id: prog_id(ole, &entry.path, storage)?,
+            class_id: entry.class_id,
+            path: entry.path,
+            kind,
+        });
+    }
+    Ok(objects)
+}
+
+/// The path of `name` in the storage at `path`.
+fn stream_in(path: &EntryPath, name: &str) -> Vec<String> {
+    let mut components = path.components().to_vec();
+    components.push(name.to_string());
+    components
+}
+
+/// Whether the OLEStream of the object at `path` marks it as linked.
+fn is_linked(ole: &OleFile, path: &EntryPath) -> Result<bool> {
+    let stream = stream_in(path, OLE_STREAM);
+    let stream = stream.iter().map(String::as_str).collect::<Vec<_>>();
+    // the version comes first, then the flags
+    let flags = ole.open_stream_range(&stream, 4, 4)?;
+    Ok(match flags[..] {
+        [a, b, c, d] => u32::from_le_bytes([a, b, c, d]) & OLE_STREAM_LINKED != 0,
+        _ => false,
+    })
+}
+
+/// The ProgID in the `\x01CompObj` stream of `storage`, if it has one and it can be read.
+fn prog_id(ole: &OleFile, path: &EntryPath, storage: &DirectoryEntry) -> Result<Option<String>> {
+    if ole.find_child(storage, COMP_OBJ_STREAM).is_none() {
+        return Ok(None);
+    }
+    let stream = stream_in(path, COMP_OBJ_STREAM);
+    let stream = stream.iter().map(String::as_str).collect::<Vec<_>>();
+    let data = match ole.open_stream(&stream) {
+        Ok(data) => data,
+        Err(Error::OleDirectoryEntryNotFound | Error::IsAStorage { .. }) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    Ok(parse_prog_id(&data))
+}
+
+/// The third string of a CompObjStream, after the user type and the clipboard format
+/// (MS-OLEDS 2.3.8).
+fn parse_prog_id(data: &[u8]) -> Option<String> {
+    let mut offset = COMP_OBJ_HEADER_LEN;
+    // AnsiUserType
+    offset += 4 + u32_at(data, offset)? as usize;
+    // AnsiClipboardFormat: absent, a standard format id, or a registered name
+    offset += match u32_at(data, offset)? {
+        0 => 4,
+        0xFFFF_FFFF | 0xFFFF_FFFE => 8,
+        len => 4 + len as usize,
+    };
+    let len = u32_at(data, offset)? as usize;
+    let prog_id = data.get(offset + 4..offset + 4 + len)?;
+    let prog_id = prog_id.split(|byte| *byte == 0).next().unwrap_or_default();
+    (!prog_id.is_empty()).then(|| prog_id.iter().map(|byte| *byte as char).collect())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset.checked_add(4)?)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    /// A CompObjStream naming `prog_id`, with a registered clipboard format.
+    fn comp_obj(prog_id: &str) -> Vec<u8> {
+        let mut data = vec![0u8; COMP_OBJ_HEADER_LEN];
+        for string in ["Package", "Embed Source", prog_id] {
+            data.extend((string.len() as u32 + 1).to_le_bytes());
+            data.extend(string.as_bytes());
+            data.push(0);
+        }
+        data
+    }
+
+    /// An OLEStream with the given flags.
+    fn ole_stream(flags: u32) -> Vec<u8> {
+        let mut data = 0x0200_0001u32.to_le_bytes().to_vec();
+        data.extend(flags.to_le_bytes());
+        data.extend([0u8; 12]);
+        data
+    }
+
+    #[test]
+    fn test_ole_objects() {
+        let bytes = CfbBuilder::new()
+            .stream("\x01CompObj", &comp_obj("Word.Document.8"))
+            .stream("WordDocument", &[0u8; 64])
+            .stream("ObjectPool/_1/\x01Ole", &ole_stream(0))
+            .stream("ObjectPool/_1/\x01CompObj", &comp_obj("Package"))
+            .stream("ObjectPool/_1/\x01Ole10Native", b"payload")
+            .stream("ObjectPool/_2/\x01Ole", &ole_stream(0))
+            .stream("ObjectPool/_2/\x01CompObj", &comp_obj("Excel.Sheet.8"))
+            .stream("ObjectPool/_2/Workbook", &[0u8; 64])
+            .stream("ObjectPool/_3/\x01Ole", &ole_stream(OLE_STREAM_LINKED))
+            .stream("ObjectPool/Other/Contents", b"not an object")
+            .build();
+        let ole = OleFile::parse_sync(&bytes[..]).unwrap();
+        let objects = ole.ole_objects().unwrap();
+        let found = objects
+            .iter()
+            .map(|object| {
+                (
+                    object.path.to_string(),
+                    object.kind,
+                    object.prog_id.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (
+                    "ObjectPool/_1".to_string(),
+                    OleObjectKind::Packager,
+                    Some("Package")
+                ),
+                (
+                    "ObjectPool/_2".to_string(),
+                    OleObjectKind::Storage,
+                    Some("Excel.Sheet.8")
+                ),
+                ("ObjectPool/_3".to_string(), OleObjectKind::Linked, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_object_entries() {
+        let bytes = CfbBuilder::new()
+            .stream("\x01CompObj", &comp_obj("Word.Document.8"))
+            .stream("ObjectPool/_1549162656/Contents", b"by name")
+            .stream("ObjectPool/_1549162657/\x01Ole", &ole_stream(0))
+            .stream("ObjectPool/_1549162657/\x01CompObj", &comp_obj("Package"))
+            .stream("ObjectPool/_1549162657/\x01Ole10Native", b"payload")
+            .stream("MBD0049DB15/Contents", b"by name")
+            .stream("_123/Contents", b"not in ObjectPool")
+            .stream("MBD123/Contents", b"too short")
+            .build();
+        let ole = OleFile::parse_sync(&bytes[..]).unwrap();
+        let found = ole
+            .embedded_object_entries()
+            .into_iter()
+            .map(|entry| (entry.path.to_string(), entry.evidence))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (
+                    "ObjectPool/_1549162656".to_string(),
+                    vec![ObjectEvidence::ObjectPoolName]
+                ),
+                (
+                    "ObjectPool/_1549162657".to_string(),
+                    vec![
+                        ObjectEvidence::OleStream,
+                        ObjectEvidence::Ole10Native,
+                        ObjectEvidence::CompObj,
+                        ObjectEvidence::ObjectPoolName
+                    ]
+                ),
+                ("MBD0049DB15".to_string(), vec![ObjectEvidence::MbdName]),
+            ]
+        );
+        // every entry is an object and the other way round
+        let objects = ole.ole_objects().unwrap();
+        assert_eq!(objects.len(), found.len());
+        assert_eq!(objects[1].kind, OleObjectKind::Packager);
+    }
+
+    #[test]
+    fn test_no_objects_in_plain_files() {
+        // the recipient and attachment storages of a message each have a CLSID
+        let msg = std::fs::read("../data/EmailWithAttachments.msg").unwrap();
+        let ole = OleFile::parse_sync(&msg[..]).unwrap();
+        assert!(ole.embedded_object_entries().is_empty());
+        assert!(ole.ole_objects().unwrap().is_empty());
+
+        // Word put an \x01Ole stream at the root, only the ObjectPool entry is an object
+        let doc = std::fs::read("../data/file-sample_1MB.doc").unwrap();
+        let ole = OleFile::parse_sync(&doc[..]).unwrap();
+        let found = ole
+            .embedded_object_entries()
+            .into_iter()
+            .map(|entry| entry.path.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(found, ["ObjectPool/_2147483647"]);
+        let objects = ole.ole_objects().unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].path.to_string(), "ObjectPool/_2147483647");
+        assert_eq!(objects[0].kind, OleObjectKind::Storage);
+    }
+
+    #[test]
+    fn test_class_id_alone_is_no_object() {
+        let bytes = CfbBuilder::new()
+            .stream("\x01Ole", &ole_stream(0))
+            .stream("Storage/Data", b"data")
+            .stream("Object/Contents", b"native data")
+            .build();
+        let mut ole = OleFile::parse_sync(&bytes[..]).unwrap();
+        for path in ["Storage", "Object"] {
+            let index = ole.find_stream_path(path).unwrap().index;
+            ole.directory_entries[index].class_id =
+                Some("00020906-0000-0000-C000-000000000046".to_string());
+        }
+        let found = ole
+            .embedded_object_entries()
+            .into_iter()
+            .map(|entry| (entry.path.to_string(), entry.evidence))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [("Object".to_string(), vec![ObjectEvidence::ClassId])]
+        );
+    }
+
+    #[test]
+    fn test_object_file() {
+        // an object saved out of its container is a compound file of its own
+        let bytes = CfbBuilder::new()
+            .stream("\x01Ole10Native", b"payload")
+            .build();
+        let ole = OleFile::parse_sync(&bytes[..]).unwrap();
+        let objects = ole.ole_objects().unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].path, EntryPath::default());
+        assert_eq!(objects[0].kind, OleObjectKind::Packager);
+        assert_eq!(objects[0].prog_id, None);
+
+        assert_eq!(parse_prog_id(&comp_obj("Package")[..40]), None);
+    }
+}
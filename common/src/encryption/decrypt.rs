@@ -0,0 +1,331 @@
+//! Decryption of password protected files, for looking at their content.
+use crate::{
+    encryption::{
+        ecma376, excel, excel::NAME_TO_RECORD_NUM_MAP, handler, rc4::u32_at, rc4::CryptoApiKey,
+        DocumentType, EncryptionScheme,
+    },
+    error::Error,
+    secret::SecretBuffer,
+    OleFile, Result,
+};
+use derivative::Derivative;
+use std::iter::once;
+
+/// The part of the Word FIB that is never encrypted (MS-DOC 2.2.6.2).
+const FIB_BASE_LENGTH: usize = 68;
+/// The Word FIB flags with `fEncrypted` and `fWhichTblStm` (MS-DOC 2.5.2).
+const FIB_FLAGS: usize = 0x0A;
+const F_ENCRYPTED: u16 = 0x0100;
+const F_WHICH_TBL_STM: u16 = 0x0200;
+/// Where the Word FIB gives the size of the encryption header in the table stream.
+const FIB_LKEY: usize = 0x0E;
+/// The RC4 CryptoAPI block sizes of Word and Excel streams.
+const WORD_BLOCK_SIZE: usize = 512;
+const EXCEL_BLOCK_SIZE: usize = 1024;
+
+/// What a decrypted file holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecryptedFormat {
+    /// a binary Word or Excel file: the compound file again, with its streams decrypted in
+    /// place
+    Compound,
+    /// the OOXML package, a zip archive, that an encrypted `.docx`, `.xlsx` or `.pptx` wraps
+    Package,
+}
+
+/// The content of an encrypted file, see [`decrypt`]. With the `zeroize` feature it is wiped
+/// when dropped.
+#[derive(Clone, Derivative)]
+#[derivative(Debug)]
+pub struct Decrypted {
+    format: DecryptedFormat,
+    scheme: EncryptionScheme,
+    #[derivative(Debug = "ignore")]
+    data: SecretBuffer,
+}
+
+impl Decrypted {
+    /// Whether [`Decrypted::data`] is a compound file or an OOXML package.
+    pub fn format(&self) -> DecryptedFormat {
+        self.format
+    }
+
+    /// The scheme the file was encrypted with.
+    pub fn scheme(&self) -> EncryptionScheme {
+        self.scheme
+    }
+
+    /// The decrypted file.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Decrypt the file `data` with `password`.
+///
+/// Binary Word and Excel files encrypted with RC4 CryptoAPI come back as a copy of the compound
+/// file with the encrypted streams decrypted and the encryption flag cleared, so the copy parses
+/// like any other. OOXML packages encrypted with ECMA-376 standard or agile encryption come back
+/// as the package itself. A password that doesn't fit is `Error::WrongPassword`, the other
+/// schemes are `Error::CurrentlyUnimplemented`.
+///
+/// ```rust
+/// let data = std::fs::read("../data/encryption/encrypted/rc4cryptoapi_password.doc").unwrap();
+/// let decrypted = ole::decrypt(&data, "Password1234_").unwrap();
+/// let ole = ole::OleFile::parse_sync(decrypted.data()).unwrap();
+/// assert!(!ole.is_encrypted());
+///
+/// assert!(matches!(
+///     ole::decrypt(&data, "guess"),
+///     Err(ole::error::Error::WrongPassword)
+/// ));
+/// ```
+pub fn decrypt(data: &[u8], password: &str) -> Result<Decrypted> {
+    let ole = OleFile::parse_lenient_sync(data)?;
+    let scheme = ole
+        .encryption_scheme()
+        .ok_or(Error::GenericError("the file is not encrypted"))?;
+    let document_type = handler(&ole).map(|handler| handler.doc_type());
+    // UTF-16 takes at most two bytes per byte of UTF-8, the buffer never grows
+    let mut utf16 = SecretBuffer::with_capacity(password.len() * 2);
+    utf16.extend(password.encode_utf16().flat_map(u16::to_le_bytes));
+    let password = utf16;
+    let (format, data) = match (document_type, scheme) {
+        (Some(DocumentType::Word), EncryptionScheme::Rc4CryptoApi) => (
+            DecryptedFormat::Compound,
+            decrypt_word(&ole, data, &password)?,
+        ),
+        (Some(DocumentType::Excel), EncryptionScheme::Rc4CryptoApi) => (
+            DecryptedFormat::Compound,
+            decrypt_workbook(&ole, data, &password)?,
+        ),
+        (Some(DocumentType::Ooxml), EncryptionScheme::Standard) => (
+            DecryptedFormat::Package,
+            ecma376::decrypt_standard(
+                &ole.open_stream(&["EncryptionInfo"])?,
+                &ole.open_stream(&["EncryptedPackage"])?,
+                &password,
+            )?,
+        ),
+        (Some(DocumentType::Ooxml), EncryptionScheme::Agile) => (
+            DecryptedFormat::Package,
+            ecma376::decrypt_agile(
+                &ole.open_stream(&["EncryptionInfo"])?,
+                &ole.open_stream(&["EncryptedPackage"])?,
+                &password,
+            )?,
+        ),
+        _ => {
+            return Err(Error::CurrentlyUnimplemented(format!(
+                "decrypting {}",
+                scheme
+            )))
+        }
+    };
+    Ok(Decrypted {
+        format,
+        scheme,
+        data,
+    })
+}
+
+/// Write `stream` over the sectors of the stream at `stream_path` in the file `data`.
+fn overwrite(ole: &OleFile, data: &mut [u8], stream_path: &str, stream: &[u8]) -> Result<()> {
+    let mut rest = stream;
+    for range in ole.stream_extents(stream_path)? {
+        let start = range.start as usize;
+        let end = (range.end as usize).min(data.len()).min(start + rest.len());
+        if start >= end {
+            break;
+        }
+        data[start..end].copy_from_slice(&rest[..end - start]);
+        rest = &rest[end - start..];
+    }
+    Ok(())
+}
+
+/// Decrypt the WordDocument, table and Data streams of a Word file (MS-OFFCRYPTO 2.3.6.4).
+fn decrypt_word(ole: &OleFile, data: &[u8], password: &[u8]) -> Result<SecretBuffer> {
+    let mut word = SecretBuffer::from(ole.open_stream(&["WordDocument"])?);
+    if word.len() < FIB_BASE_LENGTH {
+        return Err(Error::InvalidEncryptionInfo("FIB cut short".to_string()));
+    }
+    let flags = u16::from_le_bytes([word[FIB_FLAGS], word[FIB_FLAGS + 1]]);
+    let table_name = if flags & F_WHICH_TBL_STM != 0 {
+        "1Table"
+    } else {
+        "0Table"
+    };
+    let mut table = SecretBuffer::from(ole.open_stream(&[table_name])?);
+    let key = CryptoApiKey::new(&table, password)?;
+
+    // the FIB base and the encryption header are left in plaintext
+    key.decrypt(
+        &mut word,
+        WORD_BLOCK_SIZE,
+        once(FIB_BASE_LENGTH..usize::MAX),
+    );
+    word[FIB_FLAGS..FIB_FLAGS + 2].copy_from_slice(&(flags & !F_ENCRYPTED).to_le_bytes());
+    let header_size = u32_at(&word, FIB_LKEY)? as usize;
+    key.decrypt(&mut table, WORD_BLOCK_SIZE, once(header_size..usize::MAX));
+
+    let mut decrypted = SecretBuffer::from(data.to_vec());
+    overwrite(ole, &mut decrypted, "WordDocument", &word)?;
+    overwrite(ole, &mut decrypted, table_name, &table)?;
+    if let Ok(stream) = ole.open_stream(&["Data"]) {
+        let mut stream = SecretBuffer::from(stream);
+        key.decrypt(&mut stream, WORD_BLOCK_SIZE, once(0..usize::MAX));
+        overwrite(ole, &mut decrypted, "Data", &stream)?;
+    }
+    Ok(decrypted)
+}
+
+/// Decrypt the records of the Workbook stream of an Excel file (MS-XLS 2.2.10).
+fn decrypt_workbook(ole: &OleFile, data: &[u8], password: &[u8]) -> Result<SecretBuffer> {
+    let mut workbook = SecretBuffer::from(ole.open_stream(&["Workbook"])?);
+    let record = |name: &str| NAME_TO_RECORD_NUM_MAP[name];
+    // record headers, these records and the stream position in BoundSheet8 are not encrypted
+    let plaintext = [
+        "BOF",
+        "FilePass",
+        "UsrExcl",
+        "FileLock",
+        "InterfaceHdr",
+        "RRDInfo",
+        "RRDHead",
+    ]
+    .map(record);
+    let records = excel::record_ranges(&workbook);
+    let file_pass = records
+        .iter()
+        .find(|(num, _)| *num == record("FilePass"))
+        .map(|(_, range)| range.clone())
+        .ok_or_else(|| Error::InvalidEncryptionInfo("no FilePass record".to_string()))?;
+    let key = CryptoApiKey::new(
+        workbook
+            .get(file_pass.start + 2..file_pass.end)
+            .unwrap_or_default(),
+        password,
+    )?;
+
+    let encrypted = records
+        .into_iter()
+        .filter(|(num, _)| !plaintext.contains(num))
+        .map(|(num, range)| match num {
+            num if num == record("BoundSheet8") => (range.start + 4).min(range.end)..range.end,
+            _ => range,
+        });
+    key.decrypt(&mut workbook, EXCEL_BLOCK_SIZE, encrypted);
+    // the workbook is no longer encrypted, the FilePass record is blanked to say so, keeping
+    // its size so the records after it stay where they are
+    workbook[file_pass.start - 4..file_pass.start - 2].fill(0);
+    workbook[file_pass.clone()].fill(0);
+
+    let mut decrypted = SecretBuffer::from(data.to_vec());
+    overwrite(ole, &mut decrypted, "Workbook", &workbook)?;
+    Ok(decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "Password1234_";
+
+    fn read(path: &str) -> Vec<u8> {
+        std::fs::read(format!("../data/encryption/{}", path)).unwrap()
+    }
+
+    fn stream(data: &[u8], name: &str) -> Vec<u8> {
+        OleFile::parse_sync(data)
+            .unwrap()
+            .open_stream(&[name])
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decrypt_word() {
+        let data = read("encrypted/rc4cryptoapi_password.doc");
+        let decrypted = decrypt(&data, PASSWORD).unwrap();
+        assert_eq!(decrypted.format(), DecryptedFormat::Compound);
+        assert_eq!(decrypted.scheme(), EncryptionScheme::Rc4CryptoApi);
+        assert_eq!(decrypted.data().len(), data.len());
+        assert!(!OleFile::parse_sync(decrypted.data())
+            .unwrap()
+            .is_encrypted());
+
+        // the table stream is the plaintext one after the encryption header
+        let plain = read("plaintext/plain.doc");
+        let table = stream(decrypted.data(), "1Table");
+        let header_size = u32_at(&stream(decrypted.data(), "WordDocument"), FIB_LKEY).unwrap();
+        let plain_table = stream(&plain, "1Table");
+        assert_eq!(table[header_size as usize..][..4096], plain_table[..4096]);
+
+        assert!(matches!(
+            decrypt(&data, "password"),
+            Err(Error::WrongPassword)
+        ));
+        assert!(matches!(
+            decrypt(&plain, PASSWORD),
+            Err(Error::GenericError(_))
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_workbook() {
+        let data = read("encrypted/rc4cryptoapi_password.xls");
+        let decrypted = decrypt(&data, PASSWORD).unwrap();
+        assert_eq!(decrypted.format(), DecryptedFormat::Compound);
+        assert!(!OleFile::parse_sync(decrypted.data())
+            .unwrap()
+            .is_encrypted());
+
+        // the shared strings, continued over several records, are the plaintext ones
+        let sst = |workbook: &[u8]| {
+            excel::record_ranges(workbook)
+                .into_iter()
+                .skip_while(|(num, _)| *num != NAME_TO_RECORD_NUM_MAP["SST"])
+                .take_while(|(num, _)| {
+                    [
+                        NAME_TO_RECORD_NUM_MAP["SST"],
+                        NAME_TO_RECORD_NUM_MAP["Continue"],
+                    ]
+                    .contains(num)
+                })
+                .flat_map(|(_, range)| workbook[range].to_vec())
+                .collect::<Vec<_>>()
+        };
+        let workbook = stream(decrypted.data(), "Workbook");
+        let plain = stream(&read("plaintext/plain.xls"), "Workbook");
+        assert!(!sst(&workbook).is_empty());
+        assert_eq!(sst(&workbook), sst(&plain));
+
+        assert!(matches!(
+            decrypt(&data, "password"),
+            Err(Error::WrongPassword)
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_packages() {
+        for (file, scheme) in [
+            ("ecma376standard_password.docx", EncryptionScheme::Standard),
+            ("example_password.xlsx", EncryptionScheme::Agile),
+        ] {
+            let data = read(&format!("encrypted/{}", file));
+            let decrypted = decrypt(&data, PASSWORD).unwrap();
+            assert_eq!(decrypted.format(), DecryptedFormat::Package, "{}", file);
+            assert_eq!(decrypted.scheme(), scheme, "{}", file);
+            assert!(decrypted.data().starts_with(b"PK\x03\x04"), "{}", file);
+            assert!(decrypted
+                .data()
+                .windows(19)
+                .any(|name| name == b"[Content_Types].xml"));
+
+            assert!(matches!(
+                decrypt(&data, "password"),
+                Err(Error::WrongPassword)
+            ));
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! ECMA-376 encryption of OOXML packages, standard (MS-OFFCRYPTO 2.3.4.5) and agile
+//! (MS-OFFCRYPTO 2.3.4.10).
+use crate::{
+    encryption::rc4::u32_at,
+    error::Error,
+    secret::{wipe, SecretBuffer},
+    Result,
+};
+use aes::cipher::{generic_array::GenericArray, BlockDecrypt, KeyInit};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use sha1::{Digest, Sha1};
+use sha2::{Sha256, Sha384, Sha512};
+
+/// The block keys of the agile key derivation, one per value encrypted with the password.
+const VERIFIER_HASH_INPUT_BLOCK: [u8; 8] = [0xfe, 0xa7, 0xd2, 0x76, 0x3b, 0x4b, 0x9e, 0x79];
+const VERIFIER_HASH_VALUE_BLOCK: [u8; 8] = [0xd7, 0xaa, 0x0f, 0x6d, 0x30, 0x61, 0x34, 0x4e];
+const KEY_VALUE_BLOCK: [u8; 8] = [0x14, 0x6e, 0x0b, 0xe7, 0xab, 0xac, 0xd0, 0xd6];
+/// Agile packages are encrypted in segments of this many bytes, each with its own IV.
+const SEGMENT_SIZE: usize = 4096;
+/// The spin count of standard encryption, agile encryption names its own.
+const STANDARD_SPIN_COUNT: u32 = 50000;
+/// The largest spin count agile encryption allows (MS-OFFCRYPTO 2.3.4.11).
+const MAX_SPIN_COUNT: u32 = 10_000_000;
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidEncryptionInfo(message.to_string())
+}
+
+enum Aes {
+    Aes128(aes::Aes128),
+    Aes192(aes::Aes192),
+    Aes256(aes::Aes256),
+}
+
+impl Aes {
+    fn new(key: &[u8]) -> Result<Self> {
+        Ok(match key.len() {
+            16 => Aes::Aes128(aes::Aes128::new(GenericArray::from_slice(key))),
+            24 => Aes::Aes192(aes::Aes192::new(GenericArray::from_slice(key))),
+            32 => Aes::Aes256(aes::Aes256::new(GenericArray::from_slice(key))),
+            _ => return Err(invalid("AES key of an unsupported size")),
+        })
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) {
+        let block = GenericArray::from_mut_slice(block);
+        match self {
+            Aes::Aes128(cipher) => cipher.decrypt_block(block),
+            Aes::Aes192(cipher) => cipher.decrypt_block(block),
+            Aes::Aes256(cipher) => cipher.decrypt_block(block),
+        }
+    }
+
+    /// Decrypt the whole blocks of `data` in place, in ECB mode.
+    fn decrypt_ecb(&self, data: &mut [u8]) {
+        for block in data.chunks_exact_mut(16) {
+            self.decrypt_block(block);
+        }
+    }
+
+    /// Decrypt the whole blocks of `data` in place, in CBC mode.
+    fn decrypt_cbc(&self, data: &mut [u8], iv: &[u8]) {
+        let mut previous = [0u8; 16];
+        previous.copy_from_slice(&iv[..16]);
+        for block in data.chunks_exact_mut(16) {
+            let mut ciphertext = [0u8; 16];
+            ciphertext.copy_from_slice(block);
+            self.decrypt_block(block);
+            for (byte, chained) in block.iter_mut().zip(previous) {
+                *byte ^= chained;
+            }
+            previous = ciphertext;
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum HashAlgorithm {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    fn from_name(name: &str) -> Result<Self> {
+        Ok(match name {
+            "SHA1" => HashAlgorithm::Sha1,
+            "SHA256" => HashAlgorithm::Sha256,
+            "SHA384" => HashAlgorithm::Sha384,
+            "SHA512" => HashAlgorithm::Sha512,
+            _ => return Err(invalid("unsupported hash algorithm")),
+        })
+    }
+
+    fn hash(self, parts: &[&[u8]]) -> SecretBuffer {
+        fn hash<D: Digest>(parts: &[&[u8]]) -> SecretBuffer {
+            let mut hasher = D::new();
+            for part in parts {
+                hasher.update(part);
+            }
+            let mut hash = hasher.finalize();
+            let buffer = SecretBuffer::from(hash.to_vec());
+            wipe(&mut hash);
+            buffer
+        }
+        match self {
+            HashAlgorithm::Sha1 => hash::<Sha1>(parts),
+            HashAlgorithm::Sha256 => hash::<Sha256>(parts),
+            HashAlgorithm::Sha384 => hash::<Sha384>(parts),
+            HashAlgorithm::Sha512 => hash::<Sha512>(parts),
+        }
+    }
+
+    /// The hash of the salt and the password, rehashed `spin_count` times with the iteration.
+    fn spin(self, salt: &[u8], password: &[u8], spin_count: u32) -> SecretBuffer {
+        let mut hash = self.hash(&[salt, password]);
+        for iteration in 0..spin_count {
+            hash = self.hash(&[&iteration.to_le_bytes(), &hash]);
+        }
+        hash
+    }
+}
+
+/// `hash` cut or padded with 0x36 to `len` bytes.
+fn fit(hash: &[u8], len: usize) -> SecretBuffer {
+    let mut fitted = SecretBuffer::with_capacity(len);
+    fitted.extend(hash.iter().take(len));
+    fitted.resize(len, 0x36);
+    fitted
+}
+
+/// The size the `EncryptedPackage` stream gives its plaintext, and the ciphertext after it.
+fn package_parts(package: &[u8]) -> Result<(usize, &[u8])> {
+    let size = package
+        .get(..8)
+        .ok_or_else(|| invalid("EncryptedPackage cut short"))?;
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(size);
+    let size = usize::try_from(u64::from_le_bytes(bytes)).unwrap_or(usize::MAX);
+    Ok((size, &package[8..]))
+}
+
+/// Decrypt the `EncryptedPackage` of a standard encrypted file with the `EncryptionInfo` `info`.
+pub(crate) fn decrypt_standard(
+    info: &[u8],
+    package: &[u8],
+    password: &[u8],
+) -> Result<SecretBuffer> {
+    let header_size = u32_at(info, 8)? as usize;
+    let key_bytes = u32_at(info, 12 + 16)? as usize / 8;
+    let verifier = 12 + header_size;
+    let salt = info
+        .get(verifier + 4..verifier + 20)
+        .ok_or_else(|| invalid("verifier cut short"))?;
+
+    let sha1 = HashAlgorithm::Sha1;
+    let hash = sha1.spin(salt, password, STANDARD_SPIN_COUNT);
+    let hash = sha1.hash(&[&hash, &0u32.to_le_bytes()]);
+    let mut buffer = SecretBuffer::from(vec![0x36u8; 64]);
+    for (byte, hashed) in buffer.iter_mut().zip(hash.iter()) {
+        *byte ^= hashed;
+    }
+    let mut derived = SecretBuffer::with_capacity(40);
+    derived.extend_from_slice(&sha1.hash(&[&buffer]));
+    for byte in buffer.iter_mut() {
+        // 0x36 ^ 0x5c, so the buffer holds the hash XOR 0x5c
+        *byte ^= 0x6a;
+    }
+    derived.extend_from_slice(&sha1.hash(&[&buffer]));
+    let key = derived
+        .get(..key_bytes)
+        .ok_or_else(|| invalid("AES key of an unsupported size"))?;
+    let aes = Aes::new(key)?;
+
+    let mut check = SecretBuffer::with_capacity(48);
+    check.extend_from_slice(
+        info.get(verifier + 20..verifier + 36)
+            .ok_or_else(|| invalid("verifier cut short"))?,
+    );
+    check.extend_from_slice(
+        info.get(verifier + 40..verifier + 72)
+            .ok_or_else(|| invalid("verifier cut short"))?,
+    );
+    aes.decrypt_ecb(&mut check);
+    if sha1.hash(&[&check[..16]])[..] != check[16..36] {
+        return Err(Error::WrongPassword);
+    }
+
+    let (size, ciphertext) = package_parts(package)?;
+    let mut plaintext = SecretBuffer::from(ciphertext.to_vec());
+    aes.decrypt_ecb(&mut plaintext);
+    plaintext.truncate(size);
+    Ok(plaintext)
+}
+
+/// The attributes of the first element named `name` (whatever its namespace prefix) in `xml`.
+fn attributes<'a>(xml: &'a str, name: &str) -> Result<Vec<(&'a str, &'a str)>> {
+    let mut rest = xml;
+    let tag = loop {
+        let start = rest
+            .find('<')
+            .ok_or_else(|| invalid("EncryptionInfo lacks an element"))?;
+        rest = &rest[start + 1..];
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..end];
+        let qualified = tag.split_whitespace().next().unwrap_or_default();
+        if qualified.rsplit(':').next() == Some(name) {
+            break tag;
+        }
+    };
+    let mut attributes = vec![];
+    let mut rest = tag;
+    while let Some(equals) = rest.find("=\"") {
+        let key = rest[..equals].trim();
+        let value = &rest[equals + 2..];
+        let end = value
+            .find('"')
+            .ok_or_else(|| invalid("unterminated attribute in EncryptionInfo"))?;
+        let key = key.rsplit(char::is_whitespace).next().unwrap_or(key);
+        attributes.push((key, &value[..end]));
+        rest = &value[end + 1..];
+    }
+    Ok(attributes)
+}
+
+/// The parameters of one agile `keyData` or `encryptedKey` element.
+struct AgileParameters<'a> {
+    attributes: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> AgileParameters<'a> {
+    fn new(xml: &'a str, name: &str) -> Result<Self> {
+        Ok(Self {
+            attributes: attributes(xml, name)?,
+        })
+    }
+
+    fn text(&self, name: &str) -> Result<&'a str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| Error::InvalidEncryptionInfo(format!("{} missing", name)))
+    }
+
+    fn number(&self, name: &str) -> Result<u32> {
+        self.text(name)?
+            .parse()
+            .map_err(|_| Error::InvalidEncryptionInfo(format!("{} is not a number", name)))
+    }
+
+    fn bytes(&self, name: &str) -> Result<Vec<u8>> {
+        BASE64
+            .decode(self.text(name)?)
+            .map_err(|_| Error::InvalidEncryptionInfo(format!("{} is not base64", name)))
+    }
+
+    fn hash_algorithm(&self) -> Result<HashAlgorithm> {
+        HashAlgorithm::from_name(self.text("hashAlgorithm")?)
+    }
+
+    fn key_bytes(&self) -> Result<usize> {
+        Ok(self.number("keyBits")? as usize / 8)
+    }
+
+    fn check_cipher(&self) -> Result<()> {
+        match (self.text("cipherAlgorithm")?, self.text("cipherChaining")?) {
+            ("AES", "ChainingModeCBC") => Ok(()),
+            _ => Err(invalid("unsupported cipher")),
+        }
+    }
+}
+
+/// Decrypt the `EncryptedPackage` of an agile encrypted file with the `EncryptionInfo` `info`.
+pub(crate) fn decrypt_agile(info: &[u8], package: &[u8], password: &[u8]) -> Result<SecretBuffer> {
+    let xml = info
+        .get(8..)
+        .and_then(|xml| std::str::from_utf8(xml).ok())
+        .ok_or_else(|| invalid("EncryptionInfo is not XML"))?;
+    let key_data = AgileParameters::new(xml, "keyData")?;
+    let encrypted_key = AgileParameters::new(xml, "encryptedKey")?;
+    key_data.check_cipher()?;
+    encrypted_key.check_cipher()?;
+
+    let hash_algorithm = encrypted_key.hash_algorithm()?;
+    let salt = encrypted_key.bytes("saltValue")?;
+    if salt.len() < 16 {
+        return Err(invalid("salt shorter than an AES block"));
+    }
+    let spin_count = encrypted_key.number("spinCount")?;
+    if spin_count > MAX_SPIN_COUNT {
+        return Err(invalid("spin count over the limit"));
+    }
+    let spun = hash_algorithm.spin(&salt, password, spin_count);
+    let key_bytes = encrypted_key.key_bytes()?;
+    let decrypt = |block_key: &[u8], name: &str| -> Result<SecretBuffer> {
+        let key = fit(&hash_algorithm.hash(&[&spun, block_key]), key_bytes);
+        let mut value = SecretBuffer::from(encrypted_key.bytes(name)?);
+        Aes::new(&key)?.decrypt_cbc(&mut value, &salt);
+        Ok(value)
+    };
+
+    let input = decrypt(&VERIFIER_HASH_INPUT_BLOCK, "encryptedVerifierHashInput")?;
+    let value = decrypt(&VERIFIER_HASH_VALUE_BLOCK, "encryptedVerifierHashValue")?;
+    let salt_size = (encrypted_key.number("saltSize")? as usize).min(input.len());
+    let hash = hash_algorithm.hash(&[&input[..salt_size]]);
+    if value.get(..hash.len()) != Some(&hash[..]) {
+        return Err(Error::WrongPassword);
+    }
+    let mut secret = decrypt(&KEY_VALUE_BLOCK, "encryptedKeyValue")?;
+    secret.truncate(key_data.key_bytes()?);
+    let aes = Aes::new(&secret)?;
+
+    let key_data_hash = key_data.hash_algorithm()?;
+    let key_data_salt = key_data.bytes("saltValue")?;
+    let (size, ciphertext) = package_parts(package)?;
+    let mut plaintext = SecretBuffer::from(ciphertext.to_vec());
+    for (index, segment) in plaintext.chunks_mut(SEGMENT_SIZE).enumerate() {
+        let iv = fit(
+            &key_data_hash.hash(&[&key_data_salt, &(index as u32).to_le_bytes()]),
+            16,
+        );
+        aes.decrypt_cbc(segment, &iv);
+    }
+    plaintext.truncate(size);
+    Ok(plaintext)
+}
@@ -1,8 +1,12 @@
 use crate::{
-    encryption::{DocumentType, EncryptionHandler},
+    encryption::{DocumentType, EncryptionHandler, EncryptionScheme},
     OleFile,
 };
-use std::collections::HashMap;
+use log::debug;
+use std::{borrow::Cow, collections::HashMap};
+
+/// Continue: the rest of a record too large for the 8224 bytes a record can hold (MS-XLS 2.4.58)
+const CONTINUE: u16 = 60;
 
 lazy_static! {
     pub static ref NAME_TO_RECORD_NUM_MAP: HashMap<&'static str, u16> = {
@@ -378,12 +382,13 @@ impl<'a> BIFFSTream<'a> {
         }
     }
 
+    #[allow(dead_code)]
     pub fn has_record(&mut self, target: u16) -> bool {
         self.reset();
         self.into_iter().any(|item| item.num == target)
     }
 
-    pub fn skip_to(&mut self, target: u16) -> Option<BiffItem> {
+    pub fn skip_to(&mut self, target: u16) -> Option<BiffItem<'a>> {
         self.reset();
         self.into_iter().find(|item| item.num == target)
     }
@@ -393,33 +398,77 @@ impl<'a> BIFFSTream<'a> {
     }
 }
 
+/// A record with the Continue records following it joined to its data.
+#[allow(dead_code)]
 #[derive(Debug)]
 struct BiffItem<'a> {
     pub num: u16,
-    pub size: u16,
-    pub data: &'a [u8],
+    /// length of the data, continuations included
+    pub size: usize,
+    pub data: Cow<'a, [u8]>,
+}
+
+impl<'a> BIFFSTream<'a> {
+    /// The next record as it is in the stream, Continue records being records of their own.
+    /// A record cut short by the end of the stream ends the iteration, like the end itself.
+    fn next_record(&mut self) -> Option<(u16, &'a [u8])> {
+        let position = self.iterator_position.unwrap_or(0);
+        let h = self.data.get(position..position + 4)?;
+        let num = u16::from_le_bytes([h[0], h[1]]);
+        let size = u16::from_le_bytes([h[2], h[3]]);
+        let end = position + 4 + size as usize;
+        let data = match self.data.get(position + 4..end) {
+            Some(data) => data,
+            None => {
+                debug!(
+                    "BIFF record {} at {} is cut short, {} bytes",
+                    num, position, size
+                );
+                self.iterator_position = Some(self.data.len());
+                return None;
+            }
+        };
+        self.iterator_position = Some(end);
+        Some((num, data))
+    }
+
+    /// The type of the record after the current one, if there is one.
+    fn peek_num(&self) -> Option<u16> {
+        let position = self.iterator_position.unwrap_or(0);
+        let h = self.data.get(position..position + 2)?;
+        Some(u16::from_le_bytes([h[0], h[1]]))
+    }
+}
+
+/// The type of every record of a workbook stream and where its data is, Continue records
+/// being records of their own.
+#[cfg(feature = "decrypt")]
+pub(crate) fn record_ranges(stream: &[u8]) -> Vec<(u16, std::ops::Range<usize>)> {
+    let mut workbook = BIFFSTream::new(stream);
+    let mut ranges = vec![];
+    while let Some((num, data)) = workbook.next_record() {
+        let end = workbook.iterator_position.unwrap_or_default();
+        ranges.push((num, end - data.len()..end));
+    }
+    ranges
 }
 
 impl<'a> Iterator for BIFFSTream<'a> {
     type Item = BiffItem<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let position = self.iterator_position.unwrap_or(0);
-
-        let len = self.data.len();
-        let end_of_position_slice = position + 4;
-        if end_of_position_slice >= len {
-            return None;
+        let (num, data) = self.next_record()?;
+        let mut data = Cow::Borrowed(data);
+        while self.peek_num() == Some(CONTINUE) {
+            match self.next_record() {
+                Some((_, more)) => data.to_mut().extend_from_slice(more),
+                None => break,
+            }
         }
-        let h = &self.data[position..end_of_position_slice];
-        let num = u16::from_le_bytes([h[0], h[1]]);
-        let size = u16::from_le_bytes([h[2], h[3]]);
-        let end = end_of_position_slice + size as usize;
-        self.iterator_position = Some(end);
         Some(BiffItem {
             num,
-            size,
-            data: &self.data[end_of_position_slice..end],
+            size: data.len(),
+            data,
         })
     }
 }
@@ -429,42 +478,65 @@ pub(crate) struct ExcelEncryptionHandler<'a> {
     stream_name: String,
 }
 
+impl ExcelEncryptionHandler<'_> {
+    /// The workbook stream, cut at the memory the options allow, empty if its chain is broken.
+    fn workbook_stream(&self) -> Vec<u8> {
+        let len = self.ole_file.options.max_memory.unwrap_or(usize::MAX);
+        self.ole_file
+            .open_stream_range(&[self.stream_name.as_str()], 0, len)
+            .unwrap_or_default()
+    }
+
+    /// The FilePass record, `None` as well when the stream doesn't start with a BOF record.
+    fn file_pass(workbook_stream: &[u8]) -> Option<BiffItem<'_>> {
+        let mut workbook = BIFFSTream::new(workbook_stream);
+        workbook
+            .next()
+            .filter(|first| Some(&first.num) == NAME_TO_RECORD_NUM_MAP.get("BOF"))?;
+        workbook.skip_to(*NAME_TO_RECORD_NUM_MAP.get("FilePass").unwrap())
+    }
+}
+
 impl<'a> EncryptionHandler<'a> for ExcelEncryptionHandler<'a> {
     fn doc_type(&self) -> DocumentType {
         DocumentType::Excel
     }
 
     fn is_encrypted(&self) -> bool {
-        let workbook_stream = self
-            .ole_file
-            .open_stream(&[self.stream_name.as_str()])
-            .expect("unable to open workbook?");
-        let workbook = BIFFSTream::new(&workbook_stream);
-        let first = workbook.into_iter().next().expect("must have first item");
-        assert_eq!(&first.num, NAME_TO_RECORD_NUM_MAP.get("BOF").unwrap());
-        let mut workbook = BIFFSTream::new(&workbook_stream);
-        println!(
-            "aaa: {:?}",
-            workbook.skip_to(*NAME_TO_RECORD_NUM_MAP.get("FilePass").unwrap())
-        );
-        match workbook.skip_to(*NAME_TO_RECORD_NUM_MAP.get("FilePass").unwrap()) {
-            Some(item) => {
-                match &item.data[0..2] {
-                    [0x01, 0x00] => {
-                        //RC4
-                        true
-                    }
-                    [0x00, 0x00] => {
-                        // XOR Obfuscation unsupported
-                        false
-                    }
-                    _ => {
-                        //anything else is not encrypted
-                        false
-                    }
-                }
+        let workbook_stream = self.workbook_stream();
+        let file_pass = Self::file_pass(&workbook_stream);
+        debug!("FilePass record: {:?}", file_pass);
+        match file_pass.as_ref().map(|item| &item.data[..]) {
+            Some([0x01, 0x00, ..]) => {
+                //RC4
+                true
+            }
+            Some([0x00, 0x00, ..]) => {
+                // XOR Obfuscation unsupported
+                false
+            }
+            _ => {
+                //anything else, a FilePass too short to tell included, is not encrypted
+                false
             }
-            None => false,
+        }
+    }
+
+    fn scheme(&self) -> EncryptionScheme {
+        let workbook_stream = self.workbook_stream();
+        match Self::file_pass(&workbook_stream)
+            .as_ref()
+            .map(|item| &item.data[..])
+        {
+            Some([0x00, 0x00, ..]) => EncryptionScheme::XorObfuscation,
+            // RC4, the version tells plain RC4 and CryptoAPI apart
+            Some([0x01, 0x00, major_1, major_2, minor_1, minor_2, ..]) => {
+                EncryptionScheme::from_version(
+                    u16::from_le_bytes([*major_1, *major_2]),
+                    u16::from_le_bytes([*minor_1, *minor_2]),
+                )
+            }
+            _ => EncryptionScheme::Unknown,
         }
     }
 
@@ -475,3 +547,111 @@ impl<'a> EncryptionHandler<'a> for ExcelEncryptionHandler<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(num: u16, data: &[u8]) -> Vec<u8> {
+        let mut record = num.to_le_bytes().to_vec();
+        record.extend((data.len() as u16).to_le_bytes());
+        record.extend(data);
+        record
+    }
+
+    #[test]
+    fn test_continue_records() {
+        let sst = vec![0x53; 8224];
+        let stream = [
+            record(2057, &[0; 16]),
+            record(252, &sst),
+            record(CONTINUE, &[0x54; 100]),
+            record(CONTINUE, &[0x55; 10]),
+            record(10, &[]),
+            record(47, &[0x01, 0x00]),
+            record(CONTINUE, &[0x04, 0x00]),
+            record(10, &[0]),
+        ]
+        .concat();
+        let items = BIFFSTream::new(&stream).collect::<Vec<_>>();
+        assert_eq!(
+            items.iter().map(|item| item.num).collect::<Vec<_>>(),
+            [2057, 252, 10, 47, 10]
+        );
+        assert_eq!(items[1].size, 8334);
+        assert_eq!(items[1].data[8223..8225], [0x53, 0x54]);
+        assert_eq!(items[1].data[8333], 0x55);
+        assert!(matches!(items[0].data, Cow::Borrowed(_)));
+        assert_eq!(items[3].data[..], [0x01, 0x00, 0x04, 0x00]);
+        assert!(!BIFFSTream::new(&stream).has_record(CONTINUE));
+    }
+
+    #[test]
+    fn test_truncated_records() {
+        let stream = [
+            record(2057, &[0; 16]),
+            record(47, &[0x01, 0x00, 0x02, 0x00, 0x02, 0x00]),
+            record(252, &[0x53; 300]),
+            record(CONTINUE, &[0x54; 30]),
+            record(10, &[]),
+        ]
+        .concat();
+        let complete = BIFFSTream::new(&stream)
+            .map(|item| (item.num, item.size))
+            .collect::<Vec<_>>();
+        assert_eq!(complete, [(2057, 16), (47, 6), (252, 330), (10, 0)]);
+        for len in 0..stream.len() {
+            let items = BIFFSTream::new(&stream[..len])
+                .map(|item| (item.num, item.size))
+                .collect::<Vec<_>>();
+            // a cut record is dropped, a cut continuation only shortens its record
+            let kept = complete
+                .iter()
+                .zip(&items)
+                .take_while(|(complete, item)| complete == item)
+                .count();
+            assert!(kept + 1 >= items.len(), "{}", len);
+            assert!(items.len() <= complete.len(), "{}", len);
+            let file_pass = ExcelEncryptionHandler::file_pass(&stream[..len]);
+            assert_eq!(file_pass.is_some(), len >= 30, "{}", len);
+        }
+        assert!(ExcelEncryptionHandler::file_pass(&record(47, &[1, 0])).is_none());
+    }
+
+    #[test]
+    fn test_corrupt_workbooks() {
+        use crate::{test_support::CfbBuilder, EncryptionScheme, OleFile};
+
+        let data = std::fs::read("../data/encryption/encrypted/rc4cryptoapi_password.xls").unwrap();
+        let workbook = OleFile::parse_sync(&data[..])
+            .unwrap()
+            .open_stream(&["Workbook"])
+            .unwrap();
+        let parse = |workbook: &[u8]| {
+            let file = CfbBuilder::new().stream("Workbook", workbook).build();
+            OleFile::parse_sync(&file[..]).unwrap()
+        };
+        let ole = parse(&workbook);
+        assert!(ole.is_encrypted());
+        assert_eq!(ole.encryption_scheme, Some(EncryptionScheme::Rc4CryptoApi));
+
+        // xorshift, so every run corrupts the same bytes
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut random = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+        for _ in 0..200 {
+            let mut corrupt = workbook.clone();
+            for _ in 0..1 + random(8) {
+                let position = random(corrupt.len().min(512));
+                corrupt[position] = random(256) as u8;
+            }
+            corrupt.truncate(random(corrupt.len()));
+            let ole = parse(&corrupt);
+            assert!(ole.encryption_scheme.is_none() || ole.is_encrypted());
+        }
+    }
+}
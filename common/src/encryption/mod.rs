@@ -1,4 +1,5 @@
 use crate::{
+    directory::ObjectType,
     encryption::{
         excel::ExcelEncryptionHandler, ooxml::OpenOfficeXmlEncryptionHandler,
         powerpoint::PowerPointEncryptionHandler, word::WordEncryptionHandler,
@@ -6,11 +7,20 @@ use crate::{
     OleFile,
 };
 
+#[cfg(feature = "decrypt")]
+mod decrypt;
+#[cfg(feature = "decrypt")]
+mod ecma376;
 mod excel;
 mod ooxml;
 mod powerpoint;
+#[cfg(feature = "decrypt")]
+mod rc4;
 mod word;
 
+#[cfg(feature = "decrypt")]
+pub use decrypt::{decrypt, Decrypted, DecryptedFormat};
+
 lazy_static! {
     pub static ref WORD_DOC_STR: String = "WordDocument".to_lowercase();
     pub static ref POWER_POINT_STR: String = "PowerPoint Document".to_lowercase();
@@ -18,6 +28,7 @@ lazy_static! {
     pub static ref OOXML_DOC_STR: String = "EncryptionInfo".to_lowercase();
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum DocumentType {
     Word,
@@ -26,16 +37,90 @@ pub enum DocumentType {
     Ooxml,
 }
 
+/// How the content of an encrypted file is protected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionScheme {
+    /// The legacy XOR obfuscation of Word and Excel.
+    XorObfuscation,
+    /// Office binary RC4 encryption.
+    Rc4,
+    /// RC4 CryptoAPI encryption.
+    Rc4CryptoApi,
+    /// ECMA-376 standard encryption of an OOXML package.
+    Standard,
+    /// ECMA-376 agile encryption of an OOXML package.
+    Agile,
+    /// Encrypted, but with a scheme that wasn't recognized.
+    Unknown,
+}
+
+impl std::fmt::Display for EncryptionScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            EncryptionScheme::XorObfuscation => "XOR obfuscation",
+            EncryptionScheme::Rc4 => "RC4",
+            EncryptionScheme::Rc4CryptoApi => "RC4 CryptoAPI",
+            EncryptionScheme::Standard => "ECMA-376 standard",
+            EncryptionScheme::Agile => "ECMA-376 agile",
+            EncryptionScheme::Unknown => "unknown scheme",
+        })
+    }
+}
+
+impl EncryptionScheme {
+    /// The scheme named by an `EncryptionVersionInfo` (major, minor) pair, as found at the start
+    /// of the Word table stream, the Excel FilePass record and the OOXML EncryptionInfo stream.
+    pub(crate) fn from_version(major: u16, minor: u16) -> Self {
+        match (major, minor) {
+            (1, 1) => EncryptionScheme::Rc4,
+            (2..=4, 2) => EncryptionScheme::Rc4CryptoApi,
+            _ => EncryptionScheme::Unknown,
+        }
+    }
+}
+
 pub trait EncryptionHandler<'a> {
+    #[allow(dead_code)]
     fn doc_type(&self) -> DocumentType;
     fn is_encrypted(&self) -> bool;
+    /// The scheme the document is encrypted with, only called when it is encrypted.
+    fn scheme(&self) -> EncryptionScheme {
+        EncryptionScheme::Unknown
+    }
     fn new(ole_file: &'a OleFile, stream_name: String) -> Self
     where
         Self: Sized;
 }
 
 pub fn is_encrypted(ole_file: &OleFile) -> bool {
-    let streams = ole_file.list_streams();
+    handler(ole_file)
+        .map(|handler| handler.is_encrypted())
+        .unwrap_or(false)
+}
+
+pub fn encryption_scheme(ole_file: &OleFile) -> Option<EncryptionScheme> {
+    handler(ole_file)
+        .filter(|handler| handler.is_encrypted())
+        .map(|handler| handler.scheme())
+}
+
+fn handler(ole_file: &OleFile) -> Option<Box<dyn EncryptionHandler<'_> + '_>> {
+    let streams = if ole_file.options.lazy_directory {
+        // listing the streams would decode the whole directory, so the names are looked up
+        [
+            "WordDocument",
+            "PowerPoint Document",
+            "Workbook",
+            "EncryptionInfo",
+        ]
+        .into_iter()
+        .filter_map(|name| ole_file.descend_tree(ole_file.root(), name))
+        .filter(|entry| entry.object_type == ObjectType::Stream)
+        .map(|entry| entry.name.clone())
+        .collect()
+    } else {
+        ole_file.list_streams()
+    };
     let mut document_type = None;
     for stream in streams.into_iter() {
         match stream.to_lowercase() {
@@ -67,9 +152,5 @@ pub fn is_encrypted(ole_file: &OleFile) -> bool {
         }
     }
 
-    if document_type.is_none() {
-        false
-    } else {
-        document_type.as_ref().unwrap().is_encrypted()
-    }
+    document_type
 }
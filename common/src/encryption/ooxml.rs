@@ -1,11 +1,11 @@
 use crate::{
-    encryption::{DocumentType, EncryptionHandler},
+    encryption::{DocumentType, EncryptionHandler, EncryptionScheme},
     OleFile,
 };
 
 pub(crate) struct OpenOfficeXmlEncryptionHandler<'a> {
-    _ole_file: &'a OleFile,
-    _stream_name: String,
+    ole_file: &'a OleFile,
+    stream_name: String,
 }
 
 impl<'a> EncryptionHandler<'a> for OpenOfficeXmlEncryptionHandler<'a> {
@@ -14,13 +14,33 @@ impl<'a> EncryptionHandler<'a> for OpenOfficeXmlEncryptionHandler<'a> {
     }
 
     fn is_encrypted(&self) -> bool {
-        false
+        // an OOXML package is only ever wrapped in a compound file to encrypt it
+        true
+    }
+
+    fn scheme(&self) -> EncryptionScheme {
+        match self
+            .ole_file
+            .open_stream_range(&[self.stream_name.as_str()], 0, 4)
+        {
+            Ok(info) if info.len() >= 4 => {
+                match (
+                    u16::from_le_bytes([info[0], info[1]]),
+                    u16::from_le_bytes([info[2], info[3]]),
+                ) {
+                    (4, 4) => EncryptionScheme::Agile,
+                    (2..=4, 2) => EncryptionScheme::Standard,
+                    _ => EncryptionScheme::Unknown,
+                }
+            }
+            _ => EncryptionScheme::Unknown,
+        }
     }
 
     fn new(ole_file: &'a OleFile, stream_name: String) -> Self {
         Self {
-            _ole_file: ole_file,
-            _stream_name: stream_name,
+            ole_file,
+            stream_name,
         }
     }
 }
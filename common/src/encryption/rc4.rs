@@ -0,0 +1,162 @@
+//! RC4 CryptoAPI encryption of the binary Word and Excel formats (MS-OFFCRYPTO 2.3.5).
+use crate::{
+    error::Error,
+    secret::{wipe, SecretBuffer},
+    Result,
+};
+use sha1::{Digest, Sha1};
+
+/// RC4, as the CryptoAPI uses it: a fresh cipher per block of the stream.
+struct Rc4 {
+    state: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Rc4 {
+    fn new(key: &[u8]) -> Self {
+        let mut state = [0u8; 256];
+        for (index, byte) in state.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        let mut j = 0u8;
+        for i in 0..256 {
+            j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+            state.swap(i, j as usize);
+        }
+        Self { state, i: 0, j: 0 }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            self.i = self.i.wrapping_add(1);
+            self.j = self.j.wrapping_add(self.state[self.i as usize]);
+            self.state.swap(self.i as usize, self.j as usize);
+            let index = self.state[self.i as usize].wrapping_add(self.state[self.j as usize]);
+            *byte ^= self.state[index as usize];
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Rc4 {
+    fn drop(&mut self) {
+        wipe(&mut self.state);
+        self.i = 0;
+        self.j = 0;
+    }
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| Error::InvalidEncryptionInfo("encryption header cut short".to_string()))
+}
+
+/// The key of an RC4 CryptoAPI encrypted document, derived from its password.
+pub(crate) struct CryptoApiKey {
+    /// SHA-1 of the salt and the password, the base of every block key
+    base: [u8; 20],
+    key_bytes: usize,
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for CryptoApiKey {
+    fn drop(&mut self) {
+        wipe(&mut self.base);
+    }
+}
+
+impl CryptoApiKey {
+    /// Derive the key from the `RC4CryptoAPIEncryptionHeader` in `info` (from its version on)
+    /// and check the password against its verifier.
+    pub(crate) fn new(info: &[u8], password: &[u8]) -> Result<Self> {
+        let header_size = u32_at(info, 8)? as usize;
+        let key_bits = match u32_at(info, 12 + 16)? {
+            // a key size of 0 stands for 40 bits
+            0 => 40,
+            bits @ 40..=128 if bits % 8 == 0 => bits,
+            bits => {
+                return Err(Error::InvalidEncryptionInfo(format!(
+                    "RC4 key of {} bits",
+                    bits
+                )))
+            }
+        };
+        let verifier = 12 + header_size;
+        let cut_short = || Error::InvalidEncryptionInfo("verifier cut short".to_string());
+        let salt = info
+            .get(verifier + 4..verifier + 20)
+            .ok_or_else(cut_short)?;
+        let mut hasher = Sha1::new();
+        hasher.update(salt);
+        hasher.update(password);
+        let mut hash = hasher.finalize();
+        let mut key = Self {
+            base: [0; 20],
+            key_bytes: key_bits as usize / 8,
+        };
+        key.base.copy_from_slice(&hash);
+        wipe(&mut hash);
+
+        // the encrypted verifier, then the size of its hash and the encrypted SHA-1 hash
+        let mut check = SecretBuffer::with_capacity(36);
+        check.extend_from_slice(
+            info.get(verifier + 20..verifier + 36)
+                .ok_or_else(cut_short)?,
+        );
+        check.extend_from_slice(
+            info.get(verifier + 40..verifier + 60)
+                .ok_or_else(cut_short)?,
+        );
+        // the verifier and its hash are one run of the block 0 cipher
+        key.cipher(0).apply(&mut check);
+        if Sha1::digest(&check[..16])[..] != check[16..] {
+            return Err(Error::WrongPassword);
+        }
+        Ok(key)
+    }
+
+    fn cipher(&self, block: u32) -> Rc4 {
+        let mut hasher = Sha1::new();
+        hasher.update(self.base);
+        hasher.update(block.to_le_bytes());
+        let mut hash = hasher.finalize();
+        // 40 bit keys are padded with zeros to 128 bits
+        let mut key = [0u8; 16];
+        key[..self.key_bytes].copy_from_slice(&hash[..self.key_bytes]);
+        let len = if self.key_bytes == 5 {
+            16
+        } else {
+            self.key_bytes
+        };
+        let cipher = Rc4::new(&key[..len]);
+        wipe(&mut hash);
+        wipe(&mut key);
+        cipher
+    }
+
+    /// Decrypt `stream` in place, as a whole encrypted in blocks of `block_size` bytes.
+    /// Only the bytes within `ranges`, given in stream order, are decrypted; a range past the
+    /// end of the stream stops there.
+    pub(crate) fn decrypt(
+        &self,
+        stream: &mut [u8],
+        block_size: usize,
+        ranges: impl IntoIterator<Item = std::ops::Range<usize>>,
+    ) {
+        let mut keystream = SecretBuffer::from(vec![0u8; block_size]);
+        let mut current = None;
+        for range in ranges {
+            for offset in range.start..range.end.min(stream.len()) {
+                let block = offset / block_size;
+                if current != Some(block) {
+                    keystream.fill(0);
+                    self.cipher(block as u32).apply(&mut keystream);
+                    current = Some(block);
+                }
+                stream[offset] ^= keystream[offset % block_size];
+            }
+        }
+    }
+}
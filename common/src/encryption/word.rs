@@ -1,7 +1,8 @@
 use crate::{
-    encryption::{DocumentType, EncryptionHandler},
+    encryption::{DocumentType, EncryptionHandler, EncryptionScheme},
     OleFile,
 };
+use log::trace;
 use packed_struct::prelude::*;
 
 #[derive(PackedStruct, Debug, PartialEq)]
@@ -78,22 +79,49 @@ pub(crate) struct WordEncryptionHandler<'a> {
     stream_name: String,
 }
 
+impl WordEncryptionHandler<'_> {
+    /// The start of the FIB, `None` when the stream is too short to hold it.
+    fn word_header(&self) -> Option<PackedWordHeader> {
+        let stream = self
+            .ole_file
+            .open_stream_range(&[self.stream_name.as_str()], 0, 32)
+            .ok()?;
+        PackedWordHeader::unpack_from_slice(stream.get(..32)?).ok()
+    }
+}
+
 impl<'a> EncryptionHandler<'a> for WordEncryptionHandler<'a> {
     fn doc_type(&self) -> DocumentType {
         DocumentType::Word
     }
 
     fn is_encrypted(&self) -> bool {
-        let stream = self
-            .ole_file
-            .open_stream(&[self.stream_name.as_str()])
-            .expect("stream has to exist");
-
-        let bytes: Vec<u8> = stream.iter().take(32).copied().collect();
-        let word_header = PackedWordHeader::unpack_from_slice(&bytes).expect("unable to unpack?");
+        let word_header = self.word_header();
+        trace!("{word_header:#?}");
+        word_header.is_some_and(|header| header.first_flags.f_encrypted)
+    }
 
-        // println!("{word_header:#?}");
-        word_header.first_flags.f_encrypted
+    fn scheme(&self) -> EncryptionScheme {
+        let word_header = match self.word_header() {
+            Some(header) => header,
+            None => return EncryptionScheme::Unknown,
+        };
+        if word_header.first_flags.f_obfuscation {
+            return EncryptionScheme::XorObfuscation;
+        }
+        // the encryption header is at the start of the table stream the FIB points to
+        let table_stream = if word_header.first_flags.f_which_table_stream {
+            "1Table"
+        } else {
+            "0Table"
+        };
+        match self.ole_file.open_stream_range(&[table_stream], 0, 4) {
+            Ok(table) if table.len() >= 4 => EncryptionScheme::from_version(
+                u16::from_le_bytes([table[0], table[1]]),
+                u16::from_le_bytes([table[2], table[3]]),
+            ),
+            _ => EncryptionScheme::Unknown,
+        }
     }
 
     fn new(ole_file: &'a OleFile, stream_name: String) -> Self {
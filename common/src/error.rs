@@ -5,14 +5,40 @@ pub enum Error {
     OleInvalidHeader(HeaderErrorType),
     #[error("CurrentlyUnimplemented => {0}")]
     CurrentlyUnimplemented(String),
-    #[error("InvalidDirectoryEntry => {0}")]
-    OleInvalidDirectoryEntry(&'static str, String),
+    /// a directory entry breaks the rules, `field` names the part of the entry that does
+    #[error("InvalidDirectoryEntry => {field}: {message}{location}")]
+    OleInvalidDirectoryEntry {
+        field: &'static str,
+        message: String,
+        location: Location,
+    },
     #[error("UnknownOrUnallocatedDirectoryEntry")]
     OleUnknownOrUnallocatedDirectoryEntry,
     #[error("DirectoryEntryNotFound")]
     OleDirectoryEntryNotFound,
-    #[error("UnexpectedEof => {0}")]
-    OleUnexpectedEof(String),
+    /// a stream was asked for where there is a storage, with the names of its children
+    #[error("IsAStorage => children {children:?}")]
+    IsAStorage { children: Vec<String> },
+    #[error("UnexpectedEof => {message}{location}")]
+    OleUnexpectedEof { message: String, location: Location },
+    #[error("InvalidChain => {message}{location}")]
+    OleInvalidChain { message: String, location: Location },
+    #[error("SectorOutOfRange => {message}{location}")]
+    SectorOutOfRange { message: String, location: Location },
+    #[error("InvalidVbaProject => {0}")]
+    InvalidVbaProject(String),
+    #[error("ResourceLimit => {0}")]
+    ResourceLimit(String),
+    #[error("InvalidPolicy => {0}")]
+    InvalidPolicy(String),
+    #[error("InvalidManifest => {0}")]
+    InvalidManifest(String),
+    /// the encryption header of an encrypted file can't be used to decrypt it
+    #[error("InvalidEncryptionInfo => {0}")]
+    InvalidEncryptionInfo(String),
+    /// the password given to decrypt a file is not its password
+    #[error("WrongPassword")]
+    WrongPassword,
 
     // Std Errors
     #[error("StdIo => {0}")]
@@ -25,6 +51,109 @@ pub enum Error {
     GenericError(&'static str),
 }
 
+impl Error {
+    pub(crate) fn invalid_entry(field: &'static str, message: String) -> Self {
+        Error::OleInvalidDirectoryEntry {
+            field,
+            message,
+            location: Location::default(),
+        }
+    }
+
+    pub(crate) fn unexpected_eof(message: String) -> Self {
+        Error::OleUnexpectedEof {
+            message,
+            location: Location::default(),
+        }
+    }
+
+    pub(crate) fn invalid_chain(message: String) -> Self {
+        Error::OleInvalidChain {
+            message,
+            location: Location::default(),
+        }
+    }
+
+    pub(crate) fn sector_out_of_range(message: String) -> Self {
+        Error::SectorOutOfRange {
+            message,
+            location: Location::default(),
+        }
+    }
+
+    /// Where in the file the error was found, for the errors about a part of the file.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::OleInvalidDirectoryEntry { location, .. }
+            | Error::OleUnexpectedEof { location, .. }
+            | Error::OleInvalidChain { location, .. }
+            | Error::SectorOutOfRange { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// The error with what `location` knows added where the error doesn't know better, as
+    /// callers further up learn which entry or sector they were working on.
+    pub(crate) fn at(mut self, location: Location) -> Self {
+        if let Error::OleInvalidDirectoryEntry {
+            location: known, ..
+        }
+        | Error::OleUnexpectedEof {
+            location: known, ..
+        }
+        | Error::OleInvalidChain {
+            location: known, ..
+        }
+        | Error::SectorOutOfRange {
+            location: known, ..
+        } = &mut self
+        {
+            known.entry = known.entry.or(location.entry);
+            known.name = known.name.take().or(location.name);
+            known.sector = known.sector.or(location.sector);
+            known.offset = known.offset.or(location.offset);
+        }
+        self
+    }
+}
+
+/// Where in the file an error was found, as far as the parser knew at the time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    /// stream ID of the directory entry involved
+    pub entry: Option<usize>,
+    /// name of that entry, as well as it could be decoded
+    pub name: Option<String>,
+    /// the sector involved, a mini sector for errors about the mini stream
+    pub sector: Option<u32>,
+    /// offset in the file of the bytes at fault
+    pub offset: Option<u64>,
+}
+
+impl std::fmt::Display for Location {
+    /// Nothing for an unknown location, else ` (entry 3 "Data", sector 7, offset 0x1000)`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        match (self.entry, &self.name) {
+            (Some(entry), Some(name)) => parts.push(format!("entry {} {:?}", entry, name)),
+            (Some(entry), None) => parts.push(format!("entry {}", entry)),
+            (None, Some(name)) => parts.push(format!("entry {:?}", name)),
+            (None, None) => {}
+        }
+        if let Some(sector) = self.sector {
+            parts.push(format!("sector {}", sector));
+        }
+        if let Some(offset) = self.offset {
+            parts.push(format!("offset {:#x}", offset));
+        }
+        if parts.is_empty() {
+            Ok(())
+        } else {
+            write!(f, " ({})", parts.join(", "))
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum HeaderErrorType {
     #[error("the magic number was expected but not found, found {0:?} instead")]
@@ -0,0 +1,301 @@
+//! Structures of Excel 97-2003 workbooks that live outside the macros.
+use crate::{analysis, directory::ObjectType, error::Error, OleFile, Result};
+
+/// DConn: a data connection of the workbook (MS-XLS 2.4.86)
+const DCONN: u16 = 2166;
+/// OleDbConn: the connection string of an OLE DB query table (MS-XLS 2.4.187)
+const OLE_DB_CONN: u16 = 2058;
+/// Qsi: a query table (MS-XLS 2.4.208)
+const QSI: u16 = 429;
+/// DbQuery: the start of the connection of a pivot cache (MS-XLS 2.4.83)
+const DB_QUERY: u16 = 220;
+/// SXString: a segment of the connection string or query that follows DbQuery
+const SX_STRING: u16 = 205;
+/// records that carry on the data of the record before them
+const CONTINUE: u16 = 60;
+const CONTINUE_FRT: u16 = 2066;
+/// records that end the pivot cache
+const EOF: u16 = 10;
+
+/// the storage holding the pivot caches of a workbook
+const PIVOT_CACHE_STORAGE: &str = "_SX_DB_CUR";
+/// printable characters a string is taken from the records with, at least
+const MIN_STRING_LEN: usize = 4;
+
+/// A connection of a workbook to external data: a query table, a data connection or the
+/// source of a pivot cache.
+///
+/// Refreshing them fetches from wherever they point, which makes them a way to pull content or
+/// leak data without macros.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExternalConnection {
+    /// the stream the records are in, storage names first
+    pub stream: Vec<String>,
+    /// the record defining the connection: DConn, OleDbConn, Qsi or DbQuery
+    pub record: &'static str,
+    /// the text of the record and its continuations: names, connection strings, queries and
+    /// URLs
+    pub strings: Vec<String>,
+}
+
+impl ExternalConnection {
+    /// The URLs and UNC paths among the strings.
+    pub fn urls(&self) -> Vec<&str> {
+        self.strings
+            .iter()
+            .flat_map(|string| {
+                string.split(|c: char| {
+                    c.is_whitespace() || matches!(c, '"' | '\'' | ';' | '=' | '<' | '>')
+                })
+            })
+            .filter(|word| word.contains("://") || word.starts_with("\\\\"))
+            .collect()
+    }
+}
+
+/// Find the external connections of a workbook, in its `Workbook` stream and its pivot caches.
+///
+/// A file that is not a workbook or is encrypted gives an empty list.
+pub(crate) fn external_connections(ole: &OleFile) -> Result<Vec<ExternalConnection>> {
+    if ole.encrypted {
+        return Ok(vec![]);
+    }
+    let mut connections = vec![];
+    for (path, entry) in analysis::entries_with_paths(ole) {
+        if entry.object_type != ObjectType::Stream {
+            continue;
+        }
+        let in_pivot_cache = path.len() == 2 && path[0].eq_ignore_ascii_case(PIVOT_CACHE_STORAGE);
+        let is_workbook = path.len() == 1
+            && (path[0].eq_ignore_ascii_case("Workbook") || path[0].eq_ignore_ascii_case("Book"));
+        if !in_pivot_cache && !is_workbook {
+            continue;
+        }
+        let data = match ole.read_entry_data(entry) {
+            Ok(data) => data,
+            Err(Error::OleUnexpectedEof { .. })
+            | Err(Error::OleInvalidChain { .. })
+            | Err(Error::SectorOutOfRange { .. }) => continue,
+            Err(err) => return Err(err),
+        };
+        let found = if in_pivot_cache {
+            pivot_cache_connection(&data)
+                .into_iter()
+                .collect::<Vec<_>>()
+        } else {
+            workbook_connections(&data)
+        };
+        connections.extend(
+            found
+                .into_iter()
+                .map(|(record, strings)| ExternalConnection {
+                    stream: path.clone(),
+                    record,
+                    strings,
+                }),
+        );
+    }
+    Ok(connections)
+}
+
+/// The DConn, OleDbConn and Qsi records of a workbook stream, with their continuations.
+fn workbook_connections(data: &[u8]) -> Vec<(&'static str, Vec<String>)> {
+    let mut connections: Vec<(&'static str, Vec<String>)> = vec![];
+    let mut continued = false;
+    for (num, body) in records(data) {
+        let record = match num {
+            DCONN => "DConn",
+            OLE_DB_CONN => "OleDbConn",
+            QSI => "Qsi",
+            CONTINUE | CONTINUE_FRT if continued => {
+                // ContinueFrt repeats the record type before the data
+                let body = if num == CONTINUE_FRT {
+                    body.get(4..).unwrap_or_default()
+                } else {
+                    body
+                };
+                // each part on its own, so a string isn't run into the length of the next
+                if let Some((_, text)) = connections.last_mut() {
+                    text.extend(strings(body));
+                }
+                continue;
+            }
+            _ => {
+                continued = false;
+                continue;
+            }
+        };
+        continued = true;
+        connections.push((record, strings(body)));
+    }
+    connections
+}
+
+/// The connection string and query of a pivot cache stream: a DbQuery record and the
+/// SXString records following it.
+fn pivot_cache_connection(data: &[u8]) -> Option<(&'static str, Vec<String>)> {
+    let mut records = records(data).skip_while(|(num, _)| *num != DB_QUERY);
+    records.next()?;
+    let mut text = vec![];
+    for (num, body) in records {
+        match num {
+            SX_STRING => text.extend(strings(body)),
+            EOF => break,
+            _ => {}
+        }
+    }
+    Some(("DbQuery", text))
+}
+
+/// The BIFF records of `data`, up to the first one that runs past its end.
+pub(crate) fn records(data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut position = 0;
+    std::iter::from_fn(move || {
+        let header = data.get(position..position + 4)?;
+        let num = u16::from_le_bytes([header[0], header[1]]);
+        let size = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let body = data.get(position + 4..position + 4 + size)?;
+        position += 4 + size;
+        Some((num, body))
+    })
+}
+
+/// The runs of printable characters in `data`, as UTF-16 or single bytes, which is how
+/// XLUnicodeString stores text depending on its `fHighByte`.
+fn strings(data: &[u8]) -> Vec<String> {
+    let printable = |byte: u8| (0x20..0x7F).contains(&byte);
+    let mut found = vec![];
+    let mut position = 0;
+    while position < data.len() {
+        let wide = data[position..]
+            .chunks_exact(2)
+            .take_while(|pair| printable(pair[0]) && pair[1] == 0)
+            .count();
+        let narrow = data[position..]
+            .iter()
+            .take_while(|byte| printable(**byte))
+            .count();
+        let (len, text) = if wide >= MIN_STRING_LEN {
+            let text = data[position..position + wide * 2]
+                .iter()
+                .step_by(2)
+                .map(|byte| *byte as char)
+                .collect();
+            (wide * 2, text)
+        } else if narrow >= MIN_STRING_LEN {
+            let text = data[position..position + narrow]
+                .iter()
+                .map(|byte| *byte as char)
+                .collect();
+            (narrow, text)
+        } else {
+            position += 1;
+            continue;
+        };
+        found.push(text);
+        position += len;
+    }
+    found
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    fn record(num: u16, body: &[u8]) -> Vec<u8> {
+        let mut record = num.to_le_bytes().to_vec();
+        record.extend((body.len() as u16).to_le_bytes());
+        record.extend(body);
+        record
+    }
+
+    /// An XLUnicodeString, compressed or not.
+    fn xl_string(text: &str, wide: bool) -> Vec<u8> {
+        let mut string = (text.len() as u16).to_le_bytes().to_vec();
+        string.push(wide as u8);
+        if wide {
+            string.extend(text.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        } else {
+            string.extend(text.as_bytes());
+        }
+        string
+    }
+
+    #[test]
+    fn test_external_connections() {
+        let connection = "Provider=MSOLAP;Data Source=http://198.51.100.7/olap/msmdpump.dll";
+        let mut dconn = vec![0x76, 0x08, 0, 0, 3, 0, 0, 0];
+        dconn.extend(xl_string("Sales query", true));
+        let mut workbook = record(0x0809, &[0; 16]);
+        workbook.extend(record(DCONN, &dconn));
+        workbook.extend(record(
+            CONTINUE_FRT,
+            &[&[0x12, 0x08, 0, 0][..], &xl_string(connection, false)].concat(),
+        ));
+        workbook.extend(record(0x00FC, b"not a continuation"));
+        workbook.extend(record(
+            QSI,
+            &[&[0; 10][..], &xl_string("ExternalData_1", false)].concat(),
+        ));
+        workbook.extend(record(EOF, &[]));
+
+        let mut cache = record(0x00C6, &[0; 4]);
+        cache.extend(record(DB_QUERY, &[0; 6]));
+        cache.extend(record(
+            SX_STRING,
+            &xl_string("DSN=Excel Files;DBQ=\\\\files\\share\\q.xls", true),
+        ));
+        cache.extend(record(
+            SX_STRING,
+            &xl_string("SELECT * FROM `Sheet1$`", false),
+        ));
+        cache.extend(record(EOF, &[]));
+
+        let data = CfbBuilder::new()
+            .stream("Workbook", &workbook)
+            .stream("_SX_DB_CUR/0001", &cache)
+            .stream("Other", &record(DCONN, b"elsewhere"))
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let connections = ole.external_connections().unwrap();
+        assert_eq!(
+            connections,
+            vec![
+                ExternalConnection {
+                    stream: vec!["Workbook".to_string()],
+                    record: "DConn",
+                    strings: vec!["Sales query".to_string(), connection.to_string()],
+                },
+                ExternalConnection {
+                    stream: vec!["Workbook".to_string()],
+                    record: "Qsi",
+                    strings: vec!["ExternalData_1".to_string()],
+                },
+                ExternalConnection {
+                    stream: vec!["_SX_DB_CUR".to_string(), "0001".to_string()],
+                    record: "DbQuery",
+                    strings: vec![
+                        "DSN=Excel Files;DBQ=\\\\files\\share\\q.xls".to_string(),
+                        "SELECT * FROM `Sheet1$`".to_string()
+                    ],
+                },
+            ]
+        );
+        assert_eq!(
+            connections[0].urls(),
+            vec!["http://198.51.100.7/olap/msmdpump.dll"]
+        );
+        assert_eq!(connections[2].urls(), vec!["\\\\files\\share\\q.xls"]);
+    }
+
+    #[test]
+    fn test_truncated_workbook() {
+        let mut workbook = record(QSI, &xl_string("ExternalData_1", false));
+        workbook.extend(&record(DCONN, &[0x41; 100])[..50]);
+        assert_eq!(
+            workbook_connections(&workbook),
+            vec![("Qsi", vec!["ExternalData_1".to_string()])]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! The values in the FAT and the mini FAT.
+//!
+//! Every entry of the FAT holds, for its sector, the next sector of the chain the sector belongs
+//! to, or one of a few special values above [`constants::MAX_REG_STREAM_ID_VALUE`] marking the
+//! end of a chain, a free sector or one holding the FAT or the DIFAT. The mini FAT does the same
+//! for the mini sectors, where only the end of a chain and free mini sectors are special.
+use crate::constants;
+
+/// What an entry of the FAT or the mini FAT says about its sector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FatEntry {
+    /// the sector is part of a chain that continues with the sector given
+    Next(u32),
+    /// the sector is not allocated, `FREESECT`
+    Free,
+    /// the sector is the last of its chain, `ENDOFCHAIN`
+    EndOfChain,
+    /// the sector holds part of the FAT, `FATSECT`
+    FatSector,
+    /// the sector holds part of the DIFAT, `DIFSECT`
+    DifatSector,
+    /// 0xFFFFFFFB, which the spec reserves and no writer uses
+    Reserved(u32),
+}
+
+impl From<u32> for FatEntry {
+    fn from(value: u32) -> Self {
+        match value {
+            constants::UNALLOCATED_SECTOR => FatEntry::Free,
+            constants::CHAIN_END => FatEntry::EndOfChain,
+            constants::FAT_SECTOR => FatEntry::FatSector,
+            constants::DIFAT_SECTOR => FatEntry::DifatSector,
+            next if next <= constants::MAX_REG_STREAM_ID_VALUE => FatEntry::Next(next),
+            reserved => FatEntry::Reserved(reserved),
+        }
+    }
+}
+
+impl From<FatEntry> for u32 {
+    fn from(entry: FatEntry) -> Self {
+        match entry {
+            FatEntry::Next(value) | FatEntry::Reserved(value) => value,
+            FatEntry::Free => constants::UNALLOCATED_SECTOR,
+            FatEntry::EndOfChain => constants::CHAIN_END,
+            FatEntry::FatSector => constants::FAT_SECTOR,
+            FatEntry::DifatSector => constants::DIFAT_SECTOR,
+        }
+    }
+}
+
+impl FatEntry {
+    /// The next sector of the chain, `None` for the special values.
+    pub fn next(self) -> Option<u32> {
+        match self {
+            FatEntry::Next(next) => Some(next),
+            _ => None,
+        }
+    }
+
+    /// Whether the sector is allocated to anything, a chain or the FAT and DIFAT themselves.
+    pub fn is_allocated(self) -> bool {
+        self != FatEntry::Free
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::{test_support::CfbBuilder, OleFile};
+
+    #[test]
+    fn test_fat_entries() {
+        for value in [
+            0, 0xFFFFFFFA, 0xFFFFFFFB, 0xFFFFFFFC, 0xFFFFFFFD, 0xFFFFFFFE, 0xFFFFFFFF,
+        ] {
+            assert_eq!(u32::from(FatEntry::from(value)), value);
+        }
+        assert_eq!(FatEntry::from(0xFFFFFFFA), FatEntry::Next(0xFFFFFFFA));
+        assert_eq!(FatEntry::from(0xFFFFFFFB), FatEntry::Reserved(0xFFFFFFFB));
+
+        let data = CfbBuilder::new()
+            .stream("Small", &[1; 100])
+            .stream("Large", &[2; 5000])
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        assert_eq!(ole.fat().len(), ole.sector_allocation_table.len());
+        let fat_sectors = ole
+            .fat_entries()
+            .filter(|entry| *entry == FatEntry::FatSector)
+            .count();
+        assert_eq!(
+            fat_sectors,
+            ole.header().sector_allocation_table_len as usize
+        );
+        // the 100 bytes of Small take two mini sectors, chained
+        let small = ole.find_stream_path("Small").unwrap();
+        let start = small.starting_sector_location.unwrap();
+        assert_eq!(
+            ole.mini_fat_entries()
+                .skip(start as usize)
+                .take(2)
+                .collect::<Vec<_>>(),
+            [FatEntry::Next(start + 1), FatEntry::EndOfChain]
+        );
+    }
+}
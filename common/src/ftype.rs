@@ -1,9 +1,18 @@
-use crate::DirectoryEntry;
+use crate::{error::Error, excel, DirectoryEntry, OleFile, Result};
 use std::collections::HashMap;
-use std::fmt::{Debug, Display, Formatter};
+use std::fmt::Debug;
 
 type RootClassId = &'static str;
 
+/// bit of the FIB flags at 0x0A set in templates, fDot (MS-DOC 2.5.2)
+const F_DOT: u16 = 0x0001;
+/// Template: the workbook is a template (MS-XLS 2.4.323)
+const TEMPLATE: u16 = 0x0060;
+/// ADDIN: the workbook is an add-in
+const ADDIN: u16 = 0x0087;
+/// the record ending the workbook globals, which hold both
+const EOF: u16 = 0x000A;
+
 lazy_static! {
     static ref OLE_FILE_TYPE_MAP: HashMap<RootClassId, OleFileType> = {
         HashMap::from([
@@ -40,3 +49,113 @@ pub fn file_type(root: &DirectoryEntry) -> OleFileType {
         })
         .unwrap_or(OleFileType::Generic)
 }
+
+/// What a document is meant for. Office opens templates and add-ins differently from plain
+/// documents: templates are the base of new documents, add-ins load with the application, and
+/// both carry their macros along.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DocumentRole {
+    Document,
+    /// a Word template (DOT) or an Excel template (XLT)
+    Template,
+    /// an Excel add-in (XLA)
+    AddIn,
+}
+
+/// Whether a Word document or an Excel workbook is a template or an add-in, from the FIB flags
+/// and the records of the workbook globals. Other files are plain documents.
+pub(crate) fn document_role(ole: &OleFile) -> Result<DocumentRole> {
+    match ole.file_type {
+        // the FIB stays readable in encrypted documents
+        OleFileType::Word97 | OleFileType::Word6 => {
+            match ole.open_stream_range(&["WordDocument"], 0x0A, 2) {
+                Ok(flags) if flags.len() == 2 => {
+                    if u16::from_le_bytes([flags[0], flags[1]]) & F_DOT != 0 {
+                        return Ok(DocumentRole::Template);
+                    }
+                }
+                Ok(_) | Err(Error::OleDirectoryEntryNotFound | Error::IsAStorage { .. }) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        // so do the record headers of encrypted workbooks
+        OleFileType::Excel97 | OleFileType::Excel5 => {
+            let workbook = match ole.open_stream(&["Workbook"]) {
+                Err(Error::OleDirectoryEntryNotFound | Error::IsAStorage { .. }) => {
+                    ole.open_stream(&["Book"])
+                }
+                workbook => workbook,
+            };
+            let workbook = match workbook {
+                Ok(workbook) => workbook,
+                Err(Error::OleDirectoryEntryNotFound | Error::IsAStorage { .. }) => {
+                    return Ok(DocumentRole::Document)
+                }
+                Err(err) => return Err(err),
+            };
+            let mut role = DocumentRole::Document;
+            for (num, _) in excel::records(&workbook) {
+                match num {
+                    ADDIN => return Ok(DocumentRole::AddIn),
+                    TEMPLATE => role = DocumentRole::Template,
+                    EOF => break,
+                    _ => {}
+                }
+            }
+            return Ok(role);
+        }
+        _ => {}
+    }
+    Ok(DocumentRole::Document)
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    /// A file whose root has the class id of `file_type`, with one stream.
+    fn file(file_type: OleFileType, stream: &str, data: &[u8]) -> OleFile {
+        let data = CfbBuilder::new().stream(stream, data).build();
+        let mut ole = OleFile::parse_sync(&data[..]).unwrap();
+        ole.file_type = file_type;
+        ole
+    }
+
+    fn record(num: u16, body: &[u8]) -> Vec<u8> {
+        let mut record = num.to_le_bytes().to_vec();
+        record.extend((body.len() as u16).to_le_bytes());
+        record.extend(body);
+        record
+    }
+
+    #[test]
+    fn test_document_role() {
+        let mut fib = vec![0u8; 32];
+        fib[0..2].copy_from_slice(&0xA5ECu16.to_le_bytes());
+        let document = file(OleFileType::Word97, "WordDocument", &fib);
+        assert_eq!(document.document_role().unwrap(), DocumentRole::Document);
+        fib[0x0A] |= F_DOT as u8;
+        let template = file(OleFileType::Word97, "WordDocument", &fib);
+        assert_eq!(template.document_role().unwrap(), DocumentRole::Template);
+        // the flag means nothing to other files
+        let other = file(OleFileType::Generic, "WordDocument", &fib);
+        assert_eq!(other.document_role().unwrap(), DocumentRole::Document);
+
+        let mut globals = record(0x0809, &[0; 16]);
+        globals.extend(record(TEMPLATE, &[]));
+        globals.extend(record(EOF, &[]));
+        let xlt = file(OleFileType::Excel97, "Workbook", &globals);
+        assert_eq!(xlt.document_role().unwrap(), DocumentRole::Template);
+        let mut globals = record(0x0809, &[0; 16]);
+        globals.extend(record(ADDIN, &[]));
+        let xla = file(OleFileType::Excel5, "Book", &globals);
+        assert_eq!(xla.document_role().unwrap(), DocumentRole::AddIn);
+        // records of the sheets are past the globals
+        let mut workbook = record(0x0809, &[0; 16]);
+        workbook.extend(record(EOF, &[]));
+        workbook.extend(record(TEMPLATE, &[]));
+        let xls = file(OleFileType::Excel97, "Workbook", &workbook);
+        assert_eq!(xls.document_role().unwrap(), DocumentRole::Document);
+    }
+}
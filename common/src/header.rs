@@ -1,20 +1,23 @@
 use crate::{
-    constants::{self, Readable},
-    error::{Error, HeaderErrorType},
-    Result,
+    constants,
+    error::{Error, HeaderErrorType, Location},
+    read::read_full_sync,
+    OleParseOptions, ParseWarning, ParseWarningKind, Result,
 };
 use derivative::Derivative;
+use log::warn;
 use std::array::TryFromSliceError;
-use tokio::io::AsyncReadExt;
 
 #[derive(Clone, Derivative)]
 #[derivative(Debug)]
 pub struct OleHeader {
+    /// the reserved CLSID, `None` when it is all zeroes as the spec wants
+    pub(crate) class_id: Option<String>,
     pub major_version: u16,
-    minor_version: u16,
+    pub(crate) minor_version: u16,
     pub sector_size: u16,
-    mini_sector_size: u16,
-    directory_sectors_len: u32,
+    pub(crate) mini_sector_size: u16,
+    pub(crate) directory_sectors_len: u32,
     pub standard_stream_min_size: u32,
     /// sector allocation table AKA "FAT"
     pub sector_allocation_table_first_sector: u32,
@@ -23,14 +26,37 @@ pub struct OleHeader {
     pub short_sector_allocation_table_first_sector: u32,
     pub short_sector_allocation_table_len: u32,
     /// master sector allocation table AKA "DI-FAT"
-    master_sector_allocation_table_first_sector: u32,
+    pub master_sector_allocation_table_first_sector: u32,
     pub master_sector_allocation_table_len: u32,
     /// the first 109 FAT sector locations
     #[derivative(Debug = "ignore")]
     pub sector_allocation_table_head: Vec<u32>,
+    /// the header as read, see [`crate::OleFile::raw_header`]
+    #[derivative(Debug = "ignore")]
+    pub(crate) raw: RawFileHeader,
 }
 
 impl OleHeader {
+    /// The reserved CLSID, `None` when it is all zeroes as the spec wants.
+    pub fn class_id(&self) -> Option<&str> {
+        self.class_id.as_deref()
+    }
+
+    /// The minor version, 0x003E in files that follow the spec's recommendation.
+    pub fn minor_version(&self) -> u16 {
+        self.minor_version
+    }
+
+    /// The size of a mini sector in bytes, always 64 in a file that parsed.
+    pub fn mini_sector_size(&self) -> u16 {
+        self.mini_sector_size
+    }
+
+    /// The number of directory sectors, 0 in version 3 files, where the field isn't used.
+    pub fn directory_sectors_len(&self) -> u32 {
+        self.directory_sectors_len
+    }
+
     pub(crate) fn from_raw(raw_file_header: RawFileHeader) -> Self {
         let major_version = u16::from_le_bytes(raw_file_header.major_version);
         let minor_version = u16::from_le_bytes(raw_file_header.minor_version);
@@ -51,9 +77,10 @@ impl OleHeader {
             u32::from_le_bytes(raw_file_header.master_sector_allocation_table_first_sector);
         let master_sector_allocation_table_len =
             u32::from_le_bytes(raw_file_header.master_sector_allocation_table_len);
-        let sector_allocation_table_head = raw_file_header.sector_allocation_table_head;
+        let sector_allocation_table_head = raw_file_header.sector_allocation_table_head.clone();
 
         OleHeader {
+            class_id: crate::directory::format_class_id(raw_file_header.class_identifier),
             major_version,
             minor_version,
             sector_size,
@@ -67,6 +94,7 @@ impl OleHeader {
             master_sector_allocation_table_first_sector,
             master_sector_allocation_table_len,
             sector_allocation_table_head,
+            raw: raw_file_header,
         }
     }
 }
@@ -78,6 +106,10 @@ impl OleHeader {
 #[derive(Clone, Derivative)]
 #[derivative(Debug)]
 pub struct RawFileHeader {
+    /**
+    Reserved class identifier, zeroes
+     */
+    class_identifier: [u8; 16],
     /**
     Revision number of the file format
     (minor version)
@@ -139,14 +171,113 @@ pub struct RawFileHeader {
      */
     #[derivative(Debug = "ignore")]
     sector_allocation_table_head: Vec<u32>,
+    /**
+    The 512 bytes as read, reserved fields and all
+     */
+    #[derivative(Debug = "ignore")]
+    bytes: [u8; constants::HEADER_LENGTH],
+}
+
+impl RawFileHeader {
+    /// The 512 bytes of the header as read from the file.
+    pub fn bytes(&self) -> &[u8; constants::HEADER_LENGTH] {
+        &self.bytes
+    }
+
+    /// The signature at offset 0, `D0 CF 11 E0 A1 B1 1A E1` in any file that parsed.
+    pub fn signature(&self) -> &[u8] {
+        &self.bytes[0..8]
+    }
+
+    /// The reserved class identifier at offset 8, which the spec wants zeroed.
+    pub fn class_identifier(&self) -> &[u8; 16] {
+        &self.class_identifier
+    }
+
+    /// The byte order mark at offset 28, `FE FF` for little endian, the only one in use.
+    pub fn byte_order(&self) -> &[u8] {
+        &self.bytes[28..30]
+    }
+
+    /// The six reserved bytes at offset 34, which the spec wants zeroed.
+    pub fn reserved(&self) -> &[u8] {
+        &self.bytes[34..40]
+    }
+
+    /// The transaction signature number at offset 52, which only writers supporting
+    /// transactions set.
+    pub fn transaction_signature(&self) -> u32 {
+        u32::from_le_bytes([
+            self.bytes[52],
+            self.bytes[53],
+            self.bytes[54],
+            self.bytes[55],
+        ])
+    }
+
+    /// The first 109 FAT sector locations at offset 76, unused ones included.
+    pub fn sector_allocation_table_head(&self) -> &[u32] {
+        &self.sector_allocation_table_head
+    }
 }
 
-pub(crate) async fn parse_raw_header<R>(read: &mut R) -> Result<RawFileHeader>
+#[cfg(feature = "async")]
+pub(crate) async fn parse_raw_header<R>(
+    read: &mut R,
+    options: &OleParseOptions,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<RawFileHeader>
 where
-    R: Readable,
+    R: constants::Readable,
 {
     let mut header = [0u8; constants::HEADER_LENGTH];
-    let bytes_read = read.read(&mut header).await?;
+    let bytes_read = crate::read::read_full(read, &mut header).await?;
+    raw_header_from_bytes(&header, bytes_read, options, warnings)
+}
+
+pub(crate) fn parse_raw_header_sync<R>(
+    read: &mut R,
+    options: &OleParseOptions,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<RawFileHeader>
+where
+    R: std::io::Read,
+{
+    let mut header = [0u8; constants::HEADER_LENGTH];
+    let bytes_read = read_full_sync(read, &mut header)?;
+    raw_header_from_bytes(&header, bytes_read, options, warnings)
+}
+
+/// A header check that only fails if `strict`, otherwise it is recorded and the parse moves on.
+pub(crate) fn relaxed(
+    checked: Result<()>,
+    strict: bool,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<()> {
+    match checked {
+        Err(Error::OleInvalidHeader(err)) if !strict => {
+            let field = match err {
+                HeaderErrorType::Parsing(field, _) => field,
+                _ => "header",
+            };
+            let warning = format!("header: {}", err);
+            warn!("{}", warning);
+            warnings.push(ParseWarning::new(
+                ParseWarningKind::HeaderField(field),
+                warning,
+            ));
+            Ok(())
+        }
+        checked => checked,
+    }
+}
+
+fn raw_header_from_bytes(
+    header: &[u8; constants::HEADER_LENGTH],
+    bytes_read: usize,
+    options: &OleParseOptions,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<RawFileHeader> {
     if bytes_read != constants::HEADER_LENGTH {
         return Err(Error::OleInvalidHeader(HeaderErrorType::NotEnoughBytes(
             constants::HEADER_LENGTH,
@@ -174,24 +305,28 @@ where
 
     //https://winprotocoldoc.blob.core.windows.net/productionwindowsarchives/MS-CFB/%5bMS-CFB%5d.pdf
     //Reserved and unused class ID that MUST be set to all zeroes
-    let _: [u8; 16] = (&header[8..24])
-        .try_into()
-        .map_err(|err: TryFromSliceError| {
-            Error::OleInvalidHeader(HeaderErrorType::Parsing(
-                "class_identifier",
-                err.to_string(),
-            ))
-        })
-        .and_then(|class_identifier| {
-            if class_identifier != [0u8; 16] {
-                Err(Error::OleInvalidHeader(HeaderErrorType::Parsing(
+    let class_identifier: [u8; 16] =
+        (&header[8..24])
+            .try_into()
+            .map_err(|err: TryFromSliceError| {
+                Error::OleInvalidHeader(HeaderErrorType::Parsing(
                     "class_identifier",
-                    "non-zero entries in class_identifier field".to_string(),
-                )))
-            } else {
-                Ok(class_identifier)
-            }
-        })?;
+                    err.to_string(),
+                ))
+            })?;
+    // some writers put a class there, which most parsers don't mind
+    relaxed(
+        if class_identifier != [0u8; 16] {
+            Err(Error::OleInvalidHeader(HeaderErrorType::Parsing(
+                "class_identifier",
+                "non-zero entries in class_identifier field".to_string(),
+            )))
+        } else {
+            Ok(())
+        },
+        options.strict_header,
+        warnings,
+    )?;
     //https://winprotocoldoc.blob.core.windows.net/productionwindowsarchives/MS-CFB/%5bMS-CFB%5d.pdf
     //says this SHOULD be set to 0x003E.  But word95 sets it to something else because reasons.
     let minor_version: [u8; 2] =
@@ -200,6 +335,21 @@ where
             .map_err(|err: TryFromSliceError| {
                 Error::OleInvalidHeader(HeaderErrorType::Parsing("minor_version", err.to_string()))
             })?;
+    // not worth failing a strict parse over, but worth knowing about
+    if minor_version != constants::CORRECT_MINOR_VERSION {
+        let warning = format!(
+            "header: minor version {:#06x} instead of {:#06x}",
+            u16::from_le_bytes(minor_version),
+            u16::from_le_bytes(constants::CORRECT_MINOR_VERSION)
+        );
+        warn!("{}", warning);
+        warnings.push(
+            ParseWarning::new(ParseWarningKind::MinorVersion, warning).at(Location {
+                offset: Some(24),
+                ..Location::default()
+            }),
+        );
+    }
     //https://winprotocoldoc.blob.core.windows.net/productionwindowsarchives/MS-CFB/%5bMS-CFB%5d.pdf
     //This field MUST be set to either
     // 0x0003 (version 3) or 0x0004 (version 4).
@@ -281,42 +431,47 @@ where
                 format!("incorrect mini sector size {:x?}", mini_sector_size),
             ))),
         })?;
-    let _: [u8; 6] = (&header[34..40])
+    let reserved: [u8; 6] = (&header[34..40])
         .try_into()
         .map_err(|err: TryFromSliceError| {
             Error::OleInvalidHeader(HeaderErrorType::Parsing("first_reserved", err.to_string()))
-        })
-        .and_then(|reserved| {
-            if reserved != [0u8; 6] {
-                Err(Error::OleInvalidHeader(HeaderErrorType::Parsing(
-                    "first_reserved",
-                    "non-zero entries in reserved field".to_string(),
-                )))
-            } else {
-                Ok(reserved)
-            }
         })?;
+    relaxed(
+        if reserved != [0u8; 6] {
+            Err(Error::OleInvalidHeader(HeaderErrorType::Parsing(
+                "first_reserved",
+                "non-zero entries in reserved field".to_string(),
+            )))
+        } else {
+            Ok(())
+        },
+        options.strict_header,
+        warnings,
+    )?;
     //https://winprotocoldoc.blob.core.windows.net/productionwindowsarchives/MS-CFB/%5bMS-CFB%5d.pdf
     //If Major Version is 3, the Number of Directory Sectors MUST be zero. This field is not
     // supported for version 3 compound files.
-    let directory_sectors_len: [u8; 4] = (&header[40..44])
-        .try_into()
-        .map_err(|err: TryFromSliceError| {
-            Error::OleInvalidHeader(HeaderErrorType::Parsing(
-                "directory_sectors_len",
-                err.to_string(),
-            ))
-        })
-        .and_then(|directory_sectors_len| {
-            if directory_sectors_len != [0u8; 4] && major_version == constants::MAJOR_VERSION_3 {
-                Err(Error::OleInvalidHeader(HeaderErrorType::Parsing(
+    let directory_sectors_len: [u8; 4] =
+        (&header[40..44])
+            .try_into()
+            .map_err(|err: TryFromSliceError| {
+                Error::OleInvalidHeader(HeaderErrorType::Parsing(
                     "directory_sectors_len",
-                    "non-zero number of directory sectors with major version 3".to_string(),
-                )))
-            } else {
-                Ok(directory_sectors_len)
-            }
-        })?;
+                    err.to_string(),
+                ))
+            })?;
+    relaxed(
+        if directory_sectors_len != [0u8; 4] && major_version == constants::MAJOR_VERSION_3 {
+            Err(Error::OleInvalidHeader(HeaderErrorType::Parsing(
+                "directory_sectors_len",
+                "non-zero number of directory sectors with major version 3".to_string(),
+            )))
+        } else {
+            Ok(())
+        },
+        options.strict_header,
+        warnings,
+    )?;
     let sector_allocation_table_len: [u8; 4] =
         (&header[44..48])
             .try_into()
@@ -347,27 +502,30 @@ where
     // specifies the maximum size of a user-defined data stream that is allocated from the mini FAT
     // and mini stream, and that cutoff is 4,096 bytes. Any user-defined data stream that is greater than
     // or equal to this cutoff size must be allocated as normal sectors from the FAT.
-    let standard_stream_min_size: [u8; 4] = (&header[56..60])
-        .try_into()
-        .map_err(|err: TryFromSliceError| {
-            Error::OleInvalidHeader(HeaderErrorType::Parsing(
-                "standard_stream_min_size",
-                err.to_string(),
-            ))
-        })
-        .and_then(|standard_stream_min_size| {
-            if standard_stream_min_size != constants::CORRECT_STANDARD_STREAM_MIN_SIZE {
-                Err(Error::OleInvalidHeader(HeaderErrorType::Parsing(
+    // Files with other values open in Office, so the value present is honoured.
+    let standard_stream_min_size: [u8; 4] =
+        (&header[56..60])
+            .try_into()
+            .map_err(|err: TryFromSliceError| {
+                Error::OleInvalidHeader(HeaderErrorType::Parsing(
                     "standard_stream_min_size",
-                    format!(
-                        "incorrect standard_stream_min_size {:x?}",
-                        standard_stream_min_size
-                    ),
-                )))
-            } else {
-                Ok(standard_stream_min_size)
-            }
-        })?;
+                    err.to_string(),
+                ))
+            })?;
+    if standard_stream_min_size != constants::CORRECT_STANDARD_STREAM_MIN_SIZE {
+        let warning = format!(
+            "header: mini stream cutoff {} instead of {}",
+            u32::from_le_bytes(standard_stream_min_size),
+            u32::from_le_bytes(constants::CORRECT_STANDARD_STREAM_MIN_SIZE)
+        );
+        warn!("{}", warning);
+        warnings.push(
+            ParseWarning::new(ParseWarningKind::MiniStreamCutoff, warning).at(Location {
+                offset: Some(56),
+                ..Location::default()
+            }),
+        );
+    }
     let short_sector_allocation_table_first_sector: [u8; 4] = (&header[60..64])
         .try_into()
         .map_err(|err: TryFromSliceError| {
@@ -403,12 +561,13 @@ where
                 ))
             })?;
 
-    let sector_allocation_table_head = (&header[76..512])
+    let sector_allocation_table_head = header[76..512]
         .chunks_exact(4)
         .map(|quad| u32::from_le_bytes([quad[0], quad[1], quad[2], quad[3]]))
         .collect::<Vec<_>>();
 
     Ok(RawFileHeader {
+        class_identifier,
         minor_version,
         major_version,
         sector_size,
@@ -422,5 +581,6 @@ where
         master_sector_allocation_table_first_sector,
         master_sector_allocation_table_len,
         sector_allocation_table_head,
+        bytes: *header,
     })
 }
@@ -0,0 +1,420 @@
+//! Analysis of every compound file in a directory, zip archive or mailbox.
+//!
+//! [`ingest`] walks the given path and yields one [`Ingested`] per candidate as it goes, so an
+//! archive of samples never has to be held in memory at once. Directories are walked
+//! recursively, and zip archives, mbox files and `.eml` messages found on the way are opened.
+//! Their members and attachments are candidates themselves, but are not unpacked any further.
+//! Only data starting with the compound file signature is analyzed, everything else is skipped.
+//!
+//! Corpora often hold the same payload many times over, attached to one mail after another.
+//! [`Ingest::dedup`] analyzes each distinct content once and hands out that report again for
+//! every copy, pointing at the first one in [`Ingested::duplicate_of`].
+use crate::{analysis, constants, error::Error, AnalysisReport, Result};
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// The analysis of one candidate.
+#[derive(Debug)]
+pub struct Ingested {
+    /// where the candidate came from, e.g. `samples/mail.eml!invoice.doc`
+    pub origin: String,
+    pub report: Result<AnalysisReport>,
+    /// with [`Ingest::dedup`], the origin of an earlier candidate with the same content, whose
+    /// report was reused
+    pub duplicate_of: Option<String>,
+}
+
+/// Analyze every compound file found under `path`.
+///
+/// ```rust
+/// for ingested in ole::ingest("../data") {
+///     println!("{}: {:?}", ingested.origin, ingested.report.map(|report| report.file_type));
+/// }
+/// ```
+pub fn ingest<P: AsRef<Path>>(path: P) -> Ingest {
+    Ingest {
+        pending: vec![Box::new(std::iter::once(Item::Path(
+            path.as_ref().to_path_buf(),
+        )))],
+        seen: None,
+    }
+}
+
+enum Item {
+    Path(PathBuf),
+    Candidate { origin: String, data: Vec<u8> },
+    Failed { origin: String, error: Error },
+}
+
+/// The iterator returned by [`ingest`].
+pub struct Ingest {
+    pending: Vec<Box<dyn Iterator<Item = Item>>>,
+    /// sha256 of every content analyzed so far, with its origin and report, once deduplicating
+    seen: Option<HashMap<[u8; 32], (String, AnalysisReport)>>,
+}
+
+impl Ingest {
+    /// Analyze every distinct content once, keyed by its sha256. Later copies come with the
+    /// report of the first one and its origin in [`Ingested::duplicate_of`]. Contents that
+    /// failed to parse aren't remembered, their copies are analyzed again.
+    ///
+    /// ```rust
+    /// for ingested in ole::ingest("../data").dedup() {
+    ///     if let Some(first) = ingested.duplicate_of {
+    ///         println!("{} is the same as {}", ingested.origin, first);
+    ///     }
+    /// }
+    /// ```
+    pub fn dedup(mut self) -> Self {
+        self.seen.get_or_insert_with(HashMap::new);
+        self
+    }
+
+    fn analyze(&mut self, origin: String, data: &[u8]) -> Ingested {
+        let seen = match &mut self.seen {
+            Some(seen) => seen,
+            None => {
+                return Ingested {
+                    origin,
+                    report: analysis::analyze(data),
+                    duplicate_of: None,
+                }
+            }
+        };
+        let digest = Sha256::digest(data).into();
+        if let Some((first, report)) = seen.get(&digest) {
+            debug!("{} has the same content as {}", origin, first);
+            return Ingested {
+                origin,
+                report: Ok(report.clone()),
+                duplicate_of: Some(first.clone()),
+            };
+        }
+        let report = analysis::analyze(data);
+        if let Ok(report) = &report {
+            seen.insert(digest, (origin.clone(), report.clone()));
+        }
+        Ingested {
+            origin,
+            report,
+            duplicate_of: None,
+        }
+    }
+}
+
+impl Iterator for Ingest {
+    type Item = Ingested;
+
+    fn next(&mut self) -> Option<Ingested> {
+        loop {
+            let item = match self.pending.last_mut()?.next() {
+                Some(item) => item,
+                None => {
+                    self.pending.pop();
+                    continue;
+                }
+            };
+            match item {
+                Item::Path(path) => match open(&path) {
+                    Ok(items) => self.pending.push(items),
+                    Err(error) => {
+                        return Some(Ingested {
+                            origin: path.display().to_string(),
+                            report: Err(error),
+                            duplicate_of: None,
+                        })
+                    }
+                },
+                Item::Candidate { origin, data } => {
+                    if data.starts_with(&constants::MAGIC_BYTES) {
+                        return Some(self.analyze(origin, &data));
+                    }
+                    debug!("skipping {}, it is not a compound file", origin);
+                }
+                Item::Failed { origin, error } => {
+                    return Some(Ingested {
+                        origin,
+                        report: Err(error),
+                        duplicate_of: None,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// What to do with a path: walk it, open it as a container, or take it as a candidate.
+fn open(path: &Path) -> Result<Box<dyn Iterator<Item = Item>>> {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<_>>();
+        entries.sort();
+        return Ok(Box::new(entries.into_iter().map(Item::Path)));
+    }
+    let origin = path.display().to_string();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("zip") => zip_members(origin, fs::File::open(path)?),
+        Some("mbox") => Ok(Box::new(mbox_attachments(origin, fs::read(path)?))),
+        Some("eml") => Ok(Box::new(
+            mail_attachments(&origin, &fs::read(path)?).into_iter(),
+        )),
+        _ => Ok(Box::new(std::iter::once(Item::Candidate {
+            origin,
+            data: fs::read(path)?,
+        }))),
+    }
+}
+
+fn zip_members(origin: String, file: fs::File) -> Result<Box<dyn Iterator<Item = Item>>> {
+    let mut archive = zip::ZipArchive::new(file).map_err(|error| zip_error(&origin, error))?;
+    let mut index = 0;
+    let members = std::iter::from_fn(move || {
+        let position = index;
+        if position >= archive.len() {
+            return None;
+        }
+        index += 1;
+        // directories have nothing to offer, they come out as `None`
+        Some(match archive.by_index(position) {
+            Ok(mut member) if member.is_file() => {
+                let member_origin = format!("{}!{}", origin, member.name());
+                let mut data = vec![];
+                Some(match member.read_to_end(&mut data) {
+                    Ok(_) => Item::Candidate {
+                        origin: member_origin,
+                        data,
+                    },
+                    Err(error) => Item::Failed {
+                        origin: member_origin,
+                        error: error.into(),
+                    },
+                })
+            }
+            Ok(_) => None,
+            Err(error) => Some(Item::Failed {
+                origin: format!("{}!#{}", origin, position),
+                error: zip_error(&origin, error),
+            }),
+        })
+    });
+    Ok(Box::new(members.flatten()))
+}
+
+fn zip_error(origin: &str, error: zip::result::ZipError) -> Error {
+    warn!("could not read zip archive {}: {}", origin, error);
+    Error::StdIo(std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+}
+
+/// The attachments of the messages in an mbox, one message at a time.
+fn mbox_attachments(origin: String, data: Vec<u8>) -> impl Iterator<Item = Item> {
+    let mut starts = vec![];
+    for (position, window) in data.windows(6).enumerate() {
+        if position == 0 && window.starts_with(b"From ") {
+            starts.push(0);
+        } else if window == b"\nFrom " {
+            starts.push(position + 1);
+        }
+    }
+    let ends = starts
+        .iter()
+        .skip(1)
+        .copied()
+        .chain(std::iter::once(data.len()))
+        .collect::<Vec<_>>();
+    starts
+        .into_iter()
+        .zip(ends)
+        .enumerate()
+        .flat_map(move |(number, (start, end))| {
+            // skip the "From " separator line, the message starts after it
+            let message = &data[start..end];
+            let message = message
+                .iter()
+                .position(|byte| *byte == b'\n')
+                .map_or(&message[message.len()..], |newline| &message[newline + 1..]);
+            mail_attachments(&format!("{}#{}", origin, number + 1), message)
+        })
+}
+
+fn mail_attachments(origin: &str, message: &[u8]) -> Vec<Item> {
+    match mailparse::parse_mail(message) {
+        Ok(mail) => {
+            let mut items = vec![];
+            collect_attachments(origin, &mail, &mut items);
+            items
+        }
+        Err(error) => vec![Item::Failed {
+            origin: origin.to_string(),
+            error: Error::StdIo(std::io::Error::new(std::io::ErrorKind::InvalidData, error)),
+        }],
+    }
+}
+
+fn collect_attachments(origin: &str, part: &mailparse::ParsedMail, items: &mut Vec<Item>) {
+    if part.subparts.is_empty() {
+        let disposition = part.get_content_disposition();
+        let name = disposition
+            .params
+            .get("filename")
+            .or_else(|| part.ctype.params.get("name"));
+        if disposition.disposition == mailparse::DispositionType::Attachment || name.is_some() {
+            let part_origin = format!(
+                "{}!{}",
+                origin,
+                name.map(String::as_str).unwrap_or("attachment")
+            );
+            items.push(match part.get_body_raw() {
+                Ok(data) => Item::Candidate {
+                    origin: part_origin,
+                    data,
+                },
+                Err(error) => Item::Failed {
+                    origin: part_origin,
+                    error: Error::StdIo(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        error,
+                    )),
+                },
+            });
+        }
+    }
+    for subpart in &part.subparts {
+        collect_attachments(origin, subpart, items);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+    use std::io::Write;
+
+    fn base64(data: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        data.chunks(3)
+            .flat_map(|chunk| {
+                let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+                    bits | (*byte as u32) << (16 - 8 * i)
+                });
+                (0..4).map(move |i| {
+                    if i <= chunk.len() {
+                        ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char
+                    } else {
+                        '='
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn message(attachment_name: &str, attachment: &[u8]) -> String {
+        format!(
+            "From: a@example.com\r\nSubject: samples\r\nMIME-Version: 1.0\r\n\
+             Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n\
+             --b\r\nContent-Type: text/plain\r\n\r\nsee attached\r\n\
+             --b\r\nContent-Type: application/msword\r\n\
+             Content-Disposition: attachment; filename=\"{}\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\r\n{}\r\n--b--\r\n",
+            attachment_name,
+            base64(attachment)
+        )
+    }
+
+    #[test]
+    fn test_ingest_directory_zip_and_mail() {
+        let doc = CfbBuilder::new().stream("Contents", b"doc").build();
+        let dir = std::env::temp_dir().join(format!("ole-rs-ingest-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.doc"), &doc).unwrap();
+        fs::write(dir.join("notes.txt"), b"not a compound file").unwrap();
+        fs::write(dir.join("nested/broken.doc"), &doc[..600]).unwrap();
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(dir.join("archive.zip")).unwrap());
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        zip.add_directory("inner", options).unwrap();
+        zip.start_file("inner/b.doc", options).unwrap();
+        zip.write_all(&doc).unwrap();
+        zip.finish().unwrap();
+
+        fs::write(dir.join("mail.eml"), message("c.doc", &doc)).unwrap();
+        let mbox = format!(
+            "From a@example.com Mon Jan  1 00:00:00 2024\n{}\nFrom a@example.com Mon Jan  1 00:00:00 2024\n{}",
+            message("d.doc", &doc),
+            message("e.doc", &doc)
+        );
+        fs::write(dir.join("box.mbox"), mbox).unwrap();
+
+        let results = ingest(&dir)
+            .map(|ingested| {
+                let origin = ingested.origin.replace(&dir.display().to_string(), "");
+                (origin, ingested.report.is_ok())
+            })
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                ("/a.doc".to_string(), true),
+                ("/archive.zip!inner/b.doc".to_string(), true),
+                ("/box.mbox#1!d.doc".to_string(), true),
+                ("/box.mbox#2!e.doc".to_string(), true),
+                ("/mail.eml!c.doc".to_string(), true),
+                ("/nested/broken.doc".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ingest_dedup() {
+        let doc = CfbBuilder::new().stream("Contents", b"doc").build();
+        let other = CfbBuilder::new().stream("Contents", b"other").build();
+        let dir = std::env::temp_dir().join(format!("ole-rs-dedup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.doc"), &doc).unwrap();
+        fs::write(dir.join("b.doc"), &other).unwrap();
+        fs::write(dir.join("c.doc"), &doc).unwrap();
+        fs::write(dir.join("d.doc"), &doc[..600]).unwrap();
+        fs::write(dir.join("e.doc"), &doc[..600]).unwrap();
+        fs::write(dir.join("mail.eml"), message("f.doc", &doc)).unwrap();
+
+        let dir_name = dir.display().to_string();
+        let results = ingest(&dir)
+            .dedup()
+            .map(|ingested| {
+                let origin = ingested.origin.replace(&dir_name, "");
+                let duplicate_of = ingested
+                    .duplicate_of
+                    .map(|first| first.replace(&dir_name, ""));
+                (origin, ingested.report.is_ok(), duplicate_of)
+            })
+            .collect::<Vec<_>>();
+        let plain = ingest(&dir)
+            .map(|ingested| ingested.duplicate_of)
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&dir).unwrap();
+        let a = Some("/a.doc".to_string());
+        assert_eq!(
+            results,
+            vec![
+                ("/a.doc".to_string(), true, None),
+                ("/b.doc".to_string(), true, None),
+                ("/c.doc".to_string(), true, a.clone()),
+                ("/d.doc".to_string(), false, None),
+                ("/e.doc".to_string(), false, None),
+                ("/mail.eml!f.doc".to_string(), true, a),
+            ]
+        );
+        assert!(plain.iter().all(Option::is_none));
+    }
+}
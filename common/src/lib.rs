@@ -1,47 +1,149 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod analysis;
+mod anomaly;
+mod archive;
+mod capabilities;
 pub mod constants;
+mod deobfuscate;
 pub mod directory;
+mod embedded;
 mod encryption;
+mod excel;
+mod fat;
 pub mod header;
+#[cfg(feature = "ingest")]
+mod ingest;
+#[cfg(feature = "manifest")]
+mod manifest;
+mod metrics;
+mod name_index;
+mod nested;
+mod options;
+mod orphan;
+mod policy;
+mod powerpoint;
+mod read;
+mod redact;
+mod repair;
+mod secret;
+mod sector_map;
+mod sectors;
+mod signature;
+mod stream;
+#[cfg(test)]
+mod test_support;
+mod tree;
+mod vba;
+mod warning;
+mod word;
 
 pub mod ftype;
 
-pub use ftype::file_type;
+pub use analysis::{
+    analyze, analyze_with_options, AnalysisReport, HeaderInfo, StreamLocation, StreamStat,
+    TreeStats, REPORT_VERSION,
+};
+pub use anomaly::{Anomaly, AnomalyKind};
+#[cfg(feature = "archive")]
+pub use archive::archive_members;
+pub use archive::{archive_format, ArchiveFormat, ArchiveMember};
+pub use capabilities::{capabilities, Capabilities};
+pub use deobfuscate::{deobfuscate, DeobfuscatedModule, Obfuscation, RecoveredString};
+pub use embedded::{ObjectEntry, ObjectEvidence, OleObject, OleObjectKind};
+pub use encryption::EncryptionScheme;
+#[cfg(feature = "decrypt")]
+pub use encryption::{decrypt, Decrypted, DecryptedFormat};
+pub use excel::ExternalConnection;
+pub use fat::FatEntry;
+pub use ftype::{file_type, DocumentRole};
+#[cfg(feature = "ingest")]
+pub use ingest::{ingest, Ingest, Ingested};
+#[cfg(feature = "manifest")]
+pub use manifest::{Manifest, ManifestEntry, ManifestMismatch};
+pub use metrics::ParseMetrics;
+pub use nested::{NestedOleStream, Truncation, UnwrapLimits, Unwrapped, UnwrappedOle};
+pub use options::OleParseOptions;
+pub use orphan::{OrphanedEntry, UnusedSector};
+pub use policy::{Action, Condition, Policy, Rule, Verdict};
+pub use powerpoint::{CurrentUser, PptEdit};
+pub use redact::redact;
+pub use repair::{repair, Repair, RepairedChain};
+pub use sector_map::{MappedSector, SectorOwner};
+pub use signature::{Signature, SignatureKind};
+#[cfg(feature = "async")]
+pub use stream::AsyncOleStreamReader;
+pub use stream::{OleStreamReader, StreamChunks};
+pub use tree::{TreeViolation, TreeViolationKind};
+pub use vba::{AutoExec, VbaModule, VbaModuleKind, VbaProject, VbaReference, VbaReferenceKind};
+pub use warning::{ParseWarning, ParseWarningKind};
+pub use word::{DocVar, WordObject};
 
 pub mod error;
+pub mod prelude;
 pub mod util;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 use crate::{
-    constants::Readable,
-    directory::{DirectoryEntry, DirectoryEntryRaw, ObjectType},
+    directory::{DirectoryEntry, DirectoryEntryRaw, EntryPath, ObjectType},
     ftype::OleFileType,
-    header::{parse_raw_header, OleHeader},
+    header::{parse_raw_header_sync, OleHeader, RawFileHeader},
+    name_index::NameIndex,
+    read::read_full_sync,
+    secret::SecretBuffer,
+    sectors::{LazySectors, Sectors},
 };
 use derivative::Derivative;
-use error::{Error, HeaderErrorType};
-use tokio::io::AsyncReadExt;
+use error::{Error, HeaderErrorType, Location};
+use log::{debug, trace, warn};
+use std::{
+    cmp::Ordering,
+    sync::{Arc, OnceLock},
+    time::Instant,
+};
 
 #[derive(Clone, Derivative)]
 #[derivative(Debug)]
 pub struct OleFile {
     header: OleHeader,
     #[derivative(Debug = "ignore")]
-    sectors: Vec<Vec<u8>>,
+    sectors: Sectors,
     #[derivative(Debug = "ignore")]
     sector_allocation_table: Vec<u32>,
     #[derivative(Debug = "ignore")]
     short_sector_allocation_table: Vec<u32>,
     #[derivative(Debug = "ignore")]
     pub directory_stream_data: Vec<u8>,
+    /// the allocated entries by stream ID, empty with [`OleParseOptions::lazy_directory`]: use
+    /// [`OleFile::entries`] to get them either way. Lookups by path go through an index built
+    /// while parsing, which doesn't follow changes made here.
     pub directory_entries: Vec<DirectoryEntry>,
+    /// with [`OleParseOptions::lazy_directory`], a slot per entry of the directory stream,
+    /// decoded on first lookup and shared between clones
+    #[derivative(Debug = "ignore")]
+    entry_cache: Option<Arc<Vec<OnceLock<Option<DirectoryEntry>>>>>,
+    /// the entries by parent and name, built while parsing a directory that isn't lazy
     #[derivative(Debug = "ignore")]
-    mini_stream: Vec<[u8; 64]>,
+    name_index: Option<Arc<NameIndex>>,
+    /// the sectors of the root entry's chain: the mini stream, then the rest of its last sector
+    #[derivative(Debug = "ignore")]
+    mini_stream: Arc<Vec<u8>>,
+    /// how much of `mini_stream` the root entry's size covers
+    mini_stream_len: usize,
     pub file_type: OleFileType,
     pub encrypted: bool,
+    /// How the content is encrypted, `None` unless `encrypted` is set.
+    pub encryption_scheme: Option<EncryptionScheme>,
+    /// compare stream names byte for byte instead of case-insensitively
+    exact_names: bool,
+    #[derivative(Debug = "ignore")]
+    metrics: ParseMetrics,
+    /// what parsing let through
+    warnings: Vec<ParseWarning>,
+    #[derivative(Debug = "ignore")]
+    options: OleParseOptions,
 }
 
 impl OleFile {
@@ -63,7 +165,75 @@ impl OleFile {
         //! ```
 
         let f = tokio::fs::File::open(file).await?;
-        Self::parse(f).await
+        Self::parse(f, &OleParseOptions::strict()).await
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn from_reader<R: constants::Readable>(reader: R) -> Result<Self> {
+        //! Read an OLE file from any async reader and parse it
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! #[tokio::main]
+        //! async fn main() {
+        //!     let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+        //!
+        //!     let res = OleFile::from_reader(std::io::Cursor::new(data)).await;
+        //!     assert!(res.is_ok());
+        //! }
+        //! ```
+        Self::parse(reader, &OleParseOptions::strict()).await
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn from_reader_with_options<R: constants::Readable>(
+        reader: R,
+        options: &OleParseOptions,
+    ) -> Result<Self> {
+        //! Read an OLE file from any async reader and parse it as strictly or leniently as
+        //! `options` say
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{OleFile, OleParseOptions};
+        //!
+        //! #[tokio::main]
+        //! async fn main() {
+        //!     let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+        //!     let options = OleParseOptions {
+        //!         max_directory_entries: 4,
+        //!         ..OleParseOptions::lenient()
+        //!     };
+        //!
+        //!     let res = OleFile::from_reader_with_options(std::io::Cursor::new(data), &options).await;
+        //!     assert!(res.is_err());
+        //! }
+        //! ```
+        Self::parse(reader, options).await
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn from_reader_lenient<R: constants::Readable>(reader: R) -> Result<Self> {
+        //! Like [`OleFile::from_reader`], for input that ends in the middle of a sector, as mail
+        //! gateways leave attachments they cut short. The last sector is zero-padded instead of
+        //! failing with `OleUnexpectedEof`, and [`OleFile::warnings`] says so.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! #[tokio::main]
+        //! async fn main() {
+        //!     let mut data = std::fs::read("../data/oledoc1.doc_").unwrap();
+        //!     data.truncate(data.len() - 100);
+        //!
+        //!     let res = OleFile::from_reader_lenient(std::io::Cursor::new(data)).await.unwrap();
+        //!     assert_eq!(res.warnings().len(), 1);
+        //! }
+        //! ```
+        Self::parse(reader, &OleParseOptions::lenient()).await
     }
 
     #[cfg(feature = "blocking")]
@@ -78,21 +248,253 @@ impl OleFile {
         //! let res = OleFile::from_file_blocking(file);
         //! assert!(res.is_ok())
         //! ```
-        let rt = tokio::runtime::Runtime::new()?;
-        let f = rt.block_on(tokio::fs::File::open(file))?;
-        rt.block_on(Self::parse(f))
+        let f = std::fs::File::open(file)?;
+        Self::parse_sync(std::io::BufReader::new(f))
+    }
+
+    #[cfg(feature = "blocking")]
+    pub fn from_bytes_blocking(data: &[u8]) -> Result<Self> {
+        //! Parse an OLE file that is already in memory
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //! let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+        //!
+        //! let res = OleFile::from_bytes_blocking(&data);
+        //! assert!(res.is_ok())
+        //! ```
+        Self::parse_sync(data)
     }
 
     pub fn root(&self) -> &DirectoryEntry {
-        &self.directory_entries[0]
+        self.entry_by_id(0)
+            .expect("parsing fails without a root entry")
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &DirectoryEntry> {
+        //! Every allocated directory entry by stream ID, the root first. With
+        //! [`OleParseOptions::lazy_directory`] this decodes the entries not looked up yet.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for entry in res.entries() {
+        //!         println!("{} {:?}", entry.name(), entry.object_type());
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let lazy_count = self.entry_cache.as_ref().map_or(0, |slots| slots.len());
+        self.directory_entries
+            .iter()
+            .chain((0..lazy_count).filter_map(move |id| self.entry_by_id(id as u32)))
+    }
+
+    pub fn entry_by_id(&self, id: u32) -> Option<&DirectoryEntry> {
+        //! The directory entry with stream ID `id`, the number sibling and child ids refer to,
+        //! `None` if that entry is unallocated or past the end of the directory. Together with
+        //! [`DirectoryEntry::left_sibling`], [`DirectoryEntry::right_sibling`] and
+        //! [`DirectoryEntry::child`] this follows the directory tree exactly as stored.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let root = res.entry_by_id(0).unwrap();
+        //!     let top = root.child(&res).unwrap();
+        //!     println!("{} {:?} {:?}", top.name(), top.left_sibling(&res), top.right_sibling(&res));
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        if let Some(slots) = &self.entry_cache {
+            return slots
+                .get(id as usize)?
+                .get_or_init(|| self.decode_entry(id as usize))
+                .as_ref();
+        }
+        // unallocated entries aren't kept, so past the first of them positions and ids differ
+        match self.directory_entries.get(id as usize) {
+            Some(entry) if entry.index == id as usize => Some(entry),
+            _ => self
+                .directory_entries
+                .binary_search_by_key(&(id as usize), |entry| entry.index)
+                .ok()
+                .map(|position| &self.directory_entries[position]),
+        }
+    }
+
+    /// One more than the highest stream ID in use, for tables indexed by stream ID.
+    pub(crate) fn id_count(&self) -> usize {
+        match &self.entry_cache {
+            Some(slots) => slots.len(),
+            None => self
+                .directory_entries
+                .last()
+                .map_or(0, |entry| entry.index + 1),
+        }
+    }
+
+    pub fn walk(&self) -> impl Iterator<Item = (EntryPath, &DirectoryEntry)> {
+        //! Every storage and stream below the root with its full path, in directory order: by
+        //! stream ID, the order of the entries in the directory stream. Unlike the order of a
+        //! tree walk it doesn't depend on how the writer balanced the trees, so the same file
+        //! always lists the same way; a storage may come after its children.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for (path, entry) in res.walk() {
+        //!         println!("{} ({:?}, {} bytes)", path, entry.object_type(), entry.stream_size());
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        analysis::entries_with_paths(self)
+            .into_iter()
+            .map(|(path, entry)| (EntryPath::from(path), entry))
+    }
+
+    pub fn children(&self, storage_path: &[&str]) -> Result<impl Iterator<Item = &DirectoryEntry>> {
+        //! The entries directly below the storage at `storage_path`, or below the root if the
+        //! path is empty, in the order of the storage's directory tree. For a valid tree that is
+        //! the order MS-CFB sorts names in, shorter names first and then by uppercased name.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for object in res.children(&["ObjectPool"]).unwrap() {
+        //!         println!("{}", object.name());
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let storage = if storage_path.is_empty() {
+            Some(self.root())
+        } else {
+            self.get_entry(storage_path)
+        };
+        match storage {
+            Some(storage) if storage.object_type != ObjectType::Stream => {
+                Ok(self.child_entries(storage).into_iter())
+            }
+            _ => Err(Error::OleDirectoryEntryNotFound),
+        }
+    }
+
+    pub fn tree_stats(&self) -> Vec<TreeStats> {
+        //! Shape of the directory tree of the root and then of every storage in directory order,
+        //! see [`TreeStats`].
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let root = &res.tree_stats()[0];
+        //!     assert!(root.depth >= root.optimal_depth);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        analysis::tree_stats(self)
+    }
+
+    pub fn tree_violations(&self) -> Vec<TreeViolation> {
+        //! The rules of MS-CFB that the directory trees break, for the root and then every
+        //! storage in directory order: red entries with red parents, a red tree root, subtrees
+        //! differing in black height, and children out of name order or with duplicate names.
+        //! [`OleFile::children`] lists the children in the tree order these are checked in.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{OleFile, TreeViolationKind};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert!(!res
+        //!         .tree_violations()
+        //!         .iter()
+        //!         .any(|violation| matches!(violation.kind, TreeViolationKind::NameOrder(_))));
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        tree::tree_violations(self)
+    }
+
+    pub fn with_exact_names(mut self, exact: bool) -> Self {
+        //! Stream lookups compare names case-insensitively, as MS-CFB specifies. Pass `true` to
+        //! require the exact name instead.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert!(res.open_stream(&["worddocument"]).is_ok());
+        //!     let res = res.with_exact_names(true);
+        //!     assert!(res.open_stream(&["worddocument"]).is_err());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        self.exact_names = exact;
+        self
     }
 
     pub fn list_streams(&self) -> Vec<String> {
-        //! List the streams from a parsed OLE file
+        //! List the streams from a parsed OLE file, in directory order, see [`OleFile::walk`].
         //!
         //! ## Example usage
         //! ```rust
         //!
+        //! # #[cfg(feature = "async")]
         //! #[tokio::main]
         //! async fn main() {
         //!     use ole::OleFile;
@@ -102,17 +504,20 @@ impl OleFile {
         //!     let streams = res.list_streams();
         //!     assert!(!streams.is_empty());
         //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
         //! ```
         self.list_object(ObjectType::Stream)
     }
 
     pub fn list_storage(&self) -> Vec<String> {
-        //! List the Storages from a parsed OLE file
+        //! List the Storages from a parsed OLE file, in directory order, see [`OleFile::walk`].
         //!
         //! ## Example usage
         //! ```rust
         //! use ole::OleFile;
         //!
+        //! # #[cfg(feature = "async")]
         //! #[tokio::main]
         //! async fn main() {
         //! let file = "../data/oledoc1.doc_";
@@ -121,406 +526,3644 @@ impl OleFile {
         //!     let storage = res.list_storage();
         //!     assert!(!storage.is_empty());
         //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
         //! ```
         self.list_object(ObjectType::Storage)
     }
 
-    pub fn is_encrypted(&self) -> bool {
-        //! Returns true or false if a file is encrypted/password protected
+    pub fn list_streams_with_path(&self) -> Vec<String> {
+        //! Like [`OleFile::list_streams`], with each stream named by its full path below the
+        //! root, in the syntax of [`util::join_stream_path`], so two streams called `Data` in
+        //! different storages can be told apart. Every path opens with
+        //! [`OleFile::open_stream_path`].
         //!
         //! ## Example usage
         //! ```rust
         //! use ole::OleFile;
         //!
+        //! # #[cfg(feature = "async")]
         //! #[tokio::main]
         //! async fn main() {
-        //! let file = "../data/encryption/encrypted/rc4cryptoapi_password.doc";
+        //! let file = "../data/oledoc1.doc_";
         //!
         //!     let res = OleFile::from_file(file).await.expect("file not found");
-        //!     assert!(res.is_encrypted());
+        //!     let streams = res.list_streams_with_path();
+        //!     assert!(streams.contains(&"ObjectPool/_1549162656/\x01Ole10Native".to_string()));
         //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
         //! ```
-        self.encrypted
+        self.list_object_paths(ObjectType::Stream)
     }
 
-    pub fn is_excel(&self) -> bool {
-        //! Check if the file is an excel file.
+    pub fn list_storage_with_path(&self) -> Vec<String> {
+        //! Like [`OleFile::list_storage`], with each storage named by its full path below the
+        //! root, see [`OleFile::list_streams_with_path`].
+        self.list_object_paths(ObjectType::Storage)
+    }
+
+    pub fn header(&self) -> &OleHeader {
+        //! The file header as parsed, see [`OleFile::raw_header`] for the bytes it came from.
         //!
         //! ## Example usage
         //! ```rust
         //! use ole::OleFile;
         //!
+        //! # #[cfg(feature = "async")]
         //! #[tokio::main]
         //! async fn main() {
-        //! let file = "../data/maldoc.xls";
+        //! let file = "../data/oledoc1.doc_";
         //!
         //!     let res = OleFile::from_file(file).await.expect("file not found");
-        //!     assert!(res.is_excel());
+        //!     let header = res.header();
+        //!     assert_eq!((header.major_version, header.minor_version()), (3, 0x3E));
+        //!     assert_eq!(header.sector_size, 512);
         //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
         //! ```
-        return match self.file_type {
-            OleFileType::Excel5 | OleFileType::Excel97 => true,
-            _ => false,
-        };
+        &self.header
     }
 
-    pub fn open_stream(&self, stream_path: &[&str]) -> Result<Vec<u8>> {
-        if let Some(directory_entry) = self.find_stream(stream_path, None) {
-            if directory_entry.object_type == ObjectType::Stream {
-                let mut data = vec![];
-                let mut collected_bytes = 0;
-                // the unwrap is safe because the location is guaranteed to exist for this object type
-                let mut next_sector = directory_entry.starting_sector_location.unwrap();
-
-                if directory_entry.stream_size < self.header.standard_stream_min_size as u64 {
-                    // it's in the mini-FAT
-                    loop {
-                        if next_sector == constants::CHAIN_END {
-                            break;
-                        } else {
-                            let mut sector_data: Vec<u8> = vec![];
-                            for byte in self.mini_stream[next_sector as usize] {
-                                sector_data.push(byte);
-                                collected_bytes += 1;
-                                if collected_bytes == directory_entry.stream_size {
-                                    break;
-                                }
-                            }
-                            data.extend(sector_data)
-                        }
-                        next_sector = self.short_sector_allocation_table[next_sector as usize];
-                    }
-                } else {
-                    // it's in the FAT
-                    loop {
-                        if next_sector == constants::CHAIN_END {
-                            break;
-                        } else {
-                            let mut sector_data: Vec<u8> = vec![];
-                            for byte in &self.sectors[next_sector as usize] {
-                                sector_data.push(*byte);
-                                collected_bytes += 1;
-                                if collected_bytes == directory_entry.stream_size {
-                                    break;
-                                }
-                            }
-                            data.extend(sector_data)
-                        }
-                        next_sector = self.sector_allocation_table[next_sector as usize];
-                    }
-                }
-                // println!("data.len(): {}", data.len());
-                return Ok(data);
-            }
-        }
-
-        Err(Error::OleDirectoryEntryNotFound)
+    pub fn header_info(&self) -> HeaderInfo {
+        //! The layout figures from the file header.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert_eq!(res.header_info().sector_size, 512);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        HeaderInfo::from(&self.header)
     }
 
-    fn list_object(&self, object_type: ObjectType) -> Vec<String> {
-        self.directory_entries
-            .iter()
-            .filter_map(|entry| {
-                if entry.object_type == object_type {
-                    Some(entry.name.clone())
-                } else {
-                    None
-                }
-            })
-            .collect()
+    pub fn header_class_id(&self) -> Option<&str> {
+        //! The CLSID in the file header, which the spec reserves and wants zeroed. Only a
+        //! lenient parse lets through a file that sets it, and then this gives it.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert_eq!(res.header_class_id(), None);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        self.header.class_id.as_deref()
     }
 
-    fn find_stream(
-        &self,
-        stream_path: &[&str],
-        parent: Option<&DirectoryEntry>,
-    ) -> Option<&DirectoryEntry> {
-        let first_entry = stream_path[0];
-        let remainder = &stream_path[1..];
-        let remaining_len = remainder.len();
-
-        match parent {
-            Some(parent) => {
-                // println!("recursive_parent_entry: {:?}", parent);
-                // this is a recursive case
-                let mut entries_to_search = vec![];
-                if let Some(child_id) = parent.child_id {
-                    let child = self.directory_entries.get(child_id as usize).unwrap();
-                    entries_to_search.push((child, true));
-                }
-                if let Some(left_sibling_id) = parent.left_sibling_id {
-                    entries_to_search.push((
-                        self.directory_entries
-                            .get(left_sibling_id as usize)
-                            .unwrap(),
-                        false,
-                    ));
-                }
-                if let Some(right_sibling_id) = parent.right_sibling_id {
-                    entries_to_search.push((
-                        self.directory_entries
-                            .get(right_sibling_id as usize)
-                            .unwrap(),
-                        false,
-                    ));
-                }
-                for (entry, is_child) in entries_to_search {
-                    if entry.name == first_entry {
-                        return if remaining_len == 0 {
-                            // println!("found_entry: {:?}", entry);
-                            Some(entry)
-                        } else if is_child {
-                            self.find_stream(remainder, Some(entry))
-                        } else {
-                            self.find_stream(stream_path, Some(entry))
-                        };
-                    } else if let Some(found_entry) = self.find_stream(stream_path, Some(entry)) {
-                        return Some(found_entry);
-                    }
-                }
-                None
-            }
-            None => {
-                //this is the root case
-                if stream_path.is_empty() {
-                    return None;
-                }
-                if let Some(found_entry) = self
-                    .directory_entries
-                    .iter()
-                    .find(|entry| entry.name == first_entry)
-                {
-                    //handle this
-                    if remaining_len == 0 {
-                        // println!("found_entry: {:?}", found_entry);
-                        Some(found_entry)
-                    } else {
-                        self.find_stream(remainder, Some(found_entry))
-                    }
-                } else {
-                    None
-                }
-            }
-        }
+    pub fn stream_stats(&self) -> Vec<StreamStat> {
+        //! Every stream with its full path and size, in directory order, see [`OleFile::walk`].
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let stats = res.stream_stats();
+        //!     assert!(stats.iter().any(|stat| stat.name() == "WordDocument"));
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        analysis::stream_stats(self)
     }
 
-    async fn parse<R>(mut read: R) -> Result<Self>
-    where
-        R: Readable,
-    {
-        // read the header
-        let raw_file_header = parse_raw_header(&mut read).await?;
-        let file_header = OleHeader::from_raw(raw_file_header);
-        let sector_size = file_header.sector_size as usize;
-
-        //we have to read the remainder of the header if the sector size isn't what we tried to read
-        if sector_size > constants::HEADER_LENGTH {
-            let should_read_size = sector_size - constants::HEADER_LENGTH;
-            let mut should_read = vec![0u8; should_read_size];
-            let did_read_size = read.read(&mut should_read).await?;
-            if did_read_size != should_read_size {
-                return Err(Error::OleInvalidHeader(HeaderErrorType::NotEnoughBytes(
-                    should_read_size,
-                    did_read_size,
-                )));
-            } else if should_read != vec![0u8; should_read_size] {
-                return Err(Error::OleInvalidHeader(HeaderErrorType::Parsing(
-                    "all bytes must be zero for larger header sizes",
-                    "n/a".to_string(),
-                )));
-            }
+    pub fn file_type(&self) -> OleFileType {
+        //! What kind of document the file is, by the streams it has.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{ftype::OleFileType, OleFile};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert!(matches!(res.file_type(), OleFileType::Word97));
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        self.file_type
+    }
+
+    pub fn encryption_scheme(&self) -> Option<EncryptionScheme> {
+        //! How the content is encrypted, `None` unless [`OleFile::is_encrypted`].
+        self.encryption_scheme
+    }
+
+    pub fn raw_directory_bytes(&self) -> &[u8] {
+        //! The directory stream as read, unallocated entries and all, 128 bytes per entry.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert_eq!(res.raw_directory_bytes().len() % 128, 0);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        &self.directory_stream_data
+    }
+
+    pub fn raw_header(&self) -> &RawFileHeader {
+        //! The header as read, with the 512 bytes from the file, reserved fields included,
+        //! that [`OleHeader`] leaves out.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let raw = res.raw_header();
+        //!     assert_eq!(raw.signature(), [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]);
+        //!     assert_eq!(raw.bytes().len(), 512);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        &self.header.raw
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        //! Returns true or false if a file is encrypted/password protected
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/encryption/encrypted/rc4cryptoapi_password.doc";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert!(res.is_encrypted());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        self.encrypted
+    }
+
+    pub fn is_excel(&self) -> bool {
+        //! Check if the file is an excel file.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/maldoc.xls";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert!(res.is_excel());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        matches!(self.file_type, OleFileType::Excel5 | OleFileType::Excel97)
+    }
+
+    pub fn open_stream(&self, stream_path: &[&str]) -> Result<Vec<u8>> {
+        //! Read the whole stream at `stream_path` into memory. Version 4 files allow streams
+        //! over 4 GB, [`OleFile::stream_reader`] reads those without holding them at once.
+        //! A storage at `stream_path` is `Error::IsAStorage` with the names of its children,
+        //! nothing there at all `Error::OleDirectoryEntryNotFound`.
+        let directory_entry = self.stream_entry(self.get_entry(stream_path))?;
+        self.read_entry_data(directory_entry)
+    }
+
+    pub fn open_stream_path(&self, stream_path: &str) -> Result<Vec<u8>> {
+        //! Like [`OleFile::open_stream`], with the path given as one string, see
+        //! [`util::split_stream_path`] for the syntax.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert!(!res.open_stream_path("WordDocument").unwrap().is_empty());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let directory_entry = self.stream_entry(self.find_stream_path(stream_path))?;
+        self.read_entry_data(directory_entry)
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn open_streams(&self, stream_paths: &[&[&str]]) -> Vec<Result<Vec<u8>>> {
+        //! [`OleFile::open_stream`] for every path in `stream_paths`, read in parallel on the
+        //! rayon thread pool. The results are in the order of the paths, each stream failing on
+        //! its own.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //! let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+        //!
+        //! let res = OleFile::parse_sync(&data[..]).unwrap();
+        //! let streams = res.open_streams(&[&["WordDocument"], &["1Table"], &["Missing"]]);
+        //! assert_eq!(streams.len(), 3);
+        //! assert!(streams[0].is_ok() && streams[2].is_err());
+        //! ```
+        use rayon::prelude::*;
+        stream_paths
+            .par_iter()
+            .map(|stream_path| self.open_stream(stream_path))
+            .collect()
+    }
+
+    pub fn find_stream_path(&self, stream_path: &str) -> Option<&DirectoryEntry> {
+        //! The directory entry, stream or storage, at a path given as one string, see
+        //! [`util::split_stream_path`] for the syntax.
+        let components = util::split_stream_path(stream_path);
+        let components = components.iter().map(String::as_str).collect::<Vec<_>>();
+        self.get_entry(&components)
+    }
+
+    pub fn get_entry(&self, stream_path: &[&str]) -> Option<&DirectoryEntry> {
+        //! The directory entry, stream or storage, at `stream_path`, without reading any data.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let entry = res.get_entry(&["WordDocument"]).unwrap();
+        //!     assert!(entry.stream_size() > 0);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        if stream_path.is_empty() {
+            return None;
+        }
+        self.find_stream(stream_path)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<&DirectoryEntry> {
+        //! The first entry in directory order called `name`, wherever it is in the file, for a
+        //! name from [`OleFile::list_streams`] without its path. Unlike [`OleFile::get_entry`]
+        //! this doesn't follow the trees: which entry is found among several of the name
+        //! depends on the order of the directory.
+        self.entries()
+            .find(|entry| entry.index != 0 && self.names_match(&entry.name, name))
+    }
+
+    pub fn stream_size(&self, stream_path: &[&str]) -> Option<u64> {
+        //! Size of the stream at `stream_path`, `None` if there is no stream there.
+        self.get_entry(stream_path)
+            .filter(|entry| entry.object_type == ObjectType::Stream)
+            .map(|entry| entry.stream_size)
+    }
+
+    pub fn exists(&self, stream_path: &[&str]) -> bool {
+        //! Whether there is a stream or storage at `stream_path`.
+        self.get_entry(stream_path).is_some()
+    }
+
+    pub fn open_stream_range(
+        &self,
+        stream_path: &[&str],
+        offset: u64,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        //! Read `len` bytes of a stream starting at `offset`, walking the sector chain only as
+        //! far as needed. Fewer bytes are returned if the stream ends first.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let magic = res.open_stream_range(&["WordDocument"], 0, 2).unwrap();
+        //!     assert_eq!(magic, [0xEC, 0xA5]);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        use std::io::{Read, Seek, SeekFrom};
+
+        let entry = self.stream_entry(self.get_entry(stream_path))?;
+        let end = offset.saturating_add(len as u64).min(entry.stream_size);
+        let mut reader = OleStreamReader::with_limit(self, entry, end)?;
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![];
+        reader
+            .take(end.saturating_sub(offset))
+            .read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    pub fn copy_stream_to<W: std::io::Write + ?Sized>(
+        &self,
+        stream_path: &[&str],
+        writer: &mut W,
+    ) -> Result<u64> {
+        //! Write a stream into `writer` a sector at a time, without collecting it in memory
+        //! first. Returns the number of bytes written.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let mut sink = std::io::sink();
+        //!     let written = res.copy_stream_to(&["WordDocument"], &mut sink).unwrap();
+        //!     assert!(written > 0);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let mut reader = self.stream_reader(stream_path)?;
+        Ok(std::io::copy(&mut reader, writer)?)
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn copy_stream_to_async<W: tokio::io::AsyncWrite + Unpin + ?Sized>(
+        &self,
+        stream_path: &[&str],
+        writer: &mut W,
+    ) -> Result<u64> {
+        //! The async counterpart of [`OleFile::copy_stream_to`].
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let mut sink = tokio::io::sink();
+        //!     let written = res.copy_stream_to_async(&["WordDocument"], &mut sink).await.unwrap();
+        //!     assert!(written > 0);
+        //! }
+        //! ```
+        let mut reader = self.async_stream_reader(stream_path)?;
+        Ok(tokio::io::copy(&mut reader, writer).await?)
+    }
+
+    pub fn stream_reader(&self, stream_path: &[&str]) -> Result<OleStreamReader<'_>> {
+        //! Open a stream for incremental reading, see [`OleStreamReader`].
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //! use std::io::Read;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let mut reader = res.stream_reader(&["WordDocument"]).unwrap();
+        //!     let mut magic = [0u8; 2];
+        //!     reader.read_exact(&mut magic).unwrap();
+        //!     assert_eq!(magic, [0xEC, 0xA5]);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let directory_entry = self.stream_entry(self.get_entry(stream_path))?;
+        OleStreamReader::new(self, directory_entry)
+    }
+
+    pub fn stream_chunks(&self, stream_path: &[&str]) -> Result<StreamChunks<'_>> {
+        //! The data of the stream at `stream_path` one sector at a time, see [`StreamChunks`].
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let mut counts = [0u64; 256];
+        //!     for chunk in res.stream_chunks(&["WordDocument"]).unwrap() {
+        //!         for byte in chunk.unwrap().iter() {
+        //!             counts[*byte as usize] += 1;
+        //!         }
+        //!     }
+        //!     assert_eq!(counts.iter().sum::<u64>(), res.stream_size(&["WordDocument"]).unwrap());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let directory_entry = self.stream_entry(self.get_entry(stream_path))?;
+        StreamChunks::new(self, directory_entry)
+    }
+
+    #[cfg(feature = "async")]
+    pub fn async_stream_reader(&self, stream_path: &[&str]) -> Result<AsyncOleStreamReader<'_>> {
+        //! Open a stream as a tokio `AsyncRead + AsyncSeek`, see [`AsyncOleStreamReader`].
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //! use tokio::io::AsyncReadExt;
+        //!
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let mut reader = res.async_stream_reader(&["WordDocument"]).unwrap();
+        //!     let mut magic = [0u8; 2];
+        //!     reader.read_exact(&mut magic).await.unwrap();
+        //!     assert_eq!(magic, [0xEC, 0xA5]);
+        //! }
+        //! ```
+        self.stream_reader(stream_path)
+            .map(AsyncOleStreamReader::new)
+    }
+
+    pub fn fat(&self) -> &[u32] {
+        //! The FAT (AKA SAT) as read from the file: for every sector, the next sector of its
+        //! chain, or one of the special values in [`constants`]. [`OleFile::fat_entries`] tells
+        //! them apart.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{FatEntry, OleFile};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let first_fat_sector = res.header().sector_allocation_table_head[0];
+        //!     let value = res.fat()[first_fat_sector as usize];
+        //!     assert_eq!(FatEntry::from(value), FatEntry::FatSector);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        &self.sector_allocation_table
+    }
+
+    pub fn fat_entries(&self) -> impl Iterator<Item = FatEntry> + '_ {
+        //! [`OleFile::fat`] with the special values told apart, one [`FatEntry`] per sector.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{FatEntry, OleFile};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let free = res.fat_entries().filter(|entry| *entry == FatEntry::Free).count();
+        //!     println!("{} free sectors", free);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        self.sector_allocation_table
+            .iter()
+            .map(|value| FatEntry::from(*value))
+    }
+
+    pub fn mini_fat(&self) -> &[u32] {
+        //! The mini-FAT (AKA SSAT) as read from the file: for every 64 byte mini sector, the next
+        //! mini sector of its chain, or one of the special values in [`constants`].
+        &self.short_sector_allocation_table
+    }
+
+    pub fn mini_fat_entries(&self) -> impl Iterator<Item = FatEntry> + '_ {
+        //! [`OleFile::mini_fat`] with the special values told apart, one [`FatEntry`] per mini
+        //! sector.
+        self.short_sector_allocation_table
+            .iter()
+            .map(|value| FatEntry::from(*value))
+    }
+
+    pub fn mini_stream_bytes(&self) -> &[u8] {
+        //! The mini stream, the root entry's own stream that holds every stream smaller than the
+        //! cutoff, as read from the file. It includes the slack after the end of each small
+        //! stream up to the end of its last mini sector, and the ranges from
+        //! [`OleFile::mini_stream_chain`] index into it.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let mini_stream = res.mini_stream_bytes();
+        //!     assert_eq!(mini_stream.len() as u64, res.root().stream_size());
+        //!     assert!(mini_stream.len() / 64 <= res.mini_fat().len());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        &self.mini_stream[..self.mini_stream_len]
+    }
+
+    pub fn mini_stream_chain(&self, start: u32) -> Result<Vec<std::ops::Range<u64>>> {
+        //! Follow the mini-FAT chain starting at mini sector `start` and return the byte ranges it
+        //! covers within the root mini stream, in chain order.
+        //!
+        //! A chain that loops, or that steps outside the mini-FAT, is an error. Ranges are not
+        //! checked against the size of the mini stream or against the chains of other streams,
+        //! comparing them is left to the caller.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let ranges = res.mini_stream_chain(0).unwrap();
+        //!     assert_eq!(ranges[0], 0..64);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let chain = follow_chain(&self.short_sector_allocation_table, start, "mini sector")?;
+        Ok(chain
+            .into_iter()
+            .map(|id| {
+                let offset = id as u64 * constants::MINI_SECTOR_SIZE;
+                offset..offset + constants::MINI_SECTOR_SIZE
+            })
+            .collect())
+    }
+
+    pub fn stream_extents(&self, stream_path: &str) -> Result<Vec<std::ops::Range<u64>>> {
+        //! The byte ranges of the file that hold a stream, in stream order, see
+        //! [`util::split_stream_path`] for the path syntax.
+        //!
+        //! The ranges cover whole sectors (mini sectors for streams in the mini stream), so the
+        //! slack after the end of the data is included.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let extents = res.stream_extents("WordDocument").unwrap();
+        //!     let covered: u64 = extents.iter().map(|range| range.end - range.start).sum();
+        //!     assert!(covered >= res.open_stream_path("WordDocument").unwrap().len() as u64);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let entry = self.stream_entry(self.find_stream_path(stream_path))?;
+        let start = match entry.starting_sector_location {
+            Some(start) if entry.stream_size > 0 => start,
+            _ => return Ok(vec![]),
+        };
+        let sector_size = self.header.sector_size as u64;
+        let file_offset = |id: u32| (id as u64 + 1) * sector_size;
+        let at = |err: Error| err.at(entry.location());
+        if entry.stream_size >= self.header.standard_stream_min_size as u64 {
+            let chain = follow_chain(&self.sector_allocation_table, start, "sector").map_err(at)?;
+            return Ok(chain
+                .into_iter()
+                .map(|id| file_offset(id)..file_offset(id) + sector_size)
+                .collect());
+        }
+        // mini sectors are placed within the sectors of the root entry's chain
+        let root_chain = match self.root().starting_sector_location {
+            Some(root_start) => follow_chain(&self.sector_allocation_table, root_start, "sector")?,
+            None => vec![],
+        };
+        self.mini_stream_chain(start)
+            .map_err(at)?
+            .into_iter()
+            .map(|range| {
+                let sector = root_chain
+                    .get((range.start / sector_size) as usize)
+                    .ok_or_else(|| {
+                        Error::invalid_chain(format!(
+                            "mini stream offset {} is beyond the mini stream",
+                            range.start
+                        ))
+                        .at(entry.location())
+                    })?;
+                let offset = file_offset(*sector) + range.start % sector_size;
+                Ok(offset..offset + constants::MINI_SECTOR_SIZE)
+            })
+            .collect()
+    }
+
+    pub fn stream_slack(&self, stream_path: &[&str]) -> Result<Vec<u8>> {
+        //! The bytes of the sectors allocated to a stream that come after its end, which often
+        //! hold what an earlier save left there. Works for streams in the FAT and in the
+        //! mini-FAT alike. A chain that loops or leaves its table is an error.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let slack = res.stream_slack(&["WordDocument"]).unwrap();
+        //!     assert!(slack.len() < res.header_info().sector_size as usize);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let entry = self.stream_entry(self.get_entry(stream_path))?;
+        let start = match entry.starting_sector_location {
+            Some(start) if entry.stream_size > 0 => start,
+            _ => return Ok(vec![]),
+        };
+        let at = |err: Error| err.at(entry.location());
+        // only the sectors reaching past the end are read, the stream itself may be huge
+        let mut slack = vec![];
+        if entry.stream_size >= self.header.standard_stream_min_size as u64 {
+            let sector_size = self.header.sector_size as u64;
+            let chain = follow_chain(&self.sector_allocation_table, start, "sector").map_err(at)?;
+            for (position, id) in chain.into_iter().enumerate() {
+                let skip = entry
+                    .stream_size
+                    .saturating_sub(position as u64 * sector_size);
+                if skip < sector_size {
+                    slack.extend(&self.sectors.read(id).map_err(at)?[skip as usize..]);
+                }
+            }
+        } else {
+            let chain = follow_chain(&self.short_sector_allocation_table, start, "mini sector")
+                .map_err(at)?;
+            for (position, id) in chain.into_iter().enumerate() {
+                let skip = entry
+                    .stream_size
+                    .saturating_sub(position as u64 * constants::MINI_SECTOR_SIZE);
+                if skip < constants::MINI_SECTOR_SIZE {
+                    let sector = self.mini_sector(id).map_err(at)?;
+                    slack.extend_from_slice(sector.get(skip as usize..).unwrap_or_default());
+                }
+            }
+        }
+        Ok(slack)
+    }
+
+    #[cfg(feature = "manifest")]
+    pub fn manifest(&self) -> Result<Manifest> {
+        //! Record paths, sizes, SHA-256 digests, CLSIDs and timestamps of all entries, to check
+        //! the file against later with [`Manifest::verify`].
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{Manifest, OleFile};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let stored = res.manifest().unwrap().to_string();
+        //!     let manifest = stored.parse::<Manifest>().unwrap();
+        //!     assert!(manifest.verify(&res).unwrap().is_empty());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        Manifest::of(self)
+    }
+
+    pub fn doc_vars(&self) -> Result<Vec<DocVar>> {
+        //! The document variables of a Word document, names and values as stored in the table
+        //! stream. Empty for other files.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for var in res.doc_vars().unwrap() {
+        //!         println!("{} = {}", var.name, var.value);
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        word::doc_vars(self)
+    }
+
+    pub fn document_role(&self) -> Result<DocumentRole> {
+        //! Whether the file is a template (DOT, XLT) or an Excel add-in (XLA) rather than a
+        //! plain document, from the fDot flag of the FIB or the Template and ADDIN records of
+        //! the workbook.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{DocumentRole, OleFile};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert_eq!(res.document_role().unwrap(), DocumentRole::Document);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        ftype::document_role(self)
+    }
+
+    pub fn external_connections(&self) -> Result<Vec<ExternalConnection>> {
+        //! The connections of an Excel workbook to external data, from its query tables, data
+        //! connections and pivot caches, with the connection strings and URLs they hold. Empty
+        //! for other files.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/maldoc.xls";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for connection in res.external_connections().unwrap() {
+        //!         println!("{}: {:?}", connection.record, connection.urls());
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        excel::external_connections(self)
+    }
+
+    pub fn vba_projects(&self) -> Result<Vec<VbaProject>> {
+        //! The VBA projects of the file with their references and modules. References give the
+        //! registered libraries, projects and controls the code uses, with their paths. Modules
+        //! are classified as standard, class, form or document modules from the `dir` and
+        //! `PROJECT` streams, and document modules name the host object, such as ThisDocument
+        //! or Sheet1, whose events run their code.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for project in res.vba_projects().unwrap() {
+        //!         for module in project.modules {
+        //!             println!("{}: {:?} {:?}", module.name, module.kind, module.document_object());
+        //!         }
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        vba::vba_projects(self)
+    }
+
+    pub fn vba_module_source(&self, project: &VbaProject, module: &VbaModule) -> Result<String> {
+        //! The source code of a module of one of the [`OleFile::vba_projects`].
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for project in res.vba_projects().unwrap() {
+        //!         for module in &project.modules {
+        //!             println!("{}", res.vba_module_source(&project, module).unwrap());
+        //!         }
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        vba::module_source(self, project, module)
+    }
+
+    pub fn vba_autoexec(&self) -> Result<Vec<AutoExec>> {
+        //! The procedures of the VBA projects that Office runs by itself, such as `AutoOpen`
+        //! and `Document_Open` in Word or `Workbook_Open` in Excel, found by their declarations
+        //! in the module sources.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert!(res.vba_autoexec().unwrap().is_empty());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        vba::autoexec(self)
+    }
+
+    pub fn deobfuscate_vba(&self) -> Result<Vec<DeobfuscatedModule>> {
+        //! The source of every VBA module with the strings its code builds at run time:
+        //! `Chr` concatenations, `StrReverse`, character code arrays and `Xor` loops with a
+        //! constant key, evaluated statically. See [`crate::deobfuscate`] for running it on
+        //! source from elsewhere.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for module in res.deobfuscate_vba().unwrap() {
+        //!         for recovered in module.recovered {
+        //!             println!("{}: {}", module.module.name, recovered.value);
+        //!         }
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        deobfuscate::deobfuscate_vba(self)
+    }
+
+    pub fn warnings(&self) -> &[ParseWarning] {
+        //! What parsing let through instead of failing: header values the spec only recommends,
+        //! and whatever a lenient parse, such as [`OleFile::from_reader_lenient`], got past.
+        //! Each says what kind of anomaly it is and where it was found, and prints as a message.
+        //! Nothing is kept when [`OleParseOptions::collect_warnings`] is off.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     assert!(res.warnings().is_empty());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        &self.warnings
+    }
+
+    pub fn anomalies(&self) -> Vec<Anomaly> {
+        //! Structure that parses but that writers don't produce: FAT and mini FAT entries
+        //! linking past the end of the file or the mini stream, creation and modification times
+        //! that are no dates, in the future or set on streams or as the root's creation time,
+        //! stream chains that don't fit the stream size or are broken, and streams with a class
+        //! ID. Unlike [`OleFile::warnings`], nothing here is looked at while parsing.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for anomaly in res.anomalies() {
+        //!         println!("{:?}: {}", anomaly.kind, anomaly);
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        anomaly::anomalies(self)
+    }
+
+    pub fn parse_metrics(&self) -> &ParseMetrics {
+        //! How much parsing the file read and how long each phase took.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let metrics = res.parse_metrics();
+        //!     assert_eq!(metrics.bytes_read, std::fs::metadata(file).unwrap().len());
+        //!     println!("parsed in {:?}", metrics.total_time());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        &self.metrics
+    }
+
+    pub fn orphaned_entries(&self) -> Vec<OrphanedEntry> {
+        //! Directory entries marked unallocated that still carry a name, what deleting a stream
+        //! usually leaves behind. See [`OleFile::recover_orphan`] for their data.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for orphan in res.orphaned_entries() {
+        //!         let data = res.recover_orphan(&orphan);
+        //!         println!("{}: {:?} bytes recovered", orphan.name, data.map(|data| data.len()));
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        orphan::orphaned_entries(self)
+    }
+
+    pub fn recover_orphan(&self, orphan: &OrphanedEntry) -> Option<Vec<u8>> {
+        //! Best effort at the data of an orphaned entry: its old chain if that is still whole,
+        //! otherwise the sectors following its first one. The sectors may have been reused
+        //! since, so the data is a lead rather than proof. `None` if it runs past the end of
+        //! the file.
+        orphan::recover(self, orphan)
+    }
+
+    pub fn current_user(&self) -> Result<Option<CurrentUser>> {
+        //! The CurrentUserAtom of a PowerPoint presentation: the user name and release version
+        //! of the last save, and the offset its edit starts at. `None` for other files.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/encryption/plaintext/plain.ppt";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let user = res.current_user().unwrap().unwrap();
+        //!     assert_eq!(user.user_name, "Microsoft Office User");
+        //!     assert_eq!(user.release_version, 8);
+        //!     assert!(!user.encrypted);
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        powerpoint::current_user(self)
+    }
+
+    pub fn unused_sectors(&self) -> Vec<UnusedSector> {
+        //! The sectors marked free in the FAT and the mini sectors marked free in the mini FAT,
+        //! with their content. Deleted streams and data hidden from the directory end up here.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for sector in res.unused_sectors() {
+        //!         if sector.data.iter().any(|byte| *byte != 0) {
+        //!             println!("{:?} sector {} is free but not empty", sector.location, sector.index);
+        //!         }
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        orphan::unused_sectors(self)
+    }
+
+    pub fn ppt_edits(&self) -> Result<Vec<PptEdit>> {
+        //! The saves recorded in a PowerPoint presentation, the current one first, each with the
+        //! offsets of the objects it wrote and of the whole document as of that save. Objects
+        //! that later saves replaced can still be read with [`OleFile::ppt_record`]. Empty for
+        //! other files.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/encryption/plaintext/plain.ppt";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let edits = res.ppt_edits().unwrap();
+        //!     let current = &edits[0];
+        //!     let document = current.objects[&current.doc_persist_id_ref];
+        //!     assert!(!res.ppt_record(document).unwrap().is_empty());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        powerpoint::ppt_edits(self)
+    }
+
+    pub fn ppt_record(&self, offset: u32) -> Result<Vec<u8>> {
+        //! The record at `offset` in the `PowerPoint Document` stream, header included, such as
+        //! an object found through [`OleFile::ppt_edits`].
+        powerpoint::ppt_record(self, offset)
+    }
+
+    pub fn sector_map(&self) -> Vec<MappedSector> {
+        //! Every sector of the file with what it belongs to, the header first and then the
+        //! sectors in file order: the FAT, DIFAT, directory, mini FAT, mini stream or a stream,
+        //! the range lock sector, and otherwise whether the FAT marks it free.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{OleFile, SectorOwner};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for sector in res.sector_map() {
+        //!         if sector.owners.len() > 1 || sector.owners == [SectorOwner::Unreferenced] {
+        //!             println!("{:#x}: {:?}", sector.offset, sector.owners);
+        //!         }
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        sector_map::sector_map(self)
+    }
+
+    pub fn range_lock_sector(&self) -> Option<u32> {
+        //! The range lock sector, which holds file offset 0x7FFFFF00 and is only there for
+        //! writers to lock ranges of the file: sector 0x3FFFFE in a version 3 file and 0x7FFFE in
+        //! a version 4 one. `None` for files that end before it, which is most of them.
+        let id = constants::RANGE_LOCK_OFFSET / self.header.sector_size as u64 - 1;
+        (id < self.sectors.len() as u64).then_some(id as u32)
+    }
+
+    pub fn signatures(&self) -> Vec<Signature> {
+        //! The document and VBA project signatures, with the signer and signing time where the
+        //! envelope could be read. The signatures are not verified.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for signature in res.signatures() {
+        //!         println!("{:?} signed by {:?}", signature.kind, signature.signer);
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        signature::signatures(self)
+    }
+
+    pub fn word_objects(&self) -> Result<Vec<WordObject>> {
+        //! The pictures and OLE objects anchored in the text of a Word document, found through
+        //! its character formatting. Empty for other files.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{OleFile, WordObject};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let objects = res.word_objects().unwrap();
+        //!     assert!(objects.contains(&WordObject::Ole {
+        //!         storage: "_1549162656".to_string()
+        //!     }));
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        word::word_objects(self)
+    }
+
+    pub fn ole_objects(&self) -> Result<Vec<OleObject>> {
+        //! The storages of [`OleFile::embedded_object_entries`], each classified as an OLE 2
+        //! object keeping its data in the storage, an OLE 1.0 Packager object wrapping a file, or a link to data
+        //! outside the file. The root counts when the whole file is an object.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{OleFile, OleObjectKind};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let objects = res.ole_objects().unwrap();
+        //!     assert!(objects
+        //!         .iter()
+        //!         .any(|object| object.kind == OleObjectKind::Packager));
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        embedded::ole_objects(self)
+    }
+
+    pub fn embedded_object_entries(&self) -> Vec<ObjectEntry> {
+        //! The storages holding OLE objects, with what marks each of them: the `\x01Ole`,
+        //! `\x01Ole10Native` and `\x01CompObj` streams, a CLSID next to the data of an object,
+        //! or the names Word and Excel give the objects they embed. A CLSID alone and the
+        //! streams every Office document has at its root don't count. This is the one list [`OleFile::ole_objects`] and the
+        //! tools build on, so they agree on what is an object.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     let entries = res.embedded_object_entries();
+        //!     assert_eq!(entries.len(), res.ole_objects().unwrap().len());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        embedded::object_entries(self)
+    }
+
+    pub fn nested_ole_streams(&self) -> Vec<NestedOleStream> {
+        //! Find streams that carry an embedded compound file.
+        //!
+        //! Every stream is checked for the CFB magic at its start, and streams larger than a
+        //! sector are additionally scanned at each sector-aligned offset. Each hit is returned
+        //! with the bytes from the magic onwards, ready to be parsed as an OLE file of its own.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //!     let res = OleFile::from_file(file).await.expect("file not found");
+        //!     for nested in res.nested_ole_streams() {
+        //!         println!("{} @ {}", nested.stream_name, nested.offset);
+        //!     }
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        let sector_size = self.header.sector_size as usize;
+        self.entries()
+            .filter(|entry| entry.object_type == ObjectType::Stream)
+            .filter_map(|entry| self.read_entry_data(entry).ok().map(|data| (entry, data)))
+            .flat_map(|(entry, data)| {
+                nested::find_magic_offsets(&data, sector_size)
+                    .into_iter()
+                    .map(|offset| NestedOleStream {
+                        stream_name: entry.name.clone(),
+                        entry_index: entry.index,
+                        offset,
+                        data: data[offset..].to_vec(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    pub fn unwrap_nested(&self, limits: &UnwrapLimits) -> Unwrapped {
+        //! Parse the compound files nested in this one, and the ones nested in those, ...
+        //!
+        //! Content that was already unwrapped is skipped, so a file that contains itself can't
+        //! loop, and `limits` bounds the depth and the number of files. Whatever was not
+        //! unwrapped because of a limit is listed in `truncated`.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{OleFile, UnwrapLimits};
+        //!
+        //! # #[cfg(feature = "async")]
+        //! #[tokio::main]
+        //! async fn main() {
+        //!     let res = OleFile::from_file("../data/oledoc1.doc_").await.expect("file not found");
+        //!     let unwrapped = res.unwrap_nested(&UnwrapLimits::default());
+        //!     assert!(unwrapped.truncated.is_empty());
+        //! }
+        //! # #[cfg(not(feature = "async"))]
+        //! # fn main() {}
+        //! ```
+        nested::unwrap_nested(self, limits)
+    }
+
+    /// Mini sector `id`, borrowed from the mini stream. When the mini stream ends inside it,
+    /// the rest comes from the sector holding it, as far as the root entry's chain goes.
+    pub(crate) fn mini_sector(&self, id: u32) -> Result<&[u8]> {
+        let start = id as usize * constants::MINI_SECTOR_SIZE as usize;
+        if start >= self.mini_stream_len {
+            return Err(Error::sector_out_of_range(format!(
+                "mini sector {} is beyond the mini stream",
+                id
+            ))
+            .at(Location {
+                sector: Some(id),
+                ..Location::default()
+            }));
+        }
+        let end = (start + constants::MINI_SECTOR_SIZE as usize).min(self.mini_stream.len());
+        Ok(&self.mini_stream[start..end])
+    }
+
+    /// The entry found for a stream path if it is a stream, `IsAStorage` with its children
+    /// if it is a storage.
+    fn stream_entry<'s>(&'s self, entry: Option<&'s DirectoryEntry>) -> Result<&'s DirectoryEntry> {
+        match entry {
+            Some(entry) if entry.object_type == ObjectType::Stream => Ok(entry),
+            Some(storage) => Err(Error::IsAStorage {
+                children: self
+                    .child_entries(storage)
+                    .into_iter()
+                    .map(|child| child.name.clone())
+                    .collect(),
+            }),
+            None => Err(Error::OleDirectoryEntryNotFound),
+        }
+    }
+
+    fn read_entry_data(&self, directory_entry: &DirectoryEntry) -> Result<Vec<u8>> {
+        let size = directory_entry.stream_size;
+        if usize::try_from(size).is_err() {
+            return Err(Error::ResourceLimit(format!(
+                "stream {:?} of {} bytes does not fit in memory, read it with a stream reader",
+                directory_entry.name, size
+            )));
+        }
+        // writers put CHAIN_END, 0 or anything else at all in the start of an empty stream
+        if size == 0 {
+            return Ok(vec![]);
+        }
+        // the unwrap is safe because the location is guaranteed to exist for this object type
+        let start = directory_entry.starting_sector_location.unwrap();
+        let at = |err: Error| err.at(directory_entry.location());
+        let len = size as usize;
+
+        let data = if size < self.header.standard_stream_min_size as u64 {
+            // it's in the mini-FAT, where the chain has to be exactly as long as the stream
+            let chain = follow_chain(&self.short_sector_allocation_table, start, "mini sector")
+                .map_err(at)?;
+            let needed = size.div_ceil(constants::MINI_SECTOR_SIZE) as usize;
+            if chain.len() != needed {
+                return Err(Error::invalid_chain(format!(
+                    "the chain of {:?} has {} mini sectors, {} bytes need {}",
+                    directory_entry.name,
+                    chain.len(),
+                    size,
+                    needed
+                ))
+                .at(directory_entry.location()));
+            }
+            let mut data = Vec::with_capacity(len);
+            for id in chain {
+                let sector = self.mini_sector(id).map_err(at)?;
+                let take = (len - data.len()).min(sector.len());
+                data.extend_from_slice(&sector[..take]);
+            }
+            data
+        } else {
+            // it's in the FAT, sized by the chain too in case the size in the entry is made up
+            let chain = follow_chain(&self.sector_allocation_table, start, "sector").map_err(at)?;
+            let sector_size = self.header.sector_size as usize;
+            let mut data = Vec::with_capacity(len.min(chain.len().saturating_mul(sector_size)));
+            for id in chain {
+                if data.len() == len {
+                    break;
+                }
+                let sector = self.sectors.read(id).map_err(at)?;
+                let take = (len - data.len()).min(sector.len());
+                data.extend_from_slice(&sector[..take]);
+            }
+            data
+        };
+        trace!(
+            "read {} bytes from stream {:?}",
+            data.len(),
+            directory_entry.name
+        );
+        Ok(data)
+    }
+
+    fn list_object(&self, object_type: ObjectType) -> Vec<String> {
+        self.entries()
+            .filter_map(|entry| {
+                if entry.object_type == object_type {
+                    Some(entry.name.clone())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn list_object_paths(&self, object_type: ObjectType) -> Vec<String> {
+        self.walk()
+            .filter(|(_, entry)| entry.object_type == object_type)
+            .map(|(path, _)| path.to_string())
+            .collect()
+    }
+
+    fn names_match(&self, name: &str, wanted: &str) -> bool {
+        if self.exact_names {
+            name == wanted
+        } else {
+            directory::names_equal(name, wanted)
+        }
+    }
+
+    /// The entry at `stream_path`, one storage down per component, without recursion.
+    fn find_stream(&self, stream_path: &[&str]) -> Option<&DirectoryEntry> {
+        // the index keys names by their uppercase, byte for byte only the trees can tell
+        if let Some(found) = self
+            .name_index
+            .as_ref()
+            .filter(|_| !self.exact_names)
+            .and_then(|index| index.get(stream_path))
+        {
+            return found.and_then(|id| self.entry_by_id(id));
+        }
+        let mut found_entry = self.root();
+        for name in stream_path {
+            found_entry = self.find_child(found_entry, name)?;
+        }
+        trace!("found entry {:?}", found_entry.name);
+        Some(found_entry)
+    }
+
+    /// The child of `storage` called `name`, searched through the whole tree of children.
+    fn find_child(&self, storage: &DirectoryEntry, name: &str) -> Option<&DirectoryEntry> {
+        trace!("searching below entry {:?}", storage.name);
+        if self.entry_cache.is_some() {
+            // down the tree by name order, decoding a path instead of every child; a tree out
+            // of order still has the whole of it searched below
+            if let Some(entry) = self.descend_tree(storage, name) {
+                return Some(entry);
+            }
+        }
+        self.child_entries(storage)
+            .into_iter()
+            .find(|entry| self.names_match(&entry.name, name))
+    }
+
+    /// The child of `storage` called `name`, looked up like a valid red-black tree is searched.
+    fn descend_tree(&self, storage: &DirectoryEntry, name: &str) -> Option<&DirectoryEntry> {
+        let mut next = storage.child_id;
+        // a malformed tree can link entries in a cycle, and no path is longer than the entries
+        for _ in 0..self.id_count() {
+            let entry = self.entry_by_id(next?)?;
+            next = match tree::compare_names(name, &entry.name) {
+                Ordering::Less => entry.left_sibling_id,
+                Ordering::Greater => entry.right_sibling_id,
+                Ordering::Equal => {
+                    return Some(entry).filter(|entry| self.names_match(&entry.name, name))
+                }
+            };
+        }
+        None
+    }
+
+    /// The children of `storage`, in the order of their tree: left subtree, entry, right subtree.
+    fn child_entries(&self, storage: &DirectoryEntry) -> Vec<&DirectoryEntry> {
+        let mut children = vec![];
+        // a malformed tree can link entries in a cycle, so every entry is visited once at most;
+        // kept as long as the tree rather than the directory, this runs once per storage
+        let mut visited = std::collections::HashSet::new();
+        let mut pending = vec![];
+        let mut next = storage.child_id;
+        loop {
+            while let Some(id) = next {
+                if id as usize >= self.id_count() || !visited.insert(id) {
+                    break;
+                }
+                let entry = match self.entry_by_id(id) {
+                    Some(entry) => entry,
+                    None => break,
+                };
+                pending.push(entry);
+                next = entry.left_sibling_id;
+            }
+            let entry = match pending.pop() {
+                Some(entry) => entry,
+                None => return children,
+            };
+            children.push(entry);
+            next = entry.right_sibling_id;
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn parse<R>(mut read: R, options: &OleParseOptions) -> Result<Self>
+    where
+        R: constants::Readable,
+    {
+        let started = Instant::now();
+        let mut warnings = vec![];
+        // read the header
+        let raw_file_header = header::parse_raw_header(&mut read, options, &mut warnings).await?;
+        let file_header = OleHeader::from_raw(raw_file_header);
+        debug!("parsed header: {:?}", file_header);
+
+        //we have to read the remainder of the header if the sector size isn't what we tried to read
+        let mut remainder = vec![0u8; Self::header_remainder_len(&file_header)];
+        let did_read_size = read::read_full(&mut read, &mut remainder).await?;
+        Self::check_header_remainder(&remainder, did_read_size, &mut warnings)?;
+
+        let sector_size = file_header.sector_size as usize;
+        // with the `zeroize` feature the sectors are wiped when dropped, they may be decrypted
+        let mut data = SecretBuffer::default();
+        loop {
+            let start = data.len();
+            data.reserve(sector_size);
+            data.resize(start + sector_size, 0);
+            let actually_read_size = read::read_full(&mut read, &mut data[start..]).await?;
+            if !Self::push_sector(
+                &mut data,
+                sector_size,
+                actually_read_size,
+                options,
+                &mut warnings,
+            )? {
+                break;
+            }
         }
+        let sectors = Sectors::Owned {
+            data: Arc::new(data),
+            sector_size,
+        };
+        Self::from_sectors(file_header, sectors, started, options, warnings)
+    }
+
+    pub fn parse_sync<R>(read: R) -> Result<Self>
+    where
+        R: std::io::Read,
+    {
+        //! Parse an OLE file from a synchronous reader, without any async runtime
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //! let file = std::fs::File::open("../data/oledoc1.doc_").unwrap();
+        //!
+        //! let res = OleFile::parse_sync(std::io::BufReader::new(file));
+        //! assert!(res.is_ok())
+        //! ```
+        Self::parse_sync_with_options(read, &OleParseOptions::strict())
+    }
+
+    pub fn parse_sync_with_options<R>(read: R, options: &OleParseOptions) -> Result<Self>
+    where
+        R: std::io::Read,
+    {
+        //! The synchronous counterpart of [`OleFile::from_reader_with_options`]
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::{OleFile, OleParseOptions};
+        //! let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+        //! let options = OleParseOptions {
+        //!     collect_warnings: false,
+        //!     ..OleParseOptions::lenient()
+        //! };
+        //!
+        //! let res = OleFile::parse_sync_with_options(&data[..data.len() - 100], &options).unwrap();
+        //! assert!(res.warnings().is_empty());
+        //! ```
+        let started = Instant::now();
+        let mut warnings = vec![];
+        let (file_header, sectors) = Self::read_sectors_sync(read, options, &mut warnings)?;
+        Self::from_sectors(file_header, sectors, started, options, warnings)
+    }
+
+    pub fn parse_lenient_sync<R>(read: R) -> Result<Self>
+    where
+        R: std::io::Read,
+    {
+        //! The synchronous counterpart of [`OleFile::from_reader_lenient`]: a last sector cut
+        //! short is zero-padded and recorded in [`OleFile::warnings`]
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //! let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+        //!
+        //! let res = OleFile::parse_lenient_sync(&data[..data.len() - 100]).unwrap();
+        //! assert_eq!(res.warnings().len(), 1);
+        //! ```
+        Self::parse_sync_with_options(read, &OleParseOptions::lenient())
+    }
+
+    /// The header and every sector after it, read into memory, with whatever `options` let
+    /// through added to `warnings`.
+    fn read_sectors_sync<R>(
+        mut read: R,
+        options: &OleParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<(OleHeader, Sectors)>
+    where
+        R: std::io::Read,
+    {
+        let raw_file_header = parse_raw_header_sync(&mut read, options, warnings)?;
+        let file_header = OleHeader::from_raw(raw_file_header);
+        debug!("parsed header: {:?}", file_header);
 
-        let mut sectors = vec![];
+        let mut remainder = vec![0u8; Self::header_remainder_len(&file_header)];
+        let did_read_size = read_full_sync(&mut read, &mut remainder)?;
+        Self::check_header_remainder(&remainder, did_read_size, warnings)?;
+
+        let sector_size = file_header.sector_size as usize;
+        // with the `zeroize` feature the sectors are wiped when dropped, they may be decrypted
+        let mut data = SecretBuffer::default();
         loop {
-            let mut buf = vec![0u8; sector_size];
-            match read.read(&mut buf).await {
-                Ok(actually_read_size) if actually_read_size == sector_size => {
-                    sectors.push((&buf[0..actually_read_size]).to_vec());
-                }
-                Ok(wrong_size) if wrong_size != 0 => {
-                    // TODO: we might have to handle the case where the
-                    //      last sector isn't actually complete. Not sure yet.
-                    //      the spec says the entire file has to be present here,
-                    //      with equal sectors, so I'm doing it this way.
-                    return Err(Error::OleUnexpectedEof(format!(
-                        "short read when parsing sector number: {}",
-                        sectors.len()
-                    )));
+            let start = data.len();
+            data.reserve(sector_size);
+            data.resize(start + sector_size, 0);
+            let actually_read_size = read_full_sync(&mut read, &mut data[start..])?;
+            if !Self::push_sector(
+                &mut data,
+                sector_size,
+                actually_read_size,
+                options,
+                warnings,
+            )? {
+                break;
+            }
+        }
+        let sectors = Sectors::Owned {
+            data: Arc::new(data),
+            sector_size,
+        };
+        Ok((file_header, sectors))
+    }
+
+    #[cfg(feature = "mmap")]
+    pub fn from_mmap<P: AsRef<std::path::Path>>(file: P) -> Result<Self> {
+        //! Memory-map an OLE file and parse it without copying its sectors
+        //!
+        //! The sectors stay slices of the mapping, so only the allocation tables and the
+        //! directory take up memory of their own. The file must not be changed while the
+        //! parsed `OleFile` (or a clone of it) is alive.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //! let res = OleFile::from_mmap(file);
+        //! assert!(res.is_ok())
+        //! ```
+        let started = Instant::now();
+        let f = std::fs::File::open(file)?;
+        // SAFETY: the mapping is read only, and the caller is told not to modify the file
+        let map = unsafe { memmap2::Mmap::map(&f)? };
+        let mut read = &map[..];
+        let options = OleParseOptions::strict();
+        let mut warnings = vec![];
+        let raw_file_header = parse_raw_header_sync(&mut read, &options, &mut warnings)?;
+        let file_header = OleHeader::from_raw(raw_file_header);
+        debug!("parsed header: {:?}", file_header);
+
+        let mut remainder = vec![0u8; Self::header_remainder_len(&file_header)];
+        let did_read_size = read_full_sync(&mut read, &mut remainder)?;
+        Self::check_header_remainder(&remainder, did_read_size, &mut warnings)?;
+
+        let sector_size = file_header.sector_size as usize;
+        if read.len() % sector_size != 0 {
+            // the mapping holds the header too, the cut sector starts at the last whole one
+            let cut = read.len() / sector_size;
+            return Err(Error::unexpected_eof(format!(
+                "short read when parsing sector number: {}",
+                cut
+            ))
+            .at(Location {
+                sector: Some(cut.saturating_sub(1) as u32),
+                offset: Some((cut * sector_size) as u64),
+                ..Location::default()
+            }));
+        }
+        Self::from_sectors(
+            file_header,
+            Sectors::Mapped {
+                map: std::sync::Arc::new(map),
+                sector_size,
+            },
+            started,
+            &options,
+            warnings,
+        )
+    }
+
+    pub fn open_lazy<P: AsRef<std::path::Path>>(file: P) -> Result<Self> {
+        //! Open an OLE file, reading only the sectors that are needed
+        //!
+        //! The header, allocation tables and directory are read up front. Stream data is read
+        //! when the stream is opened, and the most recently used sectors are cached.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //! let res = OleFile::open_lazy(file).expect("file not found");
+        //! assert!(res.open_stream(&["WordDocument"]).is_ok())
+        //! ```
+        let f = std::fs::File::open(file)?;
+        Self::from_seekable(std::io::BufReader::new(f), constants::DEFAULT_SECTOR_CACHE)
+    }
+
+    pub fn from_seekable<R>(read: R, cache_sectors: usize) -> Result<Self>
+    where
+        R: std::io::Read + std::io::Seek + Send + 'static,
+    {
+        //! Parse an OLE file from a seekable reader, reading sectors on demand and keeping
+        //! up to `cache_sectors` of them around
+        Self::from_seekable_with_options(read, cache_sectors, &OleParseOptions::strict())
+    }
+
+    pub fn open_bounded<P: AsRef<std::path::Path>>(file: P, max_memory: usize) -> Result<Self> {
+        //! Open an OLE file holding about `max_memory` bytes of it in memory at most, however
+        //! large it is: for compound files of many gigabytes in services.
+        //!
+        //! Sectors are read on demand, with a quarter of `max_memory` caching them, but no more
+        //! than [`constants::MAX_BOUNDED_SECTOR_CACHE`] sectors of 4096 bytes (16 MiB). The rest
+        //! bounds the allocation tables, the directory and the mini stream, see
+        //! [`OleParseOptions::max_memory`]: a file needing more is refused with
+        //! `ResourceLimit`. Directory entries are decoded when first looked up, see
+        //! [`OleParseOptions::lazy_directory`]. Extract the streams with
+        //! [`OleFile::copy_stream_to`], which writes them out a sector at a time; reading one
+        //! with `open_stream` takes all of it into memory.
+        //!
+        //! ## Example usage
+        //! ```rust
+        //! use ole::OleFile;
+        //! let file = "../data/oledoc1.doc_";
+        //!
+        //! let res = OleFile::open_bounded(file, 1 << 20).expect("file not found");
+        //! let mut sink = std::io::sink();
+        //! assert!(res.copy_stream_to(&["WordDocument"], &mut sink).unwrap() > 0);
+        //! ```
+        let f = std::fs::File::open(file)?;
+        Self::from_seekable_bounded(std::io::BufReader::new(f), max_memory)
+    }
+
+    pub fn from_seekable_bounded<R>(read: R, max_memory: usize) -> Result<Self>
+    where
+        R: std::io::Read + std::io::Seek + Send + 'static,
+    {
+        //! [`OleFile::open_bounded`] for any seekable reader.
+        // counted in the larger sectors of version 4, whatever the version of the file
+        const SECTOR_SIZE: usize = 4096;
+        let cache_sectors =
+            (max_memory / 4 / SECTOR_SIZE).clamp(1, constants::MAX_BOUNDED_SECTOR_CACHE);
+        let options = OleParseOptions {
+            lazy_directory: true,
+            max_memory: Some(max_memory.saturating_sub(cache_sectors * SECTOR_SIZE)),
+            ..OleParseOptions::strict()
+        };
+        Self::from_seekable_with_options(read, cache_sectors, &options)
+    }
+
+    pub fn from_seekable_with_options<R>(
+        mut read: R,
+        cache_sectors: usize,
+        options: &OleParseOptions,
+    ) -> Result<Self>
+    where
+        R: std::io::Read + std::io::Seek + Send + 'static,
+    {
+        //! [`OleFile::from_seekable`] with the parse adjusted by `options`.
+        let started = Instant::now();
+        let mut warnings = vec![];
+        let raw_file_header = parse_raw_header_sync(&mut read, options, &mut warnings)?;
+        let file_header = OleHeader::from_raw(raw_file_header);
+        debug!("parsed header: {:?}", file_header);
+
+        let mut remainder = vec![0u8; Self::header_remainder_len(&file_header)];
+        let did_read_size = read_full_sync(&mut read, &mut remainder)?;
+        Self::check_header_remainder(&remainder, did_read_size, &mut warnings)?;
+
+        let sectors = LazySectors::new(
+            Box::new(read),
+            file_header.sector_size as usize,
+            cache_sectors,
+        )?;
+        Self::from_sectors(
+            file_header,
+            Sectors::Lazy(std::sync::Arc::new(std::sync::Mutex::new(sectors))),
+            started,
+            options,
+            warnings,
+        )
+    }
+
+    /// The header takes up a whole sector, of which only the first 512 bytes are used.
+    fn header_remainder_len(header: &OleHeader) -> usize {
+        (header.sector_size as usize).saturating_sub(constants::HEADER_LENGTH)
+    }
+
+    /// The spec wants the rest of the header sector zeroed, but writers often leave whatever
+    /// was in their buffer there. Nothing is read from it, so non-zero bytes are only a warning.
+    fn check_header_remainder(
+        remainder: &[u8],
+        did_read_size: usize,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<()> {
+        if did_read_size != remainder.len() {
+            return Err(Error::OleInvalidHeader(HeaderErrorType::NotEnoughBytes(
+                remainder.len(),
+                did_read_size,
+            )));
+        }
+        let non_zero = remainder.iter().filter(|byte| **byte != 0).count();
+        if let Some(first) = remainder.iter().position(|byte| *byte != 0) {
+            let warning = format!(
+                "header: {} of the {} bytes after the header are not zero, they were skipped",
+                non_zero,
+                remainder.len()
+            );
+            warn!("{}", warning);
+            warnings.push(
+                ParseWarning::new(ParseWarningKind::HeaderSlack, warning).at(Location {
+                    offset: Some((constants::HEADER_LENGTH + first) as u64),
+                    ..Location::default()
+                }),
+            );
+        }
+        Ok(())
+    }
+
+    /// Keep the sector just read from the input onto the end of `data`, `false` once the input
+    /// has ended. The spec wants every sector complete, so a short one is an error, unless
+    /// `options` allow truncation: then it is kept zero-padded as the last sector and
+    /// `warnings` say so.
+    fn push_sector(
+        data: &mut Vec<u8>,
+        sector_size: usize,
+        actually_read_size: usize,
+        options: &OleParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<bool> {
+        // the sector just read is already in place, zeroed past what was read
+        let index = data.len() / sector_size - 1;
+        let location = |offset: u64| Location {
+            sector: Some(index as u32),
+            offset: Some((index as u64 + 1) * sector_size as u64 + offset),
+            ..Location::default()
+        };
+        if actually_read_size > 0 && index >= options.max_sectors {
+            Err(Error::ResourceLimit(format!(
+                "more than {} sectors",
+                options.max_sectors
+            )))
+        } else if actually_read_size == sector_size {
+            Ok(true)
+        } else if actually_read_size == 0 {
+            data.truncate(index * sector_size);
+            Ok(false)
+        } else if options.allow_truncation {
+            let warning = format!(
+                "sector {} is cut short at {} of {} bytes, padded with zeros",
+                index, actually_read_size, sector_size
+            );
+            warn!("{}", warning);
+            warnings.push(
+                ParseWarning::new(ParseWarningKind::TruncatedSector, warning)
+                    .at(location(actually_read_size as u64)),
+            );
+            Ok(false)
+        } else {
+            Err(
+                Error::unexpected_eof(format!("short read when parsing sector number: {}", index))
+                    .at(location(0)),
+            )
+        }
+    }
+
+    /// Parse the tables and the directory. `started` is when reading the input began,
+    /// `warnings` is what reading it let through so far.
+    fn from_sectors(
+        file_header: OleHeader,
+        sectors: Sectors,
+        started: Instant,
+        options: &OleParseOptions,
+        warnings: Vec<ParseWarning>,
+    ) -> Result<Self> {
+        debug!(
+            "read {} sectors of {} bytes",
+            sectors.len(),
+            file_header.sector_size
+        );
+        if sectors.len() > options.max_sectors {
+            return Err(Error::ResourceLimit(format!(
+                "{} sectors, more than {}",
+                sectors.len(),
+                options.max_sectors
+            )));
+        }
+        let read_time = started.elapsed();
+
+        let phase = Instant::now();
+        let mut self_to_init = Self::with_allocation_table(file_header, sectors, options)?;
+        if options.collect_warnings {
+            self_to_init.warnings = warnings;
+        }
+        let fat_time = phase.elapsed();
+        // the mini FAT is read before the directory, it is counted with the mini stream
+        let phase = Instant::now();
+        self_to_init.initialize_short_sector_allocation_table()?;
+        let mut mini_stream_time = phase.elapsed();
+        let phase = Instant::now();
+        self_to_init.initialize_directory_stream()?;
+        let directory_time = phase.elapsed();
+        self_to_init.check_header_counts()?;
+        // only walks the whole directory when it is decoded anyway
+        if !self_to_init.options.lazy_directory {
+            self_to_init.check_storage_depth()?;
+            self_to_init.name_index = NameIndex::build(&self_to_init).map(Arc::new);
+        }
+        let phase = Instant::now();
+        self_to_init.initialize_mini_stream()?;
+        mini_stream_time += phase.elapsed();
+        let phase = Instant::now();
+        self_to_init.file_type = ftype::file_type(self_to_init.root());
+        self_to_init.encrypted = encryption::is_encrypted(&self_to_init);
+        if self_to_init.encrypted {
+            self_to_init.encryption_scheme = encryption::encryption_scheme(&self_to_init);
+        }
+        self_to_init.metrics = ParseMetrics {
+            bytes_read: self_to_init.header.sector_size as u64
+                + self_to_init
+                    .sectors
+                    .bytes_read(self_to_init.header.sector_size as u64),
+            sectors: self_to_init.sectors.len(),
+            directory_entries: self_to_init.decoded_entries(),
+            memory: self_to_init.memory(),
+            read_time,
+            fat_time,
+            directory_time,
+            mini_stream_time,
+            classify_time: phase.elapsed(),
+        };
+        debug!(
+            "parsed {} directory entries, file type {:?}, encrypted: {}",
+            self_to_init.decoded_entries(),
+            self_to_init.file_type,
+            self_to_init.encrypted
+        );
+        Ok(self_to_init)
+    }
+
+    /// A file of which only the header and the FAT are read, the first step of parsing.
+    fn with_allocation_table(
+        file_header: OleHeader,
+        sectors: Sectors,
+        options: &OleParseOptions,
+    ) -> Result<Self> {
+        let mut self_to_init = OleFile {
+            header: file_header,
+            sectors,
+            sector_allocation_table: vec![],
+            short_sector_allocation_table: vec![],
+            directory_stream_data: vec![],
+            directory_entries: vec![],
+            entry_cache: None,
+            name_index: None,
+            mini_stream: Arc::default(),
+            mini_stream_len: 0,
+            file_type: OleFileType::Generic,
+            encrypted: false,
+            encryption_scheme: None,
+            exact_names: false,
+            metrics: ParseMetrics::default(),
+            warnings: vec![],
+            options: options.clone(),
+        };
+        self_to_init.initialize_sector_allocation_table()?;
+        Ok(self_to_init)
+    }
+
+    fn initialize_sector_allocation_table(&mut self) -> Result<()> {
+        let sector_indices = self.sector_allocation_table_sector_indices()?;
+        self.check_memory(
+            "the FAT",
+            sector_indices.len() * self.header.sector_size as usize,
+        )?;
+        for sector_index in sector_indices {
+            trace!("FAT sector index: {:#x?}", sector_index);
+            let sector = self.sectors.read(sector_index)?;
+            self.sector_allocation_table.extend(
+                sector
+                    .chunks_exact(4)
+                    .map(|quad| u32::from_le_bytes([quad[0], quad[1], quad[2], quad[3]])),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Locations of all FAT sectors: the first 109 come from the header, the remainder from
+    /// the chain of DIFAT (MSAT) sectors, each of which ends with the location of the next one.
+    fn sector_allocation_table_sector_indices(&self) -> Result<Vec<u32>> {
+        let is_end = |sector_index: &u32| {
+            *sector_index == constants::UNALLOCATED_SECTOR || *sector_index == constants::CHAIN_END
+        };
+        let mut sector_indices = self
+            .header
+            .sector_allocation_table_head
+            .iter()
+            .copied()
+            .take_while(|sector_index| !is_end(sector_index))
+            .collect::<Vec<_>>();
+
+        let mut next_difat_sector = self.header.master_sector_allocation_table_first_sector;
+        let mut visited = std::collections::HashSet::new();
+        // the header count comes from the file, a chain can't be longer than the file is
+        let difat_len =
+            (self.header.master_sector_allocation_table_len as usize).min(self.sectors.len());
+        for _ in 0..difat_len {
+            if is_end(&next_difat_sector) {
+                break;
+            }
+            if !visited.insert(next_difat_sector) {
+                return Err(Error::invalid_chain(format!(
+                    "DIFAT sector {} is visited twice",
+                    next_difat_sector
+                ))
+                .at(Location {
+                    sector: Some(next_difat_sector),
+                    ..Location::default()
+                }));
+            }
+            let difat_sector = self
+                .sectors
+                .read(next_difat_sector)?
+                .chunks_exact(4)
+                .map(|quad| u32::from_le_bytes([quad[0], quad[1], quad[2], quad[3]]))
+                .collect::<Vec<_>>();
+            let (entries, next) = difat_sector.split_at(difat_sector.len() - 1);
+            trace!(
+                "DIFAT sector {:#x?} holds {} FAT sector locations",
+                next_difat_sector,
+                entries.len()
+            );
+            sector_indices.extend(
+                entries
+                    .iter()
+                    .copied()
+                    .take_while(|sector_index| !is_end(sector_index)),
+            );
+            next_difat_sector = next[0];
+            if sector_indices.len() > self.options.max_sectors {
+                return Err(Error::ResourceLimit(format!(
+                    "the DIFAT lists more than {} FAT sectors",
+                    self.options.max_sectors
+                )));
+            }
+            self.check_memory(
+                "the FAT",
+                sector_indices.len() * self.header.sector_size as usize,
+            )?;
+        }
+
+        Ok(sector_indices)
+    }
+
+    fn initialize_short_sector_allocation_table(&mut self) -> Result<()> {
+        if self.header.short_sector_allocation_table_len == 0
+            || self.header.short_sector_allocation_table_first_sector == constants::CHAIN_END
+        {
+            return Ok(()); // no mini stream here
+        }
+
+        let chain = follow_chain(
+            &self.sector_allocation_table,
+            self.header.short_sector_allocation_table_first_sector,
+            "sector",
+        )?;
+        self.check_memory(
+            "the mini FAT",
+            chain.len() * self.header.sector_size as usize,
+        )?;
+        for index in chain {
+            let sector = self.sectors.read(index)?;
+            self.short_sector_allocation_table.extend(
+                sector
+                    .chunks_exact(4)
+                    .map(|quad| u32::from_le_bytes([quad[0], quad[1], quad[2], quad[3]])),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn initialize_directory_stream(&mut self) -> Result<()> {
+        let first_directory_index = self.header.sector_allocation_table_first_sector;
+        // the directory has at least the sector holding the root entry
+        self.directory_stream_data
+            .extend(self.sectors.read(first_directory_index)?.iter());
+        let chain = follow_chain(
+            &self.sector_allocation_table,
+            first_directory_index,
+            "sector",
+        )?;
+        self.check_memory(
+            "the directory",
+            chain.len().saturating_sub(1) * self.header.sector_size as usize,
+        )?;
+        for index in chain.into_iter().skip(1) {
+            self.directory_stream_data
+                .extend(self.sectors.read(index)?.iter());
+        }
+
+        self.initialize_directory_entries()?;
+
+        Ok(())
+    }
+
+    /// Refuse directories nesting storages deeper than the options allow. Every path is as long
+    /// as its entry is deep, so a crafted chain of storages would make walking the directory
+    /// quadratic in time and memory.
+    fn check_storage_depth(&self) -> Result<()> {
+        let limit = self.options.max_storage_depth;
+        // a malformed tree can link entries in a cycle, so every entry is visited once at most
+        let mut visited = vec![false; self.id_count()];
+        let mut pending = self
+            .root()
+            .child_id
+            .map(|id| (id, 0))
+            .into_iter()
+            .collect::<Vec<_>>();
+        while let Some((id, depth)) = pending.pop() {
+            let entry = match self.entry_by_id(id) {
+                Some(entry) if !visited[id as usize] => entry,
+                _ => continue,
+            };
+            visited[id as usize] = true;
+            if depth > limit {
+                return Err(Error::ResourceLimit(format!(
+                    "entry {:?} is {} storages deep, more than {}",
+                    entry.name, depth, limit
+                )));
+            }
+            for sibling in [entry.left_sibling_id, entry.right_sibling_id]
+                .into_iter()
+                .flatten()
+            {
+                pending.push((sibling, depth));
+            }
+            if let Some(child) = entry.child_id {
+                pending.push((child, depth + 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compare the sector counts in the header with the chains that were read. Office keeps
+    /// them in step, so a mismatch means corruption or a file built to confuse parsers that
+    /// trust one over the other. Only a strict parse fails on it.
+    fn check_header_counts(&mut self) -> Result<()> {
+        let ids_per_sector = self.header.sector_size as usize / 4;
+        let mini_fat_sectors =
+            if self.header.short_sector_allocation_table_first_sector == constants::CHAIN_END {
+                0
+            } else {
+                self.short_sector_allocation_table.len() / ids_per_sector
+            };
+        let mut counts = vec![
+            (
+                "sector_allocation_table_len",
+                "FAT",
+                self.header.sector_allocation_table_len,
+                self.sector_allocation_table.len() / ids_per_sector,
+            ),
+            (
+                "short_sector_allocation_table_len",
+                "mini FAT",
+                self.header.short_sector_allocation_table_len,
+                mini_fat_sectors,
+            ),
+        ];
+        // version 3 files leave the directory count at zero
+        if self.header.major_version != constants::MAJOR_VERSION_3_VALUE {
+            counts.push((
+                "directory_sectors_len",
+                "directory",
+                self.header.directory_sectors_len,
+                self.directory_stream_data.len() / self.header.sector_size as usize,
+            ));
+        }
+        let mut warnings = vec![];
+        for (field, table, declared, found) in counts {
+            let checked = if declared as usize == found {
+                Ok(())
+            } else {
+                Err(Error::OleInvalidHeader(HeaderErrorType::Parsing(
+                    field,
+                    format!("{} {} sectors declared, {} found", declared, table, found),
+                )))
+            };
+            header::relaxed(checked, self.options.strict_counts, &mut warnings)?;
+        }
+        if self.options.collect_warnings {
+            self.warnings.extend(warnings);
+        }
+        Ok(())
+    }
+
+    fn initialize_directory_entries(&mut self) -> Result<()> {
+        if self.directory_stream_data.len() % constants::SIZE_OF_DIRECTORY_ENTRY != 0 {
+            return Err(Error::invalid_entry(
+                "directory_stream_size",
+                format!(
+                    "size of directory stream data is not correct? {}",
+                    self.directory_stream_data.len()
+                ),
+            ));
+        }
+        let entry_count = self.directory_stream_data.len() / constants::SIZE_OF_DIRECTORY_ENTRY;
+        if entry_count > self.options.max_directory_entries {
+            return Err(Error::ResourceLimit(format!(
+                "{} directory entries, more than {}",
+                entry_count, self.options.max_directory_entries
+            )));
+        }
+
+        // a lazy directory only has the root decoded here, everything is found through it
+        let decoded_count = if self.options.lazy_directory {
+            1
+        } else {
+            entry_count
+        };
+        let mut entries = Vec::with_capacity(decoded_count);
+        let mut warnings = vec![];
+        for index in 0..decoded_count {
+            match self.parse_entry(index, &mut warnings) {
+                Ok(directory_entry) => entries.push(directory_entry),
+                Err(Error::OleUnknownOrUnallocatedDirectoryEntry) => {
+                    trace!("skipping unallocated directory entry {}", index);
+                    continue;
                 }
-                Ok(_empty) => {
-                    break;
+                Err(anything_else) => return Err(anything_else),
+            }
+        }
+        // everything below the root is found through the first entry
+        if entries.first().map(|root| root.index) != Some(0) {
+            return Err(Error::invalid_entry(
+                "object_type",
+                "the first directory entry is unallocated".to_string(),
+            )
+            .at(self.directory_entry_location(0, None)));
+        }
+        if self.options.lazy_directory {
+            self.check_memory(
+                "the directory entries",
+                entry_count * std::mem::size_of::<OnceLock<Option<DirectoryEntry>>>(),
+            )?;
+            let slots = std::iter::once(OnceLock::from(entries.pop()))
+                .chain((1..entry_count).map(|_| OnceLock::new()))
+                .collect();
+            self.entry_cache = Some(Arc::new(slots));
+        } else {
+            self.directory_entries = entries;
+        }
+        if self.options.collect_warnings {
+            self.warnings.extend(warnings);
+        }
+
+        Ok(())
+    }
+
+    /// Parse directory entry `index` of the directory stream, which holds at least `index + 1`.
+    fn parse_entry(
+        &self,
+        index: usize,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<DirectoryEntry> {
+        let offset = index * constants::SIZE_OF_DIRECTORY_ENTRY;
+        let unparsed_entry =
+            &self.directory_stream_data[offset..offset + constants::SIZE_OF_DIRECTORY_ENTRY];
+        DirectoryEntryRaw::parse(unparsed_entry)
+            .and_then(|raw_directory_entry| {
+                DirectoryEntry::from_raw(
+                    &self.header,
+                    raw_directory_entry,
+                    index,
+                    &self.options,
+                    warnings,
+                )
+            })
+            .map_err(|err| match err {
+                Error::OleUnknownOrUnallocatedDirectoryEntry => err,
+                anything_else => {
+                    let name = DirectoryEntryRaw::parse(unparsed_entry)
+                        .ok()
+                        .map(|raw| raw.name());
+                    anything_else.at(self.directory_entry_location(index, name))
                 }
-                Err(error) => {
-                    return Err(Error::StdIo(error));
+            })
+    }
+
+    /// Decode directory entry `index` of a lazy directory on its first lookup. Parsing is over
+    /// by then, so an entry that can't be decoded is left out like an unallocated one.
+    fn decode_entry(&self, index: usize) -> Option<DirectoryEntry> {
+        match self.parse_entry(index, &mut vec![]) {
+            Ok(directory_entry) => Some(directory_entry),
+            Err(Error::OleUnknownOrUnallocatedDirectoryEntry) => None,
+            Err(err) => {
+                warn!("leaving out directory entry {}: {}", index, err);
+                None
+            }
+        }
+    }
+
+    /// Refuse to take `len` more bytes of `what` into memory than the options allow.
+    fn check_memory(&self, what: &str, len: usize) -> Result<()> {
+        match self.options.max_memory {
+            Some(max_memory) if self.memory().saturating_add(len) > max_memory => {
+                Err(Error::ResourceLimit(format!(
+                    "{} would take {} bytes in memory, {} are taken and {} allowed",
+                    what,
+                    len,
+                    self.memory(),
+                    max_memory
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The bytes `max_memory` counts: the allocation tables, the directory and the mini stream.
+    fn memory(&self) -> usize {
+        let slots = self.entry_cache.as_ref().map_or(0, |slots| {
+            slots.len() * std::mem::size_of::<OnceLock<Option<DirectoryEntry>>>()
+        });
+        (self.sector_allocation_table.len() + self.short_sector_allocation_table.len()) * 4
+            + self.directory_stream_data.len()
+            + slots
+            + self.mini_stream.len()
+    }
+
+    /// How many directory entries are decoded so far.
+    fn decoded_entries(&self) -> usize {
+        match &self.entry_cache {
+            Some(slots) => slots
+                .iter()
+                .filter(|slot| matches!(slot.get(), Some(Some(_))))
+                .count(),
+            None => self.directory_entries.len(),
+        }
+    }
+
+    /// Where directory entry `index` is in the file, for the errors about it.
+    fn directory_entry_location(&self, index: usize, name: Option<String>) -> Location {
+        let sector_size = self.header.sector_size as usize;
+        let position = index * constants::SIZE_OF_DIRECTORY_ENTRY;
+        let sector = follow_chain(
+            &self.sector_allocation_table,
+            self.header.sector_allocation_table_first_sector,
+            "sector",
+        )
+        .ok()
+        .and_then(|chain| chain.get(position / sector_size).copied());
+        Location {
+            entry: Some(index),
+            name,
+            sector,
+            offset: sector.map(|sector| {
+                (sector as u64 + 1) * sector_size as u64 + (position % sector_size) as u64
+            }),
+        }
+    }
+
+    fn initialize_mini_stream(&mut self) -> Result<()> {
+        let (next_sector, mini_stream_size) = {
+            let root_entry = self.root();
+            match root_entry.starting_sector_location {
+                None => return Ok(()), //no mini-stream here
+                // an empty mini stream often starts at 0, which is some other chain's sector
+                Some(_) if root_entry.stream_size == 0 => return Ok(()),
+                Some(starting_sector_location) => {
+                    (starting_sector_location, root_entry.stream_size)
                 }
             }
+        };
+
+        let root = self.root().location();
+        let chain = follow_chain(&self.sector_allocation_table, next_sector, "sector")
+            .map_err(|err| err.at(root.clone()))?;
+        self.check_memory(
+            "the mini stream",
+            chain.len() * self.header.sector_size as usize,
+        )?;
+        let mut mini_stream =
+            Vec::with_capacity(chain.len().saturating_mul(self.header.sector_size as usize));
+        for index in chain {
+            mini_stream.extend_from_slice(&self.sectors.read(index)?);
+        }
+        if mini_stream_size % constants::MINI_SECTOR_SIZE != 0 {
+            if !self.options.allow_truncation {
+                return Err(Error::invalid_entry(
+                    "stream_size",
+                    "root storage object type must have stream size % 64 === 0".to_string(),
+                )
+                .at(root));
+            }
+            let warning = format!(
+                "mini stream is cut short at {} bytes, the rest of its last mini sector is read from the sector holding it",
+                mini_stream_size
+            );
+            warn!("{}", warning);
+            if self.options.collect_warnings {
+                self.warnings.push(
+                    ParseWarning::new(ParseWarningKind::TruncatedMiniStream, warning).at(root),
+                );
+            }
         }
+        // the rest of the last sector stays, for the mini sector the mini stream ends inside
+        self.mini_stream_len = mini_stream.len().min(mini_stream_size as usize);
+        self.mini_stream = Arc::new(mini_stream);
 
-        let mut self_to_init = OleFile {
-            header: file_header,
-            sectors,
-            sector_allocation_table: vec![],
-            short_sector_allocation_table: vec![],
-            directory_stream_data: vec![],
-            directory_entries: vec![],
-            mini_stream: vec![],
-            file_type: OleFileType::Generic,
-            encrypted: false,
+        Ok(())
+    }
+}
+
+/// The ids of a chain in `table` starting at `start`, an error if it loops or leaves the table.
+/// `unit` names what the ids count in the error messages.
+fn follow_chain(table: &[u32], start: u32, unit: &str) -> Result<Vec<u32>> {
+    // only as much bookkeeping as the chain is long, not a FAT sized bitmap per stream
+    let mut visited = std::collections::HashSet::new();
+    let mut chain = vec![];
+    let mut next = start;
+    while next != constants::CHAIN_END {
+        let location = Location {
+            sector: Some(next),
+            ..Location::default()
         };
+        if next as usize >= table.len() {
+            return Err(Error::sector_out_of_range(format!(
+                "{} {} is not in the allocation table",
+                unit, next
+            ))
+            .at(location));
+        }
+        if !visited.insert(next) {
+            return Err(
+                Error::invalid_chain(format!("{} {} is visited twice", unit, next)).at(location),
+            );
+        }
+        chain.push(next);
+        next = table[next as usize];
+    }
+    Ok(chain)
+}
 
-        self_to_init.initialize_sector_allocation_table()?;
-        self_to_init.initialize_short_sector_allocation_table()?;
-        self_to_init.initialize_directory_stream()?;
-        self_to_init.initialize_mini_stream()?;
-        self_to_init.file_type = ftype::file_type(self_to_init.root());
-        self_to_init.encrypted = encryption::is_encrypted(&self_to_init);
-        Ok(self_to_init)
+// the tests drive the parser through the async constructors
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    #[cfg(feature = "async")]
+    async fn parse_bytes(bytes: &[u8]) -> Result<OleFile> {
+        OleFile::from_reader(std::io::Cursor::new(bytes)).await
     }
 
-    fn initialize_sector_allocation_table(&mut self) -> Result<()> {
-        for sector_index in self.header.sector_allocation_table_head.iter() {
-            // println!("sector_index: {:#x?}", *sector_index);
-            if *sector_index == constants::UNALLOCATED_SECTOR
-                || *sector_index == constants::CHAIN_END
-            {
-                break;
-            }
-            let sector = self.sectors[*sector_index as usize]
-                .chunks_exact(4)
-                .map(|quad| u32::from_le_bytes([quad[0], quad[1], quad[2], quad[3]]));
-            self.sector_allocation_table.extend(sector);
+    // without the async parser the same tests go through the sync one
+    #[cfg(not(feature = "async"))]
+    async fn parse_bytes(bytes: &[u8]) -> Result<OleFile> {
+        OleFile::parse_sync(bytes)
+    }
+
+    #[test]
+    pub fn test_parse_sync() {
+        let bytes = CfbBuilder::new()
+            .stream("small", b"tiny payload")
+            .stream("Storage/large", &[0x42; 5000])
+            .build();
+        let ole_file = OleFile::parse_sync(bytes.as_slice()).unwrap();
+        assert_eq!(ole_file.open_stream(&["small"]).unwrap(), b"tiny payload");
+        assert_eq!(
+            ole_file.open_stream(&["Storage", "large"]).unwrap(),
+            vec![0x42; 5000]
+        );
+        assert!(OleFile::parse_sync(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[cfg(all(feature = "mmap", feature = "async"))]
+    #[tokio::test]
+    pub async fn test_from_mmap() {
+        let mapped = OleFile::from_mmap("../data/oledoc1.doc_").unwrap();
+        let read = OleFile::from_file("../data/oledoc1.doc_").await.unwrap();
+        assert_eq!(mapped.list_streams(), read.list_streams());
+        for stream in read.list_streams_with_path() {
+            assert_eq!(
+                mapped.open_stream_path(&stream).unwrap(),
+                read.open_stream_path(&stream).unwrap()
+            );
         }
+    }
 
-        if self.header.master_sector_allocation_table_len > 0 {
-            return Err(Error::CurrentlyUnimplemented(
-                "MSAT/DI-FAT unsupported todo impl me".to_string(),
-            ));
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    pub async fn test_open_lazy() {
+        let lazy = OleFile::open_lazy("../data/oledoc1.doc_").unwrap();
+        let read = OleFile::from_file("../data/oledoc1.doc_").await.unwrap();
+        assert_eq!(lazy.list_streams(), read.list_streams());
+        for stream in read.list_streams_with_path() {
+            assert_eq!(
+                lazy.open_stream_path(&stream).unwrap(),
+                read.open_stream_path(&stream).unwrap()
+            );
         }
 
-        Ok(())
+        let bytes = CfbBuilder::new().stream("Payload", &[0x42; 5000]).build();
+        let lazy = OleFile::from_seekable(std::io::Cursor::new(bytes), 0).unwrap();
+        assert_eq!(lazy.open_stream(&["Payload"]).unwrap(), vec![0x42; 5000]);
     }
 
-    fn initialize_short_sector_allocation_table(&mut self) -> Result<()> {
-        if self.header.short_sector_allocation_table_len == 0
-            || self.header.short_sector_allocation_table_first_sector == constants::CHAIN_END
-        {
-            return Ok(()); // no mini stream here
+    #[test]
+    pub fn test_parse_metrics() {
+        let bytes = CfbBuilder::new()
+            .stream("small", &[1; 100])
+            .stream("Storage/large", &[2; 5000])
+            .build();
+        let ole_file = OleFile::parse_sync(&bytes[..]).unwrap();
+        let metrics = ole_file.parse_metrics();
+        assert_eq!(metrics.bytes_read, bytes.len() as u64);
+        assert_eq!(metrics.sectors, bytes.len() / 512 - 1);
+        assert_eq!(metrics.directory_entries, 4);
+        assert!(metrics.total_time() >= metrics.read_time + metrics.directory_time);
+
+        // on demand, the sectors of the large stream are left alone
+        let lazy = OleFile::from_seekable(std::io::Cursor::new(bytes.clone()), 0).unwrap();
+        assert_eq!(lazy.parse_metrics().sectors, metrics.sectors);
+        assert_eq!(
+            lazy.parse_metrics().bytes_read,
+            metrics.bytes_read - 10 * 512
+        );
+    }
+
+    #[test]
+    pub fn test_lazy_directory() {
+        // the builder chains siblings to the right, so they are added in name order
+        let mut builder = CfbBuilder::new().stream("Storage/inner", b"inner");
+        for index in 0..40u8 {
+            builder = builder.stream(&format!("the stream numbered {:02}", index), &[index; 10]);
         }
+        let bytes = builder.build();
+        let eager = OleFile::parse_sync(&bytes[..]).unwrap();
+        let options = OleParseOptions {
+            lazy_directory: true,
+            ..OleParseOptions::strict()
+        };
+        let lazy = OleFile::parse_sync_with_options(&bytes[..], &options).unwrap();
+        // parsing decodes the root and what looking for encrypted streams passes on the way,
+        // a lookup the path down the trees
+        assert!(lazy.directory_entries.is_empty());
+        assert_eq!(lazy.parse_metrics().directory_entries, 3);
+        assert_eq!(lazy.open_stream(&["storage", "INNER"]).unwrap(), b"inner");
+        assert_eq!(lazy.decoded_entries(), 4);
+        assert_eq!(
+            lazy.open_stream(&["the stream numbered 39"]).unwrap(),
+            [39; 10]
+        );
 
-        let mut next_index = self.header.short_sector_allocation_table_first_sector;
-        let mut short_sector_allocation_table_raw_data: Vec<u8> = vec![];
-        loop {
-            if next_index == constants::CHAIN_END {
-                break;
-            } else {
-                short_sector_allocation_table_raw_data
-                    .extend(self.sectors[next_index as usize].iter());
-            }
-            next_index = self.sector_allocation_table[next_index as usize];
+        // a name missing from the tree is still searched for among all children
+        assert!(lazy.open_stream(&["missing"]).is_err());
+        assert_eq!(lazy.entries().count(), eager.directory_entries.len());
+        assert_eq!(lazy.list_streams(), eager.list_streams());
+        assert_eq!(
+            lazy.walk().map(|(path, _)| path).collect::<Vec<_>>(),
+            eager.walk().map(|(path, _)| path).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            lazy.clone().decoded_entries(),
+            eager.directory_entries.len()
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    pub async fn test_from_buffered_reader() {
+        let file = tokio::fs::File::open("../data/oledoc1.doc_").await.unwrap();
+        let from_reader = OleFile::from_reader(tokio::io::BufReader::new(file))
+            .await
+            .unwrap();
+        let from_file = OleFile::from_file("../data/oledoc1.doc_").await.unwrap();
+        assert_eq!(from_reader.list_streams(), from_file.list_streams());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    pub async fn test_truncated_last_sector() {
+        let bytes = CfbBuilder::new()
+            .stream("small", b"tiny payload")
+            .stream("Storage/large", &[0x42; 5000])
+            .build();
+        // the FAT comes last, and only its first entries are in use
+        let truncated = &bytes[..bytes.len() - 100];
+        assert!(matches!(
+            OleFile::parse_sync(truncated),
+            Err(Error::OleUnexpectedEof { .. })
+        ));
+        assert!(matches!(
+            parse_bytes(truncated).await,
+            Err(Error::OleUnexpectedEof { .. })
+        ));
+
+        let sectors = bytes.len() / 512 - 1;
+        let warning = format!(
+            "sector {} is cut short at 412 of 512 bytes, padded with zeros",
+            sectors - 1
+        );
+        let lenient = OleFile::parse_lenient_sync(truncated).unwrap();
+        assert_eq!(
+            lenient.warnings(),
+            [ParseWarning {
+                kind: ParseWarningKind::TruncatedSector,
+                message: warning.clone(),
+                location: Location {
+                    sector: Some(sectors as u32 - 1),
+                    offset: Some(truncated.len() as u64),
+                    ..Location::default()
+                },
+            }]
+        );
+        assert_eq!(lenient.sectors.len(), sectors);
+        assert_eq!(lenient.open_stream(&["small"]).unwrap(), b"tiny payload");
+        assert_eq!(
+            lenient.open_stream(&["Storage", "large"]).unwrap(),
+            vec![0x42; 5000]
+        );
+        let lenient = OleFile::from_reader_lenient(std::io::Cursor::new(truncated))
+            .await
+            .unwrap();
+        assert_eq!(lenient.warnings()[0].message, warning);
+
+        let whole = OleFile::parse_lenient_sync(&bytes[..]).unwrap();
+        assert!(whole.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_minor_version() {
+        let mut bytes = CfbBuilder::new().stream("small", b"tiny payload").build();
+        // written by Word 95 and some non-Microsoft writers
+        bytes[24] = 0x3B;
+        let ole = OleFile::parse_sync(&bytes[..]).unwrap();
+        assert_eq!(ole.header.minor_version, 0x3B);
+        assert_eq!(ole.warnings().len(), 1);
+        assert_eq!(ole.warnings()[0].kind, ParseWarningKind::MinorVersion);
+        assert_eq!(
+            ole.warnings()[0].to_string(),
+            "header: minor version 0x003b instead of 0x003e"
+        );
+        assert_eq!(ole.open_stream(&["small"]).unwrap(), b"tiny payload");
+    }
+
+    #[test]
+    fn test_mini_stream_cutoff() {
+        for cutoff in [0x800, 0x2000] {
+            let bytes = CfbBuilder::new()
+                .mini_stream_cutoff(cutoff)
+                .stream("small", &[0x11; 3000])
+                .stream("large", &[0x22; 5000])
+                .build();
+            let ole = OleFile::parse_sync(&bytes[..]).unwrap();
+            assert_eq!(ole.header_info().mini_stream_cutoff, cutoff);
+            assert_eq!(ole.warnings().len(), 1);
+            assert_eq!(ole.warnings()[0].kind, ParseWarningKind::MiniStreamCutoff);
+            assert_eq!(
+                ole.warnings()[0].message,
+                format!("header: mini stream cutoff {} instead of 4096", cutoff)
+            );
+            assert_eq!(ole.open_stream(&["small"]).unwrap(), vec![0x11; 3000]);
+            assert_eq!(ole.open_stream(&["large"]).unwrap(), vec![0x22; 5000]);
         }
+    }
 
-        self.short_sector_allocation_table.extend(
-            short_sector_allocation_table_raw_data
-                .chunks_exact(4)
-                .map(|quad| u32::from_le_bytes([quad[0], quad[1], quad[2], quad[3]])),
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    pub async fn test_parse_options() {
+        let mut bytes = CfbBuilder::new()
+            .stream("small", b"tiny payload")
+            .stream("Storage/large", &[0x42; 5000])
+            .build();
+        // the first reserved field of the header
+        bytes[34] = 0x01;
+        assert!(matches!(
+            OleFile::parse_sync(&bytes[..]),
+            Err(Error::OleInvalidHeader(_))
+        ));
+        let lenient = OleFile::from_reader_with_options(
+            std::io::Cursor::new(bytes.clone()),
+            &OleParseOptions::lenient(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(lenient.warnings().len(), 1);
+        assert_eq!(
+            lenient.warnings()[0].kind,
+            ParseWarningKind::HeaderField("first_reserved")
         );
+        assert_eq!(lenient.open_stream(&["small"]).unwrap(), b"tiny payload");
 
-        Ok(())
+        let mut bytes_with_class = bytes.clone();
+        bytes_with_class[8] = 0x01;
+        assert!(matches!(
+            OleFile::parse_sync(&bytes_with_class[..]),
+            Err(Error::OleInvalidHeader(_))
+        ));
+        let lenient = OleFile::parse_lenient_sync(&bytes_with_class[..]).unwrap();
+        // the reserved field is still set too
+        assert_eq!(lenient.warnings().len(), 2);
+        assert!(lenient.warnings()[0].message.contains("class_identifier"));
+        assert_eq!(
+            lenient.header_class_id(),
+            Some("00000001-0000-0000-0000-000000000000")
+        );
+
+        let quiet = OleParseOptions {
+            collect_warnings: false,
+            ..OleParseOptions::lenient()
+        };
+        let truncated = &bytes[..bytes.len() - 100];
+        let res = OleFile::parse_sync_with_options(truncated, &quiet).unwrap();
+        assert!(res.warnings().is_empty());
+
+        let sectors = bytes.len() / 512 - 1;
+        let limited = OleParseOptions {
+            max_sectors: sectors - 1,
+            ..OleParseOptions::lenient()
+        };
+        assert!(matches!(
+            OleFile::parse_sync_with_options(&bytes[..], &limited),
+            Err(Error::ResourceLimit(_))
+        ));
+        let limited = OleParseOptions {
+            max_sectors: sectors,
+            ..OleParseOptions::lenient()
+        };
+        assert!(OleFile::parse_sync_with_options(&bytes[..], &limited).is_ok());
+
+        // one directory sector holds four entries
+        let limited = OleParseOptions {
+            max_directory_entries: 3,
+            ..OleParseOptions::lenient()
+        };
+        assert!(matches!(
+            OleFile::parse_sync_with_options(&bytes[..], &limited),
+            Err(Error::ResourceLimit(_))
+        ));
+
+        let nested = CfbBuilder::new().stream("A/B/C/D", b"deep").build();
+        let limited = OleParseOptions {
+            max_storage_depth: 2,
+            ..OleParseOptions::lenient()
+        };
+        assert!(matches!(
+            OleFile::parse_sync_with_options(&nested[..], &limited),
+            Err(Error::ResourceLimit(_))
+        ));
+        let limited = OleParseOptions {
+            max_storage_depth: 3,
+            ..OleParseOptions::lenient()
+        };
+        assert!(OleFile::parse_sync_with_options(&nested[..], &limited).is_ok());
     }
 
-    fn initialize_directory_stream(&mut self) -> Result<()> {
-        let mut next_directory_index = self.header.sector_allocation_table_first_sector;
-        self.directory_stream_data
-            .extend(self.sectors[next_directory_index as usize].iter());
+    #[test]
+    pub fn test_directory_names() {
+        for (name, problem) in [("bad!name", "'!'"), ("Da\x01ta", "'\\u{1}'")] {
+            let bytes = CfbBuilder::new()
+                .stream("\x01CompObj", b"fine")
+                .stream(name, b"data")
+                .build();
+            let err = OleFile::parse_sync(&bytes[..]).unwrap_err();
+            assert!(matches!(
+                err,
+                Error::OleInvalidDirectoryEntry { field: "name", .. }
+            ));
+            let directory = u32::from_le_bytes(bytes[0x30..0x34].try_into().unwrap()) as u64;
+            assert_eq!(
+                err.location(),
+                Some(&Location {
+                    entry: Some(2),
+                    name: Some(name.to_string()),
+                    sector: Some(directory as u32),
+                    offset: Some((directory + 1) * 512 + 2 * 128),
+                })
+            );
+            let lenient = OleFile::parse_lenient_sync(&bytes[..]).unwrap();
+            assert_eq!(lenient.warnings().len(), 1);
+            assert_eq!(lenient.warnings()[0].kind, ParseWarningKind::IllegalName);
+            assert_eq!(lenient.warnings()[0].location.entry, Some(2));
+            assert!(
+                lenient.warnings()[0].message.contains(problem),
+                "{}",
+                problem
+            );
+            assert_eq!(lenient.open_stream(&[name]).unwrap(), b"data");
+        }
 
-        loop {
-            next_directory_index = self.sector_allocation_table[next_directory_index as usize];
-            if next_directory_index == constants::CHAIN_END {
-                break;
-            } else {
-                self.directory_stream_data
-                    .extend(self.sectors[next_directory_index as usize].iter());
+        let mut bytes = CfbBuilder::new().stream("A", b"a").build();
+        let entry =
+            (u32::from_le_bytes(bytes[0x30..0x34].try_into().unwrap()) as usize + 1) * 512 + 128;
+        // something left behind the terminator is legal, and only seen in the raw name
+        bytes[entry + 4..entry + 8].copy_from_slice(b"B\0C\0");
+        let ole_file = OleFile::parse_sync(&bytes[..]).unwrap();
+        let a = ole_file.root().child(&ole_file).unwrap();
+        assert_eq!(a.name(), "A");
+        assert_eq!(a.raw_name()[..8], *b"A\0\0\0B\0C\0");
+        // a length past the name field
+        bytes[entry + 64..entry + 66].copy_from_slice(&80u16.to_le_bytes());
+        assert!(OleFile::parse_sync(&bytes[..]).is_err());
+        let lenient = OleFile::parse_lenient_sync(&bytes[..]).unwrap();
+        assert!(lenient.warnings()[0].message.contains("name length of 80"));
+        assert!(lenient.open_stream(&["A"]).is_err());
+    }
+
+    #[test]
+    pub fn test_header_counts() {
+        let bytes = CfbBuilder::new()
+            .version_4()
+            .stream("small", b"tiny payload")
+            .stream("large", &[0x42; 5000])
+            .build();
+        let ole = OleFile::parse_sync(&bytes[..]).unwrap();
+        assert!(ole.warnings().is_empty());
+        let header = ole.header();
+        assert_eq!((header.major_version, header.sector_size), (4, 4096));
+        assert_eq!(header.mini_sector_size(), 64);
+        assert_eq!(header.directory_sectors_len(), 1);
+        assert_eq!(header.sector_allocation_table_len, 1);
+        assert_eq!(header.class_id(), None);
+        for (offset, field) in [
+            (0x2C, "sector_allocation_table_len"),
+            (0x40, "short_sector_allocation_table_len"),
+            (0x28, "directory_sectors_len"),
+        ] {
+            let mut bytes = bytes.clone();
+            let count = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+            bytes[offset..offset + 4].copy_from_slice(&(count + 1).to_le_bytes());
+            for parsed in [
+                OleFile::parse_sync(&bytes[..]),
+                OleFile::parse_lenient_sync(&bytes[..]),
+            ] {
+                let parsed = parsed.unwrap();
+                assert_eq!(parsed.warnings().len(), 1, "{}", field);
+                assert_eq!(
+                    parsed.warnings()[0].kind,
+                    ParseWarningKind::HeaderField(field)
+                );
             }
+            let options = OleParseOptions {
+                strict_counts: true,
+                ..OleParseOptions::lenient()
+            };
+            assert!(
+                matches!(
+                    OleFile::parse_sync_with_options(&bytes[..], &options),
+                    Err(Error::OleInvalidHeader(_))
+                ),
+                "{}",
+                field
+            );
         }
+    }
 
-        self.initialize_directory_entries()?;
+    #[test]
+    pub fn test_header_remainder() {
+        let mut bytes = CfbBuilder::new()
+            .version_4()
+            .stream("large", &[0x42; 5000])
+            .build();
+        bytes[0x200..0x204].copy_from_slice(b"junk");
+        bytes[0xFFF] = 0xFF;
+        let ole = OleFile::parse_sync(&bytes[..]).unwrap();
+        assert_eq!(ole.open_stream(&["large"]).unwrap(), [0x42; 5000]);
+        let lenient = OleFile::parse_lenient_sync(&bytes[..]).unwrap();
+        assert_eq!(lenient.warnings().len(), 1);
+        assert_eq!(lenient.warnings()[0].kind, ParseWarningKind::HeaderSlack);
+        assert_eq!(lenient.warnings()[0].location.offset, Some(0x200));
+        assert_eq!(
+            lenient.warnings()[0].message,
+            "header: 5 of the 3584 bytes after the header are not zero, they were skipped"
+        );
+        assert!(OleFile::from_seekable(std::io::Cursor::new(bytes), 4).is_ok());
+    }
 
-        Ok(())
+    #[test]
+    pub fn test_clone_shares_data() {
+        let bytes = CfbBuilder::new()
+            .stream("small", b"tiny payload")
+            .stream("large", &[0x42; 5000])
+            .build();
+        let ole = OleFile::parse_sync(&bytes[..]).unwrap();
+        let clone = ole.clone();
+        assert!(std::ptr::eq(
+            ole.sectors.read(0).unwrap().as_ptr(),
+            clone.sectors.read(0).unwrap().as_ptr()
+        ));
+        assert!(std::ptr::eq(
+            ole.mini_stream_bytes().as_ptr(),
+            clone.mini_stream_bytes().as_ptr()
+        ));
     }
 
-    fn initialize_directory_entries(&mut self) -> Result<()> {
-        if self.directory_stream_data.len() % constants::SIZE_OF_DIRECTORY_ENTRY != 0 {
-            return Err(Error::OleInvalidDirectoryEntry(
-                "directory_stream_size",
-                format!(
-                    "size of directory stream data is not correct? {}",
-                    self.directory_stream_data.len()
-                ),
-            ));
+    #[test]
+    pub fn test_raw_header() {
+        let mut bytes = CfbBuilder::new().stream("a", b"a").build();
+        // a reserved byte only a lenient parse lets through, and a transaction signature
+        bytes[34] = 0x11;
+        bytes[52..56].copy_from_slice(&7u32.to_le_bytes());
+        let ole = OleFile::parse_lenient_sync(&bytes[..]).unwrap();
+        let raw = ole.raw_header();
+        assert_eq!(raw.bytes()[..], bytes[..512]);
+        assert_eq!(raw.byte_order(), [0xFE, 0xFF]);
+        assert_eq!(raw.reserved(), [0x11, 0, 0, 0, 0, 0]);
+        assert_eq!(raw.transaction_signature(), 7);
+        assert_eq!(raw.class_identifier(), &[0; 16]);
+        assert_eq!(
+            raw.sector_allocation_table_head(),
+            &ole.header.sector_allocation_table_head[..]
+        );
+    }
+
+    /// Hands out at most 7 bytes per call and is interrupted every other call, like a pipe.
+    struct ShortReads<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    impl std::io::Read for ShortReads<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            let n = buf.len().min(7).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
         }
+    }
+
+    #[tokio::test]
+    pub async fn test_parse_across_short_reads() {
+        let bytes = CfbBuilder::new()
+            .stream("small", b"tiny payload")
+            .stream("Storage/large", &[0x42; 5000])
+            .build();
+        let whole = OleFile::parse_sync(&bytes[..]).unwrap();
 
-        self.directory_entries = Vec::with_capacity(
-            self.directory_stream_data.len() / constants::SIZE_OF_DIRECTORY_ENTRY,
+        let short = OleFile::parse_sync(ShortReads {
+            data: &bytes,
+            interrupt: false,
+        })
+        .unwrap();
+        assert_eq!(short.list_streams(), whole.list_streams());
+        assert_eq!(
+            short.open_stream(&["Storage", "large"]).unwrap(),
+            vec![0x42; 5000]
         );
-        for (index, unparsed_entry) in self
-            .directory_stream_data
-            .chunks(constants::SIZE_OF_DIRECTORY_ENTRY)
-            .enumerate()
+
+        #[cfg(feature = "async")]
         {
-            // println!("unparsed_entry: {}", unparsed_entry.len());
-            let raw_directory_entry = DirectoryEntryRaw::parse(unparsed_entry)?;
-            match DirectoryEntry::from_raw(&self.header, raw_directory_entry, index) {
-                Ok(directory_entry) => self.directory_entries.push(directory_entry),
-                Err(Error::OleUnknownOrUnallocatedDirectoryEntry) => continue,
-                Err(anything_else) => return Err(anything_else),
+            let mut reader = tokio_test::io::Builder::new();
+            for chunk in bytes.chunks(100) {
+                reader.read(chunk);
             }
+            let short = OleFile::from_reader(reader.build()).await.unwrap();
+            assert_eq!(short.list_streams(), whole.list_streams());
+            assert_eq!(short.open_stream(&["small"]).unwrap(), b"tiny payload");
         }
+    }
 
-        Ok(())
+    #[tokio::test]
+    pub async fn test_small_synthesized_file() {
+        let bytes = CfbBuilder::new()
+            .stream("small", b"tiny payload")
+            .stream("Storage/large", &[0x42; 5000])
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(ole_file.open_stream(&["small"]).unwrap(), b"tiny payload");
+        assert_eq!(
+            ole_file.open_stream(&["Storage", "large"]).unwrap(),
+            vec![0x42; 5000]
+        );
     }
 
-    fn initialize_mini_stream(&mut self) -> Result<()> {
-        let (mut next_sector, mini_stream_size) = {
-            let root_entry = &self.directory_entries[0];
-            match root_entry.starting_sector_location {
-                None => return Ok(()), //no mini-stream here
-                Some(starting_sector_location) => {
-                    (starting_sector_location, root_entry.stream_size)
+    #[tokio::test]
+    pub async fn test_open_stream_path() {
+        let bytes = CfbBuilder::new()
+            .stream("Macros/VBA/ThisDocument", b"code")
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(
+            ole_file
+                .open_stream_path("Macros/VBA/ThisDocument")
+                .unwrap(),
+            b"code"
+        );
+        assert_eq!(
+            ole_file
+                .open_stream_path("/Macros/VBA/ThisDocument")
+                .unwrap(),
+            b"code"
+        );
+        assert!(ole_file.find_stream_path("Macros/VBA").is_some());
+        assert!(ole_file.open_stream_path("Macros/VBA").is_err());
+        assert!(ole_file.open_stream_path("").is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_find_stream_many_children() {
+        let mut builder = CfbBuilder::new();
+        for i in 0..500 {
+            builder = builder
+                .stream(&format!("Root{}", i), b"r")
+                .stream(&format!("A/Child{}", i), b"a");
+        }
+        let bytes = builder.stream("B/Only", b"b").build();
+        let mut ole_file = parse_bytes(&bytes).await.unwrap();
+        // the builder chains the children to the right, 500 deep below the root and below A
+        for i in (0..500).step_by(37) {
+            assert_eq!(
+                ole_file
+                    .open_stream(&["A", &format!("Child{}", i)])
+                    .unwrap(),
+                b"a"
+            );
+            assert!(ole_file.get_entry(&[&format!("Root{}", i)]).is_some());
+        }
+        // a child is only looked for below its own storage
+        assert!(ole_file.get_entry(&["A", "Only"]).is_none());
+        assert!(ole_file.get_entry(&["B", "Child0"]).is_none());
+        assert!(ole_file.get_entry(&["A", "Child0", "Child1"]).is_none());
+        assert_eq!(ole_file.open_stream(&["B", "Only"]).unwrap(), b"b");
+        // and a name that is only below a storage isn't found at the root
+        assert!(ole_file.get_entry(&["Child0"]).is_none());
+        assert!(!ole_file.exists(&["Only"]));
+        assert!(ole_file.open_stream(&["Only"]).is_err());
+        assert_eq!(
+            ole_file.find_by_name("child0").unwrap().index,
+            ole_file.find_stream_path("A/Child0").unwrap().index
+        );
+        assert!(ole_file.find_by_name("Missing").is_none());
+
+        // a cycle back to the first child of A ends the search instead of looping
+        let a = ole_file.find_stream_path("A").unwrap().index;
+        let first = ole_file.directory_entries[a].child_id;
+        let last = ole_file.find_stream_path("A/Child499").unwrap().index;
+        ole_file.directory_entries[last].right_sibling_id = first;
+        // the index was built before the cycle, so it is dropped to search the trees
+        ole_file.name_index = None;
+        assert!(ole_file.get_entry(&["A", "Missing"]).is_none());
+        assert!(ole_file.get_entry(&["Child0"]).is_none());
+        assert!(ole_file.get_entry(&["A", "Child499"]).is_some());
+    }
+
+    #[tokio::test]
+    pub async fn test_case_insensitive_names() {
+        let bytes = CfbBuilder::new()
+            .stream("Macros/VBA/ThisDocument", b"code")
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(
+            ole_file
+                .open_stream(&["MACROS", "vba", "thisdocument"])
+                .unwrap(),
+            b"code"
+        );
+        let ole_file = ole_file.with_exact_names(true);
+        assert!(ole_file
+            .open_stream(&["MACROS", "vba", "thisdocument"])
+            .is_err());
+        assert!(ole_file
+            .open_stream(&["Macros", "VBA", "ThisDocument"])
+            .is_ok());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    pub fn test_open_streams() {
+        let sizes = [10, 5000, 100, 70000];
+        let mut builder = CfbBuilder::new();
+        for (index, size) in sizes.iter().enumerate() {
+            builder = builder.stream(&format!("S{}", index), &vec![index as u8; *size]);
+        }
+        let bytes = builder.build();
+        let ole_file = OleFile::parse_sync(&bytes[..]).unwrap();
+        let streams = ole_file.open_streams(&[&["S3"], &["S0"], &["Missing"], &["S1"], &["S2"]]);
+        assert_eq!(streams.len(), 5);
+        for (result, index) in streams
+            .iter()
+            .zip([Some(3), Some(0), None, Some(1), Some(2)])
+        {
+            match index {
+                Some(index) => {
+                    assert_eq!(result.as_ref().unwrap(), &vec![index as u8; sizes[index]])
                 }
+                None => assert!(result.is_err()),
             }
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_entry_metadata() {
+        let bytes = CfbBuilder::new().stream("Storage/Data", &[0; 5000]).build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(ole_file.stream_size(&["Storage", "Data"]), Some(5000));
+        assert_eq!(ole_file.stream_size(&["Storage"]), None);
+        assert!(ole_file.exists(&["Storage"]));
+        assert!(!ole_file.exists(&["Missing"]));
+        assert!(!ole_file.exists(&[]));
+        let entry = ole_file.get_entry(&["Storage", "Data"]).unwrap();
+        assert_eq!(entry.name(), "Data");
+        assert_eq!(entry.object_type(), ObjectType::Stream);
+    }
+
+    #[tokio::test]
+    pub async fn test_open_storage() {
+        let bytes = CfbBuilder::new()
+            .stream("Storage/Data", &[0; 5000])
+            .stream("Storage/Inner/Small", b"small")
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        let is_storage = |result: Result<()>| match result {
+            Err(Error::IsAStorage { children }) => children == ["Data", "Inner"],
+            _ => false,
         };
+        assert!(is_storage(ole_file.open_stream(&["Storage"]).map(drop)));
+        assert!(is_storage(ole_file.open_stream_path("Storage").map(drop)));
+        assert!(is_storage(ole_file.stream_reader(&["Storage"]).map(drop)));
+        assert!(is_storage(
+            ole_file.open_stream_range(&["Storage"], 0, 4).map(drop)
+        ));
+        assert!(is_storage(ole_file.stream_extents("Storage").map(drop)));
+        assert!(is_storage(ole_file.stream_slack(&["Storage"]).map(drop)));
+        assert!(matches!(
+            ole_file.open_stream(&["Storage", "Inner"]),
+            Err(Error::IsAStorage { children }) if children == ["Small"]
+        ));
+        assert!(matches!(
+            ole_file.open_stream(&["Missing"]),
+            Err(Error::OleDirectoryEntryNotFound)
+        ));
+    }
 
-        let mut raw_mini_stream_data: Vec<u8> = vec![];
-        loop {
-            if next_sector == constants::CHAIN_END {
-                break;
-            } else {
-                raw_mini_stream_data.extend(self.sectors[next_sector as usize].iter());
+    #[tokio::test]
+    pub async fn test_empty_streams() {
+        let directory = |bytes: &[u8]| {
+            (u32::from_le_bytes(bytes[0x30..0x34].try_into().unwrap()) as usize + 1) * 512
+        };
+        for cutoff in [4096, 0] {
+            let bytes = CfbBuilder::new()
+                .mini_stream_cutoff(cutoff)
+                .stream("Empty", b"")
+                .stream("Small", &[1; 100])
+                .build();
+            // CHAIN_END, a sector of another stream and a free sector
+            for start in [constants::CHAIN_END, 0, u32::MAX] {
+                let mut bytes = bytes.clone();
+                let entry = directory(&bytes) + 128;
+                bytes[entry + 116..entry + 120].copy_from_slice(&start.to_le_bytes());
+                let ole_file = parse_bytes(&bytes).await.unwrap();
+                assert!(ole_file.open_stream(&["Empty"]).unwrap().is_empty());
+                let mut read = vec![];
+                std::io::Read::read_to_end(
+                    &mut ole_file.stream_reader(&["Empty"]).unwrap(),
+                    &mut read,
+                )
+                .unwrap();
+                assert!(read.is_empty());
+                assert!(ole_file.stream_extents("Empty").unwrap().is_empty());
+                assert!(ole_file.stream_slack(&["Empty"]).unwrap().is_empty());
+                assert_eq!(ole_file.stream_stats()[0].start_sector, None);
+                assert_eq!(ole_file.open_stream(&["Small"]).unwrap(), [1; 100]);
+                assert!(ole_file.sector_map().iter().all(|sector| !sector
+                    .owners
+                    .iter()
+                    .any(|owner| matches!(owner, SectorOwner::Stream(path) if path.to_string() == "Empty"))));
+            }
+        }
+
+        // an empty mini stream starting at a sector of a regular stream
+        let mut bytes = CfbBuilder::new().stream("Large", &[2; 5000]).build();
+        let root = directory(&bytes);
+        bytes[root + 116..root + 120].copy_from_slice(&0u32.to_le_bytes());
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert!(ole_file.mini_stream_bytes().is_empty());
+        assert_eq!(ole_file.open_stream(&["Large"]).unwrap(), [2; 5000]);
+        assert_eq!(ole_file.sector_map()[1].owners.len(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_walk() {
+        let bytes = CfbBuilder::new()
+            .stream("A", b"a")
+            .stream("Storage/B", b"b")
+            .stream("Storage/Inner/C", b"c")
+            .stream("D", b"d")
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        let walked = ole_file
+            .walk()
+            .map(|(path, entry)| (path.to_string(), entry.object_type()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            walked,
+            vec![
+                ("A".to_string(), ObjectType::Stream),
+                ("Storage".to_string(), ObjectType::Storage),
+                ("Storage/B".to_string(), ObjectType::Stream),
+                ("Storage/Inner".to_string(), ObjectType::Storage),
+                ("Storage/Inner/C".to_string(), ObjectType::Stream),
+                ("D".to_string(), ObjectType::Stream),
+            ]
+        );
+        for (path, entry) in ole_file.walk() {
+            if entry.object_type() == ObjectType::Stream {
+                assert_eq!(
+                    ole_file.open_stream(&path.as_strs()).unwrap(),
+                    path.name().to_lowercase().as_bytes()
+                );
             }
-            next_sector = self.sector_allocation_table[next_sector as usize];
         }
-        raw_mini_stream_data.truncate(mini_stream_size as usize);
-        raw_mini_stream_data.chunks_exact(64).for_each(|chunk| {
-            self.mini_stream.push(<[u8; 64]>::try_from(chunk).unwrap());
+    }
+
+    #[tokio::test]
+    pub async fn test_walk_order() {
+        let chained = CfbBuilder::new()
+            .stream("A", b"a")
+            .stream("B", b"b")
+            .stream("C", b"c")
+            .build();
+        // the builder chains A, B and C to the right; balance the tree around B instead
+        let mut balanced = chained.clone();
+        let directory =
+            (u32::from_le_bytes(balanced[0x30..0x34].try_into().unwrap()) as usize + 1) * 512;
+        let mut link = |id: usize, offset: usize, to: u32| {
+            let at = directory + id * 128 + offset;
+            balanced[at..at + 4].copy_from_slice(&to.to_le_bytes());
+        };
+        link(0, 76, 2);
+        link(1, 72, u32::from_le_bytes(constants::NO_STREAM));
+        link(2, 68, 1);
+        link(2, 72, 3);
+        let orders = [chained, balanced].map(|bytes| {
+            let ole_file = OleFile::parse_sync(&bytes[..]).unwrap();
+            ole_file.list_streams_with_path()
         });
+        assert_eq!(orders[0], ["A", "B", "C"]);
+        assert_eq!(orders[0], orders[1]);
+    }
 
-        Ok(())
+    #[tokio::test]
+    pub async fn test_children() {
+        let bytes = CfbBuilder::new()
+            .stream("A", b"a")
+            .stream("ObjectPool/_1/Contents", b"1")
+            .stream("ObjectPool/_2/Contents", b"2")
+            .stream("ObjectPool/_3", b"3")
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        let names = |path: &[&str]| {
+            ole_file
+                .children(path)
+                .unwrap()
+                .map(|entry| entry.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&[]), vec!["A", "ObjectPool"]);
+        assert_eq!(names(&["ObjectPool"]), vec!["_1", "_2", "_3"]);
+        assert_eq!(names(&["ObjectPool", "_2"]), vec!["Contents"]);
+        assert!(ole_file.children(&["A"]).is_err());
+        assert!(ole_file.children(&["Missing"]).is_err());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    pub async fn test_mini_stream_tail() {
+        let mut bytes = CfbBuilder::new().stream("Small", &[7; 100]).build();
+        // end the mini stream where the stream ends rather than on a mini sector boundary, as
+        // writers that don't pad the mini stream do
+        let directory =
+            (u32::from_le_bytes(bytes[0x30..0x34].try_into().unwrap()) as usize + 1) * 512;
+        bytes[directory + 120..directory + 128].copy_from_slice(&100u64.to_le_bytes());
+        // what the sector holds after the mini stream is slack of the mini sector it ends in
+        let mini_stream =
+            (u32::from_le_bytes(bytes[directory + 116..directory + 120].try_into().unwrap())
+                as usize
+                + 1)
+                * 512;
+        bytes[mini_stream + 100] = 0x55;
+        assert!(matches!(
+            parse_bytes(&bytes).await,
+            Err(Error::OleInvalidDirectoryEntry {
+                field: "stream_size",
+                ..
+            })
+        ));
+        let ole_file = OleFile::parse_lenient_sync(&bytes[..]).unwrap();
+        assert_eq!(ole_file.warnings().len(), 1);
+        assert_eq!(
+            ole_file.warnings()[0].kind,
+            ParseWarningKind::TruncatedMiniStream
+        );
+        assert_eq!(ole_file.warnings()[0].location.entry, Some(0));
+        assert_eq!(ole_file.mini_stream_bytes().len(), 100);
+        assert_eq!(ole_file.open_stream(&["Small"]).unwrap(), vec![7; 100]);
+        let mut reader = ole_file.stream_reader(&["Small"]).unwrap();
+        let mut data = vec![];
+        std::io::Read::read_to_end(&mut reader, &mut data).unwrap();
+        assert_eq!(data, vec![7; 100]);
+        let mut slack = vec![0; 28];
+        slack[0] = 0x55;
+        assert_eq!(ole_file.stream_slack(&["Small"]).unwrap(), slack);
+    }
+
+    #[tokio::test]
+    pub async fn test_entry_by_id() {
+        let mut bytes = CfbBuilder::new()
+            .stream("A", b"a")
+            .stream("B", b"b")
+            .stream("C", b"c")
+            .build();
+        // free entry 1 (A) and link the root straight to B, leaving a gap in the directory
+        let directory =
+            (u32::from_le_bytes(bytes[0x30..0x34].try_into().unwrap()) as usize + 1) * 512;
+        bytes[directory + 128 + 66] = 0;
+        bytes[directory + 76..directory + 80].copy_from_slice(&2u32.to_le_bytes());
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+
+        assert!(ole_file.entry_by_id(1).is_none());
+        assert!(ole_file.entry_by_id(99).is_none());
+        let b = ole_file.root().child(&ole_file).unwrap();
+        assert_eq!((b.name(), b.index()), ("B", 2));
+        assert_eq!(ole_file.entry_by_id(2).unwrap().name(), "B");
+        let c = b.right_sibling(&ole_file).unwrap();
+        assert_eq!((c.name(), c.index()), ("C", 3));
+        assert!(c.left_sibling(&ole_file).is_none());
+        assert!(c.child(&ole_file).is_none());
+        assert_eq!(ole_file.open_stream(&["C"]).unwrap(), b"c");
+        let names = ole_file
+            .children(&[])
+            .unwrap()
+            .map(|entry| entry.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["B", "C"]);
+    }
+
+    #[tokio::test]
+    pub async fn test_list_with_path() {
+        let bytes = CfbBuilder::new()
+            .stream("Data", b"top")
+            .stream("ObjectPool/_1/Data", b"nested")
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(ole_file.list_streams(), vec!["Data", "Data"]);
+        let streams = ole_file.list_streams_with_path();
+        assert_eq!(streams, vec!["Data", "ObjectPool/_1/Data"]);
+        assert_eq!(
+            ole_file.list_storage_with_path(),
+            vec!["ObjectPool", "ObjectPool/_1"]
+        );
+        assert_eq!(
+            ole_file.open_stream_path("ObjectPool/_1/Data").unwrap(),
+            b"nested"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_stream_slack() {
+        let mut bytes = CfbBuilder::new()
+            .stream("Small", &[1; 100])
+            .stream("Large", &[2; 5000])
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(ole_file.stream_slack(&["Small"]).unwrap(), vec![0; 28]);
+        assert_eq!(ole_file.stream_slack(&["Large"]).unwrap(), vec![0; 120]);
+        assert!(ole_file.stream_slack(&["Missing"]).is_err());
+
+        // leftovers of an earlier save after the end of both streams
+        let small_end = ole_file.stream_extents("Small").unwrap()[1].end as usize;
+        let large_end = ole_file.stream_extents("Large").unwrap()[9].end as usize;
+        bytes[small_end - 28..small_end].fill(0xAB);
+        bytes[large_end - 120..large_end].fill(0xCD);
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(ole_file.stream_slack(&["Small"]).unwrap(), vec![0xAB; 28]);
+        assert_eq!(ole_file.stream_slack(&["Large"]).unwrap(), vec![0xCD; 120]);
+        assert_eq!(ole_file.open_stream(&["Small"]).unwrap(), vec![1; 100]);
+    }
+
+    #[tokio::test]
+    pub async fn test_mini_stream_chain() {
+        let bytes = CfbBuilder::new()
+            .stream("first", &[1; 100])
+            .stream("second", &[2; 10])
+            .build();
+        let mut ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(
+            ole_file.mini_fat()[..3],
+            [1, constants::CHAIN_END, constants::CHAIN_END]
+        );
+        assert_eq!(ole_file.mini_stream_chain(0).unwrap(), vec![0..64, 64..128]);
+        assert_eq!(ole_file.mini_stream_chain(2).unwrap(), vec![128..192]);
+        let mini_stream = ole_file.mini_stream_bytes();
+        assert_eq!(mini_stream.len(), 192);
+        assert_eq!(mini_stream[..100], [1; 100]);
+        // the slack of the first stream, then the second stream and its slack
+        assert_eq!(mini_stream[100..128], [0; 28]);
+        assert_eq!(mini_stream[128..138], [2; 10]);
+        assert_eq!(mini_stream[138..], [0; 54]);
+
+        ole_file.short_sector_allocation_table[1] = 0;
+        assert!(matches!(
+            ole_file.mini_stream_chain(0),
+            Err(Error::OleInvalidChain { .. })
+        ));
+        assert!(matches!(
+            ole_file.mini_stream_chain(1000),
+            Err(Error::SectorOutOfRange { .. })
+        ));
+    }
+
+    #[tokio::test]
+    pub async fn test_mini_stream_chain_validation() {
+        let bytes = CfbBuilder::new()
+            .stream("first", &[1; 100])
+            .stream("second", &[2; 10])
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(ole_file.open_stream(&["first"]).unwrap(), vec![1; 100]);
+        assert_eq!(ole_file.open_stream(&["second"]).unwrap(), vec![2; 10]);
+
+        // a loop, a chain too short and one too long for the stream size
+        for (id, next, stream) in [
+            (1, 1, "first"),
+            (0, constants::CHAIN_END, "first"),
+            (2, 0, "second"),
+        ] {
+            let mut broken = ole_file.clone();
+            broken.short_sector_allocation_table[id] = next;
+            assert!(matches!(
+                broken.open_stream(&[stream]),
+                Err(Error::OleInvalidChain { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_sector_out_of_range() {
+        let bytes = CfbBuilder::new()
+            .stream("small", &[1; 100])
+            .stream("large", &[2; 5000])
+            .build();
+        let mut ole_file = parse_bytes(&bytes).await.unwrap();
+        let large = ole_file
+            .get_entry(&["large"])
+            .unwrap()
+            .starting_sector_location
+            .unwrap();
+        ole_file.sector_allocation_table[large as usize] = 0x00FF_FFFF;
+        ole_file.short_sector_allocation_table[0] = 5000;
+        let err = ole_file.open_stream(&["large"]).unwrap_err();
+        assert!(matches!(err, Error::SectorOutOfRange { .. }));
+        assert_eq!(
+            err.location(),
+            Some(&Location {
+                entry: Some(2),
+                name: Some("large".to_string()),
+                sector: Some(0x00FF_FFFF),
+                offset: None,
+            })
+        );
+        assert!(err
+            .to_string()
+            .ends_with(r#"(entry 2 "large", sector 16777215)"#));
+        assert!(matches!(
+            ole_file.open_stream(&["small"]),
+            Err(Error::SectorOutOfRange { .. })
+        ));
+
+        // a directory chain leaving the FAT fails the parse
+        let mut bytes = bytes;
+        let fat = (u32::from_le_bytes(bytes[0x4C..0x50].try_into().unwrap()) as usize + 1) * 512;
+        let directory = u32::from_le_bytes(bytes[0x30..0x34].try_into().unwrap()) as usize;
+        bytes[fat + directory * 4..fat + directory * 4 + 4]
+            .copy_from_slice(&0x00FF_FFFFu32.to_le_bytes());
+        assert!(matches!(
+            parse_bytes(&bytes).await,
+            Err(Error::SectorOutOfRange { .. })
+        ));
+    }
+
+    #[tokio::test]
+    pub async fn test_small_synthesized_version_4_file() {
+        let bytes = CfbBuilder::new()
+            .version_4()
+            .stream("small", b"tiny payload")
+            .stream("large", &[0x42; 5000])
+            .build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert_eq!(ole_file.header.sector_size, 4096);
+        assert_eq!(ole_file.open_stream(&["small"]).unwrap(), b"tiny payload");
+        assert_eq!(ole_file.open_stream(&["large"]).unwrap(), vec![0x42; 5000]);
+    }
+
+    #[tokio::test]
+    pub async fn test_difat_for_large_file() {
+        // more than 109 FAT sectors are needed to map this, so the DIFAT chain must be walked
+        let payload = (0..8 * 1024 * 1024)
+            .map(|i: u32| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let bytes = CfbBuilder::new().stream("Payload", &payload).build();
+        let ole_file = parse_bytes(&bytes).await.unwrap();
+        assert!(ole_file.header.master_sector_allocation_table_len > 0);
+        assert_eq!(ole_file.open_stream(&["Payload"]).unwrap(), payload);
+    }
 
+    #[tokio::test]
+    pub async fn test_looping_difat() {
+        let mut bytes = CfbBuilder::new().stream("A", b"a").build();
+        // a DIFAT sector listing FAT sector 0 over and over, and itself as the next one
+        let difat = (bytes.len() / 512 - 1) as u32;
+        for _ in 0..127 {
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+        }
+        bytes.extend_from_slice(&difat.to_le_bytes());
+        bytes[0x44..0x48].copy_from_slice(&difat.to_le_bytes());
+        bytes[0x48..0x4C].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = parse_bytes(&bytes).await.unwrap_err();
+        assert!(matches!(err, Error::OleInvalidChain { .. }));
+        assert_eq!(err.location().unwrap().sector, Some(difat));
+        assert!(matches!(
+            OleFile::parse_lenient_sync(&bytes[..]),
+            Err(Error::OleInvalidChain { .. })
+        ));
+    }
+
+    #[cfg(feature = "async")]
     #[tokio::test]
     pub async fn test_word_encryption_detection_on() {
         let ole_file = OleFile::from_file("../data/encryption/encrypted/rc4cryptoapi_password.doc")
             .await
             .unwrap();
         assert!(ole_file.is_encrypted());
+        assert_eq!(
+            ole_file.encryption_scheme,
+            Some(EncryptionScheme::Rc4CryptoApi)
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    pub async fn test_ooxml_encryption_detection() {
+        let standard =
+            OleFile::from_file("../data/encryption/encrypted/ecma376standard_password.docx")
+                .await
+                .unwrap();
+        assert!(standard.is_encrypted());
+        assert_eq!(standard.encryption_scheme, Some(EncryptionScheme::Standard));
+        let agile = OleFile::from_file("../data/encryption/encrypted/example_password.xlsx")
+            .await
+            .unwrap();
+        assert_eq!(agile.encryption_scheme, Some(EncryptionScheme::Agile));
     }
 
+    #[cfg(feature = "async")]
     #[tokio::test]
     pub async fn test_word_encryption_detection_off() {
         let ole_file = OleFile::from_file("../data/encryption/plaintext/plain.doc")
@@ -529,14 +4172,20 @@ mod tests {
         assert!(!ole_file.is_encrypted());
     }
 
+    #[cfg(feature = "async")]
     #[tokio::test]
     pub async fn test_excel_encryption_detection_on() {
         let ole_file = OleFile::from_file("../data/encryption/encrypted/rc4cryptoapi_password.xls")
             .await
             .expect("file not found");
         assert!(ole_file.is_encrypted());
+        assert_eq!(
+            ole_file.encryption_scheme,
+            Some(EncryptionScheme::Rc4CryptoApi)
+        );
     }
 
+    #[cfg(feature = "async")]
     #[tokio::test]
     pub async fn test_excel_encryption_detection_off() {
         let ole_file = OleFile::from_file("../data/encryption/plaintext/plain.xls")
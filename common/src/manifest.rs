@@ -0,0 +1,289 @@
+//! Integrity manifests: a record of every entry in a file that can be stored as text and
+//! checked against the file later.
+//!
+//! The text form starts with a version line, followed by one line per entry with tab separated
+//! fields: type, size, SHA-256, CLSID, creation time, modification time and path. Missing values
+//! are written as `-`. Entries are sorted by path, so the same file always gives the same text.
+use crate::{analysis, directory::ObjectType, error::Error, OleFile, Result};
+use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr};
+
+const VERSION_LINE: &str = "# ole manifest v1";
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+const NONE: &str = "-";
+
+/// What a manifest records about a storage or stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ManifestEntry {
+    /// storage names leading to the entry, then its own name
+    pub path: Vec<String>,
+    pub object_type: ObjectType,
+    pub size: u64,
+    /// hex digest of the stream data, `None` for storages
+    pub sha256: Option<String>,
+    pub class_id: Option<String>,
+    pub created: Option<NaiveDateTime>,
+    pub modified: Option<NaiveDateTime>,
+}
+
+/// The entries of a file, see the module docs for the text form.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A difference found by [`Manifest::verify`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ManifestMismatch {
+    /// in the manifest but not in the file
+    Missing(String),
+    /// in the file but not in the manifest
+    Added(String),
+    /// in both, with the named field differing
+    Changed { path: String, field: &'static str },
+}
+
+impl fmt::Display for ManifestMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestMismatch::Missing(path) => write!(f, "{} is missing", path),
+            ManifestMismatch::Added(path) => write!(f, "{} was added", path),
+            ManifestMismatch::Changed { path, field } => write!(f, "{} changed {}", path, field),
+        }
+    }
+}
+
+impl Manifest {
+    /// Record every storage and stream of `ole`, hashing the stream data.
+    pub fn of(ole: &OleFile) -> Result<Self> {
+        let mut entries = analysis::entries_with_paths(ole)
+            .into_iter()
+            .map(|(path, entry)| {
+                let sha256 = match entry.object_type {
+                    ObjectType::Stream => {
+                        let components = path.iter().map(String::as_str).collect::<Vec<_>>();
+                        let data = ole.open_stream(&components)?;
+                        Some(hex(&Sha256::digest(data)))
+                    }
+                    _ => None,
+                };
+                Ok(ManifestEntry {
+                    object_type: entry.object_type,
+                    size: entry.stream_size,
+                    sha256,
+                    class_id: entry.class_id.clone(),
+                    created: entry.creation_time,
+                    modified: entry.modification_time,
+                    path,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self { entries })
+    }
+
+    /// Compare `ole` against this manifest, an empty list means it matches.
+    pub fn verify(&self, ole: &OleFile) -> Result<Vec<ManifestMismatch>> {
+        let current = Self::of(ole)?;
+        let mut mismatches = vec![];
+        for expected in &self.entries {
+            let path = escape_path(&expected.path);
+            match current
+                .entries
+                .iter()
+                .find(|entry| entry.path == expected.path)
+            {
+                None => mismatches.push(ManifestMismatch::Missing(path)),
+                Some(found) => {
+                    let fields = [
+                        ("type", found.object_type != expected.object_type),
+                        ("size", found.size != expected.size),
+                        ("sha256", found.sha256 != expected.sha256),
+                        ("class id", found.class_id != expected.class_id),
+                        ("creation time", found.created != expected.created),
+                        ("modification time", found.modified != expected.modified),
+                    ];
+                    mismatches.extend(fields.iter().filter(|(_, differs)| *differs).map(
+                        |(field, _)| ManifestMismatch::Changed {
+                            path: path.clone(),
+                            field,
+                        },
+                    ));
+                }
+            }
+        }
+        mismatches.extend(
+            current
+                .entries
+                .iter()
+                .filter(|entry| !self.entries.iter().any(|e| e.path == entry.path))
+                .map(|entry| ManifestMismatch::Added(escape_path(&entry.path))),
+        );
+        Ok(mismatches)
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", VERSION_LINE)?;
+        for entry in &self.entries {
+            let time = |time: &Option<NaiveDateTime>| {
+                time.map(|time| time.format(TIME_FORMAT).to_string())
+                    .unwrap_or_else(|| NONE.to_string())
+            };
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                match entry.object_type {
+                    ObjectType::Stream => "stream",
+                    _ => "storage",
+                },
+                entry.size,
+                entry.sha256.as_deref().unwrap_or(NONE),
+                entry.class_id.as_deref().unwrap_or(NONE),
+                time(&entry.created),
+                time(&entry.modified),
+                escape_path(&entry.path)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+        if lines.next() != Some(VERSION_LINE) {
+            return Err(Error::InvalidManifest(
+                "missing or unsupported version line".to_string(),
+            ));
+        }
+        let entries = lines
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(number, line)| {
+                let invalid = |what: &str| {
+                    Error::InvalidManifest(format!("line {}: invalid {}", number + 2, what))
+                };
+                let fields = line.split('\t').collect::<Vec<_>>();
+                if fields.len() != 7 {
+                    return Err(invalid("number of fields"));
+                }
+                let optional = |field: &str| (field != NONE).then(|| field.to_string());
+                let time = |field: &str| match field {
+                    NONE => Ok(None),
+                    time => NaiveDateTime::parse_from_str(time, TIME_FORMAT)
+                        .map(Some)
+                        .map_err(|_| invalid("time")),
+                };
+                Ok(ManifestEntry {
+                    object_type: match fields[0] {
+                        "stream" => ObjectType::Stream,
+                        "storage" => ObjectType::Storage,
+                        _ => return Err(invalid("type")),
+                    },
+                    size: fields[1].parse().map_err(|_| invalid("size"))?,
+                    sha256: optional(fields[2]),
+                    class_id: optional(fields[3]),
+                    created: time(fields[4])?,
+                    modified: time(fields[5])?,
+                    path: unescape_path(fields[6]),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { entries })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Like [`crate::util::join_stream_path`], also escaping the characters that would break a line
+/// of the manifest.
+fn escape_path(path: &[String]) -> String {
+    path.iter()
+        .map(|name| {
+            name.chars()
+                .map(|c| match c {
+                    '\\' => "\\\\".to_string(),
+                    '/' => "\\/".to_string(),
+                    '\t' => "\\t".to_string(),
+                    '\n' => "\\n".to_string(),
+                    '\r' => "\\r".to_string(),
+                    c => c.to_string(),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn unescape_path(path: &str) -> Vec<String> {
+    let mut components = vec![String::new()];
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        let current = components.last_mut().unwrap();
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => current.push('\t'),
+                Some('n') => current.push('\n'),
+                Some('r') => current.push('\r'),
+                other => current.extend(other),
+            },
+            '/' => components.push(String::new()),
+            c => current.push(c),
+        }
+    }
+    components
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    #[test]
+    fn test_manifest_round_trip_and_verify() {
+        let original = CfbBuilder::new()
+            .stream("Storage/Data", b"original")
+            .stream("Other", &[7; 5000])
+            .build();
+        let ole = OleFile::parse_sync(&original[..]).unwrap();
+        let manifest = ole.manifest().unwrap();
+        assert_eq!(manifest.entries.len(), 3);
+        let text = manifest.to_string();
+        assert!(text.contains("stream\t8\t"));
+        let parsed = text.parse::<Manifest>().unwrap();
+        assert_eq!(parsed, manifest);
+        assert!(parsed.verify(&ole).unwrap().is_empty());
+
+        let tampered = CfbBuilder::new()
+            .stream("Storage/Data", b"tampered")
+            .stream("New", b"new")
+            .build();
+        let ole = OleFile::parse_sync(&tampered[..]).unwrap();
+        assert_eq!(
+            parsed.verify(&ole).unwrap(),
+            vec![
+                ManifestMismatch::Missing("Other".to_string()),
+                ManifestMismatch::Changed {
+                    path: "Storage/Data".to_string(),
+                    field: "sha256"
+                },
+                ManifestMismatch::Added("New".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_path_escaping() {
+        let path = vec!["a/b".to_string(), "tab\there\\".to_string()];
+        assert_eq!(unescape_path(&escape_path(&path)), path);
+        assert!(!escape_path(&path).contains('\t'));
+    }
+}
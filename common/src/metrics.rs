@@ -0,0 +1,39 @@
+//! Figures on how a file was parsed, for spotting inputs that are slow or large to parse.
+use std::time::Duration;
+
+/// What parsing a file took, see [`crate::OleFile::parse_metrics`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseMetrics {
+    /// bytes taken from the input: the header and the sectors, only those needed for the
+    /// tables and the directory when sectors are read on demand, and the whole mapping for a
+    /// memory-mapped file
+    pub bytes_read: u64,
+    pub sectors: usize,
+    /// allocated directory entries
+    pub directory_entries: usize,
+    /// bytes the allocation tables, the directory and the mini stream take in memory, what
+    /// [`OleParseOptions::max_memory`](crate::OleParseOptions::max_memory) limits
+    pub memory: usize,
+    /// reading the header, and the sectors unless they are read on demand
+    pub read_time: Duration,
+    /// locating and reading the FAT
+    pub fat_time: Duration,
+    /// reading the directory and parsing its entries
+    pub directory_time: Duration,
+    /// reading the mini FAT and the mini stream
+    pub mini_stream_time: Duration,
+    /// finding out the file type and whether the content is encrypted
+    pub classify_time: Duration,
+}
+
+impl ParseMetrics {
+    /// The time all the phases took.
+    pub fn total_time(&self) -> Duration {
+        self.read_time
+            + self.fat_time
+            + self.directory_time
+            + self.mini_stream_time
+            + self.classify_time
+    }
+}
@@ -0,0 +1,121 @@
+//! Looking up entries by path without searching the directory trees.
+//!
+//! Names are compared after mapping them to uppercase, as MS-CFB does, so the index keys every
+//! entry by its parent and its uppercased name. It only stands in for the tree search where it
+//! gives the same answer: a directory with an entry in two trees, or twice in one, isn't indexed
+//! at all, and names that more than one child of a storage shares are left to the search.
+use crate::OleFile;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct NameIndex {
+    /// stream ID by parent stream ID and uppercased name, `None` if several children share it
+    children: HashMap<(u32, String), Option<u32>>,
+}
+
+fn normalize(name: &str) -> String {
+    name.chars().flat_map(char::to_uppercase).collect()
+}
+
+impl NameIndex {
+    /// The index of the directory of `ole`, `None` if its trees share entries or link back to
+    /// them.
+    pub(crate) fn build(ole: &OleFile) -> Option<Self> {
+        let mut index = NameIndex::default();
+        // by stream ID, whether the entry was met in the trees below the root
+        let mut reached = vec![false; ole.id_count()];
+        // the siblings of the root aren't its children, which the tree search wouldn't find
+        let root = ole.root();
+        if root.left_sibling_id.is_some() || root.right_sibling_id.is_some() {
+            return None;
+        }
+        reached[0] = true;
+        let mut pending = root
+            .child_id
+            .map(|id| (id, 0))
+            .into_iter()
+            .collect::<Vec<_>>();
+        while let Some((id, parent)) = pending.pop() {
+            let entry = match ole.entry_by_id(id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if std::mem::replace(&mut reached[id as usize], true) {
+                return None;
+            }
+            index
+                .children
+                .entry((parent, normalize(&entry.name)))
+                .and_modify(|shared| *shared = None)
+                .or_insert(Some(id));
+            for sibling in [entry.left_sibling_id, entry.right_sibling_id]
+                .into_iter()
+                .flatten()
+            {
+                pending.push((sibling, parent));
+            }
+            if let Some(child) = entry.child_id {
+                pending.push((child, id));
+            }
+        }
+        Some(index)
+    }
+
+    /// The stream ID at `path` as the tree search finds it, `Some(None)` if nothing is there
+    /// and `None` if only the search can tell.
+    pub(crate) fn get(&self, path: &[&str]) -> Option<Option<u32>> {
+        if path.is_empty() {
+            return Some(None);
+        }
+        let mut id = 0;
+        for name in path {
+            id = match self.children.get(&(id, normalize(name))) {
+                Some(found) => (*found)?,
+                None => return Some(None),
+            };
+        }
+        Some(Some(id))
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    #[test]
+    fn test_name_index() {
+        let data = CfbBuilder::new()
+            .stream("Macros/VBA/dir", b"dir")
+            .stream("Macros/VBA/Module1", b"module")
+            .stream("WordDocument", b"word")
+            .build();
+        let mut ole = OleFile::parse_sync(&data[..]).unwrap();
+        let id = |path: &str| ole.find_stream_path(path).unwrap().index as u32;
+        let (vba, dir, module, word) = (
+            id("Macros/VBA"),
+            id("Macros/VBA/dir"),
+            id("Macros/VBA/Module1"),
+            id("WordDocument"),
+        );
+        let index = NameIndex::build(&ole).unwrap();
+        assert_eq!(index.get(&["macros", "vba", "DIR"]), Some(Some(dir)));
+        assert_eq!(index.get(&["WordDocument"]), Some(Some(word)));
+        assert_eq!(index.get(&["Macros", "dir"]), Some(None));
+        assert_eq!(index.get(&["Missing"]), Some(None));
+        assert_eq!(index.get(&[]), Some(None));
+        // a name below a storage isn't found from the root
+        assert_eq!(index.get(&["module1"]), Some(None));
+        assert_eq!(index.get(&["Macros", "VBA", "module1"]), Some(Some(module)));
+
+        // children sharing a name are left to the tree search
+        ole.directory_entries[module as usize].name = "DIR".to_string();
+        let index = NameIndex::build(&ole).unwrap();
+        assert_eq!(index.get(&["Macros", "VBA", "dir"]), None);
+        assert_eq!(index.get(&["Macros", "VBA"]), Some(Some(vba)));
+
+        // an entry linked from two trees isn't indexed
+        ole.directory_entries[module as usize].right_sibling_id = Some(word);
+        assert!(NameIndex::build(&ole).is_none());
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{constants, directory::ObjectType, OleFile};
+use log::trace;
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+/// A compound file found inside the data of another stream.
+#[derive(Clone, Debug)]
+pub struct NestedOleStream {
+    /// name of the stream holding the embedded file
+    pub stream_name: String,
+    /// index of that stream in the directory array
+    pub entry_index: usize,
+    /// byte offset of the CFB magic within the stream
+    pub offset: usize,
+    /// the stream bytes from `offset` to the end of the stream
+    pub data: Vec<u8>,
+}
+
+/// Bounds for [`OleFile::unwrap_nested`], so a crafted file can't make it recurse forever.
+#[derive(Clone, Copy, Debug)]
+pub struct UnwrapLimits {
+    /// how deep compound files may be nested, the outermost file being depth 0
+    pub max_depth: usize,
+    /// how many nested compound files are unwrapped in total
+    pub max_artifacts: usize,
+}
+
+impl Default for UnwrapLimits {
+    fn default() -> Self {
+        UnwrapLimits {
+            max_depth: 8,
+            max_artifacts: 256,
+        }
+    }
+}
+
+/// Why [`OleFile::unwrap_nested`] stopped before looking at everything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Truncation {
+    MaxDepth(usize),
+    MaxArtifacts(usize),
+}
+
+impl fmt::Display for Truncation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Truncation::MaxDepth(depth) => {
+                write!(
+                    f,
+                    "files nested deeper than {} levels were not unwrapped",
+                    depth
+                )
+            }
+            Truncation::MaxArtifacts(count) => {
+                write!(f, "only the first {} nested files were unwrapped", count)
+            }
+        }
+    }
+}
+
+/// A compound file unwrapped from inside another one.
+#[derive(Clone, Debug)]
+pub struct UnwrappedOle {
+    /// the names of the streams that lead to this file, from the outermost one
+    pub path: Vec<String>,
+    /// 1 for a file inside the outermost file, 2 for one inside that, ...
+    pub depth: usize,
+    pub file: OleFile,
+}
+
+/// Everything [`OleFile::unwrap_nested`] found.
+#[derive(Clone, Debug, Default)]
+pub struct Unwrapped {
+    /// the nested files, shallowest first
+    pub files: Vec<UnwrappedOle>,
+    /// nested files skipped because the same content was already unwrapped
+    pub duplicates_skipped: usize,
+    /// the limits that were hit, each reported once
+    pub truncated: Vec<Truncation>,
+}
+
+impl Unwrapped {
+    fn truncate(&mut self, truncation: Truncation) {
+        if !self.truncated.contains(&truncation) {
+            self.truncated.push(truncation);
+        }
+    }
+}
+
+/// Breadth first unwrapping, see [`OleFile::unwrap_nested`].
+pub(crate) fn unwrap_nested(outer: &OleFile, limits: &UnwrapLimits) -> Unwrapped {
+    let mut unwrapped = Unwrapped::default();
+    // the outer file counts as seen, in case it contains itself
+    let mut seen = HashSet::from([content_hash(outer)]);
+    // `None` is the outer file, `Some(i)` the i-th unwrapped one
+    let mut position = None;
+    loop {
+        let (parent, path, depth) = match position {
+            None => (outer, vec![], 0),
+            Some(index) => {
+                let found: &UnwrappedOle = match unwrapped.files.get(index) {
+                    Some(found) => found,
+                    None => break,
+                };
+                (&found.file, found.path.clone(), found.depth)
+            }
+        };
+        let mut children = vec![];
+        for nested in parent.nested_ole_streams() {
+            if depth + 1 > limits.max_depth {
+                unwrapped.truncate(Truncation::MaxDepth(limits.max_depth));
+                break;
+            }
+            let file = match OleFile::parse_lenient_sync(whole_sectors(&nested.data)) {
+                Ok(file) => file,
+                Err(err) => {
+                    trace!(
+                        "could not parse nested file in stream {}: {}",
+                        nested.stream_name,
+                        err
+                    );
+                    continue;
+                }
+            };
+            if !seen.insert(content_hash(&file)) {
+                unwrapped.duplicates_skipped += 1;
+                continue;
+            }
+            if unwrapped.files.len() + children.len() >= limits.max_artifacts {
+                unwrapped.truncate(Truncation::MaxArtifacts(limits.max_artifacts));
+                break;
+            }
+            let mut path = path.clone();
+            path.push(nested.stream_name);
+            children.push(UnwrappedOle {
+                path,
+                depth: depth + 1,
+                file,
+            });
+        }
+        unwrapped.files.extend(children);
+        position = Some(position.map_or(0, |index| index + 1));
+    }
+    unwrapped
+}
+
+/// Hashes the names and data of the streams, not the bytes the file was parsed from: those run
+/// to the end of the outer stream, so the same file can be found with different trailing bytes.
+fn content_hash(file: &OleFile) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for entry in file.entries() {
+        entry.name.hash(&mut hasher);
+        if entry.object_type == ObjectType::Stream {
+            file.read_entry_data(entry).ok().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// A nested file runs to the end of its stream, which need not end on a sector boundary.
+/// Drop the partial sector at the end so it doesn't fail the parse.
+fn whole_sectors(data: &[u8]) -> &[u8] {
+    let sector_size = match data.get(30..32) {
+        Some([0x0C, 0x00]) => 4096,
+        _ => 512,
+    };
+    &data[..data.len() - data.len() % sector_size]
+}
+
+/// Returns every offset in `data` at which a CFB header could start.
+///
+/// The start of the stream is always checked. Streams larger than one sector are also checked
+/// at each `sector_size`-aligned offset, as writers that wrap a compound file in a record
+/// structure tend to keep it sector aligned. A candidate needs room for a full header.
+pub(crate) fn find_magic_offsets(data: &[u8], sector_size: usize) -> Vec<usize> {
+    let step = if data.len() > sector_size && sector_size > 0 {
+        sector_size
+    } else {
+        data.len().max(1)
+    };
+
+    (0..data.len())
+        .step_by(step)
+        .filter(|offset| {
+            data.len() - offset >= constants::HEADER_LENGTH
+                && data[*offset..].starts_with(&constants::MAGIC_BYTES)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    fn with_magic_at(len: usize, offsets: &[usize]) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        for offset in offsets {
+            data[*offset..*offset + 8].copy_from_slice(&constants::MAGIC_BYTES);
+        }
+        data
+    }
+
+    #[test]
+    pub fn test_magic_at_stream_start() {
+        let data = with_magic_at(600, &[0]);
+        assert_eq!(find_magic_offsets(&data, 512), vec![0]);
+    }
+
+    #[test]
+    pub fn test_magic_at_sector_aligned_offsets_only() {
+        let data = with_magic_at(4096, &[512, 1000, 2048]);
+        assert_eq!(find_magic_offsets(&data, 512), vec![512, 2048]);
+    }
+
+    #[test]
+    pub fn test_magic_without_room_for_header() {
+        let data = with_magic_at(1024 + 100, &[1024]);
+        assert!(find_magic_offsets(&data, 512).is_empty());
+        assert!(find_magic_offsets(&[], 512).is_empty());
+    }
+
+    fn nested_file() -> OleFile {
+        let inner = CfbBuilder::new().stream("Payload", b"payload").build();
+        // the padding keeps the inner file off a sector boundary of the middle one, so it is
+        // only found once the middle file is parsed
+        let middle = CfbBuilder::new()
+            .stream("Pad", &[0; 64])
+            .stream("Inner", &inner)
+            .build();
+        let outer = CfbBuilder::new()
+            .stream("Middle", &middle)
+            .stream("Copy", &middle)
+            .build();
+        OleFile::parse_sync(outer.as_slice()).unwrap()
+    }
+
+    #[test]
+    pub fn test_unwrap_nested() {
+        let unwrapped = nested_file().unwrap_nested(&UnwrapLimits::default());
+        let paths = unwrapped
+            .files
+            .iter()
+            .map(|found| (found.path.join("/"), found.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![("Middle".to_string(), 1), ("Middle/Inner".to_string(), 2)]
+        );
+        assert_eq!(
+            unwrapped.files[1].file.open_stream(&["Payload"]).unwrap(),
+            b"payload"
+        );
+        assert_eq!(unwrapped.duplicates_skipped, 1);
+        assert!(unwrapped.truncated.is_empty());
+    }
+
+    #[test]
+    pub fn test_unwrap_nested_limits() {
+        let file = nested_file();
+        let unwrapped = file.unwrap_nested(&UnwrapLimits {
+            max_depth: 1,
+            ..Default::default()
+        });
+        assert_eq!(unwrapped.files.len(), 1);
+        assert_eq!(unwrapped.truncated, vec![Truncation::MaxDepth(1)]);
+
+        let unwrapped = file.unwrap_nested(&UnwrapLimits {
+            max_artifacts: 1,
+            ..Default::default()
+        });
+        assert_eq!(unwrapped.files.len(), 1);
+        assert_eq!(unwrapped.truncated, vec![Truncation::MaxArtifacts(1)]);
+    }
+}
@@ -0,0 +1,78 @@
+//! How forgiving parsing is, and how much of the input it is willing to take in.
+
+/// Settings for [`OleFile::from_reader_with_options`] and [`OleFile::parse_sync_with_options`].
+///
+/// Spec validation wants the parser to reject anything MS-CFB forbids, malware analysis wants
+/// it to get as far as it can. [`OleParseOptions::strict`] is the first and the default,
+/// [`OleParseOptions::lenient`] the second; the fields can be adjusted from either.
+///
+/// [`OleFile::from_reader_with_options`]: crate::OleFile::from_reader_with_options
+/// [`OleFile::parse_sync_with_options`]: crate::OleFile::parse_sync_with_options
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OleParseOptions {
+    /// reject headers with a non-zero CLSID or reserved field instead of recording a warning
+    pub strict_header: bool,
+    /// reject headers whose FAT, mini FAT or directory sector counts disagree with the chains
+    /// read instead of recording a warning. Off in both presets: the chains are what the parse
+    /// goes by, and a count that was tampered with is worth a look rather than a refusal.
+    pub strict_counts: bool,
+    /// reject directory entries with names MS-CFB forbids instead of recording a warning: names
+    /// with `/`, `\`, `:`, `!` or control characters past the first, with a bad length or
+    /// without a terminator
+    pub strict_names: bool,
+    /// zero-pad a last sector that is cut short instead of failing with `OleUnexpectedEof`, and
+    /// let through a mini stream that ends inside its last mini sector
+    pub allow_truncation: bool,
+    /// keep what was let through in [`OleFile::warnings`](crate::OleFile::warnings)
+    pub collect_warnings: bool,
+    /// how many sectors the file may have, the header not counted
+    pub max_sectors: usize,
+    /// how many entries the directory stream may hold, unallocated ones included
+    pub max_directory_entries: usize,
+    /// how many storages deep entries may be nested, 0 allowing only children of the root
+    pub max_storage_depth: usize,
+    /// decode directory entries when they are first looked up instead of all while parsing,
+    /// for directories with many entries of which only a few are needed. Only the root has to
+    /// decode then: an entry that can't be decoded is left out like an unallocated one, the
+    /// warnings about entries decoded later aren't kept and the storage depth isn't checked.
+    pub lazy_directory: bool,
+    /// how many bytes the allocation tables, the directory and the mini stream may take in
+    /// memory together, checked before each is read; streams read to find out whether the file
+    /// is encrypted are cut at it as well. With sectors read on demand this bounds what a file
+    /// of any size holds in memory, see [`OleFile::open_bounded`](crate::OleFile::open_bounded)
+    pub max_memory: Option<usize>,
+}
+
+impl OleParseOptions {
+    /// Everything the spec says MUST be so has to be so.
+    pub fn strict() -> Self {
+        OleParseOptions {
+            strict_header: true,
+            strict_counts: false,
+            strict_names: true,
+            allow_truncation: false,
+            collect_warnings: true,
+            max_sectors: 1 << 24,
+            max_directory_entries: 1 << 20,
+            max_storage_depth: 256,
+            lazy_directory: false,
+            max_memory: None,
+        }
+    }
+
+    /// Get past whatever can be got past, and say what that was.
+    pub fn lenient() -> Self {
+        OleParseOptions {
+            strict_header: false,
+            strict_names: false,
+            allow_truncation: true,
+            ..Self::strict()
+        }
+    }
+}
+
+impl Default for OleParseOptions {
+    fn default() -> Self {
+        Self::strict()
+    }
+}
@@ -0,0 +1,246 @@
+//! Deleted directory entries and free sectors.
+//!
+//! Deleting a stream usually only sets its object type to unallocated and frees its sectors: the
+//! name, first sector and size stay in the directory until the slot is reused, and so does the
+//! data until the sectors are. Nothing is kept for them while parsing, they are read from the
+//! directory stream when asked for.
+use crate::{constants, follow_chain, OleFile, StreamLocation};
+
+/// A directory entry marked unallocated that still has a name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrphanedEntry {
+    /// position in the directory, the id siblings and children referred to
+    pub index: usize,
+    pub name: String,
+    pub start_sector: u32,
+    pub stream_size: u64,
+}
+
+/// A sector marked free in the FAT or mini FAT, with what it still holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnusedSector {
+    /// whether this is a regular sector or one of the mini stream
+    pub location: StreamLocation,
+    pub index: u32,
+    pub data: Vec<u8>,
+}
+
+pub(crate) fn orphaned_entries(ole: &OleFile) -> Vec<OrphanedEntry> {
+    ole.directory_stream_data
+        .chunks_exact(constants::SIZE_OF_DIRECTORY_ENTRY)
+        .enumerate()
+        .filter(|(_, entry)| entry[66] == constants::OBJECT_TYPE_UNKNOWN_OR_UNALLOCATED[0])
+        .filter_map(|(index, entry)| {
+            let name_len = u16::from_le_bytes([entry[64], entry[65]]) as usize;
+            if name_len > 64 {
+                return None;
+            }
+            let name = entry[..name_len]
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .take_while(|unit| *unit != 0)
+                .collect::<Vec<_>>();
+            if name.is_empty() {
+                return None;
+            }
+            let mut stream_size = u64::from_le_bytes(entry[120..128].try_into().unwrap());
+            // like for allocated entries, version 3 files may leave garbage in the high half
+            if ole.header.major_version == constants::MAJOR_VERSION_3_VALUE {
+                stream_size &= 0xFFFF_FFFF;
+            }
+            Some(OrphanedEntry {
+                index,
+                name: String::from_utf16_lossy(&name),
+                start_sector: u32::from_le_bytes(entry[116..120].try_into().unwrap()),
+                stream_size,
+            })
+        })
+        .collect()
+}
+
+/// The data of an orphaned stream, from its old chain if that is still whole, otherwise from the
+/// sectors following its first one. `None` if that runs past the end of the file.
+pub(crate) fn recover(ole: &OleFile, orphan: &OrphanedEntry) -> Option<Vec<u8>> {
+    let size = orphan.stream_size as usize;
+    if size == 0 {
+        return Some(vec![]);
+    }
+    let (table, unit, available) =
+        if orphan.stream_size < ole.header.standard_stream_min_size as u64 {
+            (
+                &ole.short_sector_allocation_table,
+                constants::MINI_SECTOR_SIZE as usize,
+                ole.mini_stream_bytes().len() / constants::MINI_SECTOR_SIZE as usize,
+            )
+        } else {
+            (
+                &ole.sector_allocation_table,
+                ole.header.sector_size as usize,
+                ole.sectors.len(),
+            )
+        };
+    let len = size.div_ceil(unit);
+    let chain = match follow_chain(table, orphan.start_sector, "sector") {
+        Ok(chain) if chain.len() == len && chain.iter().all(|id| (*id as usize) < available) => {
+            chain
+        }
+        _ if orphan.start_sector as usize + len <= available => {
+            (orphan.start_sector..).take(len).collect()
+        }
+        _ => return None,
+    };
+
+    let mut data = Vec::with_capacity(len * unit);
+    for id in chain {
+        if unit == constants::MINI_SECTOR_SIZE as usize {
+            let offset = id as usize * unit;
+            data.extend(&ole.mini_stream_bytes()[offset..offset + unit]);
+        } else {
+            data.extend(ole.sectors.read(id).ok()?.iter());
+        }
+    }
+    data.truncate(size);
+    Some(data)
+}
+
+/// The sectors of the file and of the mini stream that their allocation table marks free. Table
+/// entries past the end of the file or of the mini stream are only padding and left out, and so
+/// is the range lock sector, which never held data even when a writer left it free.
+pub(crate) fn unused_sectors(ole: &OleFile) -> Vec<UnusedSector> {
+    let free = |table: &[u32], available: usize| {
+        table
+            .iter()
+            .enumerate()
+            .take(available)
+            .filter(|(_, next)| **next == constants::UNALLOCATED_SECTOR)
+            .map(|(index, _)| index as u32)
+            .collect::<Vec<_>>()
+    };
+    let mut unused = vec![];
+    let range_lock = ole.range_lock_sector();
+    for index in free(&ole.sector_allocation_table, ole.sectors.len()) {
+        if Some(index) == range_lock {
+            continue;
+        }
+        if let Ok(data) = ole.sectors.read(index) {
+            unused.push(UnusedSector {
+                location: StreamLocation::Fat,
+                index,
+                data: data.to_vec(),
+            });
+        }
+    }
+    let mini_stream = ole.mini_stream_bytes();
+    let unit = constants::MINI_SECTOR_SIZE as usize;
+    for index in free(&ole.short_sector_allocation_table, mini_stream.len() / unit) {
+        let offset = index as usize * unit;
+        unused.push(UnusedSector {
+            location: StreamLocation::MiniFat,
+            index,
+            data: mini_stream[offset..offset + unit].to_vec(),
+        });
+    }
+    unused
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    #[test]
+    fn test_orphaned_entries() {
+        let large = (0..5000).map(|i| i as u8).collect::<Vec<_>>();
+        let mut data = CfbBuilder::new()
+            .stream("Kept", b"kept")
+            .stream("Small", &[1; 100])
+            .stream("Large", &large)
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        assert!(ole.orphaned_entries().is_empty());
+        let large_start = ole
+            .get_entry(&["Large"])
+            .unwrap()
+            .starting_sector_location
+            .unwrap() as usize;
+
+        // delete Small and Large: unallocate their entries, unlink them from the tree and free
+        // the sectors of Large
+        let directory =
+            (u32::from_le_bytes(data[0x30..0x34].try_into().unwrap()) as usize + 1) * 512;
+        let fat = (u32::from_le_bytes(data[0x4C..0x50].try_into().unwrap()) as usize + 1) * 512;
+        for index in [2, 3] {
+            data[directory + index * 128 + 66] = 0;
+        }
+        data[directory + 128 + 72..directory + 128 + 76].copy_from_slice(&[0xFF; 4]);
+        for sector in large_start..large_start + 10 {
+            data[fat + sector * 4..fat + sector * 4 + 4].copy_from_slice(&[0xFF; 4]);
+        }
+
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        assert_eq!(ole.list_streams(), vec!["Kept"]);
+        let orphans = ole.orphaned_entries();
+        assert_eq!(
+            orphans
+                .iter()
+                .map(|orphan| (orphan.index, orphan.name.as_str(), orphan.stream_size))
+                .collect::<Vec<_>>(),
+            vec![(2, "Small", 100), (3, "Large", 5000)]
+        );
+        assert_eq!(ole.recover_orphan(&orphans[0]), Some(vec![1; 100]));
+        assert_eq!(ole.recover_orphan(&orphans[1]), Some(large));
+
+        let beyond = OrphanedEntry {
+            start_sector: 10_000,
+            ..orphans[1].clone()
+        };
+        assert_eq!(ole.recover_orphan(&beyond), None);
+    }
+
+    #[test]
+    fn test_unused_sectors() {
+        let large = (0..5000).map(|i| i as u8).collect::<Vec<_>>();
+        let mut data = CfbBuilder::new()
+            .stream("Small", &[1; 100])
+            .stream("Large", &large)
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        assert!(ole
+            .unused_sectors()
+            .iter()
+            .all(|sector| sector.location == StreamLocation::MiniFat));
+        let large_start = ole
+            .get_entry(&["Large"])
+            .unwrap()
+            .starting_sector_location
+            .unwrap() as usize;
+        let small_start = ole
+            .get_entry(&["Small"])
+            .unwrap()
+            .starting_sector_location
+            .unwrap() as usize;
+
+        // cut Large after its first sector, freeing the second and the first mini sector of Small
+        let fat = (u32::from_le_bytes(data[0x4C..0x50].try_into().unwrap()) as usize + 1) * 512;
+        let mini_fat =
+            (u32::from_le_bytes(data[0x3C..0x40].try_into().unwrap()) as usize + 1) * 512;
+        let offset = fat + large_start * 4;
+        data[offset..offset + 4].copy_from_slice(&constants::CHAIN_END.to_le_bytes());
+        data[offset + 4..offset + 8].copy_from_slice(&[0xFF; 4]);
+        let offset = mini_fat + small_start * 4;
+        data[offset..offset + 4].copy_from_slice(&[0xFF; 4]);
+
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let unused = ole.unused_sectors();
+        assert!(unused.contains(&UnusedSector {
+            location: StreamLocation::Fat,
+            index: large_start as u32 + 1,
+            data: large[512..1024].to_vec(),
+        }));
+        assert!(unused.contains(&UnusedSector {
+            location: StreamLocation::MiniFat,
+            index: small_start as u32,
+            data: vec![1; 64],
+        }));
+    }
+}
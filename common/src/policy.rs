@@ -0,0 +1,420 @@
+//! Policies deciding what to do with a file from its [`AnalysisReport`], kept as text so a
+//! gateway can change them without being rebuilt.
+//!
+//! The text form starts with a version line, followed by one rule per line: an action, then the
+//! conditions that must all hold, joined by `&`. A condition is negated with `!`, and a rule
+//! without conditions always matches. Blank lines and lines starting with `#` are skipped.
+//!
+//! ```text
+//! # ole policy v1
+//! block macros & autoexec
+//! block packager
+//! flag encrypted
+//! ```
+//!
+//! Rules are tried in order. The first `allow` or `block` rule that matches decides, and the
+//! `flag` rules matching before it are collected. The verdict is `block` if a block rule
+//! decided, otherwise `flag` if a flag rule matched, otherwise `allow`.
+use crate::{error::Error, AnalysisReport, OleObjectKind, Result};
+use std::{fmt, str::FromStr};
+
+const VERSION_LINE: &str = "# ole policy v1";
+
+/// What a rule, or a whole policy, says to do with a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Allow,
+    Flag,
+    Block,
+}
+
+/// Something a report can show, which a rule tests for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// a VBA project with at least one module
+    Macros,
+    /// a VBA procedure that runs by itself, such as `AutoOpen`
+    Autoexec,
+    /// an OLE 1.0 Packager object, wrapping an arbitrary file
+    Packager,
+    /// an OLE object linking to data outside the file
+    Linked,
+    /// an OLE object of any kind
+    OleObject,
+    Encrypted,
+    /// a workbook connection to external data
+    ExternalData,
+    /// a compound file nested in this one
+    Nested,
+    Not(Box<Condition>),
+}
+
+/// An action and the conditions under which it applies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub action: Action,
+    /// all of them must hold, none means the rule always matches
+    pub conditions: Vec<Condition>,
+}
+
+/// Rules to evaluate reports against, see the module docs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+}
+
+/// The outcome of [`Policy::evaluate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Verdict {
+    pub action: Action,
+    /// the `allow` or `block` rule that decided, `None` when none matched
+    pub decided_by: Option<Rule>,
+    /// the `flag` rules that matched before the decision
+    pub flagged: Vec<Rule>,
+}
+
+impl Condition {
+    pub fn holds(&self, report: &AnalysisReport) -> bool {
+        let has_object = |kind| report.ole_objects.iter().any(|object| object.kind == kind);
+        match self {
+            Condition::Macros => report.macros,
+            Condition::Autoexec => !report.autoexec.is_empty(),
+            Condition::Packager => has_object(OleObjectKind::Packager),
+            Condition::Linked => has_object(OleObjectKind::Linked),
+            Condition::OleObject => !report.ole_objects.is_empty(),
+            Condition::Encrypted => report.encrypted,
+            Condition::ExternalData => !report.external_connections.is_empty(),
+            Condition::Nested => report.nested_files > 0,
+            Condition::Not(condition) => !condition.holds(report),
+        }
+    }
+}
+
+impl Rule {
+    pub fn new(action: Action, conditions: Vec<Condition>) -> Self {
+        Rule { action, conditions }
+    }
+
+    pub fn matches(&self, report: &AnalysisReport) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.holds(report))
+    }
+}
+
+impl Policy {
+    /// Decide what to do with the file `report` describes.
+    ///
+    /// ```rust
+    /// use ole::{Action, Policy};
+    ///
+    /// let policy: Policy = "# ole policy v1\nblock packager\nflag ole-object"
+    ///     .parse()
+    ///     .unwrap();
+    /// let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+    /// let verdict = policy.evaluate(&ole::analyze(&data).unwrap());
+    /// assert_eq!(verdict.action, Action::Block);
+    /// ```
+    pub fn evaluate(&self, report: &AnalysisReport) -> Verdict {
+        let mut flagged = vec![];
+        for rule in &self.rules {
+            if !rule.matches(report) {
+                continue;
+            }
+            match rule.action {
+                Action::Flag => flagged.push(rule.clone()),
+                Action::Allow | Action::Block => {
+                    let action = match rule.action {
+                        Action::Allow if !flagged.is_empty() => Action::Flag,
+                        action => action,
+                    };
+                    return Verdict {
+                        action,
+                        decided_by: Some(rule.clone()),
+                        flagged,
+                    };
+                }
+            }
+        }
+        Verdict {
+            action: if flagged.is_empty() {
+                Action::Allow
+            } else {
+                Action::Flag
+            },
+            decided_by: None,
+            flagged,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Allow => "allow",
+            Action::Flag => "flag",
+            Action::Block => "block",
+        })
+    }
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "allow" => Ok(Action::Allow),
+            "flag" => Ok(Action::Flag),
+            "block" => Ok(Action::Block),
+            _ => Err(Error::InvalidPolicy(format!("unknown action {:?}", text))),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Macros => f.write_str("macros"),
+            Condition::Autoexec => f.write_str("autoexec"),
+            Condition::Packager => f.write_str("packager"),
+            Condition::Linked => f.write_str("linked"),
+            Condition::OleObject => f.write_str("ole-object"),
+            Condition::Encrypted => f.write_str("encrypted"),
+            Condition::ExternalData => f.write_str("external-data"),
+            Condition::Nested => f.write_str("nested"),
+            Condition::Not(condition) => write!(f, "!{}", condition),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        if let Some(negated) = text.strip_prefix('!') {
+            return Ok(Condition::Not(Box::new(negated.trim_start().parse()?)));
+        }
+        match text {
+            "macros" => Ok(Condition::Macros),
+            "autoexec" => Ok(Condition::Autoexec),
+            "packager" => Ok(Condition::Packager),
+            "linked" => Ok(Condition::Linked),
+            "ole-object" => Ok(Condition::OleObject),
+            "encrypted" => Ok(Condition::Encrypted),
+            "external-data" => Ok(Condition::ExternalData),
+            "nested" => Ok(Condition::Nested),
+            _ => Err(Error::InvalidPolicy(format!(
+                "unknown condition {:?}",
+                text
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.action)?;
+        for (position, condition) in self.conditions.iter().enumerate() {
+            let separator = if position == 0 { " " } else { " & " };
+            write!(f, "{}{}", separator, condition)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let (action, conditions) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let conditions = if conditions.trim().is_empty() {
+            vec![]
+        } else {
+            conditions
+                .split('&')
+                .map(|condition| condition.trim().parse())
+                .collect::<Result<_>>()?
+        };
+        Ok(Rule {
+            action: action.parse()?,
+            conditions,
+        })
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", VERSION_LINE)?;
+        for rule in &self.rules {
+            writeln!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Policy {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, line)) if line.trim() == VERSION_LINE => {}
+            _ => {
+                return Err(Error::InvalidPolicy(format!(
+                    "expected {:?} on the first line",
+                    VERSION_LINE
+                )))
+            }
+        }
+        let mut rules = vec![];
+        for (number, line) in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            rules.push(line.parse().map_err(|err| match err {
+                Error::InvalidPolicy(reason) => {
+                    Error::InvalidPolicy(format!("line {}: {}", number + 1, reason))
+                }
+                err => err,
+            })?);
+        }
+        Ok(Policy { rules })
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.action)?;
+        let rules = self.flagged.iter().chain(self.decided_by.as_ref());
+        for (position, rule) in rules.enumerate() {
+            let separator = if position == 0 { " by " } else { ", " };
+            write!(f, "{}`{}`", separator, rule)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::HeaderInfo, ftype::OleFileType, AutoExec, OleObject};
+
+    fn report() -> AnalysisReport {
+        AnalysisReport {
+            version: crate::REPORT_VERSION,
+            header: HeaderInfo {
+                major_version: 3,
+                minor_version: 0x3E,
+                sector_size: 512,
+                mini_sector_size: 64,
+                mini_stream_cutoff: 4096,
+                directory_sectors: 0,
+                fat_sectors: 1,
+                mini_fat_sectors: 0,
+                difat_sectors: 0,
+            },
+            file_type: OleFileType::Generic,
+            encrypted: false,
+            encryption_scheme: None,
+            streams: vec![],
+            nested_files: 0,
+            trees: vec![],
+            external_connections: vec![],
+            macros: false,
+            autoexec: vec![],
+            ole_objects: vec![],
+        }
+    }
+
+    const POLICY: &str = "# ole policy v1
+# a comment
+
+block macros & autoexec
+allow !encrypted & ole-object & !packager
+flag encrypted
+block packager
+flag macros
+";
+
+    #[test]
+    fn test_policy_round_trip() {
+        let policy: Policy = POLICY.parse().unwrap();
+        assert_eq!(policy.rules.len(), 5);
+        assert_eq!(
+            policy.rules[1],
+            Rule::new(
+                Action::Allow,
+                vec![
+                    Condition::Not(Box::new(Condition::Encrypted)),
+                    Condition::OleObject,
+                    Condition::Not(Box::new(Condition::Packager)),
+                ]
+            )
+        );
+        assert_eq!(policy.to_string().parse::<Policy>().unwrap(), policy);
+        assert_eq!("block".parse::<Rule>().unwrap().to_string(), "block");
+
+        assert!(matches!(
+            "block macros".parse::<Policy>(),
+            Err(Error::InvalidPolicy(_))
+        ));
+        match "# ole policy v1\nblock macro".parse::<Policy>() {
+            Err(Error::InvalidPolicy(reason)) => {
+                assert_eq!(reason, "line 2: unknown condition \"macro\"")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            "# ole policy v1\ndeny macros".parse::<Policy>(),
+            Err(Error::InvalidPolicy(_))
+        ));
+    }
+
+    #[test]
+    fn test_policy_evaluate() {
+        let policy: Policy = POLICY.parse().unwrap();
+        let clean = report();
+        let verdict = policy.evaluate(&clean);
+        assert_eq!(verdict.action, Action::Allow);
+        assert_eq!(verdict.decided_by, None);
+
+        let mut macros = report();
+        macros.macros = true;
+        let verdict = policy.evaluate(&macros);
+        assert_eq!(verdict.action, Action::Flag);
+        assert_eq!(verdict.to_string(), "flag by `flag macros`");
+
+        macros.autoexec.push(AutoExec {
+            storage: vec!["Macros".to_string()],
+            module: "ThisDocument".to_string(),
+            procedure: "Document_Open".to_string(),
+        });
+        let verdict = policy.evaluate(&macros);
+        assert_eq!(verdict.action, Action::Block);
+        assert_eq!(verdict.decided_by, Some(policy.rules[0].clone()));
+
+        let object = |kind| OleObject {
+            path: vec!["ObjectPool".to_string(), "_1".to_string()].into(),
+            kind,
+            class_id: None,
+            prog_id: None,
+        };
+        let mut chart = report();
+        chart.ole_objects.push(object(OleObjectKind::Storage));
+        let verdict = policy.evaluate(&chart);
+        assert_eq!(verdict.action, Action::Allow);
+        assert_eq!(verdict.decided_by, Some(policy.rules[1].clone()));
+
+        let mut package = report();
+        package.encrypted = true;
+        package.ole_objects.push(object(OleObjectKind::Packager));
+        let verdict = policy.evaluate(&package);
+        assert_eq!(verdict.action, Action::Block);
+        assert_eq!(
+            verdict.to_string(),
+            "block by `flag encrypted`, `block packager`"
+        );
+    }
+}
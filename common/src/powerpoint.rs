@@ -0,0 +1,421 @@
+//! Structures of PowerPoint 97-2003 presentations.
+//!
+//! Saving a presentation incrementally appends the changed objects to the `PowerPoint Document`
+//! stream, with a persist directory giving their offsets and a UserEditAtom pointing at the
+//! previous edit. The objects an edit replaced stay in the stream, so walking the edits back
+//! from the one the CurrentUserAtom names gives every earlier state of the document.
+use crate::{error::Error, OleFile, Result};
+use std::collections::{BTreeMap, HashSet};
+
+/// record type of the CurrentUserAtom (MS-PPT 2.3.2)
+const RT_CURRENT_USER_ATOM: u16 = 0x0FF6;
+/// value of `size`, the length of the fixed part of the atom
+const CURRENT_USER_ATOM_SIZE: u32 = 0x14;
+/// `headerToken` of a presentation that is not encrypted
+const HEADER_TOKEN: u32 = 0xE391_C05F;
+/// `headerToken` of an encrypted presentation
+const ENCRYPTED_HEADER_TOKEN: u32 = 0xF3D1_C4DF;
+/// record type of the UserEditAtom (MS-PPT 2.3.3)
+const RT_USER_EDIT_ATOM: u16 = 0x0FF5;
+/// record type of the PersistDirectoryAtom (MS-PPT 2.3.4)
+const RT_PERSIST_DIRECTORY_ATOM: u16 = 0x1772;
+/// length of the UserEditAtom without and with `encryptSessionPersistIdRef`
+const USER_EDIT_ATOM_LEN: u32 = 0x1C;
+const ENCRYPTED_USER_EDIT_ATOM_LEN: u32 = 0x20;
+/// length of a record header
+const RECORD_HEADER_LEN: usize = 8;
+/// fixed fields up to the ANSI user name: record header, size, headerToken,
+/// offsetToCurrentEdit, lenUserName, docFileVersion, majorVersion, minorVersion and unused
+const USER_NAME_OFFSET: usize = 8 + 4 + 4 + 4 + 2 + 2 + 1 + 1 + 2;
+
+/// The CurrentUserAtom of the `Current User` stream: who last saved the presentation, with
+/// what, and where that edit starts in the `PowerPoint Document` stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CurrentUser {
+    /// the user name of the application that saved last, from the Unicode copy when present
+    pub user_name: String,
+    /// 8 for files saved by PowerPoint 97 to 2003, 9 when the file also holds PowerPoint 2007
+    /// content
+    pub release_version: u32,
+    /// offset of the last UserEditAtom in the `PowerPoint Document` stream, where the edit
+    /// history starts
+    pub offset_to_current_edit: u32,
+    /// `docFileVersion`, 0x03F4 for any file this century
+    pub doc_file_version: u16,
+    pub major_version: u8,
+    pub minor_version: u8,
+    /// whether the header token marks the document as encrypted
+    pub encrypted: bool,
+}
+
+/// One save of a presentation, from its UserEditAtom and persist directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PptEdit {
+    /// offset of the UserEditAtom in the `PowerPoint Document` stream
+    pub offset: u32,
+    /// the slide that was shown when the file was saved, 0 for none
+    pub last_slide_id_ref: u32,
+    /// persist id of the DocumentContainer
+    pub doc_persist_id_ref: u32,
+    /// highest persist id in use
+    pub persist_id_seed: u32,
+    /// the view the file was saved in
+    pub last_view: u16,
+    /// persist id of the encryption header, for encrypted documents
+    pub encrypt_session_persist_id_ref: Option<u32>,
+    /// persist id to stream offset of the objects written by this edit
+    pub persist_directory: BTreeMap<u32, u32>,
+    /// persist id to stream offset of every object of the document as of this edit, which
+    /// include those left unchanged from earlier edits
+    pub objects: BTreeMap<u32, u32>,
+}
+
+/// Read the CurrentUserAtom of a PowerPoint presentation. `None` for files without a
+/// `Current User` stream.
+pub(crate) fn current_user(ole: &OleFile) -> Result<Option<CurrentUser>> {
+    match ole.open_stream(&["Current User"]) {
+        Ok(data) => parse_current_user(&data).map(Some),
+        Err(Error::OleDirectoryEntryNotFound | Error::IsAStorage { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Walk the edits of a PowerPoint presentation, the current one first. Empty for files without
+/// a `Current User` stream.
+pub(crate) fn ppt_edits(ole: &OleFile) -> Result<Vec<PptEdit>> {
+    let user = match current_user(ole)? {
+        Some(user) => user,
+        None => return Ok(vec![]),
+    };
+    let document = ole.open_stream(&["PowerPoint Document"])?;
+    let mut edits = vec![];
+    let mut seen = HashSet::new();
+    let mut offset = user.offset_to_current_edit;
+    loop {
+        if !seen.insert(offset) {
+            return Err(Error::GenericError(
+                "the edit history of the presentation loops",
+            ));
+        }
+        let (edit, previous) = parse_user_edit(&document, offset)?;
+        edits.push(edit);
+        // the oldest edit has no previous one and gives 0
+        if previous == 0 {
+            break;
+        }
+        offset = previous;
+    }
+
+    let mut objects = BTreeMap::new();
+    for edit in edits.iter_mut().rev() {
+        objects.extend(&edit.persist_directory);
+        edit.objects = objects.clone();
+    }
+    Ok(edits)
+}
+
+/// The whole record, header included, at `offset` in the `PowerPoint Document` stream.
+pub(crate) fn ppt_record(ole: &OleFile, offset: u32) -> Result<Vec<u8>> {
+    let header =
+        ole.open_stream_range(&["PowerPoint Document"], offset as u64, RECORD_HEADER_LEN)?;
+    if header.len() < RECORD_HEADER_LEN {
+        return Err(Error::unexpected_eof(format!(
+            "no record at offset {} of the PowerPoint Document stream",
+            offset
+        )));
+    }
+    let len = RECORD_HEADER_LEN + u32_at(&header, 4) as usize;
+    let record = ole.open_stream_range(&["PowerPoint Document"], offset as u64, len)?;
+    if record.len() < len {
+        return Err(Error::unexpected_eof(format!(
+            "the record at offset {} runs past the end of the PowerPoint Document stream",
+            offset
+        )));
+    }
+    Ok(record)
+}
+
+/// The body of the record of type `rec_type` at `offset`.
+fn record_body(document: &[u8], offset: u32, rec_type: u16) -> Result<&[u8]> {
+    let offset = offset as usize;
+    let header = document
+        .get(offset..offset.saturating_add(RECORD_HEADER_LEN))
+        .ok_or_else(|| {
+            Error::unexpected_eof(format!(
+                "no record at offset {} of the PowerPoint Document stream",
+                offset
+            ))
+        })?;
+    if u16_at(header, 2) != rec_type {
+        return Err(Error::invalid_chain(format!(
+            "expected a record of type {:#06x} at offset {}, found {:#06x}",
+            rec_type,
+            offset,
+            u16_at(header, 2)
+        )));
+    }
+    let start = offset + RECORD_HEADER_LEN;
+    document
+        .get(start..start.saturating_add(u32_at(header, 4) as usize))
+        .ok_or_else(|| {
+            Error::unexpected_eof(format!(
+                "the record at offset {} runs past the end of the PowerPoint Document stream",
+                offset
+            ))
+        })
+}
+
+/// The UserEditAtom at `offset` with its persist directory, and the offset of the edit before.
+fn parse_user_edit(document: &[u8], offset: u32) -> Result<(PptEdit, u32)> {
+    let atom = record_body(document, offset, RT_USER_EDIT_ATOM)?;
+    if atom.len() != USER_EDIT_ATOM_LEN as usize
+        && atom.len() != ENCRYPTED_USER_EDIT_ATOM_LEN as usize
+    {
+        return Err(Error::GenericError(
+            "the UserEditAtom has an unexpected size",
+        ));
+    }
+    let directory = record_body(document, u32_at(atom, 12), RT_PERSIST_DIRECTORY_ATOM)?;
+    let mut persist_directory = BTreeMap::new();
+    let mut position = 0;
+    while position + 4 <= directory.len() {
+        let entry = u32_at(directory, position);
+        let (first_id, count) = (entry & 0x000F_FFFF, entry >> 20);
+        position += 4;
+        for id in first_id..first_id + count {
+            if position + 4 > directory.len() {
+                return Err(Error::unexpected_eof(format!(
+                    "the persist directory at offset {} ends within an entry",
+                    u32_at(atom, 12)
+                )));
+            }
+            persist_directory.insert(id, u32_at(directory, position));
+            position += 4;
+        }
+    }
+    let edit = PptEdit {
+        offset,
+        last_slide_id_ref: u32_at(atom, 0),
+        doc_persist_id_ref: u32_at(atom, 16),
+        persist_id_seed: u32_at(atom, 20),
+        last_view: u16_at(atom, 24),
+        encrypt_session_persist_id_ref: (atom.len() == ENCRYPTED_USER_EDIT_ATOM_LEN as usize)
+            .then(|| u32_at(atom, 28)),
+        persist_directory,
+        objects: BTreeMap::new(),
+    };
+    Ok((edit, u32_at(atom, 8)))
+}
+
+fn parse_current_user(data: &[u8]) -> Result<CurrentUser> {
+    let truncated = |offset: usize| {
+        Error::unexpected_eof(format!("the CurrentUserAtom ends before offset {}", offset))
+    };
+    if data.len() < USER_NAME_OFFSET {
+        return Err(truncated(USER_NAME_OFFSET));
+    }
+    if u16_at(data, 2) != RT_CURRENT_USER_ATOM {
+        return Err(Error::GenericError(
+            "the Current User stream doesn't start with a CurrentUserAtom",
+        ));
+    }
+    if u32_at(data, 8) != CURRENT_USER_ATOM_SIZE {
+        return Err(Error::GenericError(
+            "the CurrentUserAtom has an unexpected size",
+        ));
+    }
+    let encrypted = match u32_at(data, 12) {
+        HEADER_TOKEN => false,
+        ENCRYPTED_HEADER_TOKEN => true,
+        _ => {
+            return Err(Error::GenericError(
+                "the CurrentUserAtom has an unknown header token",
+            ))
+        }
+    };
+    let len = u16_at(data, 20) as usize;
+    let release_version_offset = USER_NAME_OFFSET + len;
+    let release_version = data
+        .get(release_version_offset..release_version_offset + 4)
+        .map(|_| u32_at(data, release_version_offset))
+        .ok_or_else(|| truncated(release_version_offset + 4))?;
+    // the ANSI name is what older writers leave, the Unicode one is optional
+    let unicode_offset = release_version_offset + 4;
+    let user_name = match data.get(unicode_offset..unicode_offset + len * 2) {
+        Some(unicode) => {
+            let units = unicode
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        None => data[USER_NAME_OFFSET..release_version_offset]
+            .iter()
+            .map(|byte| *byte as char)
+            .collect(),
+    };
+    Ok(CurrentUser {
+        user_name,
+        release_version,
+        offset_to_current_edit: u32_at(data, 16),
+        doc_file_version: u16_at(data, 22),
+        major_version: data[24],
+        minor_version: data[25],
+        encrypted,
+    })
+}
+
+/// The callers check the length first.
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    fn current_user_atom(token: u32, ansi: &str, unicode: Option<&str>) -> Vec<u8> {
+        let mut atom = vec![0, 0];
+        atom.extend(RT_CURRENT_USER_ATOM.to_le_bytes());
+        atom.extend([0; 4]);
+        atom.extend(CURRENT_USER_ATOM_SIZE.to_le_bytes());
+        atom.extend(token.to_le_bytes());
+        atom.extend(0x1234u32.to_le_bytes());
+        atom.extend((ansi.len() as u16).to_le_bytes());
+        atom.extend([0xF4, 0x03, 3, 0, 0, 0]);
+        atom.extend(ansi.as_bytes());
+        atom.extend(8u32.to_le_bytes());
+        if let Some(unicode) = unicode {
+            atom.extend(unicode.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        }
+        let len = (atom.len() - 8) as u32;
+        atom[4..8].copy_from_slice(&len.to_le_bytes());
+        atom
+    }
+
+    #[test]
+    fn test_current_user() {
+        let data = CfbBuilder::new()
+            .stream(
+                "Current User",
+                &current_user_atom(ENCRYPTED_HEADER_TOKEN, "Mallory", Some("Mallöry")),
+            )
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        assert_eq!(
+            ole.current_user().unwrap(),
+            Some(CurrentUser {
+                user_name: "Mallöry".to_string(),
+                release_version: 8,
+                offset_to_current_edit: 0x1234,
+                doc_file_version: 0x03F4,
+                major_version: 3,
+                minor_version: 0,
+                encrypted: true,
+            })
+        );
+
+        let ansi_only = parse_current_user(&current_user_atom(HEADER_TOKEN, "Bob", None)).unwrap();
+        assert_eq!(ansi_only.user_name, "Bob");
+        assert!(!ansi_only.encrypted);
+    }
+
+    fn record(rec_type: u16, body: &[u8]) -> Vec<u8> {
+        let mut record = vec![0, 0];
+        record.extend(rec_type.to_le_bytes());
+        record.extend((body.len() as u32).to_le_bytes());
+        record.extend(body);
+        record
+    }
+
+    /// Append a save writing `objects` to `document`, and return the offset of its UserEditAtom.
+    fn save(document: &mut Vec<u8>, objects: &[(u32, &[u8])], previous: u32) -> u32 {
+        let mut directory = vec![];
+        for (id, body) in objects {
+            directory.extend((id | 1 << 20).to_le_bytes());
+            directory.extend((document.len() as u32).to_le_bytes());
+            document.extend(record(0x03E8, body));
+        }
+        let directory_offset = document.len() as u32;
+        document.extend(record(RT_PERSIST_DIRECTORY_ATOM, &directory));
+        let mut atom = vec![];
+        for field in [256, 0x0300_0000, previous, directory_offset, 1, 2] {
+            atom.extend(u32::to_le_bytes(field));
+        }
+        atom.extend([1, 0, 0, 0]);
+        let offset = document.len() as u32;
+        document.extend(record(RT_USER_EDIT_ATOM, &atom));
+        offset
+    }
+
+    fn presentation(document: &[u8], current_edit: u32) -> OleFile {
+        let mut user = current_user_atom(HEADER_TOKEN, "Bob", None);
+        user[16..20].copy_from_slice(&current_edit.to_le_bytes());
+        let data = CfbBuilder::new()
+            .stream("PowerPoint Document", document)
+            .stream("Current User", &user)
+            .build();
+        OleFile::parse_sync(&data[..]).unwrap()
+    }
+
+    #[test]
+    fn test_ppt_edits() {
+        let mut document = vec![];
+        let first = save(&mut document, &[(1, b"document"), (2, b"old slide")], 0);
+        let second = save(&mut document, &[(2, b"new slide")], first);
+        let ole = presentation(&document, second);
+
+        let edits = ole.ppt_edits().unwrap();
+        assert_eq!(
+            edits.iter().map(|edit| edit.offset).collect::<Vec<_>>(),
+            vec![second, first]
+        );
+        assert_eq!(
+            edits[0].persist_directory.keys().collect::<Vec<_>>(),
+            vec![&2]
+        );
+        assert_eq!(edits[0].objects[&1], edits[1].objects[&1]);
+        assert_eq!(edits[0].doc_persist_id_ref, 1);
+        assert_eq!(edits[0].last_slide_id_ref, 256);
+        assert_eq!(edits[0].encrypt_session_persist_id_ref, None);
+        assert_eq!(
+            ole.ppt_record(edits[0].objects[&2]).unwrap(),
+            record(0x03E8, b"new slide")
+        );
+        assert_eq!(
+            ole.ppt_record(edits[1].objects[&2]).unwrap(),
+            record(0x03E8, b"old slide")
+        );
+    }
+
+    #[test]
+    fn test_invalid_ppt_edits() {
+        let mut document = vec![];
+        let first = save(&mut document, &[(1, b"document")], 0);
+        // a save that claims to follow itself
+        let own_offset = document.len() as u32 + RECORD_HEADER_LEN as u32;
+        let looping = save(&mut document, &[], own_offset);
+        assert_eq!(looping, own_offset);
+        assert!(presentation(&document, looping).ppt_edits().is_err());
+        assert!(presentation(&document, first + 1).ppt_edits().is_err());
+        assert!(presentation(&document, first).ppt_record(1000).is_err());
+        let ole =
+            OleFile::parse_sync(&CfbBuilder::new().stream("Other", b"x").build()[..]).unwrap();
+        assert!(ole.ppt_edits().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_current_user() {
+        let atom = current_user_atom(HEADER_TOKEN, "Bob", None);
+        assert!(parse_current_user(&atom[..atom.len() - 2]).is_err());
+        assert!(parse_current_user(&current_user_atom(0xDEAD_BEEF, "Bob", None)).is_err());
+        let ole =
+            OleFile::parse_sync(&CfbBuilder::new().stream("Other", b"x").build()[..]).unwrap();
+        assert_eq!(ole.current_user().unwrap(), None);
+    }
+}
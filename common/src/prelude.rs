@@ -0,0 +1,40 @@
+//! The types most consumers of the crate need, gathered in one place.
+//!
+//! ```rust
+//! use ole::prelude::*;
+//!
+//! # #[cfg(feature = "async")]
+//! #[tokio::main]
+//! async fn main() {
+//!     let file = OleFile::from_file("../data/oledoc1.doc_").await.unwrap();
+//!     assert!(matches!(file.file_type, OleFileType::Word97));
+//! }
+//! # #[cfg(not(feature = "async"))]
+//! # fn main() {}
+//! ```
+#[cfg(feature = "archive")]
+pub use crate::archive_members;
+#[cfg(feature = "async")]
+pub use crate::AsyncOleStreamReader;
+pub use crate::{
+    analyze, analyze_with_options, archive_format, capabilities, deobfuscate,
+    directory::{DirectoryEntry, EntryPath, NodeColor, ObjectType},
+    error::{Error, HeaderErrorType},
+    ftype::{file_type, DocumentRole, OleFileType},
+    header::{OleHeader, RawFileHeader},
+    redact, repair,
+    util::StringUtils,
+    Action, AnalysisReport, Anomaly, AnomalyKind, ArchiveFormat, ArchiveMember, AutoExec,
+    Capabilities, CurrentUser, DeobfuscatedModule, DocVar, EncryptionScheme, ExternalConnection,
+    FatEntry, HeaderInfo, MappedSector, NestedOleStream, Obfuscation, ObjectEntry, ObjectEvidence,
+    OleFile, OleObject, OleObjectKind, OleParseOptions, OleStreamReader, OrphanedEntry,
+    ParseMetrics, ParseWarning, ParseWarningKind, PptEdit, RecoveredString, Repair, RepairedChain,
+    Result, Rule, SectorOwner, Signature, SignatureKind, StreamChunks, StreamLocation, StreamStat,
+    TreeStats, TreeViolation, TreeViolationKind, Truncation, UnusedSector, UnwrapLimits, Unwrapped,
+    UnwrappedOle, VbaModule, VbaModuleKind, VbaProject, VbaReference, VbaReferenceKind, Verdict,
+    WordObject,
+};
+#[cfg(feature = "ingest")]
+pub use crate::{ingest, Ingest, Ingested};
+#[cfg(feature = "manifest")]
+pub use crate::{Manifest, ManifestEntry, ManifestMismatch};
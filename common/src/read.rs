@@ -0,0 +1,65 @@
+//! Reading helpers shared by the async and the synchronous parser.
+
+/// Fill `buf` from `read`, stopping early only at the end of the input.
+/// Returns how many bytes were read, a single `read` call may return less than asked for.
+#[cfg(feature = "async")]
+pub(crate) async fn read_full<R: crate::constants::Readable>(
+    read: &mut R,
+    buf: &mut [u8],
+) -> std::io::Result<usize> {
+    use tokio::io::AsyncReadExt;
+
+    let mut filled = 0;
+    while filled < buf.len() {
+        match read.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// The synchronous counterpart of [`read_full`].
+pub(crate) fn read_full_sync<R: std::io::Read>(
+    read: &mut R,
+    buf: &mut [u8],
+) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match read.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out at most two bytes per call, like a slow socket.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(2).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_read_full_sync_across_short_reads() {
+        let mut buf = [0u8; 5];
+        assert_eq!(
+            read_full_sync(&mut Trickle(b"abcdefg"), &mut buf).unwrap(),
+            5
+        );
+        assert_eq!(&buf, b"abcde");
+        assert_eq!(read_full_sync(&mut Trickle(b"abc"), &mut buf).unwrap(), 3);
+    }
+}
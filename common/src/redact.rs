@@ -0,0 +1,61 @@
+//! Copies of a file with chosen streams blanked out, for sharing samples without their payload.
+use crate::{OleFile, Result};
+
+/// Copy `data` with the sectors of the given streams zeroed.
+///
+/// Paths use the syntax of [`crate::util::split_stream_path`]. Only stream content is touched:
+/// the header, allocation tables and directory are left as they are, so the copy parses like
+/// the original and the redacted streams keep their size but read as zeros. An unknown path is
+/// an error, nothing is redacted then.
+///
+/// ```rust
+/// let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+/// let redacted = ole::redact(&data, &["WordDocument"]).unwrap();
+/// let ole = ole::OleFile::parse_sync(&redacted[..]).unwrap();
+/// assert!(ole.open_stream_path("WordDocument").unwrap().iter().all(|byte| *byte == 0));
+/// ```
+pub fn redact(data: &[u8], stream_paths: &[&str]) -> Result<Vec<u8>> {
+    let ole = OleFile::parse_sync(data)?;
+    let mut extents = vec![];
+    for path in stream_paths {
+        extents.extend(ole.stream_extents(path)?);
+    }
+    let mut redacted = data.to_vec();
+    for range in extents {
+        let end = (range.end as usize).min(redacted.len());
+        if let Some(bytes) = redacted.get_mut(range.start as usize..end) {
+            bytes.fill(0);
+        }
+    }
+    Ok(redacted)
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::{error::Error, test_support::CfbBuilder};
+
+    #[test]
+    fn test_redact() {
+        let data = CfbBuilder::new()
+            .stream("Keep", &[1; 100])
+            .stream("Payload/Small", &[2; 100])
+            .stream("Payload/Large", &[3; 5000])
+            .build();
+        let redacted = redact(&data, &["Payload/Small", "Payload/Large"]).unwrap();
+        assert_eq!(redacted.len(), data.len());
+
+        let ole = OleFile::parse_sync(&redacted[..]).unwrap();
+        assert_eq!(ole.open_stream_path("Keep").unwrap(), vec![1; 100]);
+        assert_eq!(ole.open_stream_path("Payload/Small").unwrap(), vec![0; 100]);
+        assert_eq!(
+            ole.open_stream_path("Payload/Large").unwrap(),
+            vec![0; 5000]
+        );
+
+        assert!(matches!(
+            redact(&data, &["Missing"]),
+            Err(Error::OleDirectoryEntryNotFound)
+        ));
+    }
+}
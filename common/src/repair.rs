@@ -0,0 +1,340 @@
+//! Recovery of files whose allocation tables are damaged while the directory is intact.
+//!
+//! Every chain the directory implies is checked: the directory itself, the mini FAT, the mini
+//! stream and each stream, against the size it should have. A chain that loops, leaves the
+//! file or has the wrong length is rebuilt on the assumption that its sectors follow each other
+//! from its first one, which is how nearly every writer lays streams out. The repaired copy
+//! differs from the original only in the FAT and mini FAT sectors.
+use crate::{
+    analysis, constants,
+    directory::{EntryPath, ObjectType},
+    error::Error,
+    follow_chain, OleFile, OleParseOptions, Result,
+};
+use log::warn;
+
+/// A chain of the file, as named in a [`Repair`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RepairedChain {
+    Directory,
+    MiniFat,
+    /// the root entry's stream, which holds the streams smaller than the cutoff
+    MiniStream,
+    Stream(EntryPath),
+}
+
+/// The outcome of [`repair`].
+#[derive(Clone, Debug)]
+pub struct Repair {
+    /// the repaired copy of the file
+    pub data: Vec<u8>,
+    /// broken chains that were rebuilt
+    pub reconstructed: Vec<RepairedChain>,
+    /// broken chains that could not be rebuilt, they still fail to read in the copy
+    pub unrecovered: Vec<RepairedChain>,
+}
+
+impl Repair {
+    /// Whether the file needed no repair.
+    pub fn is_intact(&self) -> bool {
+        self.reconstructed.is_empty() && self.unrecovered.is_empty()
+    }
+
+    /// The chain of `len` units from `start` in `table`, rebuilt as `start..start + len` if it
+    /// is broken. `None` if it can't be, because that would leave the file or the table.
+    fn check(
+        &mut self,
+        chain: RepairedChain,
+        table: &mut [u32],
+        start: u32,
+        len: usize,
+        available: usize,
+    ) -> Option<Vec<u32>> {
+        if let Some(ids) = intact_chain(table, start, Some(len), available) {
+            return Some(ids);
+        }
+        match relink(table, start, len, available) {
+            Some(ids) => {
+                warn!("rebuilt the chain of {:?} from unit {}", chain, start);
+                self.reconstructed.push(chain);
+                Some(ids)
+            }
+            None => {
+                warn!("could not rebuild the chain of {:?}", chain);
+                self.unrecovered.push(chain);
+                None
+            }
+        }
+    }
+}
+
+/// Copy `data` with the broken chains of its allocation tables rebuilt.
+///
+/// The header, the locations of the FAT sectors and the directory entries have to be readable,
+/// otherwise there is nothing to go by and an error is returned.
+///
+/// ```rust
+/// let data = std::fs::read("../data/oledoc1.doc_").unwrap();
+/// let repair = ole::repair(&data).unwrap();
+/// assert!(repair.is_intact());
+/// assert_eq!(repair.data, data);
+/// ```
+pub fn repair(data: &[u8]) -> Result<Repair> {
+    let (header, sectors) =
+        OleFile::read_sectors_sync(data, &OleParseOptions::strict(), &mut vec![])?;
+    let sector_count = sectors.len();
+    let mut ole = OleFile::with_allocation_table(header, sectors, &OleParseOptions::strict())?;
+    let fat_sectors = ole.sector_allocation_table_sector_indices()?;
+    let mut fat = std::mem::take(&mut ole.sector_allocation_table);
+    let sector_size = ole.header.sector_size as usize;
+    let mut repair = Repair {
+        data: data.to_vec(),
+        reconstructed: vec![],
+        unrecovered: vec![],
+    };
+
+    // the directory, whose length version 3 files don't record
+    let directory_start = ole.header.sector_allocation_table_first_sector;
+    let directory = match intact_chain(&fat, directory_start, None, sector_count) {
+        Some(ids) => ids,
+        None => {
+            let len = match ole.header.directory_sectors_len {
+                0 => (directory_start as usize..sector_count)
+                    .take_while(|id| {
+                        ole.sectors
+                            .read(*id as u32)
+                            .is_ok_and(|sector| looks_like_directory(&sector))
+                    })
+                    .count(),
+                len => len as usize,
+            };
+            repair
+                .check(
+                    RepairedChain::Directory,
+                    &mut fat,
+                    directory_start,
+                    len,
+                    sector_count,
+                )
+                .ok_or_else(|| Error::invalid_chain("the directory can't be located".to_string()))?
+        }
+    };
+    for id in directory {
+        ole.directory_stream_data
+            .extend(ole.sectors.read(id)?.iter());
+    }
+    ole.initialize_directory_entries()?;
+    let root = ole
+        .directory_entries
+        .first()
+        .filter(|root| root.object_type == ObjectType::RootStorage)
+        .ok_or(Error::invalid_entry(
+            "root",
+            "the directory has no root entry".to_string(),
+        ))?;
+
+    let mini_fat_len = ole.header.short_sector_allocation_table_len as usize;
+    let mini_fat_sectors = match ole.header.short_sector_allocation_table_first_sector {
+        start if mini_fat_len > 0 && start != constants::CHAIN_END => repair.check(
+            RepairedChain::MiniFat,
+            &mut fat,
+            start,
+            mini_fat_len,
+            sector_count,
+        ),
+        _ => Some(vec![]),
+    };
+    let mut mini_fat = vec![];
+    for id in mini_fat_sectors.iter().flatten() {
+        mini_fat.extend(
+            ole.sectors
+                .read(*id)?
+                .chunks_exact(4)
+                .map(|quad| u32::from_le_bytes([quad[0], quad[1], quad[2], quad[3]])),
+        );
+    }
+    let mini_sector_size = ole.header.mini_sector_size as usize;
+    let mini_sector_count = (root.stream_size as usize).div_ceil(mini_sector_size);
+    if let Some(start) = root
+        .starting_sector_location
+        .filter(|_| root.stream_size > 0)
+    {
+        let len = (root.stream_size as usize).div_ceil(sector_size);
+        repair.check(
+            RepairedChain::MiniStream,
+            &mut fat,
+            start,
+            len,
+            sector_count,
+        );
+    }
+
+    for (path, entry) in analysis::entries_with_paths(&ole) {
+        if entry.object_type != ObjectType::Stream || entry.stream_size == 0 {
+            continue;
+        }
+        let path = RepairedChain::Stream(EntryPath::from(path));
+        let start = match entry.starting_sector_location {
+            Some(start) => start,
+            None => {
+                repair.unrecovered.push(path);
+                continue;
+            }
+        };
+        let size = entry.stream_size as usize;
+        if entry.stream_size >= ole.header.standard_stream_min_size as u64 {
+            let len = size.div_ceil(sector_size);
+            repair.check(path, &mut fat, start, len, sector_count);
+        } else if mini_fat_sectors.is_some() {
+            let len = size.div_ceil(mini_sector_size);
+            repair.check(path, &mut mini_fat, start, len, mini_sector_count);
+        } else {
+            repair.unrecovered.push(path);
+        }
+    }
+
+    if !repair.reconstructed.is_empty() {
+        write_table(&mut repair.data, sector_size, &fat_sectors, &fat);
+        write_table(
+            &mut repair.data,
+            sector_size,
+            &mini_fat_sectors.unwrap_or_default(),
+            &mini_fat,
+        );
+    }
+    Ok(repair)
+}
+
+/// The chain from `start` if it is whole: no loops, every unit below `available`, and `len`
+/// units long when that is known.
+fn intact_chain(
+    table: &[u32],
+    start: u32,
+    len: Option<usize>,
+    available: usize,
+) -> Option<Vec<u32>> {
+    let ids = follow_chain(table, start, "sector").ok()?;
+    let in_file = ids.iter().all(|id| (*id as usize) < available);
+    (in_file && !ids.is_empty() && len.map_or(true, |len| ids.len() == len)).then_some(ids)
+}
+
+/// Link `start..start + len` into one chain of `table`.
+fn relink(table: &mut [u32], start: u32, len: usize, available: usize) -> Option<Vec<u32>> {
+    let end = (start as usize).checked_add(len)?;
+    if len == 0 || end > available.min(table.len()) {
+        return None;
+    }
+    for (link, next) in table[start as usize..end].iter_mut().zip(start + 1..) {
+        *link = next;
+    }
+    table[end - 1] = constants::CHAIN_END;
+    Some((start..end as u32).collect())
+}
+
+/// Whether a sector holds directory entries: every entry has a known object type and color and a
+/// name length that fits, and at least one entry is in use.
+fn looks_like_directory(sector: &[u8]) -> bool {
+    let entries = sector.chunks_exact(constants::SIZE_OF_DIRECTORY_ENTRY);
+    let plausible = entries.clone().all(|entry| {
+        let name_len = u16::from_le_bytes([entry[64], entry[65]]);
+        matches!(entry[66], 0 | 1 | 2 | 5) && entry[67] <= 1 && name_len <= 64 && name_len % 2 == 0
+    });
+    plausible && entries.clone().any(|entry| entry[66] != 0)
+}
+
+/// Store `table` in the sectors `ids` of the file in `data`.
+fn write_table(data: &mut [u8], sector_size: usize, ids: &[u32], table: &[u32]) {
+    let bytes = table
+        .iter()
+        .flat_map(|id| id.to_le_bytes())
+        .collect::<Vec<_>>();
+    for (id, chunk) in ids.iter().zip(bytes.chunks(sector_size)) {
+        let offset = (*id as usize + 1) * sector_size;
+        if let Some(sector) = data.get_mut(offset..offset + chunk.len()) {
+            sector.copy_from_slice(chunk);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn set_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn test_repair_broken_chains() {
+        let large = (0..5000).map(|i| i as u8).collect::<Vec<_>>();
+        let data = CfbBuilder::new()
+            .stream("Small", &[1; 100])
+            .stream("Storage/Large", &large)
+            .build();
+        assert!(repair(&data).unwrap().is_intact());
+
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let large_start = ole
+            .get_entry(&["Storage", "Large"])
+            .unwrap()
+            .starting_sector_location
+            .unwrap();
+        let small_start = ole
+            .get_entry(&["Small"])
+            .unwrap()
+            .starting_sector_location
+            .unwrap();
+        let fat = (u32_at(&data, 0x4C) as usize + 1) * 512;
+        let directory = u32_at(&data, 0x30) as usize;
+        let mini_fat = (u32_at(&data, 0x3C) as usize + 1) * 512;
+
+        let mut broken = data.clone();
+        // Large runs off into a free sector, the directory loops and Small leaves the mini stream
+        set_u32(
+            &mut broken,
+            fat + large_start as usize * 4,
+            constants::UNALLOCATED_SECTOR,
+        );
+        set_u32(&mut broken, fat + directory * 4, directory as u32);
+        set_u32(&mut broken, mini_fat + small_start as usize * 4, 9999);
+
+        let repair = repair(&broken).unwrap();
+        assert_eq!(
+            repair.reconstructed,
+            vec![
+                RepairedChain::Directory,
+                RepairedChain::Stream(EntryPath::from(vec!["Small".to_string()])),
+                RepairedChain::Stream(EntryPath::from(vec![
+                    "Storage".to_string(),
+                    "Large".to_string()
+                ])),
+            ]
+        );
+        assert!(repair.unrecovered.is_empty());
+        assert_eq!(repair.data, data);
+        let ole = OleFile::parse_sync(&repair.data[..]).unwrap();
+        assert_eq!(ole.open_stream_path("Storage/Large").unwrap(), large);
+        assert_eq!(ole.open_stream_path("Small").unwrap(), vec![1; 100]);
+    }
+
+    #[test]
+    fn test_unrecoverable_chain() {
+        let mut data = CfbBuilder::new().stream("Large", &[2; 5000]).build();
+        // the stream claims to be larger than what is left of the file
+        let directory = (u32_at(&data, 0x30) as usize + 1) * 512;
+        set_u32(&mut data, directory + 128 + 120, 1_000_000);
+        let repair = repair(&data).unwrap();
+        assert!(repair.reconstructed.is_empty());
+        assert_eq!(
+            repair.unrecovered,
+            vec![RepairedChain::Stream(EntryPath::from(vec![
+                "Large".to_string()
+            ]))]
+        );
+    }
+}
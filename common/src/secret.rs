@@ -0,0 +1,84 @@
+//! Buffers that may hold passwords, keys or decrypted content. With the `zeroize` feature they
+//! are wiped when dropped, and so is what they leave behind when they grow.
+use std::ops::{Deref, DerefMut};
+
+/// A byte buffer wiped on drop with the `zeroize` feature, a plain `Vec<u8>` otherwise.
+#[derive(Clone, Default)]
+pub(crate) struct SecretBuffer(Vec<u8>);
+
+impl SecretBuffer {
+    #[cfg(feature = "decrypt")]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(Vec::with_capacity(capacity))
+    }
+
+    /// Make room for at least `additional` more bytes. Growing moves the bytes to a new
+    /// allocation, with the `zeroize` feature the old one is wiped first.
+    pub fn reserve(&mut self, additional: usize) {
+        #[cfg(feature = "zeroize")]
+        if self.0.capacity() - self.0.len() < additional {
+            let capacity = (self.0.len() + additional).max(self.0.capacity() * 2);
+            let mut grown = Vec::with_capacity(capacity);
+            grown.extend_from_slice(&self.0);
+            zeroize::Zeroize::zeroize(&mut self.0);
+            self.0 = grown;
+        }
+        self.0.reserve(additional);
+    }
+}
+
+impl From<Vec<u8>> for SecretBuffer {
+    fn from(buffer: Vec<u8>) -> Self {
+        Self(buffer)
+    }
+}
+
+impl Deref for SecretBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for SecretBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SecretBuffer {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+/// Wipe `bytes` with the `zeroize` feature, for key material kept outside a [`SecretBuffer`].
+#[cfg(feature = "decrypt")]
+#[allow(unused_variables)]
+pub(crate) fn wipe(bytes: &mut [u8]) {
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_keeps_the_content() {
+        let mut buffer = SecretBuffer::from(vec![1, 2, 3]);
+        buffer.reserve(100);
+        assert!(buffer.capacity() >= 103);
+        assert_eq!(buffer[..], [1, 2, 3]);
+    }
+
+    #[cfg(feature = "decrypt")]
+    #[test]
+    fn test_wipe() {
+        let mut bytes = [7u8; 4];
+        wipe(&mut bytes);
+        assert_eq!(bytes == [0; 4], cfg!(feature = "zeroize"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Which structure of the file every sector belongs to.
+//!
+//! Chains are followed as far as they stay inside the file and don't revisit a sector, so a
+//! damaged or tampered file still maps: a sector claimed twice lists both owners, and one the
+//! FAT allocates without any chain reaching it is [`SectorOwner::Unreferenced`], unless it is the
+//! range lock sector, which no chain is meant to reach.
+use crate::{analysis, constants, directory::EntryPath, directory::ObjectType, OleFile};
+
+/// What a sector is used for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SectorOwner {
+    /// the header, which comes before sector 0
+    Header,
+    Fat,
+    /// the DIFAT sectors, listing the FAT sectors past the first 109
+    Difat,
+    Directory,
+    MiniFat,
+    /// the root entry's stream, which holds the streams smaller than the cutoff
+    MiniStream,
+    Stream(EntryPath),
+    /// the sector holding file offset 0x7FFFFF00, see [`OleFile::range_lock_sector`], listed
+    /// with the chain that uses it when one does
+    RangeLock,
+    /// marked free in the FAT
+    Free,
+    /// allocated in the FAT but not part of any chain
+    Unreferenced,
+}
+
+/// A sector of the file with its owners, more than one when chains overlap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MappedSector {
+    /// the sector id, `None` for the header
+    pub index: Option<u32>,
+    /// where the sector starts in the file
+    pub offset: u64,
+    pub owners: Vec<SectorOwner>,
+}
+
+pub(crate) fn sector_map(ole: &OleFile) -> Vec<MappedSector> {
+    let count = ole.sectors.len();
+    let mut owners = vec![vec![]; count];
+    let mut claim = |ids: Vec<u32>, owner: SectorOwner| {
+        for id in ids {
+            owners[id as usize].push(owner.clone());
+        }
+    };
+    let fat = &ole.sector_allocation_table;
+
+    claim(
+        ole.sector_allocation_table_sector_indices()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|id| (*id as usize) < count)
+            .collect(),
+        SectorOwner::Fat,
+    );
+    claim(difat_sectors(ole), SectorOwner::Difat);
+    claim(
+        walk(fat, ole.header.sector_allocation_table_first_sector, count),
+        SectorOwner::Directory,
+    );
+    claim(
+        walk(
+            fat,
+            ole.header.short_sector_allocation_table_first_sector,
+            count,
+        ),
+        SectorOwner::MiniFat,
+    );
+    let root = ole.root();
+    if let Some(start) = root
+        .starting_sector_location
+        .filter(|_| root.object_type == ObjectType::RootStorage && root.stream_size > 0)
+    {
+        claim(walk(fat, start, count), SectorOwner::MiniStream);
+    }
+    for (path, entry) in analysis::entries_with_paths(ole) {
+        if entry.object_type != ObjectType::Stream
+            || entry.stream_size == 0
+            || entry.stream_size < ole.header.standard_stream_min_size as u64
+        {
+            continue;
+        }
+        if let Some(start) = entry.starting_sector_location {
+            claim(
+                walk(fat, start, count),
+                SectorOwner::Stream(EntryPath::from(path)),
+            );
+        }
+    }
+
+    if let Some(id) = ole.range_lock_sector() {
+        owners[id as usize].push(SectorOwner::RangeLock);
+    }
+
+    let sector_size = ole.header.sector_size as u64;
+    let mut map = vec![MappedSector {
+        index: None,
+        offset: 0,
+        owners: vec![SectorOwner::Header],
+    }];
+    map.extend(owners.into_iter().enumerate().map(|(id, mut owners)| {
+        if owners.is_empty() {
+            owners.push(match fat.get(id) {
+                Some(&constants::UNALLOCATED_SECTOR) | None => SectorOwner::Free,
+                Some(_) => SectorOwner::Unreferenced,
+            });
+        }
+        MappedSector {
+            index: Some(id as u32),
+            offset: (id as u64 + 1) * sector_size,
+            owners,
+        }
+    }));
+    map
+}
+
+/// The chain from `start`, up to the first id that leaves the file or repeats.
+fn walk(table: &[u32], start: u32, count: usize) -> Vec<u32> {
+    let mut ids = vec![];
+    let mut seen = vec![false; count];
+    let mut id = start;
+    while (id as usize) < count && !seen[id as usize] {
+        seen[id as usize] = true;
+        ids.push(id);
+        id = match table.get(id as usize) {
+            Some(next) => *next,
+            None => break,
+        };
+    }
+    ids
+}
+
+/// The DIFAT sectors, chained through their last entry rather than the FAT.
+fn difat_sectors(ole: &OleFile) -> Vec<u32> {
+    let count = ole.sectors.len();
+    let mut ids = vec![];
+    let mut id = ole.header.master_sector_allocation_table_first_sector;
+    for _ in 0..ole.header.master_sector_allocation_table_len {
+        if id as usize >= count || ids.contains(&id) {
+            break;
+        }
+        ids.push(id);
+        id = match ole.sectors.read(id) {
+            Ok(sector) => {
+                let last = sector.len() - 4;
+                u32::from_le_bytes(sector[last..].try_into().unwrap())
+            }
+            Err(_) => break,
+        };
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{huge_stream_file, CfbBuilder};
+
+    fn owners_of(map: &[MappedSector], id: u32) -> &[SectorOwner] {
+        &map[id as usize + 1].owners
+    }
+
+    #[test]
+    fn test_sector_map() {
+        let mut data = CfbBuilder::new()
+            .stream("Small", &[1; 100])
+            .stream("Storage/Large", &[2; 5000])
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let map = ole.sector_map();
+        assert_eq!(map.len(), ole.sectors.len() + 1);
+        assert_eq!(map[0].owners, vec![SectorOwner::Header]);
+        let large = EntryPath::from(vec!["Storage".to_string(), "Large".to_string()]);
+        // Large takes the first 10 sectors, then come the mini stream, mini FAT, directory
+        // and FAT
+        for id in 0..10 {
+            assert_eq!(owners_of(&map, id), [SectorOwner::Stream(large.clone())]);
+        }
+        assert_eq!(map[1].offset, 512);
+        assert_eq!(owners_of(&map, 10), [SectorOwner::MiniStream]);
+        assert_eq!(owners_of(&map, 11), [SectorOwner::MiniFat]);
+        assert_eq!(owners_of(&map, 12), [SectorOwner::Directory]);
+        assert_eq!(owners_of(&map, 13), [SectorOwner::Fat]);
+        assert_eq!(ole.range_lock_sector(), None);
+
+        // point the mini stream at the last sector of Large, leaving its own sector behind
+        let fat = (u32::from_le_bytes(data[0x4C..0x50].try_into().unwrap()) as usize + 1) * 512;
+        let directory =
+            (u32::from_le_bytes(data[0x30..0x34].try_into().unwrap()) as usize + 1) * 512;
+        data[directory + 116..directory + 120].copy_from_slice(&9u32.to_le_bytes());
+        let map = OleFile::parse_sync(&data[..]).unwrap().sector_map();
+        assert_eq!(
+            owners_of(&map, 9),
+            [SectorOwner::MiniStream, SectorOwner::Stream(large)]
+        );
+        assert_eq!(owners_of(&map, 10), [SectorOwner::Unreferenced]);
+
+        data[fat + 40..fat + 44].copy_from_slice(&[0xFF; 4]);
+        let map = OleFile::parse_sync(&data[..]).unwrap().sector_map();
+        assert_eq!(owners_of(&map, 10), [SectorOwner::Free]);
+    }
+
+    #[test]
+    fn test_range_lock_sector() {
+        let size = (1 << 31) + 5000;
+        let ole = OleFile::from_seekable(huge_stream_file(size, b"the end"), 16).unwrap();
+        assert_eq!(ole.range_lock_sector(), Some(0x7FFFE));
+        let map = ole.sector_map();
+        assert_eq!(map[0x7FFFF].offset, 0x7FFFF000);
+        assert_eq!(owners_of(&map, 0x7FFFE), [SectorOwner::RangeLock]);
+        assert!(!map
+            .iter()
+            .any(|sector| sector.owners.contains(&SectorOwner::Unreferenced)));
+        assert!(ole
+            .stream_extents("Big")
+            .unwrap()
+            .iter()
+            .all(|extent| !extent.contains(&constants::RANGE_LOCK_OFFSET)));
+        assert_eq!(
+            ole.open_stream_range(&["Big"], size - 7, 7).unwrap(),
+            b"the end"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Storage for the sectors of a parsed file.
+use crate::{
+    error::{Error, Location},
+    secret::SecretBuffer,
+    Result,
+};
+use lru::LruCache;
+use std::{
+    io::{Read, Seek, SeekFrom},
+    num::NonZeroUsize,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+/// The sectors following the header, in file order.
+#[derive(Clone)]
+pub(crate) enum Sectors {
+    /// copied out of the input into one buffer, the header sector is skipped; shared between
+    /// clones of the file, which never change it
+    Owned {
+        data: Arc<SecretBuffer>,
+        sector_size: usize,
+    },
+    /// borrowed from a memory-mapped file, the header sector is skipped
+    #[cfg(feature = "mmap")]
+    Mapped {
+        map: Arc<memmap2::Mmap>,
+        sector_size: usize,
+    },
+    /// read from the source when first needed, with the recently used ones cached
+    Lazy(Arc<Mutex<LazySectors>>),
+}
+
+impl Sectors {
+    pub fn len(&self) -> usize {
+        match self {
+            Sectors::Owned { data, sector_size } => data.len() / sector_size,
+            #[cfg(feature = "mmap")]
+            Sectors::Mapped { map, sector_size } => map.len() / sector_size - 1,
+            Sectors::Lazy(lazy) => lazy.lock().map(|lazy| lazy.count).unwrap_or(0),
+        }
+    }
+
+    /// How many bytes of sectors were taken from the input so far.
+    pub fn bytes_read(&self, sector_size: u64) -> u64 {
+        match self {
+            Sectors::Lazy(lazy) => lazy.lock().map(|lazy| lazy.bytes_read).unwrap_or(0),
+            // all of them, copied or mapped
+            _ => self.len() as u64 * sector_size,
+        }
+    }
+
+    /// The sector at `index`, an error if there is no such sector or it can't be read.
+    pub fn read(&self, index: u32) -> Result<Sector<'_>> {
+        let id = index;
+        let index = index as usize;
+        let sector = match self {
+            Sectors::Owned { data, sector_size } => index
+                .checked_mul(*sector_size)
+                .and_then(|start| data.get(start..start + sector_size))
+                .map(Sector::Borrowed),
+            #[cfg(feature = "mmap")]
+            Sectors::Mapped { map, sector_size } => (index + 1)
+                .checked_mul(*sector_size)
+                .and_then(|start| map.get(start..start + sector_size))
+                .map(Sector::Borrowed),
+            Sectors::Lazy(lazy) => lazy
+                .lock()
+                .map_err(|_| Error::GenericError("lazy sector source was poisoned"))?
+                .read(index)?
+                .map(Sector::Shared),
+        };
+        sector.ok_or_else(|| {
+            Error::sector_out_of_range(format!("sector {} is beyond the end of the file", index))
+                .at(Location {
+                    sector: Some(id),
+                    ..Location::default()
+                })
+        })
+    }
+}
+
+/// A sector handed out by [`Sectors::read`], borrowed from the file's data or shared with the
+/// cache of a lazily read file.
+pub(crate) enum Sector<'a> {
+    Borrowed(&'a [u8]),
+    Shared(Arc<[u8]>),
+}
+
+impl Deref for Sector<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Sector::Borrowed(sector) => sector,
+            Sector::Shared(sector) => sector,
+        }
+    }
+}
+
+pub(crate) trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Sectors that are read from a seekable source on demand.
+pub(crate) struct LazySectors {
+    source: Box<dyn ReadSeek>,
+    sector_size: usize,
+    count: usize,
+    /// the recently used sectors by index, `None` if nothing is cached
+    cache: Option<LruCache<usize, Arc<[u8]>>>,
+    /// bytes read from the source, sectors read again after leaving the cache count again
+    bytes_read: u64,
+}
+
+impl LazySectors {
+    /// `source` must hold a complete file, header included.
+    pub fn new(
+        mut source: Box<dyn ReadSeek>,
+        sector_size: usize,
+        cache_capacity: usize,
+    ) -> Result<Self> {
+        // in u64, a version 4 file may well be larger than usize on 32 bit targets
+        let len = source.seek(SeekFrom::End(0))?;
+        let count = (len / sector_size as u64).saturating_sub(1) as usize;
+        if len % sector_size as u64 != 0 {
+            return Err(Error::unexpected_eof(format!(
+                "short read when parsing sector number: {}",
+                count
+            ))
+            .at(Location {
+                sector: Some(count as u32),
+                offset: Some((count as u64 + 1) * sector_size as u64),
+                ..Location::default()
+            }));
+        }
+        Ok(LazySectors {
+            source,
+            sector_size,
+            count,
+            cache: NonZeroUsize::new(cache_capacity).map(LruCache::new),
+            bytes_read: 0,
+        })
+    }
+
+    fn read(&mut self, index: usize) -> Result<Option<Arc<[u8]>>> {
+        if index >= self.count {
+            return Ok(None);
+        }
+        if let Some(sector) = self.cache.as_mut().and_then(|cache| cache.get(&index)) {
+            return Ok(Some(sector.clone()));
+        }
+        let mut sector = vec![0u8; self.sector_size];
+        self.source.seek(SeekFrom::Start(
+            (index as u64 + 1) * self.sector_size as u64,
+        ))?;
+        self.source.read_exact(&mut sector)?;
+        self.bytes_read += self.sector_size as u64;
+        let sector: Arc<[u8]> = sector.into();
+        if let Some(cache) = self.cache.as_mut() {
+            cache.put(index, sector.clone());
+        }
+        Ok(Some(sector))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_lazy_sectors_cache() {
+        let data = (0..4u8)
+            .flat_map(|sector| vec![sector; 512])
+            .collect::<Vec<_>>();
+        let mut lazy = LazySectors::new(Box::new(Cursor::new(data)), 512, 2).unwrap();
+        assert_eq!(lazy.count, 3);
+        assert_eq!(lazy.read(0).unwrap().unwrap()[0], 1);
+        assert_eq!(lazy.read(2).unwrap().unwrap()[0], 3);
+        assert_eq!(lazy.read(0).unwrap().unwrap()[0], 1);
+        assert_eq!(lazy.read(1).unwrap().unwrap()[0], 2);
+        // sector 2 was the least recently used one
+        let cached = lazy
+            .cache
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|(index, _)| *index)
+            .collect::<Vec<_>>();
+        assert_eq!(cached, vec![1, 0]);
+        // a cached sector is handed out without copying it
+        assert!(Arc::ptr_eq(
+            &lazy.read(1).unwrap().unwrap(),
+            &lazy.read(1).unwrap().unwrap()
+        ));
+        assert!(lazy.read(3).unwrap().is_none());
+        assert!(LazySectors::new(Box::new(Cursor::new(vec![0; 700])), 512, 2).is_err());
+    }
+
+    #[test]
+    fn test_bounded_cache_is_capped() {
+        let ole = crate::OleFile::from_seekable_bounded(
+            crate::test_support::huge_stream_file(1 << 20, b""),
+            1 << 30,
+        )
+        .unwrap();
+        let Sectors::Lazy(lazy) = &ole.sectors else {
+            panic!("a bounded file reads its sectors on demand");
+        };
+        let capacity = lazy.lock().unwrap().cache.as_ref().unwrap().cap().get();
+        assert_eq!(capacity, crate::constants::MAX_BOUNDED_SECTOR_CACHE);
+    }
+}
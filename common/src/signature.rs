@@ -0,0 +1,284 @@
+//! Digital signatures stored in a compound file, for documents and for VBA projects.
+//!
+//! Only as much of the PKCS#7 (CMS) envelope is decoded as is needed to say who signed and when,
+//! nothing is verified.
+use crate::{analysis, directory::ObjectType, OleFile};
+use chrono::NaiveDateTime;
+
+/// signedData, 1.2.840.113549.1.7.2
+const OID_SIGNED_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02];
+/// signingTime, 1.2.840.113549.1.9.5
+const OID_SIGNING_TIME: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x05];
+/// countersignature, 1.2.840.113549.1.9.6, which carries the timestamp of older Authenticode
+const OID_COUNTERSIGNATURE: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x06];
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OID: u8 = 0x06;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_CONTEXT_0: u8 = 0xA0;
+const TAG_CONTEXT_1: u8 = 0xA1;
+
+/// What a signature covers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureKind {
+    /// the document, kept in the `_signatures` or `_xmlsignatures` storage
+    Document,
+    /// the VBA project, kept in a `\x05DigitalSignature` stream next to it
+    VbaProject,
+}
+
+/// A signature stream and what could be read from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    /// storage names leading to the stream, then the stream's own name
+    pub path: Vec<String>,
+    pub kind: SignatureKind,
+    /// subject of the signing certificate, `None` if the envelope could not be decoded
+    pub signer: Option<String>,
+    /// signing time, from the signed attributes or a countersignature
+    pub signing_time: Option<NaiveDateTime>,
+}
+
+pub(crate) fn signatures(ole: &OleFile) -> Vec<Signature> {
+    analysis::entries_with_paths(ole)
+        .into_iter()
+        .filter(|(_, entry)| entry.object_type == ObjectType::Stream)
+        .filter_map(|(path, _)| {
+            let kind = if path[0] == "_signatures" || path[0] == "_xmlsignatures" {
+                SignatureKind::Document
+            } else if path.last()?.starts_with("\x05DigitalSignature") {
+                SignatureKind::VbaProject
+            } else {
+                return None;
+            };
+            let components = path.iter().map(String::as_str).collect::<Vec<_>>();
+            let data = ole.open_stream(&components).unwrap_or_default();
+            let signed_data = find_signed_data(&data);
+            Some(Signature {
+                signer: signed_data.and_then(signer),
+                signing_time: signed_data.and_then(signing_time),
+                path,
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// One DER element: its tag, its content, and everything after it.
+fn element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7F) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, &rest[count..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// The elements of a constructed element's content.
+fn children(mut data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut children = vec![];
+    while let Some((tag, content, rest)) = element(data) {
+        children.push((tag, content));
+        data = rest;
+    }
+    children
+}
+
+/// The SignedData content, wherever the ContentInfo starts in `data`.
+///
+/// VBA signature streams put a DigSigInfoSerialized header in front of the envelope, so rather
+/// than decode that, the signedData identifier is searched for.
+fn find_signed_data(data: &[u8]) -> Option<&[u8]> {
+    let position = data
+        .windows(OID_SIGNED_DATA.len())
+        .position(|window| window == OID_SIGNED_DATA)?;
+    // the ContentInfo starts a few bytes before its identifier, at most 4 length bytes and 2 tags
+    (position.saturating_sub(8)..position).find_map(|start| {
+        let (tag, content, _) = element(&data[start..])?;
+        match children(content)[..] {
+            [(TAG_OID, OID_SIGNED_DATA), (TAG_CONTEXT_0, explicit), ..] if tag == TAG_SEQUENCE => {
+                match element(explicit)? {
+                    (TAG_SEQUENCE, signed_data, _) => Some(signed_data),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    })
+}
+
+fn signer_infos(signed_data: &[u8]) -> Vec<&[u8]> {
+    children(signed_data)
+        .into_iter()
+        .rev()
+        .find(|(tag, _)| *tag == TAG_SET)
+        .map(|(_, set)| {
+            children(set)
+                .into_iter()
+                .filter(|(tag, _)| *tag == TAG_SEQUENCE)
+                .map(|(_, info)| info)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn signer(signed_data: &[u8]) -> Option<String> {
+    let certificates = children(signed_data)
+        .into_iter()
+        .find(|(tag, _)| *tag == TAG_CONTEXT_0)
+        .map(|(_, certificates)| children(certificates))?;
+    // TBSCertificate: optional version, serial, algorithm, issuer, validity, subject
+    let tbs = certificates
+        .iter()
+        .filter_map(|(_, certificate)| {
+            let (_, tbs, _) = element(certificate)?;
+            let fields = children(tbs)
+                .into_iter()
+                .skip_while(|(tag, _)| *tag == TAG_CONTEXT_0)
+                .collect::<Vec<_>>();
+            (fields.len() >= 5).then_some(fields)
+        })
+        .collect::<Vec<_>>();
+    // the signer is named by issuer and serial number, failing that take the first certificate
+    let wanted = signer_infos(signed_data).first().and_then(|info| {
+        match children(children(info).get(1)?.1)[..] {
+            [(TAG_SEQUENCE, issuer), (TAG_INTEGER, serial)] => Some((issuer, serial)),
+            _ => None,
+        }
+    });
+    let certificate = wanted
+        .and_then(|(issuer, serial)| {
+            tbs.iter()
+                .find(|fields| fields[0].1 == serial && fields[2].1 == issuer)
+        })
+        .or_else(|| tbs.first())?;
+    Some(name(certificate[4].1))
+}
+
+/// An X.501 name as `C=US, O=Example, CN=Signer`.
+fn name(data: &[u8]) -> String {
+    children(data)
+        .into_iter()
+        .flat_map(|(_, set)| children(set))
+        .filter_map(|(_, attribute)| match children(attribute)[..] {
+            [(TAG_OID, oid), (_, value)] => Some(format!(
+                "{}={}",
+                attribute_name(oid),
+                String::from_utf8_lossy(value)
+            )),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn attribute_name(oid: &[u8]) -> String {
+    match oid {
+        [0x55, 0x04, 0x03] => "CN".to_string(),
+        [0x55, 0x04, 0x06] => "C".to_string(),
+        [0x55, 0x04, 0x07] => "L".to_string(),
+        [0x55, 0x04, 0x08] => "ST".to_string(),
+        [0x55, 0x04, 0x0A] => "O".to_string(),
+        [0x55, 0x04, 0x0B] => "OU".to_string(),
+        _ => oid
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(""),
+    }
+}
+
+fn signing_time(signed_data: &[u8]) -> Option<NaiveDateTime> {
+    signer_infos(signed_data).into_iter().find_map(|info| {
+        let info = children(info);
+        let attributes = |tag| {
+            info.iter()
+                .find(|(found, _)| *found == tag)
+                .map(|(_, attributes)| children(attributes))
+                .unwrap_or_default()
+        };
+        let counter_signed = attribute(&attributes(TAG_CONTEXT_1), OID_COUNTERSIGNATURE)
+            .and_then(|(_, counter_signer)| {
+                children(counter_signer)
+                    .into_iter()
+                    .find(|(tag, _)| *tag == TAG_CONTEXT_0)
+                    .map(|(_, attributes)| children(attributes))
+            })
+            .unwrap_or_default();
+        attribute(&attributes(TAG_CONTEXT_0), OID_SIGNING_TIME)
+            .or_else(|| attribute(&counter_signed, OID_SIGNING_TIME))
+            .and_then(|(tag, time)| parse_time(tag, time))
+    })
+}
+
+/// The first value of the attribute identified by `wanted`.
+fn attribute<'a>(attributes: &[(u8, &'a [u8])], wanted: &[u8]) -> Option<(u8, &'a [u8])> {
+    attributes
+        .iter()
+        .find_map(|(_, attribute)| match children(attribute)[..] {
+            [(TAG_OID, oid), (TAG_SET, values)] if oid == wanted => {
+                children(values).first().copied()
+            }
+            _ => None,
+        })
+}
+
+fn parse_time(tag: u8, time: &[u8]) -> Option<NaiveDateTime> {
+    let time = std::str::from_utf8(time).ok()?;
+    match tag {
+        TAG_UTC_TIME => NaiveDateTime::parse_from_str(time, "%y%m%d%H%M%SZ").ok(),
+        TAG_GENERALIZED_TIME => NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M%SZ").ok(),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    #[test]
+    fn test_vba_signature() {
+        let envelope = std::fs::read("../data/signature/cms_signed.der").unwrap();
+        // stand-in for the DigSigInfoSerialized header in front of the envelope
+        let mut stream = vec![0u8; 36];
+        stream.extend(&envelope);
+        let data = CfbBuilder::new()
+            .stream("Macros/VBA/dir", b"vba")
+            .stream("Macros/\x05DigitalSignature", &stream)
+            .stream("_signatures/broken", b"not an envelope")
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let signatures = ole.signatures();
+        assert_eq!(signatures.len(), 2);
+
+        let vba = signatures
+            .iter()
+            .find(|signature| signature.kind == SignatureKind::VbaProject)
+            .unwrap();
+        assert_eq!(vba.path, vec!["Macros", "\x05DigitalSignature"]);
+        assert_eq!(
+            vba.signer.as_deref(),
+            Some("C=US, O=Example Corp, CN=Example Macro Signer")
+        );
+        assert!(vba.signing_time.is_some());
+
+        let document = signatures
+            .iter()
+            .find(|signature| signature.kind == SignatureKind::Document)
+            .unwrap();
+        assert_eq!(document.signer, None);
+    }
+}
@@ -0,0 +1,478 @@
+//! Incremental access to the data of a single stream.
+use crate::{
+    constants,
+    directory::DirectoryEntry,
+    error::{Error, Location},
+    sectors::Sector,
+    OleFile, Result,
+};
+use std::{
+    borrow::Cow,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+/// Reads a stream sector by sector instead of collecting it into one buffer.
+///
+/// The sector chain is resolved up front, the data is only touched as it is read. Returned by
+/// [`OleFile::stream_reader`].
+pub struct OleStreamReader<'a> {
+    ole: &'a OleFile,
+    /// sector ids of the stream, in the mini stream or among the regular sectors
+    chain: Vec<u32>,
+    in_mini_stream: bool,
+    sector_size: u64,
+    size: u64,
+    position: u64,
+}
+
+impl<'a> OleStreamReader<'a> {
+    pub(crate) fn new(ole: &'a OleFile, entry: &DirectoryEntry) -> Result<Self> {
+        Self::with_limit(ole, entry, entry.stream_size)
+    }
+
+    /// A reader that only resolves the chain as far as needed for the first `limit` bytes,
+    /// reading past them is an error.
+    pub(crate) fn with_limit(ole: &'a OleFile, entry: &DirectoryEntry, limit: u64) -> Result<Self> {
+        let limit = limit.min(entry.stream_size);
+        let in_mini_stream = entry.stream_size < ole.header.standard_stream_min_size as u64;
+        let (table, sector_size) = if in_mini_stream {
+            (&ole.short_sector_allocation_table, 64)
+        } else {
+            (&ole.sector_allocation_table, ole.header.sector_size as u64)
+        };
+        let mut chain = vec![];
+        let mut next_sector = entry
+            .starting_sector_location
+            .unwrap_or(constants::CHAIN_END);
+        while next_sector != constants::CHAIN_END && (chain.len() as u64) * sector_size < limit {
+            // a chain can't be longer than its table without visiting a sector twice
+            if chain.len() >= table.len() {
+                return Err(Error::invalid_chain(format!(
+                    "chain of {:?} loops back on itself",
+                    entry.name
+                ))
+                .at(entry.location()));
+            }
+            chain.push(next_sector);
+            next_sector = *table.get(next_sector as usize).ok_or_else(|| {
+                Error::sector_out_of_range(format!(
+                    "sector {} is not in the allocation table",
+                    next_sector
+                ))
+                .at(Location {
+                    sector: Some(next_sector),
+                    ..entry.location()
+                })
+            })?;
+        }
+        Ok(Self {
+            ole,
+            chain,
+            in_mini_stream,
+            sector_size,
+            size: entry.stream_size,
+            position: 0,
+        })
+    }
+
+    /// The size of the stream as recorded in its directory entry.
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+impl Read for OleStreamReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = (self.position / self.sector_size) as usize;
+        let offset = (self.position % self.sector_size) as usize;
+        let sector_id = *self.chain.get(index).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "sector chain ends before the stream",
+            )
+        })?;
+        let available = (self.size - self.position).min(self.sector_size - offset as u64) as usize;
+        let len = available.min(buf.len());
+        let sector = if self.in_mini_stream {
+            Sector::Borrowed(self.ole.mini_sector(sector_id).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "mini sector is beyond the mini stream",
+                )
+            })?)
+        } else {
+            self.ole
+                .sectors
+                .read(sector_id)
+                .map_err(|err| io::Error::new(io::ErrorKind::UnexpectedEof, err))?
+        };
+        let bytes = sector.get(offset..offset + len).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "sector is shorter than expected",
+            )
+        })?;
+        buf[..len].copy_from_slice(bytes);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for OleStreamReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        match target {
+            Some(target) => {
+                self.position = target;
+                Ok(target)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// The data of a stream one sector at a time, in chain order, the last one cut to the size of
+/// the stream. Returned by [`OleFile::stream_chunks`].
+///
+/// The chunks borrow from the file when its sectors are in memory or mapped, so hashing or
+/// scanning a stream never assembles it. Sectors read lazily come as owned copies. A sector
+/// that can't be read ends the iteration with an error.
+pub struct StreamChunks<'a> {
+    ole: &'a OleFile,
+    chain: std::vec::IntoIter<u32>,
+    in_mini_stream: bool,
+    /// bytes of the stream not handed out yet
+    remaining: u64,
+    location: Location,
+}
+
+impl<'a> StreamChunks<'a> {
+    pub(crate) fn new(ole: &'a OleFile, entry: &DirectoryEntry) -> Result<Self> {
+        let reader = OleStreamReader::new(ole, entry)?;
+        Ok(Self {
+            ole,
+            chain: reader.chain.into_iter(),
+            in_mini_stream: reader.in_mini_stream,
+            remaining: reader.size,
+            location: entry.location(),
+        })
+    }
+}
+
+impl<'a> Iterator for StreamChunks<'a> {
+    type Item = Result<Cow<'a, [u8]>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let sector = match self.chain.next() {
+            Some(id) if self.in_mini_stream => self.ole.mini_sector(id).map(Sector::Borrowed),
+            Some(id) => self.ole.sectors.read(id),
+            None => Err(Error::unexpected_eof(
+                "sector chain ends before the stream".to_string(),
+            )),
+        };
+        let sector = match sector {
+            Ok(sector) => sector,
+            Err(err) => {
+                self.remaining = 0;
+                return Some(Err(err.at(self.location.clone())));
+            }
+        };
+        let take = (sector.len() as u64).min(self.remaining) as usize;
+        self.remaining -= take as u64;
+        Some(Ok(match sector {
+            Sector::Borrowed(sector) => Cow::Borrowed(&sector[..take]),
+            Sector::Shared(sector) => Cow::Owned(sector[..take].to_vec()),
+        }))
+    }
+}
+
+/// The async counterpart of [`OleStreamReader`], returned by [`OleFile::async_stream_reader`].
+///
+/// The sectors are already in memory or behind the file's sector cache, so reads complete
+/// right away; the adaptor lets async pipelines consume a stream without buffering all of it.
+#[cfg(feature = "async")]
+pub struct AsyncOleStreamReader<'a> {
+    inner: OleStreamReader<'a>,
+    /// outcome of the last `start_seek`, handed out by `poll_complete`
+    seek: Option<io::Result<u64>>,
+}
+
+#[cfg(feature = "async")]
+impl<'a> AsyncOleStreamReader<'a> {
+    pub(crate) fn new(inner: OleStreamReader<'a>) -> Self {
+        Self { inner, seek: None }
+    }
+
+    /// The size of the stream as recorded in its directory entry.
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+#[cfg(feature = "async")]
+impl tokio::io::AsyncRead for AsyncOleStreamReader<'_> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let result = this.inner.read(buf.initialize_unfilled()).map(|len| {
+            buf.advance(len);
+        });
+        std::task::Poll::Ready(result)
+    }
+}
+
+#[cfg(feature = "async")]
+impl tokio::io::AsyncSeek for AsyncOleStreamReader<'_> {
+    fn start_seek(self: std::pin::Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        this.seek = Some(this.inner.seek(position));
+        Ok(())
+    }
+
+    fn poll_complete(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        std::task::Poll::Ready(this.seek.take().unwrap_or(Ok(this.inner.position)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_support::{huge_stream_file, CfbBuilder},
+        OleFile,
+    };
+    use std::io::{Read, Seek, SeekFrom};
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_reads_match_open_stream() {
+        let data = CfbBuilder::new()
+            .stream("Small", &sample(100))
+            .stream("Storage/Large", &sample(5000))
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        for path in [&["Small"][..], &["Storage", "Large"][..]] {
+            let mut reader = ole.stream_reader(path).unwrap();
+            let mut read: Vec<u8> = vec![];
+            // small reads cross sector boundaries at odd offsets
+            let mut buf = [0u8; 7];
+            loop {
+                let len = reader.read(&mut buf).unwrap();
+                if len == 0 {
+                    break;
+                }
+                read.extend(&buf[..len]);
+            }
+            assert_eq!(read, ole.open_stream(path).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_stream_chunks() {
+        let data = CfbBuilder::new()
+            .stream("Small", &sample(100))
+            .stream("Storage/Large", &sample(5000))
+            .build();
+        let mut ole = OleFile::parse_sync(&data[..]).unwrap();
+        for (path, chunk_size) in [(&["Small"][..], 64), (&["Storage", "Large"][..], 512)] {
+            let chunks = ole
+                .stream_chunks(path)
+                .unwrap()
+                .collect::<crate::Result<Vec<_>>>()
+                .unwrap();
+            let stream = ole.open_stream(path).unwrap();
+            assert_eq!(chunks.len(), stream.len().div_ceil(chunk_size));
+            assert!(chunks.iter().all(|chunk| chunk.len() <= chunk_size));
+            assert_eq!(chunks.concat(), stream);
+        }
+
+        // the chain of Large ends after its second sector
+        let start = ole
+            .find_stream_path("Storage/Large")
+            .unwrap()
+            .starting_sector_location;
+        let second = ole.sector_allocation_table[start.unwrap() as usize];
+        ole.sector_allocation_table[second as usize] = crate::constants::CHAIN_END;
+        let chunks = ole
+            .stream_chunks(&["Storage", "Large"])
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 3);
+        assert!(matches!(
+            chunks[2],
+            Err(crate::error::Error::OleUnexpectedEof { .. })
+        ));
+    }
+
+    #[test]
+    fn test_seek() {
+        let data = CfbBuilder::new().stream("Large", &sample(5000)).build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let mut reader = ole.stream_reader(&["Large"]).unwrap();
+        assert_eq!(reader.len(), 5000);
+
+        assert_eq!(reader.seek(SeekFrom::Start(1030)).unwrap(), 1030);
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &sample(5000)[1030..1034]);
+
+        assert_eq!(reader.seek(SeekFrom::End(-2)).unwrap(), 4998);
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &sample(5000)[4998..]);
+
+        assert!(reader.seek(SeekFrom::Current(-6000)).is_err());
+        // past the end is allowed, and reads nothing
+        reader.seek(SeekFrom::Start(6000)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_open_stream_range() {
+        let data = CfbBuilder::new()
+            .stream("Small", &sample(100))
+            .stream("Large", &sample(5000))
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        assert_eq!(
+            ole.open_stream_range(&["Large"], 510, 4).unwrap(),
+            &sample(5000)[510..514]
+        );
+        assert_eq!(
+            ole.open_stream_range(&["Small"], 90, 20).unwrap(),
+            &sample(100)[90..]
+        );
+        assert!(ole
+            .open_stream_range(&["Small"], 200, 4)
+            .unwrap()
+            .is_empty());
+        assert!(ole.open_stream_range(&["Missing"], 0, 4).is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_copy_stream_to() {
+        let data = CfbBuilder::new()
+            .stream("Small", &sample(100))
+            .stream("Large", &sample(5000))
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        for name in ["Small", "Large"] {
+            let mut copied = vec![];
+            let written = ole.copy_stream_to(&[name], &mut copied).unwrap();
+            assert_eq!(written, copied.len() as u64);
+            assert_eq!(copied, ole.open_stream(&[name]).unwrap());
+
+            let mut copied_async = vec![];
+            ole.copy_stream_to_async(&[name], &mut copied_async)
+                .await
+                .unwrap();
+            assert_eq!(copied_async, copied);
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_reader() {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        let data = CfbBuilder::new()
+            .stream("Small", &sample(100))
+            .stream("Large", &sample(5000))
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        for name in ["Small", "Large"] {
+            let mut reader = ole.async_stream_reader(&[name]).unwrap();
+            let mut read: Vec<u8> = vec![];
+            reader.read_to_end(&mut read).await.unwrap();
+            assert_eq!(read, ole.open_stream(&[name]).unwrap());
+        }
+
+        let mut reader = ole.async_stream_reader(&["Large"]).unwrap();
+        assert_eq!(reader.seek(SeekFrom::Start(4000)).await.unwrap(), 4000);
+        let mut buf = [0u8; 3];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf[..], &sample(5000)[4000..4003]);
+        assert_eq!(reader.stream_position().await.unwrap(), 4003);
+    }
+
+    #[test]
+    fn test_huge_stream() {
+        let size = (1 << 32) + 5000;
+        let ole = OleFile::from_seekable(huge_stream_file(size, b"the end"), 16).unwrap();
+        assert_eq!(ole.stream_size(&["Big"]), Some(size));
+
+        let mut reader = ole.stream_reader(&["Big"]).unwrap();
+        assert_eq!(reader.len(), size);
+        reader.seek(SeekFrom::Start(u32::MAX as u64 - 1)).unwrap();
+        let mut buf = [0xFFu8; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0; 4]);
+        reader.seek(SeekFrom::End(-7)).unwrap();
+        let mut tail = vec![];
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, b"the end");
+
+        assert_eq!(
+            ole.open_stream_range(&["Big"], size - 3, 10).unwrap(),
+            b"end"
+        );
+        let extents = ole.stream_extents("Big").unwrap();
+        assert!(extents.last().unwrap().start > u32::MAX as u64);
+        assert_eq!(
+            ole.stream_slack(&["Big"]).unwrap(),
+            vec![0; 4096 - 5000 % 4096]
+        );
+    }
+
+    #[test]
+    fn test_bounded_extraction() {
+        let size = (1 << 28) + 5000;
+        // the FAT of the stream takes 256 KB, a quarter of the memory goes to the sector cache
+        let ole =
+            OleFile::from_seekable_bounded(huge_stream_file(size, b"the end"), 1 << 20).unwrap();
+        assert!(ole.parse_metrics().memory <= 3 << 18);
+        assert!(ole.directory_entries.is_empty());
+        let written = ole.copy_stream_to(&["Big"], &mut std::io::sink()).unwrap();
+        assert_eq!(written, size);
+        assert_eq!(
+            ole.open_stream_range(&["Big"], size - 7, 7).unwrap(),
+            b"the end"
+        );
+
+        assert!(matches!(
+            OleFile::from_seekable_bounded(huge_stream_file(size, b""), 1 << 18),
+            Err(crate::error::Error::ResourceLimit(_))
+        ));
+    }
+}
@@ -0,0 +1,443 @@
+//! Builds small compound files in memory so tests don't need a sample on disk for every layout.
+use crate::constants;
+
+const NO_STREAM: u32 = 0xFFFFFFFF;
+const MINI_SECTOR_SIZE: usize = 64;
+
+enum NodeKind {
+    Root,
+    Storage,
+    Stream(Vec<u8>),
+}
+
+struct Node {
+    name: String,
+    kind: NodeKind,
+    children: Vec<usize>,
+}
+
+/// Writes a minimal, spec-conformant compound file containing the given streams.
+///
+/// Streams are added by slash separated path, and any storage on the way is created. Streams
+/// smaller than the mini stream cutoff are placed in the mini stream, the rest get FAT chains.
+pub(crate) struct CfbBuilder {
+    major_version: u16,
+    mini_stream_cutoff: u32,
+    nodes: Vec<Node>,
+}
+
+impl CfbBuilder {
+    pub fn new() -> Self {
+        Self {
+            major_version: 3,
+            mini_stream_cutoff: 4096,
+            nodes: vec![Node {
+                name: "Root Entry".to_string(),
+                kind: NodeKind::Root,
+                children: vec![],
+            }],
+        }
+    }
+
+    pub fn version_4(mut self) -> Self {
+        self.major_version = 4;
+        self
+    }
+
+    /// Write `cutoff` to the header instead of the 4096 the spec wants, and place streams by it.
+    pub fn mini_stream_cutoff(mut self, cutoff: u32) -> Self {
+        self.mini_stream_cutoff = cutoff;
+        self
+    }
+
+    pub fn stream(mut self, path: &str, data: &[u8]) -> Self {
+        let mut parent = 0;
+        let components = path.split('/').collect::<Vec<_>>();
+        for (position, name) in components.iter().enumerate() {
+            let is_last = position == components.len() - 1;
+            let existing = self.nodes[parent]
+                .children
+                .iter()
+                .copied()
+                .find(|child| self.nodes[*child].name == *name);
+            parent = match existing {
+                Some(child) if !is_last => child,
+                _ => {
+                    self.nodes.push(Node {
+                        name: name.to_string(),
+                        kind: if is_last {
+                            NodeKind::Stream(data.to_vec())
+                        } else {
+                            NodeKind::Storage
+                        },
+                        children: vec![],
+                    });
+                    let child = self.nodes.len() - 1;
+                    self.nodes[parent].children.push(child);
+                    child
+                }
+            };
+        }
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let sector_size: usize = if self.major_version == 3 { 512 } else { 4096 };
+        let ids_per_sector = sector_size / 4;
+        let mut sectors: Vec<Vec<u8>> = vec![];
+        let mut fat: Vec<u32> = vec![];
+
+        let mut allocate = |data: &[u8], sectors: &mut Vec<Vec<u8>>| -> u32 {
+            if data.is_empty() {
+                return constants::CHAIN_END;
+            }
+            let first = sectors.len() as u32;
+            for chunk in data.chunks(sector_size) {
+                let mut sector = chunk.to_vec();
+                sector.resize(sector_size, 0);
+                sectors.push(sector);
+                fat.push(sectors.len() as u32);
+            }
+            *fat.last_mut().unwrap() = constants::CHAIN_END;
+            first
+        };
+
+        // stream data, either in the FAT or in the mini stream
+        let mut starts = vec![constants::CHAIN_END; self.nodes.len()];
+        let mut mini_stream: Vec<u8> = vec![];
+        let mut mini_fat: Vec<u32> = vec![];
+        for (index, node) in self.nodes.iter().enumerate() {
+            if let NodeKind::Stream(data) = &node.kind {
+                if data.len() >= self.mini_stream_cutoff as usize {
+                    starts[index] = allocate(data, &mut sectors);
+                } else if !data.is_empty() {
+                    starts[index] = (mini_stream.len() / MINI_SECTOR_SIZE) as u32;
+                    for chunk in data.chunks(MINI_SECTOR_SIZE) {
+                        mini_stream.extend(chunk);
+                        mini_stream.resize(mini_stream.len() + MINI_SECTOR_SIZE - chunk.len(), 0);
+                        mini_fat.push((mini_stream.len() / MINI_SECTOR_SIZE) as u32);
+                    }
+                    *mini_fat.last_mut().unwrap() = constants::CHAIN_END;
+                }
+            }
+        }
+        starts[0] = allocate(&mini_stream, &mut sectors);
+        let mini_fat_bytes = mini_fat
+            .iter()
+            .flat_map(|id| id.to_le_bytes())
+            .collect::<Vec<_>>();
+        let mini_fat_start = allocate(&mini_fat_bytes, &mut sectors);
+        let mini_fat_len = mini_fat_bytes.len().div_ceil(sector_size);
+
+        // directory
+        let mut directory: Vec<u8> = vec![];
+        for (index, node) in self.nodes.iter().enumerate() {
+            let mut entry = [0u8; 128];
+            let name = node
+                .name
+                .encode_utf16()
+                .chain([0])
+                .flat_map(|unit| unit.to_le_bytes())
+                .collect::<Vec<_>>();
+            entry[..name.len()].copy_from_slice(&name);
+            entry[64..66].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            let (object_type, size) = match &node.kind {
+                NodeKind::Root => (constants::OBJECT_TYPE_ROOT_STORAGE, mini_stream.len()),
+                NodeKind::Storage => (constants::OBJECT_TYPE_STORAGE, 0),
+                NodeKind::Stream(data) => (constants::OBJECT_TYPE_STREAM, data.len()),
+            };
+            entry[66] = object_type[0];
+            entry[67] = constants::NODE_COLOR_BLACK[0];
+            // siblings are chained to the right, which keeps the tree trivially searchable
+            let right_sibling = self
+                .nodes
+                .iter()
+                .find_map(|parent| {
+                    let position = parent.children.iter().position(|c| *c == index)?;
+                    parent.children.get(position + 1).map(|id| *id as u32)
+                })
+                .unwrap_or(NO_STREAM);
+            let child = node
+                .children
+                .first()
+                .map(|id| *id as u32)
+                .unwrap_or(NO_STREAM);
+            entry[68..72].copy_from_slice(&NO_STREAM.to_le_bytes());
+            entry[72..76].copy_from_slice(&right_sibling.to_le_bytes());
+            entry[76..80].copy_from_slice(&child.to_le_bytes());
+            let start = match node.kind {
+                NodeKind::Storage => 0,
+                _ => starts[index],
+            };
+            entry[116..120].copy_from_slice(&start.to_le_bytes());
+            entry[120..128].copy_from_slice(&(size as u64).to_le_bytes());
+            directory.extend(entry);
+        }
+        let directory_start = allocate(&directory, &mut sectors);
+        let directory_len = directory.len().div_ceil(sector_size);
+
+        // FAT and DIFAT sectors have to account for themselves
+        let data_sectors = sectors.len();
+        let (mut fat_len, mut difat_len) = (0, 0);
+        loop {
+            let total = data_sectors + fat_len + difat_len;
+            let needed_fat = total.div_ceil(ids_per_sector);
+            let needed_difat = needed_fat
+                .saturating_sub(constants::HEADER_DIFAT_ENTRIES)
+                .div_ceil(ids_per_sector - 1);
+            if (needed_fat, needed_difat) == (fat_len, difat_len) {
+                break;
+            }
+            (fat_len, difat_len) = (needed_fat, needed_difat);
+        }
+        let fat_ids = (data_sectors..data_sectors + fat_len)
+            .map(|id| id as u32)
+            .collect::<Vec<_>>();
+        let difat_ids = (data_sectors + fat_len..data_sectors + fat_len + difat_len)
+            .map(|id| id as u32)
+            .collect::<Vec<_>>();
+        fat.extend(fat_ids.iter().map(|_| constants::FAT_SECTOR));
+        fat.extend(difat_ids.iter().map(|_| constants::DIFAT_SECTOR));
+        fat.resize(fat_len * ids_per_sector, constants::UNALLOCATED_SECTOR);
+        for chunk in fat.chunks(ids_per_sector) {
+            sectors.push(chunk.iter().flat_map(|id| id.to_le_bytes()).collect());
+        }
+        let overflow = fat_ids
+            .iter()
+            .skip(constants::HEADER_DIFAT_ENTRIES)
+            .copied()
+            .collect::<Vec<_>>();
+        for (position, chunk) in overflow.chunks(ids_per_sector - 1).enumerate() {
+            let mut ids = chunk.to_vec();
+            ids.resize(ids_per_sector - 1, constants::UNALLOCATED_SECTOR);
+            ids.push(
+                difat_ids
+                    .get(position + 1)
+                    .copied()
+                    .unwrap_or(constants::CHAIN_END),
+            );
+            sectors.push(ids.iter().flat_map(|id| id.to_le_bytes()).collect());
+        }
+
+        // header
+        let mut header = vec![0u8; constants::HEADER_LENGTH];
+        header[0..8].copy_from_slice(&constants::MAGIC_BYTES);
+        header[24..26].copy_from_slice(&[0x3E, 0x00]);
+        header[26..28].copy_from_slice(&self.major_version.to_le_bytes());
+        header[28..30].copy_from_slice(&[0xFE, 0xFF]);
+        header[30..32].copy_from_slice(if self.major_version == 3 {
+            &constants::SECTOR_SIZE_VERSION_3
+        } else {
+            &constants::SECTOR_SIZE_VERSION_4
+        });
+        header[32..34].copy_from_slice(&[0x06, 0x00]);
+        if self.major_version == 4 {
+            header[40..44].copy_from_slice(&(directory_len as u32).to_le_bytes());
+        }
+        header[44..48].copy_from_slice(&(fat_len as u32).to_le_bytes());
+        header[48..52].copy_from_slice(&directory_start.to_le_bytes());
+        header[56..60].copy_from_slice(&self.mini_stream_cutoff.to_le_bytes());
+        header[60..64].copy_from_slice(&mini_fat_start.to_le_bytes());
+        header[64..68].copy_from_slice(&(mini_fat_len as u32).to_le_bytes());
+        header[68..72].copy_from_slice(
+            &difat_ids
+                .first()
+                .copied()
+                .unwrap_or(constants::CHAIN_END)
+                .to_le_bytes(),
+        );
+        header[72..76].copy_from_slice(&(difat_len as u32).to_le_bytes());
+        for position in 0..constants::HEADER_DIFAT_ENTRIES {
+            let id = fat_ids
+                .get(position)
+                .copied()
+                .unwrap_or(constants::UNALLOCATED_SECTOR);
+            let offset = 76 + position * 4;
+            header[offset..offset + 4].copy_from_slice(&id.to_le_bytes());
+        }
+        header.resize(sector_size, 0);
+
+        header.extend(sectors.concat());
+        header
+    }
+}
+
+/// A file that is zeros but for a few runs of bytes, so tests can parse files of many GB.
+pub(crate) struct SparseFile {
+    len: u64,
+    /// runs of bytes by offset, in order and not overlapping
+    runs: Vec<(u64, Vec<u8>)>,
+    position: u64,
+}
+
+impl std::io::Read for SparseFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = (buf.len() as u64).min(self.len.saturating_sub(self.position)) as usize;
+        let (start, end) = (self.position, self.position + len as u64);
+        buf[..len].fill(0);
+        for (offset, run) in &self.runs {
+            let run_end = offset + run.len() as u64;
+            if *offset < end && run_end > start {
+                let from = (*offset).max(start);
+                let to = run_end.min(end);
+                buf[(from - start) as usize..(to - start) as usize]
+                    .copy_from_slice(&run[(from - offset) as usize..(to - offset) as usize]);
+            }
+        }
+        self.position = end;
+        Ok(len)
+    }
+}
+
+impl std::io::Seek for SparseFile {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.position = match pos {
+            std::io::SeekFrom::Start(offset) => Some(offset),
+            std::io::SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            std::io::SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        Ok(self.position)
+    }
+}
+
+/// A version 4 file holding one stream, `Big`, of `size` bytes in contiguous sectors but for the
+/// range lock sector, which it skips. The stream is zeros but for `tail` at its very end.
+pub(crate) fn huge_stream_file(size: u64, tail: &[u8]) -> SparseFile {
+    const SECTOR_SIZE: u64 = 4096;
+    let ids_per_sector = SECTOR_SIZE / 4;
+    let range_lock = constants::RANGE_LOCK_OFFSET / SECTOR_SIZE - 1;
+    let mut data_sectors = size.div_ceil(SECTOR_SIZE);
+    if data_sectors > range_lock {
+        data_sectors += 1;
+    }
+    // the sector holding the stream offset `position`
+    let sector_of = |position: u64| {
+        let index = position / SECTOR_SIZE;
+        index + u64::from(index >= range_lock)
+    };
+    let directory_id = data_sectors;
+    let (mut fat_len, mut difat_len) = (0, 0);
+    loop {
+        let needed_fat = (data_sectors + 1 + fat_len + difat_len).div_ceil(ids_per_sector);
+        let needed_difat = needed_fat
+            .saturating_sub(constants::HEADER_DIFAT_ENTRIES as u64)
+            .div_ceil(ids_per_sector - 1);
+        if (needed_fat, needed_difat) == (fat_len, difat_len) {
+            break;
+        }
+        (fat_len, difat_len) = (needed_fat, needed_difat);
+    }
+    let fat_ids = (directory_id + 1..directory_id + 1 + fat_len)
+        .map(|id| id as u32)
+        .collect::<Vec<_>>();
+    let difat_ids = (directory_id + 1 + fat_len..directory_id + 1 + fat_len + difat_len)
+        .map(|id| id as u32)
+        .collect::<Vec<_>>();
+
+    let mut fat = (1..data_sectors as u32).collect::<Vec<_>>();
+    fat.push(constants::CHAIN_END);
+    if let Some(before) = range_lock
+        .checked_sub(1)
+        .filter(|_| range_lock < data_sectors)
+    {
+        fat[before as usize] = range_lock as u32 + 1;
+        fat[range_lock as usize] = constants::CHAIN_END;
+    }
+    fat.push(constants::CHAIN_END);
+    fat.extend(fat_ids.iter().map(|_| constants::FAT_SECTOR));
+    fat.extend(difat_ids.iter().map(|_| constants::DIFAT_SECTOR));
+    fat.resize(
+        (fat_len * ids_per_sector) as usize,
+        constants::UNALLOCATED_SECTOR,
+    );
+    let mut tables = fat
+        .iter()
+        .flat_map(|id| id.to_le_bytes())
+        .collect::<Vec<_>>();
+    let overflow = &fat_ids[constants::HEADER_DIFAT_ENTRIES.min(fat_ids.len())..];
+    for (position, chunk) in overflow.chunks(ids_per_sector as usize - 1).enumerate() {
+        let mut ids = chunk.to_vec();
+        ids.resize(ids_per_sector as usize - 1, constants::UNALLOCATED_SECTOR);
+        ids.push(
+            difat_ids
+                .get(position + 1)
+                .copied()
+                .unwrap_or(constants::CHAIN_END),
+        );
+        tables.extend(ids.iter().flat_map(|id| id.to_le_bytes()));
+    }
+
+    let mut directory = vec![0u8; SECTOR_SIZE as usize];
+    for (index, (name, object_type)) in [
+        ("Root Entry", constants::OBJECT_TYPE_ROOT_STORAGE),
+        ("Big", constants::OBJECT_TYPE_STREAM),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let entry = &mut directory[index * 128..(index + 1) * 128];
+        let name = name
+            .encode_utf16()
+            .chain([0])
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect::<Vec<_>>();
+        entry[..name.len()].copy_from_slice(&name);
+        entry[64..66].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        entry[66] = object_type[0];
+        entry[67] = constants::NODE_COLOR_BLACK[0];
+        entry[68..76].copy_from_slice(&[0xFF; 8]);
+        let (child, start, stream_size) = match index {
+            0 => (1, constants::CHAIN_END, 0),
+            _ => (NO_STREAM, 0, size),
+        };
+        entry[76..80].copy_from_slice(&child.to_le_bytes());
+        entry[116..120].copy_from_slice(&start.to_le_bytes());
+        entry[120..128].copy_from_slice(&stream_size.to_le_bytes());
+    }
+
+    let mut header = vec![0u8; constants::HEADER_LENGTH];
+    header[0..8].copy_from_slice(&constants::MAGIC_BYTES);
+    header[24..26].copy_from_slice(&[0x3E, 0x00]);
+    header[26..28].copy_from_slice(&4u16.to_le_bytes());
+    header[28..30].copy_from_slice(&[0xFE, 0xFF]);
+    header[30..32].copy_from_slice(&constants::SECTOR_SIZE_VERSION_4);
+    header[32..34].copy_from_slice(&[0x06, 0x00]);
+    header[40..44].copy_from_slice(&1u32.to_le_bytes());
+    header[44..48].copy_from_slice(&(fat_len as u32).to_le_bytes());
+    header[48..52].copy_from_slice(&(directory_id as u32).to_le_bytes());
+    header[56..60].copy_from_slice(&4096u32.to_le_bytes());
+    header[60..64].copy_from_slice(&constants::CHAIN_END.to_le_bytes());
+    header[68..72].copy_from_slice(
+        &difat_ids
+            .first()
+            .copied()
+            .unwrap_or(constants::CHAIN_END)
+            .to_le_bytes(),
+    );
+    header[72..76].copy_from_slice(&(difat_len as u32).to_le_bytes());
+    for position in 0..constants::HEADER_DIFAT_ENTRIES {
+        let id = fat_ids
+            .get(position)
+            .copied()
+            .unwrap_or(constants::UNALLOCATED_SECTOR);
+        let offset = 76 + position * 4;
+        header[offset..offset + 4].copy_from_slice(&id.to_le_bytes());
+    }
+
+    let offset = |id: u64| (id + 1) * SECTOR_SIZE;
+    SparseFile {
+        len: offset(directory_id + 1 + fat_len + difat_len),
+        runs: vec![
+            (0, header),
+            (
+                offset(sector_of(size - tail.len() as u64))
+                    + (size - tail.len() as u64) % SECTOR_SIZE,
+                tail.to_vec(),
+            ),
+            (offset(directory_id), directory),
+            (offset(directory_id + 1), tables),
+        ],
+        position: 0,
+    }
+}
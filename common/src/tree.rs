@@ -0,0 +1,242 @@
+//! Checks of the red-black trees holding the children of every storage.
+//!
+//! MS-CFB keeps the children of a storage in a red-black tree ordered by name: shorter names
+//! first, names of the same length by their uppercase code points. Office and the reference
+//! implementation write valid trees. A tree that breaks the name order can't be searched, so
+//! other parsers miss or find different entries, which is why crafted files break it on purpose.
+use crate::{
+    analysis,
+    directory::{EntryPath, NodeColor, ObjectType},
+    OleFile,
+};
+use std::cmp::Ordering;
+
+/// A rule of MS-CFB that the tree of a storage breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeViolation {
+    /// path of the storage whose tree it is, empty for the root storage
+    pub storage: EntryPath,
+    /// stream ID of the entry breaking the rule
+    pub entry: u32,
+    pub kind: TreeViolationKind,
+}
+
+/// Which rule a [`TreeViolation`] breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TreeViolationKind {
+    /// the root of the tree is red
+    RedRoot,
+    /// a red entry has a red parent, the stream ID of the parent
+    RedParent(u32),
+    /// the subtrees on the left and on the right have different numbers of black entries on
+    /// their paths down
+    BlackHeight { left: usize, right: usize },
+    /// the entry doesn't sort after the name of the entry before it in tree order; a name
+    /// equal to the one before is a duplicate
+    NameOrder(String),
+}
+
+/// Compare two entry names the way MS-CFB orders the trees: by length in UTF-16 code units
+/// first, then by the uppercase code points.
+pub(crate) fn compare_names(a: &str, b: &str) -> Ordering {
+    a.encode_utf16()
+        .count()
+        .cmp(&b.encode_utf16().count())
+        .then_with(|| {
+            a.chars()
+                .flat_map(char::to_uppercase)
+                .cmp(b.chars().flat_map(char::to_uppercase))
+        })
+}
+
+/// The violations in the tree of the root and then of every storage, in directory order.
+pub(crate) fn tree_violations(ole: &OleFile) -> Vec<TreeViolation> {
+    let storages = analysis::entries_with_paths(ole)
+        .into_iter()
+        .filter(|(_, entry)| entry.object_type == ObjectType::Storage);
+    let root = Some((vec![], ole.root()));
+    // as for the tree statistics, an entry shared between trees belongs to the first reaching it
+    let mut visited = vec![false; ole.id_count()];
+    // shared by the storages, each resets what its own nodes set, so that many storages don't
+    // cost a directory sized buffer each
+    let mut in_tree = vec![false; ole.id_count()];
+    let mut black_heights = vec![1usize; ole.id_count()];
+    let mut violations = vec![];
+    for (path, storage) in root.into_iter().chain(storages) {
+        let path = EntryPath::from(path);
+        let mut report = |entry: u32, kind: TreeViolationKind| {
+            violations.push(TreeViolation {
+                storage: path.clone(),
+                entry,
+                kind,
+            })
+        };
+        let color = |id: u32| {
+            ole.entry_by_id(id)
+                .map_or(NodeColor::Black, |entry| entry.color)
+        };
+
+        // nodes from the top down with their parents, without recursion
+        let mut nodes: Vec<(u32, Option<u32>)> = vec![];
+        let mut pending = storage
+            .child_id
+            .map(|id| (id, None))
+            .into_iter()
+            .collect::<Vec<_>>();
+        while let Some((id, parent)) = pending.pop() {
+            match visited.get_mut(id as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => continue,
+            }
+            let entry = match ole.entry_by_id(id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            in_tree[id as usize] = true;
+            nodes.push((id, parent));
+            for child in [entry.right_sibling_id, entry.left_sibling_id]
+                .into_iter()
+                .flatten()
+            {
+                pending.push((child, Some(id)));
+            }
+        }
+
+        for (id, parent) in &nodes {
+            match parent {
+                None if color(*id) == NodeColor::Red => report(*id, TreeViolationKind::RedRoot),
+                Some(parent)
+                    if color(*id) == NodeColor::Red && color(*parent) == NodeColor::Red =>
+                {
+                    report(*id, TreeViolationKind::RedParent(*parent))
+                }
+                _ => {}
+            }
+        }
+        // black heights, children before parents, missing children count as black leaves
+        for (id, _) in nodes.iter().rev() {
+            let entry = match ole.entry_by_id(*id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let height = |child: Option<u32>| {
+                child
+                    .filter(|child| in_tree.get(*child as usize) == Some(&true))
+                    .map_or(1, |child| black_heights[child as usize])
+            };
+            let (left, right) = (
+                height(entry.left_sibling_id),
+                height(entry.right_sibling_id),
+            );
+            if left != right {
+                report(*id, TreeViolationKind::BlackHeight { left, right });
+            }
+            black_heights[*id as usize] =
+                left.max(right) + usize::from(color(*id) == NodeColor::Black);
+        }
+
+        for pair in ole.child_entries(storage).windows(2) {
+            if compare_names(&pair[0].name, &pair[1].name) != Ordering::Less {
+                report(
+                    pair[1].index as u32,
+                    TreeViolationKind::NameOrder(pair[0].name.clone()),
+                );
+            }
+        }
+        for (id, _) in &nodes {
+            in_tree[*id as usize] = false;
+            black_heights[*id as usize] = 1;
+        }
+    }
+    violations
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    #[test]
+    fn test_compare_names() {
+        assert_eq!(compare_names("Z", "AA"), Ordering::Less);
+        assert_eq!(compare_names("abc", "ABD"), Ordering::Less);
+        assert_eq!(
+            compare_names("WordDocument", "worddocument"),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_tree_violations() {
+        let data = CfbBuilder::new()
+            .stream("A", b"a")
+            .stream("B", b"b")
+            .stream("C", b"c")
+            .build();
+        let mut ole = OleFile::parse_sync(&data[..]).unwrap();
+        let violation = |entry: u32, kind: TreeViolationKind| TreeViolation {
+            storage: EntryPath::default(),
+            entry,
+            kind,
+        };
+        // the builder chains siblings to the right, all black, so only black heights differ
+        assert_eq!(
+            ole.tree_violations(),
+            vec![
+                violation(2, TreeViolationKind::BlackHeight { left: 1, right: 2 }),
+                violation(1, TreeViolationKind::BlackHeight { left: 1, right: 3 }),
+            ]
+        );
+
+        // a valid tree: black B with red children A and C
+        ole.directory_entries[0].child_id = Some(2);
+        ole.directory_entries[1].right_sibling_id = None;
+        ole.directory_entries[2].left_sibling_id = Some(1);
+        ole.directory_entries[1].color = NodeColor::Red;
+        ole.directory_entries[3].color = NodeColor::Red;
+        assert_eq!(ole.tree_violations(), vec![]);
+        let names = ole
+            .root()
+            .children(&ole)
+            .map(|entry| entry.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["A", "B", "C"]);
+
+        // a red root, and C renamed out of order
+        ole.directory_entries[2].color = NodeColor::Red;
+        ole.directory_entries[3].name = "a".to_string();
+        assert_eq!(
+            ole.tree_violations(),
+            vec![
+                violation(2, TreeViolationKind::RedRoot),
+                violation(1, TreeViolationKind::RedParent(2)),
+                violation(3, TreeViolationKind::RedParent(2)),
+                violation(3, TreeViolationKind::NameOrder("B".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tree_violations_per_storage() {
+        // the same tree in every storage gives the same violations in each, nothing carried
+        // over from the storage before
+        let mut builder = CfbBuilder::new();
+        for storage in ["S1", "S2", "S3"] {
+            for stream in ["A", "B", "C"] {
+                builder = builder.stream(&format!("{}/{}", storage, stream), b"x");
+            }
+        }
+        let ole = OleFile::parse_sync(&builder.build()[..]).unwrap();
+        let violations = ole.tree_violations();
+        let of = |storage: &str| {
+            violations
+                .iter()
+                .filter(|violation| violation.storage.components() == [storage])
+                .map(|violation| violation.kind.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(of("S1").len(), 2);
+        assert_eq!(of("S1"), of("S2"));
+        assert_eq!(of("S1"), of("S3"));
+    }
+}
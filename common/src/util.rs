@@ -8,10 +8,83 @@ impl StringUtils for String {
     fn substring(&self, range: RangeFrom<usize>) -> String {
         let chars = self.chars().map(|x| x.to_string()).collect::<Vec<_>>();
         let mut substring = Vec::new();
-        for char_index in range.start..chars.len() {
+        // offsets count from 1, 0 is taken as 1
+        for char_index in range.start.max(1)..chars.len() {
             let cur_char = chars[char_index - 1].clone();
             substring.push(cur_char);
         }
         substring.join("")
     }
 }
+
+/// A Windows FILETIME, 100 nanosecond intervals since 1601, as a date. `None` for 0, which
+/// stands for "not recorded", and for values out of range.
+pub fn filetime(value: u64) -> Option<chrono::NaiveDateTime> {
+    match value {
+        0 => None,
+        value => epochs::windows_file(i64::try_from(value).ok()?),
+    }
+}
+
+/// Split a slash separated stream path, such as `Macros/VBA/ThisDocument`, into its components.
+///
+/// A backslash takes the next character literally, so `\/` is a slash inside a name and `\\` is
+/// a backslash. Leading, trailing and repeated slashes are ignored.
+pub fn split_stream_path(path: &str) -> Vec<String> {
+    let mut components = vec![];
+    let mut current = String::new();
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            '/' => {
+                if !current.is_empty() {
+                    components.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        components.push(current);
+    }
+    components
+}
+
+/// The inverse of [`split_stream_path`], escaping slashes and backslashes in the names.
+pub fn join_stream_path<S: AsRef<str>>(components: &[S]) -> String {
+    components
+        .iter()
+        .map(|name| name.as_ref().replace('\\', "\\\\").replace('/', "\\/"))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filetime() {
+        assert_eq!(filetime(0), None);
+        // the Unix epoch
+        assert_eq!(
+            filetime(116_444_736_000_000_000).unwrap().to_string(),
+            "1970-01-01 00:00:00"
+        );
+    }
+
+    #[test]
+    fn test_stream_path_round_trip() {
+        assert_eq!(
+            split_stream_path("/Macros/VBA//ThisDocument/"),
+            vec!["Macros", "VBA", "ThisDocument"]
+        );
+        assert_eq!(split_stream_path(r"a\/b/c\\d"), vec!["a/b", r"c\d"]);
+        let components = ["a/b", r"c\d", "\x01Ole"];
+        assert_eq!(
+            split_stream_path(&join_stream_path(&components)),
+            components
+        );
+    }
+}
@@ -0,0 +1,849 @@
+//! VBA projects: the references and modules listed in the `dir` stream, and what the `PROJECT`
+//! stream says the modules are.
+use crate::{analysis, directory::ObjectType, error::Error, OleFile, Result};
+
+/// PROJECTCODEPAGE: the code page of the names in the project (MS-OVBA 2.3.4.2.1.4)
+const PROJECT_CODE_PAGE: u16 = 0x0003;
+/// PROJECTVERSION, whose size field is always 4 although 6 bytes follow it
+const PROJECT_VERSION: u16 = 0x0009;
+/// REFERENCENAME and the Unicode name that follows it (MS-OVBA 2.3.4.2.2.2)
+const REFERENCE_NAME: u16 = 0x0016;
+const REFERENCE_NAME_UNICODE: u16 = 0x003E;
+/// REFERENCEORIGINAL: the library an ActiveX control reference was made from
+const REFERENCE_ORIGINAL: u16 = 0x0033;
+/// REFERENCECONTROL, and the part of it holding the extended type library
+const REFERENCE_CONTROL: u16 = 0x002F;
+const REFERENCE_CONTROL_EXTENDED: u16 = 0x0030;
+const REFERENCE_REGISTERED: u16 = 0x000D;
+const REFERENCE_PROJECT: u16 = 0x000E;
+const MODULE_NAME: u16 = 0x0019;
+const MODULE_NAME_UNICODE: u16 = 0x0047;
+const MODULE_STREAM_NAME: u16 = 0x001A;
+const MODULE_STREAM_NAME_UNICODE: u16 = 0x0032;
+/// MODULEOFFSET: where the source starts in the module stream, after the p-code
+const MODULE_OFFSET: u16 = 0x0031;
+/// MODULETYPE of a procedural module
+const MODULE_TYPE_PROCEDURAL: u16 = 0x0021;
+/// MODULETYPE shared by document, class and designer modules
+const MODULE_TYPE_OTHER: u16 = 0x0022;
+/// the record ending a module
+const MODULE_TERMINATOR: u16 = 0x002B;
+
+/// the size of a decompressed chunk, and of the raw data of an uncompressed one
+const CHUNK_SIZE: usize = 4096;
+
+/// Procedures Office runs by itself when a document is opened, created or closed, or when the
+/// application starts or quits.
+const AUTOEXEC_PROCEDURES: &[&str] = &[
+    "AutoExec",
+    "AutoOpen",
+    "AutoNew",
+    "AutoClose",
+    "AutoExit",
+    "Auto_Open",
+    "Auto_Close",
+    "Document_Open",
+    "Document_New",
+    "Document_Close",
+    "DocumentOpen",
+    "DocumentBeforeClose",
+    "Workbook_Open",
+    "Workbook_Activate",
+    "Workbook_Close",
+    "Workbook_BeforeClose",
+];
+
+/// What a module is, as far as the `dir` and `PROJECT` streams tell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VbaModuleKind {
+    /// a procedural module, `Module=` in the PROJECT stream
+    Standard,
+    /// a class module, `Class=`
+    Class,
+    /// a UserForm or other designer, `BaseClass=`
+    Form,
+    /// the code behind a host object, such as ThisDocument, ThisWorkbook or Sheet1, whose
+    /// event handlers run on document events, `Document=`
+    Document,
+    /// a document, class or designer module the PROJECT stream doesn't list, which happens
+    /// when it was stripped or tampered with
+    Unlisted,
+}
+
+/// A module of a VBA project.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VbaModule {
+    pub name: String,
+    /// the stream holding the module in the `VBA` storage
+    pub stream_name: String,
+    /// where the compressed source starts in the stream
+    pub text_offset: u32,
+    pub kind: VbaModuleKind,
+}
+
+impl VbaModule {
+    /// The host object the module belongs to, for a document module.
+    pub fn document_object(&self) -> Option<&str> {
+        (self.kind == VbaModuleKind::Document).then_some(self.name.as_str())
+    }
+}
+
+/// What a reference points at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VbaReferenceKind {
+    /// a type library registered on the machine, such as stdole2.tlb, scrrun.dll or wshom.ocx
+    Registered,
+    /// another VBA project, such as the Normal template or an add-in
+    Project {
+        /// the path of the project relative to this one
+        relative_libid: String,
+    },
+    /// the type library of an ActiveX control
+    Control {
+        /// the library the reference to the control was made from
+        original_libid: Option<String>,
+        /// the extended type library, where the control's own definitions live
+        extended_libid: String,
+    },
+}
+
+/// A reference of a VBA project to a type library or another project.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VbaReference {
+    pub name: Option<String>,
+    /// the registered libid, `*\G{GUID}#version#lcid#path#description`, or the absolute path
+    /// of a project, `*\C` followed by the path
+    pub libid: String,
+    pub kind: VbaReferenceKind,
+}
+
+impl VbaReference {
+    /// The file paths in the libids: of the library, the project, or the control's libraries.
+    ///
+    /// They are paths on the machine the project was last saved on.
+    pub fn paths(&self) -> Vec<&str> {
+        let mut libids = vec![self.libid.as_str()];
+        match &self.kind {
+            VbaReferenceKind::Registered => {}
+            VbaReferenceKind::Project { relative_libid } => libids.push(relative_libid),
+            VbaReferenceKind::Control {
+                original_libid,
+                extended_libid,
+            } => {
+                libids.extend(original_libid.as_deref());
+                libids.push(extended_libid);
+            }
+        }
+        libids.into_iter().filter_map(libid_path).collect()
+    }
+}
+
+/// A procedure of a VBA module that runs without anyone calling it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AutoExec {
+    /// the storage of the project, as in [`VbaProject::storage`]
+    pub storage: Vec<String>,
+    pub module: String,
+    /// the name as the source declares it
+    pub procedure: String,
+}
+
+/// A VBA project: a storage holding a `VBA` storage with a `dir` stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VbaProject {
+    /// the storage of the project, `Macros` in Word, `_VBA_PROJECT_CUR` in Excel and empty when
+    /// it is the root
+    pub storage: Vec<String>,
+    /// the code page the names are stored in
+    pub code_page: u16,
+    pub references: Vec<VbaReference>,
+    pub modules: Vec<VbaModule>,
+}
+
+pub(crate) fn vba_projects(ole: &OleFile) -> Result<Vec<VbaProject>> {
+    let mut projects = vec![];
+    for (path, entry) in analysis::entries_with_paths(ole) {
+        let is_dir = entry.object_type == ObjectType::Stream
+            && path.len() >= 2
+            && path[path.len() - 1].eq_ignore_ascii_case("dir")
+            && path[path.len() - 2].eq_ignore_ascii_case("VBA");
+        if !is_dir {
+            continue;
+        }
+        let dir = decompress(&ole.read_entry_data(entry)?)?;
+        let storage = path[..path.len() - 2].to_vec();
+        let mut project_path = storage.iter().map(String::as_str).collect::<Vec<_>>();
+        project_path.push("PROJECT");
+        let listed = match ole.open_stream(&project_path) {
+            Ok(data) => project_modules(&data),
+            Err(Error::OleDirectoryEntryNotFound | Error::IsAStorage { .. }) => vec![],
+            Err(err) => return Err(err),
+        };
+        projects.push(parse_dir(&dir, &listed, storage)?);
+    }
+    Ok(projects)
+}
+
+/// The source of `module`: its stream from the text offset on, decompressed. Bytes past ASCII
+/// are taken as Latin-1 whatever the project's code page.
+pub(crate) fn module_source(
+    ole: &OleFile,
+    project: &VbaProject,
+    module: &VbaModule,
+) -> Result<String> {
+    let mut path = project
+        .storage
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    path.extend(["VBA", module.stream_name.as_str()]);
+    let data = ole.open_stream(&path)?;
+    let compressed = data.get(module.text_offset as usize..).ok_or_else(|| {
+        Error::InvalidVbaProject(format!(
+            "text offset {} of {} is past the end of its stream",
+            module.text_offset, module.name
+        ))
+    })?;
+    Ok(latin1(&decompress(compressed)?))
+}
+
+/// The auto-run procedures declared in the modules of every VBA project of `ole`.
+pub(crate) fn autoexec(ole: &OleFile) -> Result<Vec<AutoExec>> {
+    let mut found = vec![];
+    for project in vba_projects(ole)? {
+        for module in &project.modules {
+            let source = module_source(ole, &project, module)?;
+            found.extend(
+                autoexec_procedures(&source)
+                    .into_iter()
+                    .map(|procedure| AutoExec {
+                        storage: project.storage.clone(),
+                        module: module.name.clone(),
+                        procedure,
+                    }),
+            );
+        }
+    }
+    Ok(found)
+}
+
+/// The names of the `Sub`s and `Function`s declared in `source` that Office runs by itself.
+fn autoexec_procedures(source: &str) -> Vec<String> {
+    let mut procedures = vec![];
+    for line in source.lines() {
+        let mut words = line.split_whitespace().peekable();
+        if let Some(scope) = words.peek() {
+            if ["Public", "Private", "Static"]
+                .iter()
+                .any(|keyword| scope.eq_ignore_ascii_case(keyword))
+            {
+                words.next();
+            }
+        }
+        let is_procedure = words.next().is_some_and(|keyword| {
+            keyword.eq_ignore_ascii_case("Sub") || keyword.eq_ignore_ascii_case("Function")
+        });
+        let name = match words.next() {
+            Some(name) if is_procedure => name.split('(').next().unwrap_or_default(),
+            _ => continue,
+        };
+        if AUTOEXEC_PROCEDURES
+            .iter()
+            .any(|autoexec| name.eq_ignore_ascii_case(autoexec))
+            && !procedures.iter().any(|found| found == name)
+        {
+            procedures.push(name.to_string());
+        }
+    }
+    procedures
+}
+
+/// The project described by a decompressed `dir` stream.
+fn parse_dir(
+    dir: &[u8],
+    listed: &[(String, VbaModuleKind)],
+    storage: Vec<String>,
+) -> Result<VbaProject> {
+    let mut code_page = 0;
+    let mut references = vec![];
+    let mut modules = vec![];
+    // the reference being read: its name and original libid, and whether the records are
+    // inside a REFERENCECONTROL, whose extended name isn't the reference's
+    let mut reference_name = None;
+    let mut original_libid = None;
+    let mut in_control = false;
+    // the module being read: name, Unicode name, stream name, offset, procedural
+    let mut name = None;
+    let mut unicode_name = None;
+    let mut stream_name = None;
+    let mut text_offset = 0;
+    let mut procedural = false;
+    for (id, data) in records(dir)? {
+        match id {
+            PROJECT_CODE_PAGE if data.len() >= 2 => {
+                code_page = u16::from_le_bytes([data[0], data[1]]);
+            }
+            REFERENCE_NAME if !in_control => reference_name = Some(latin1(data)),
+            REFERENCE_NAME_UNICODE if !in_control => reference_name = Some(utf16(data)),
+            REFERENCE_ORIGINAL => original_libid = Some(latin1(data)),
+            REFERENCE_REGISTERED => references.push(VbaReference {
+                name: reference_name.take(),
+                libid: counted(data, 0).unwrap_or_default().0,
+                kind: VbaReferenceKind::Registered,
+            }),
+            REFERENCE_PROJECT => {
+                let (libid, next) = counted(data, 0).unwrap_or_default();
+                let relative_libid = counted(data, next).unwrap_or_default().0;
+                references.push(VbaReference {
+                    name: reference_name.take(),
+                    libid,
+                    kind: VbaReferenceKind::Project { relative_libid },
+                });
+            }
+            REFERENCE_CONTROL => {
+                references.push(VbaReference {
+                    name: reference_name.take(),
+                    libid: counted(data, 0).unwrap_or_default().0,
+                    kind: VbaReferenceKind::Control {
+                        original_libid: original_libid.take(),
+                        extended_libid: String::new(),
+                    },
+                });
+                in_control = true;
+            }
+            REFERENCE_CONTROL_EXTENDED => {
+                if let Some(VbaReference {
+                    kind: VbaReferenceKind::Control { extended_libid, .. },
+                    ..
+                }) = references.last_mut()
+                {
+                    *extended_libid = counted(data, 0).unwrap_or_default().0;
+                }
+                in_control = false;
+            }
+            MODULE_NAME => name = Some(latin1(data)),
+            MODULE_NAME_UNICODE => unicode_name = Some(utf16(data)),
+            MODULE_STREAM_NAME => stream_name = Some(latin1(data)),
+            MODULE_STREAM_NAME_UNICODE => stream_name = Some(utf16(data)),
+            MODULE_OFFSET if data.len() >= 4 => {
+                text_offset = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+            }
+            MODULE_TYPE_PROCEDURAL => procedural = true,
+            MODULE_TYPE_OTHER => procedural = false,
+            MODULE_TERMINATOR => {
+                let name = unicode_name.take().or(name.take()).unwrap_or_default();
+                let kind = if procedural {
+                    VbaModuleKind::Standard
+                } else {
+                    listed
+                        .iter()
+                        .find(|(listed, _)| listed.eq_ignore_ascii_case(&name))
+                        .map(|(_, kind)| *kind)
+                        .filter(|kind| *kind != VbaModuleKind::Standard)
+                        .unwrap_or(VbaModuleKind::Unlisted)
+                };
+                modules.push(VbaModule {
+                    stream_name: stream_name.take().unwrap_or_else(|| name.clone()),
+                    name,
+                    text_offset,
+                    kind,
+                });
+                text_offset = 0;
+                procedural = false;
+            }
+            _ => {}
+        }
+    }
+    Ok(VbaProject {
+        storage,
+        code_page,
+        references,
+        modules,
+    })
+}
+
+/// The string at `position` in `data` behind its 32-bit length, and where it ends.
+fn counted(data: &[u8], position: usize) -> Option<(String, usize)> {
+    let len = data.get(position..position + 4)?;
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    let text = data.get(position + 4..)?.get(..len)?;
+    Some((latin1(text), position + 4 + len))
+}
+
+/// The file path in a libid: the fourth field of a registered libid, what follows the kind of a
+/// project's.
+fn libid_path(libid: &str) -> Option<&str> {
+    let rest = libid.strip_prefix("*\\")?;
+    let rest = rest.get(rest.chars().next()?.len_utf8()..)?;
+    let path = if rest.starts_with('{') {
+        rest.split('#').nth(3)?
+    } else {
+        rest
+    };
+    (!path.is_empty()).then_some(path)
+}
+
+/// The records of a decompressed `dir` stream, ids and data.
+fn records(dir: &[u8]) -> Result<Vec<(u16, &[u8])>> {
+    let mut records = vec![];
+    let mut position = 0;
+    while position < dir.len() {
+        let header = dir.get(position..position + 6).ok_or_else(|| {
+            Error::InvalidVbaProject(format!("dir record header cut short at {}", position))
+        })?;
+        let id = u16::from_le_bytes([header[0], header[1]]);
+        let size = if id == PROJECT_VERSION {
+            6
+        } else {
+            u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize
+        };
+        let data = dir
+            .get(position + 6..)
+            .and_then(|rest| rest.get(..size))
+            .ok_or_else(|| {
+                Error::InvalidVbaProject(format!(
+                    "dir record {:#06x} at {} runs past the end",
+                    id, position
+                ))
+            })?;
+        records.push((id, data));
+        position += 6 + size;
+    }
+    Ok(records)
+}
+
+/// The modules listed in a `PROJECT` stream, by the key they are listed under.
+fn project_modules(data: &[u8]) -> Vec<(String, VbaModuleKind)> {
+    latin1(data)
+        .lines()
+        // the host extenders and workspace sections come after the module list
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let kind = match key.trim() {
+                "Module" => VbaModuleKind::Standard,
+                "Class" => VbaModuleKind::Class,
+                "BaseClass" => VbaModuleKind::Form,
+                // followed by the version of the host object, `ThisDocument/&H00000000`
+                "Document" => VbaModuleKind::Document,
+                _ => return None,
+            };
+            let name = value.split('/').next().unwrap_or_default().trim();
+            Some((name.to_string(), kind))
+        })
+        .collect()
+}
+
+/// Decompress a container compressed as described in MS-OVBA 2.4.1.
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    match data.first() {
+        Some(1) => {}
+        _ => {
+            return Err(Error::InvalidVbaProject(
+                "compressed container doesn't start with 0x01".to_string(),
+            ))
+        }
+    }
+    let mut output = Vec::with_capacity(data.len() * 2);
+    let mut position = 1;
+    while position + 2 <= data.len() {
+        let header = u16::from_le_bytes([data[position], data[position + 1]]);
+        let chunk_end = (position + (header & 0x0FFF) as usize + 3).min(data.len());
+        position += 2;
+        if header & 0x8000 == 0 {
+            let raw = data.get(position..position + CHUNK_SIZE).ok_or_else(|| {
+                Error::InvalidVbaProject(format!("uncompressed chunk cut short at {}", position))
+            })?;
+            output.extend_from_slice(raw);
+            position += CHUNK_SIZE;
+            continue;
+        }
+        let chunk_start = output.len();
+        while position < chunk_end {
+            let flags = data[position];
+            position += 1;
+            for bit in 0..8 {
+                if position >= chunk_end {
+                    break;
+                }
+                if flags & (1 << bit) == 0 {
+                    output.push(data[position]);
+                    position += 1;
+                    continue;
+                }
+                let token = data
+                    .get(position..position + 2)
+                    .map(|token| u16::from_le_bytes([token[0], token[1]]))
+                    .ok_or_else(|| {
+                        Error::InvalidVbaProject(format!("copy token cut short at {}", position))
+                    })?;
+                position += 2;
+                let decompressed = output.len() - chunk_start;
+                // the offset takes as many bits as it needs to reach the chunk start, 4 at least
+                let bit_count =
+                    (usize::BITS - decompressed.saturating_sub(1).leading_zeros()).max(4);
+                let length_mask = 0xFFFF >> bit_count;
+                let length = (token & length_mask) as usize + 3;
+                let offset = (token >> (16 - bit_count)) as usize + 1;
+                if offset > decompressed {
+                    return Err(Error::InvalidVbaProject(format!(
+                        "copy token at {} reaches before its chunk",
+                        position - 2
+                    )));
+                }
+                let source = output.len() - offset;
+                // the copy can overlap what it produces, so byte by byte
+                for index in 0..length {
+                    output.push(output[source + index]);
+                }
+            }
+        }
+        position = chunk_end;
+    }
+    Ok(output)
+}
+
+/// Bytes of an MBCS string, each taken as the character with the same code.
+fn latin1(data: &[u8]) -> String {
+    data.iter().map(|byte| *byte as char).collect()
+}
+
+fn utf16(data: &[u8]) -> String {
+    let units = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    /// A compressed container holding `data`, full chunks stored raw and the last one as
+    /// literals, which fit as long as it is under 3640 bytes.
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut container = vec![1];
+        for chunk in data.chunks(CHUNK_SIZE) {
+            if chunk.len() == CHUNK_SIZE {
+                container.extend(0x3FFFu16.to_le_bytes());
+                container.extend(chunk);
+                continue;
+            }
+            let mut tokens = vec![];
+            for group in chunk.chunks(8) {
+                tokens.push(0);
+                tokens.extend(group);
+            }
+            let header = 0xB000 | (tokens.len() + 2 - 3) as u16;
+            container.extend(header.to_le_bytes());
+            container.extend(tokens);
+        }
+        container
+    }
+
+    fn record(id: u16, data: &[u8]) -> Vec<u8> {
+        let mut record = id.to_le_bytes().to_vec();
+        record.extend((data.len() as u32).to_le_bytes());
+        record.extend(data);
+        record
+    }
+
+    fn counted(text: &str) -> Vec<u8> {
+        let mut counted = (text.len() as u32).to_le_bytes().to_vec();
+        counted.extend(text.as_bytes());
+        counted
+    }
+
+    /// A `dir` stream with the given reference records, listing modules by name and MODULETYPE.
+    fn dir(references: &[u8], modules: &[(&str, u16)]) -> Vec<u8> {
+        let mut dir = record(0x0001, &1u32.to_le_bytes());
+        dir.extend(record(PROJECT_CODE_PAGE, &1252u16.to_le_bytes()));
+        dir.extend([0x09, 0, 4, 0, 0, 0, 0x5E, 0, 0, 0, 3, 0]);
+        dir.extend(references);
+        dir.extend(record(0x000F, &(modules.len() as u16).to_le_bytes()));
+        for (position, (name, module_type)) in modules.iter().enumerate() {
+            dir.extend(record(MODULE_NAME, name.as_bytes()));
+            let unicode = name
+                .encode_utf16()
+                .flat_map(|unit| unit.to_le_bytes())
+                .collect::<Vec<_>>();
+            dir.extend(record(MODULE_NAME_UNICODE, &unicode));
+            dir.extend(record(MODULE_STREAM_NAME, name.as_bytes()));
+            dir.extend(record(MODULE_STREAM_NAME_UNICODE, &unicode));
+            dir.extend(record(
+                MODULE_OFFSET,
+                &(position as u32 * 100).to_le_bytes(),
+            ));
+            dir.extend(record(*module_type, &[]));
+            dir.extend(record(MODULE_TERMINATOR, &[]));
+        }
+        dir.extend(record(0x0010, &[]));
+        dir
+    }
+
+    #[test]
+    fn test_autoexec_procedures() {
+        let source = "Private Sub Document_Open()\r\n\
+            End Sub\r\n\
+            Public Function autoopen(x As Long)\r\n\
+            End Function\r\n\
+            ' Sub AutoClose()\r\n\
+            Sub AutoOpenLater()\r\n\
+            End Sub\r\n\
+            Call AutoExec\r\n\
+            Sub Workbook_Open\r\n";
+        assert_eq!(
+            autoexec_procedures(source),
+            ["Document_Open", "autoopen", "Workbook_Open"]
+        );
+    }
+
+    #[test]
+    fn test_decompress() {
+        // the example of MS-OVBA 3.2.3, with copy tokens
+        let compressed = [
+            0x01, 0x2F, 0xB0, 0x00, 0x23, 0x61, 0x61, 0x61, 0x62, 0x63, 0x64, 0x65, 0x82, 0x66,
+            0x00, 0x70, 0x61, 0x67, 0x68, 0x69, 0x6A, 0x01, 0x38, 0x08, 0x61, 0x6B, 0x6C, 0x00,
+            0x30, 0x6D, 0x6E, 0x6F, 0x70, 0x06, 0x71, 0x02, 0x70, 0x04, 0x10, 0x72, 0x73, 0x74,
+            0x75, 0x76, 0x10, 0x77, 0x78, 0x79, 0x7A, 0x00, 0x3C,
+        ];
+        assert_eq!(
+            decompress(&compressed).unwrap(),
+            b"#aaabcdefaaaaghijaaaaaklaaamnopqaaaaaaaaaaaarstuvwxyzaaa"
+        );
+        let long = (0..8292).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        assert_eq!(decompress(&compress(&long)).unwrap(), long);
+        assert!(decompress(b"\x02abc").is_err());
+        // a copy token reaching back before anything was decompressed
+        assert!(decompress(&[0x01, 0x03, 0xB0, 0x01, 0x00, 0x10]).is_err());
+    }
+
+    #[test]
+    fn test_vba_projects() {
+        let project = "ID=\"{00000000-0000-0000-0000-000000000000}\"\r\n\
+            Document=ThisDocument/&H00000000\r\n\
+            Module=Module1\r\n\
+            Class=Class1\r\n\
+            BaseClass=UserForm1\r\n\
+            Name=\"Project\"\r\n\
+            \r\n\
+            [Workspace]\r\n\
+            ThisDocument=0, 0, 0, 0, C\r\n";
+        let dir = dir(
+            &[],
+            &[
+                ("ThisDocument", MODULE_TYPE_OTHER),
+                ("Module1", MODULE_TYPE_PROCEDURAL),
+                ("Class1", MODULE_TYPE_OTHER),
+                ("UserForm1", MODULE_TYPE_OTHER),
+                ("Hidden", MODULE_TYPE_OTHER),
+            ],
+        );
+        let data = CfbBuilder::new()
+            .stream("WordDocument", &[0; 100])
+            .stream("Macros/PROJECT", project.as_bytes())
+            .stream("Macros/VBA/dir", &compress(&dir))
+            .stream("Macros/VBA/ThisDocument", b"code")
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let projects = ole.vba_projects().unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].storage, vec!["Macros"]);
+        assert_eq!(projects[0].code_page, 1252);
+        let kinds = projects[0]
+            .modules
+            .iter()
+            .map(|module| (module.name.as_str(), module.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ("ThisDocument", VbaModuleKind::Document),
+                ("Module1", VbaModuleKind::Standard),
+                ("Class1", VbaModuleKind::Class),
+                ("UserForm1", VbaModuleKind::Form),
+                ("Hidden", VbaModuleKind::Unlisted),
+            ]
+        );
+        let this_document = &projects[0].modules[0];
+        assert_eq!(this_document.document_object(), Some("ThisDocument"));
+        assert_eq!(this_document.stream_name, "ThisDocument");
+        assert_eq!(projects[0].modules[1].text_offset, 100);
+        assert_eq!(projects[0].modules[1].document_object(), None);
+
+        // without a PROJECT stream, only the procedural modules are known for sure
+        let data = CfbBuilder::new()
+            .stream("_VBA_PROJECT_CUR/VBA/dir", &compress(&dir))
+            .build();
+        let projects = OleFile::parse_sync(&data[..])
+            .unwrap()
+            .vba_projects()
+            .unwrap();
+        assert_eq!(projects[0].storage, vec!["_VBA_PROJECT_CUR"]);
+        assert_eq!(projects[0].modules[0].kind, VbaModuleKind::Unlisted);
+        assert_eq!(projects[0].modules[1].kind, VbaModuleKind::Standard);
+    }
+
+    #[test]
+    fn test_truncated_dir() {
+        let mut dir = dir(&[], &[("Module1", MODULE_TYPE_PROCEDURAL)]);
+        dir.truncate(dir.len() - 20);
+        assert!(matches!(
+            parse_dir(&dir, &[], vec![]),
+            Err(Error::InvalidVbaProject(_))
+        ));
+    }
+
+    #[test]
+    fn test_vba_references() {
+        let stdole = "*\\G{00020430-0000-0000-C000-000000000046}#2.0#0#C:\\Windows\\System32\\stdole2.tlb#OLE Automation";
+        let scrrun = "*\\G{420B2830-E718-11CF-893D-00A0C9054228}#1.0#0#C:\\Windows\\System32\\scrrun.dll#Microsoft Scripting Runtime";
+        let forms = "*\\G{0D452EE1-E08F-101A-852E-02608C4D0BB4}#2.0#0#C:\\Windows\\System32\\FM20.DLL#Microsoft Forms 2.0 Object Library";
+        let extended = "*\\G{6C2B7C8E-1F5B-4B27-A5C2-6B4E30F3B1F2}#2.0#0#C:\\Users\\build\\AppData\\Local\\Temp\\Word8.0\\MSForms.exd#Microsoft Forms 2.0 Object Library";
+
+        let mut references = record(REFERENCE_NAME, b"stdole");
+        references.extend(record(REFERENCE_NAME_UNICODE, &[b's', 0, b't', 0, b'd', 0]));
+        references.extend(record(
+            REFERENCE_REGISTERED,
+            &[&counted(stdole)[..], &[0; 6]].concat(),
+        ));
+        references.extend(record(
+            REFERENCE_REGISTERED,
+            &[&counted(scrrun)[..], &[0; 6]].concat(),
+        ));
+        references.extend(record(REFERENCE_NAME, b"Normal"));
+        references.extend(record(
+            REFERENCE_PROJECT,
+            &[
+                &counted("*\\CC:\\Users\\build\\Templates\\Normal.dotm")[..],
+                &counted("*\\CNormal.dotm"),
+                &[0; 6],
+            ]
+            .concat(),
+        ));
+        references.extend(record(REFERENCE_NAME, b"MSForms"));
+        references.extend(record(REFERENCE_ORIGINAL, forms.as_bytes()));
+        references.extend(record(
+            REFERENCE_CONTROL,
+            &[
+                &counted("*\\G{00000000-0000-0000-0000-000000000000}#2.0#0##")[..],
+                &[0; 6],
+            ]
+            .concat(),
+        ));
+        references.extend(record(REFERENCE_NAME, b"extended name"));
+        references.extend(record(
+            REFERENCE_CONTROL_EXTENDED,
+            &[&counted(extended)[..], &[0; 26]].concat(),
+        ));
+        let dir = dir(&references, &[("Module1", MODULE_TYPE_PROCEDURAL)]);
+        let project = parse_dir(&dir, &[], vec![]).unwrap();
+        assert_eq!(
+            project.references,
+            vec![
+                VbaReference {
+                    name: Some("std".to_string()),
+                    libid: stdole.to_string(),
+                    kind: VbaReferenceKind::Registered,
+                },
+                VbaReference {
+                    name: None,
+                    libid: scrrun.to_string(),
+                    kind: VbaReferenceKind::Registered,
+                },
+                VbaReference {
+                    name: Some("Normal".to_string()),
+                    libid: "*\\CC:\\Users\\build\\Templates\\Normal.dotm".to_string(),
+                    kind: VbaReferenceKind::Project {
+                        relative_libid: "*\\CNormal.dotm".to_string()
+                    },
+                },
+                VbaReference {
+                    name: Some("MSForms".to_string()),
+                    libid: "*\\G{00000000-0000-0000-0000-000000000000}#2.0#0##".to_string(),
+                    kind: VbaReferenceKind::Control {
+                        original_libid: Some(forms.to_string()),
+                        extended_libid: extended.to_string(),
+                    },
+                },
+            ]
+        );
+        assert_eq!(project.modules.len(), 1);
+        assert_eq!(
+            project.references[1].paths(),
+            vec!["C:\\Windows\\System32\\scrrun.dll"]
+        );
+        assert_eq!(
+            project.references[2].paths(),
+            vec!["C:\\Users\\build\\Templates\\Normal.dotm", "Normal.dotm"]
+        );
+        assert_eq!(
+            project.references[3].paths(),
+            vec![
+                "C:\\Windows\\System32\\FM20.DLL",
+                "C:\\Users\\build\\AppData\\Local\\Temp\\Word8.0\\MSForms.exd"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_module_source() {
+        let source = "Attribute VB_Name = \"Module1\"\r\n\
+            Sub AutoOpen()\r\n\
+            Shell StrReverse(\"exe.clac\")\r\n\
+            End Sub\r\n";
+        // the second module's source starts at offset 100, behind its p-code
+        let mut second = vec![0xCC; 100];
+        second.extend(compress(b"Sub Nothing()\r\nEnd Sub\r\n"));
+        let dir = dir(
+            &[],
+            &[
+                ("Module1", MODULE_TYPE_PROCEDURAL),
+                ("Module2", MODULE_TYPE_PROCEDURAL),
+            ],
+        );
+        let data = CfbBuilder::new()
+            .stream("Macros/VBA/dir", &compress(&dir))
+            .stream("Macros/VBA/Module1", &compress(source.as_bytes()))
+            .stream("Macros/VBA/Module2", &second)
+            .build();
+        let ole = OleFile::parse_sync(&data[..]).unwrap();
+        let project = &ole.vba_projects().unwrap()[0];
+        assert_eq!(
+            ole.vba_module_source(project, &project.modules[0]).unwrap(),
+            source
+        );
+        assert_eq!(
+            ole.vba_module_source(project, &project.modules[1]).unwrap(),
+            "Sub Nothing()\r\nEnd Sub\r\n"
+        );
+
+        let modules = ole.deobfuscate_vba().unwrap();
+        assert_eq!(modules.len(), 2);
+        assert_eq!(modules[0].storage, vec!["Macros"]);
+        assert_eq!(modules[0].module.name, "Module1");
+        assert_eq!(modules[0].source, source);
+        assert_eq!(modules[0].recovered.len(), 1);
+        assert_eq!(modules[0].recovered[0].value, "calc.exe");
+        assert_eq!(modules[0].recovered[0].line, 3);
+        assert!(modules[1].recovered.is_empty());
+
+        assert_eq!(
+            ole.vba_autoexec().unwrap(),
+            [AutoExec {
+                storage: vec!["Macros".to_string()],
+                module: "Module1".to_string(),
+                procedure: "AutoOpen".to_string(),
+            }]
+        );
+
+        let mut module = project.modules[1].clone();
+        module.text_offset = 1000;
+        assert!(matches!(
+            ole.vba_module_source(project, &module),
+            Err(Error::InvalidVbaProject(_))
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+//! What parsing let through instead of failing, in a form programs can act on.
+use crate::error::Location;
+
+/// An anomaly parsing got past, see [`crate::OleFile::warnings`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseWarning {
+    pub kind: ParseWarningKind,
+    /// what was found, for people
+    pub message: String,
+    /// where in the file it was found, as far as known
+    pub location: Location,
+}
+
+/// What kind of anomaly a [`ParseWarning`] is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ParseWarningKind {
+    /// a header field breaks the spec or disagrees with the file, by the name of the field
+    HeaderField(&'static str),
+    /// the minor version isn't 0x003E, which the spec only recommends
+    MinorVersion,
+    /// the mini stream cutoff isn't 4096
+    MiniStreamCutoff,
+    /// bytes after the header in the first sector of a version 4 file aren't zero
+    HeaderSlack,
+    /// the last sector is cut short and was padded with zeros
+    TruncatedSector,
+    /// the mini stream ends inside its last mini sector, the rest of which is read from the
+    /// sector holding it
+    TruncatedMiniStream,
+    /// a directory entry has a name the spec forbids, or one with a bad length or terminator
+    IllegalName,
+}
+
+impl ParseWarning {
+    pub(crate) fn new(kind: ParseWarningKind, message: String) -> Self {
+        ParseWarning {
+            kind,
+            message,
+            location: Location::default(),
+        }
+    }
+
+    pub(crate) fn at(mut self, location: Location) -> Self {
+        self.location = location;
+        self
+    }
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
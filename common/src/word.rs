@@ -0,0 +1,421 @@
+//! Structures of Word 97-2003 documents that live outside the VBA project.
+use crate::{error::Error, OleFile, Result};
+
+/// offset of `cbRgFcLcb`, the number of fc/lcb pairs that follow it (MS-DOC 2.5.1)
+const CB_RG_FC_LCB_OFFSET: usize = 0x98;
+/// offset of the first fc/lcb pair, FibRgFcLcb97 starts here (MS-DOC 2.5.6)
+const RG_FC_LCB_OFFSET: usize = 0x9A;
+/// pairs of FibRgFcLcb97 that are read here
+const PLCF_BTE_CHPX: usize = 12;
+const STW_USER: usize = 60;
+/// bit of the flags at 0x0A telling which table stream is in use
+const F_WHICH_TBL_STM: u16 = 0x0200;
+
+/// size of the pages holding character formatting (ChpxFkp)
+const FKP_SIZE: usize = 512;
+/// sprmCPicLocation: where the picture or OLE object of a character is
+const SPRM_C_PIC_LOCATION: u16 = 0x6A03;
+/// sprmCFOle2: the character is an OLE object
+const SPRM_C_F_OLE2: u16 = 0x080A;
+
+/// A document variable, set through `ActiveDocument.Variables` and kept in the table stream.
+///
+/// Macro droppers use them to stage payload strings outside the VBA project.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocVar {
+    pub name: String,
+    pub value: String,
+}
+
+/// A picture or OLE object anchored in the text of a Word document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WordObject {
+    /// a PICFAndOfficeArtData record in the `Data` stream, `data` holds the whole record
+    Picture { data_offset: u32, data: Vec<u8> },
+    /// an OLE object, stored in the `ObjectPool` storage under `storage`
+    Ole { storage: String },
+}
+
+/// The start of the FIB, with the pairs of offset and length into the table stream.
+struct Fib {
+    data: Vec<u8>,
+}
+
+impl Fib {
+    /// `None` if the file is not a Word document or its content can't be read.
+    fn read(ole: &OleFile) -> Result<Option<Self>> {
+        if ole.encrypted {
+            return Ok(None);
+        }
+        let len = RG_FC_LCB_OFFSET + (STW_USER + 1) * 8;
+        match ole.open_stream_range(&["WordDocument"], 0, len) {
+            Ok(data) => Ok(Some(Self { data })),
+            Err(Error::OleDirectoryEntryNotFound | Error::IsAStorage { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        u16_at(&self.data, offset)
+    }
+
+    /// The fc/lcb pair at `index`, `None` when the FIB is too old to have it or it is empty.
+    fn fc_lcb(&self, index: usize) -> Option<(u32, u32)> {
+        // older FIBs don't have all the fields
+        if (self.u16_at(CB_RG_FC_LCB_OFFSET)? as usize) <= index {
+            return None;
+        }
+        let offset = RG_FC_LCB_OFFSET + index * 8;
+        match (u32_at(&self.data, offset)?, u32_at(&self.data, offset + 4)?) {
+            (_, 0) => None,
+            pair => Some(pair),
+        }
+    }
+
+    fn table_stream(&self) -> &'static str {
+        if self.u16_at(0x0A).unwrap_or(0) & F_WHICH_TBL_STM != 0 {
+            "1Table"
+        } else {
+            "0Table"
+        }
+    }
+
+    /// Read the structure at `index` from the table stream.
+    fn table_data(&self, ole: &OleFile, index: usize) -> Result<Option<Vec<u8>>> {
+        match self.fc_lcb(index) {
+            Some((fc, lcb)) => Ok(Some(ole.open_stream_range(
+                &[self.table_stream()],
+                fc as u64,
+                lcb as usize,
+            )?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Read the document variables (the StwUser structure, MS-DOC 2.9.303) of a Word document.
+///
+/// A document without any gives an empty list, as does a file that is not a Word document or
+/// one whose table stream is encrypted.
+pub(crate) fn doc_vars(ole: &OleFile) -> Result<Vec<DocVar>> {
+    let fib = match Fib::read(ole)? {
+        Some(fib) => fib,
+        None => return Ok(vec![]),
+    };
+    match fib.table_data(ole, STW_USER)? {
+        Some(data) => parse_stw_user(&data),
+        None => Ok(vec![]),
+    }
+}
+
+/// Find the pictures and OLE objects of a Word document.
+///
+/// They are referenced from the character formatting: the text runs carrying a
+/// sprmCPicLocation point either into the `Data` stream or, for OLE objects, at a storage in the
+/// `ObjectPool`. The runs are found through the PlcBteChpx in the table stream and the ChpxFkp
+/// pages in the WordDocument stream (MS-DOC 2.8.14 and 2.9.44).
+pub(crate) fn word_objects(ole: &OleFile) -> Result<Vec<WordObject>> {
+    let fib = match Fib::read(ole)? {
+        Some(fib) => fib,
+        None => return Ok(vec![]),
+    };
+    let plc = match fib.table_data(ole, PLCF_BTE_CHPX)? {
+        Some(plc) => plc,
+        None => return Ok(vec![]),
+    };
+    // n + 1 file offsets, then n page numbers
+    let runs = plc.len().saturating_sub(4) / 8;
+    let mut objects = vec![];
+    for run in 0..runs {
+        let page = match u32_at(&plc, (runs + 1) * 4 + run * 4) {
+            Some(pn) => pn & 0x003F_FFFF,
+            None => break,
+        };
+        let fkp =
+            ole.open_stream_range(&["WordDocument"], page as u64 * FKP_SIZE as u64, FKP_SIZE)?;
+        if fkp.len() < FKP_SIZE {
+            return Err(Error::unexpected_eof(format!(
+                "character formatting page {} is beyond the WordDocument stream",
+                page
+            )));
+        }
+        let count = fkp[FKP_SIZE - 1] as usize;
+        for position in 0..count {
+            let offset = match fkp.get((count + 1) * 4 + position) {
+                Some(0) | None => continue,
+                Some(word_offset) => *word_offset as usize * 2,
+            };
+            let chpx_len = fkp[offset] as usize;
+            let grpprl = match fkp.get(offset + 1..offset + 1 + chpx_len) {
+                Some(grpprl) => grpprl,
+                None => continue,
+            };
+            if let Some(object) = object_of_run(ole, grpprl)? {
+                if !objects.contains(&object) {
+                    objects.push(object);
+                }
+            }
+        }
+    }
+    Ok(objects)
+}
+
+/// The object a run of text is formatted as, from the sprms in its `grpprl`.
+fn object_of_run(ole: &OleFile, grpprl: &[u8]) -> Result<Option<WordObject>> {
+    let mut location = None;
+    let mut is_ole = false;
+    for (sprm, operand) in sprms(grpprl) {
+        match sprm {
+            SPRM_C_PIC_LOCATION => location = u32_at(operand, 0),
+            SPRM_C_F_OLE2 => is_ole = operand.first() == Some(&1),
+            _ => {}
+        }
+    }
+    let location = match location {
+        Some(location) => location,
+        None => return Ok(None),
+    };
+    if is_ole {
+        return Ok(Some(WordObject::Ole {
+            storage: format!("_{}", location),
+        }));
+    }
+    // PICFAndOfficeArtData starts with its own length
+    let len = ole.open_stream_range(&["Data"], location as u64, 4)?;
+    let len = match u32_at(&len, 0) {
+        Some(len) => len,
+        None => return Ok(None),
+    };
+    Ok(Some(WordObject::Picture {
+        data_offset: location,
+        data: ole.open_stream_range(&["Data"], location as u64, len as usize)?,
+    }))
+}
+
+/// The sprms of a grpprl with their operands (MS-DOC 2.2.5.1), stopping at the first one that
+/// doesn't fit.
+fn sprms(mut grpprl: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut sprms = vec![];
+    while let Some(sprm) = u16_at(grpprl, 0) {
+        let (operand_offset, operand_len) = match sprm >> 13 {
+            0 | 1 => (2, 1),
+            2 | 4 | 5 => (2, 2),
+            3 => (2, 4),
+            7 => (2, 3),
+            // variable length, prefixed by its size
+            _ => match grpprl.get(2) {
+                Some(len) => (3, *len as usize),
+                None => break,
+            },
+        };
+        match grpprl.get(operand_offset..operand_offset + operand_len) {
+            Some(operand) => sprms.push((sprm, operand)),
+            None => break,
+        }
+        grpprl = &grpprl[operand_offset + operand_len..];
+    }
+    sprms
+}
+
+/// StwUser: an STTB of variable names, followed by one Xstz value per name.
+fn parse_stw_user(data: &[u8]) -> Result<Vec<DocVar>> {
+    let mut reader = Utf16Reader { data, position: 0 };
+    if reader.u16()? != 0xFFFF {
+        return Err(Error::GenericError(
+            "document variable names are not extended characters",
+        ));
+    }
+    let count = reader.u16()?;
+    let extra = reader.u16()? as usize;
+    let mut names = vec![];
+    for _ in 0..count {
+        let len = reader.u16()? as usize;
+        names.push(reader.string(len)?);
+        reader.skip(extra)?;
+    }
+    names
+        .into_iter()
+        .map(|name| {
+            let len = reader.u16()? as usize;
+            let value = reader.string(len)?;
+            // Xstz strings end with a null character
+            reader.skip(2)?;
+            Ok(DocVar { name, value })
+        })
+        .collect()
+}
+
+struct Utf16Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Utf16Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.saturating_add(len))
+            .ok_or_else(|| {
+                Error::unexpected_eof(format!(
+                    "document variables end before offset {}",
+                    self.position + len
+                ))
+            })?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn string(&mut self, chars: usize) -> Result<String> {
+        let units = self
+            .take(chars * 2)?
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        Ok(String::from_utf16_lossy(&units))
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use crate::test_support::CfbBuilder;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        let mut bytes = (text.encode_utf16().count() as u16).to_le_bytes().to_vec();
+        bytes.extend(text.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        bytes
+    }
+
+    /// A Word document with just enough of a FIB to point at `structures` in the 1Table stream.
+    /// `pages` follow the first 1024 bytes of the WordDocument stream, and `data` is the Data
+    /// stream.
+    fn word(structures: &[(usize, &[u8])], pages: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut fib = vec![0u8; 2 * FKP_SIZE];
+        fib[0..2].copy_from_slice(&[0xEC, 0xA5]);
+        fib[0x0A..0x0C].copy_from_slice(&F_WHICH_TBL_STM.to_le_bytes());
+        fib[CB_RG_FC_LCB_OFFSET..CB_RG_FC_LCB_OFFSET + 2].copy_from_slice(&93u16.to_le_bytes());
+        let mut table = vec![0u8; 16];
+        for (index, structure) in structures {
+            let pair = RG_FC_LCB_OFFSET + index * 8;
+            fib[pair..pair + 4].copy_from_slice(&(table.len() as u32).to_le_bytes());
+            fib[pair + 4..pair + 8].copy_from_slice(&(structure.len() as u32).to_le_bytes());
+            table.extend(*structure);
+        }
+        fib.extend(pages);
+        let mut builder = CfbBuilder::new()
+            .stream("WordDocument", &fib)
+            .stream("1Table", &table);
+        if !data.is_empty() {
+            builder = builder.stream("Data", data);
+        }
+        builder.build()
+    }
+
+    fn word_with_doc_vars(stw_user: &[u8]) -> Vec<u8> {
+        word(&[(STW_USER, stw_user)], &[], &[])
+    }
+
+    #[test]
+    fn test_word_objects() {
+        // one page of character formatting with a picture run and an OLE object run
+        let mut fkp = vec![0u8; FKP_SIZE];
+        for (position, fc) in [0x800u32, 0x810, 0x820].iter().enumerate() {
+            fkp[position * 4..position * 4 + 4].copy_from_slice(&fc.to_le_bytes());
+        }
+        let picture = [&[0x03, 0x6A][..], &8u32.to_le_bytes()].concat();
+        let ole = [
+            &[0x0A, 0x08, 0x01][..],
+            &[0x03, 0x6A],
+            &1234u32.to_le_bytes(),
+        ]
+        .concat();
+        for (position, (word_offset, grpprl)) in
+            [(100usize, &picture), (120, &ole)].iter().enumerate()
+        {
+            fkp[12 + position] = *word_offset as u8;
+            fkp[word_offset * 2] = grpprl.len() as u8;
+            fkp[word_offset * 2 + 1..word_offset * 2 + 1 + grpprl.len()].copy_from_slice(grpprl);
+        }
+        fkp[FKP_SIZE - 1] = 2;
+        let plc = [
+            &0x800u32.to_le_bytes()[..],
+            &0x820u32.to_le_bytes(),
+            &2u32.to_le_bytes(),
+        ]
+        .concat();
+        let mut data = vec![0xAA; 8];
+        let record = [&10u32.to_le_bytes()[..], &[0x44, 0x00, 1, 2, 3, 4]].concat();
+        data.extend(&record);
+
+        let file = word(&[(PLCF_BTE_CHPX, &plc)], &fkp, &data);
+        let ole_file = OleFile::parse_sync(&file[..]).unwrap();
+        assert_eq!(
+            ole_file.word_objects().unwrap(),
+            vec![
+                WordObject::Picture {
+                    data_offset: 8,
+                    data: record
+                },
+                WordObject::Ole {
+                    storage: "_1234".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_doc_vars() {
+        let mut stw_user = vec![0xFF, 0xFF, 2, 0, 4, 0];
+        for name in ["url", "key"] {
+            stw_user.extend(utf16(name));
+            stw_user.extend([0; 4]);
+        }
+        for value in ["http://example.com/p", "s3cr3t"] {
+            stw_user.extend(utf16(value));
+            stw_user.extend([0; 2]);
+        }
+        let ole = OleFile::parse_sync(&word_with_doc_vars(&stw_user)[..]).unwrap();
+        assert_eq!(
+            ole.doc_vars().unwrap(),
+            vec![
+                DocVar {
+                    name: "url".to_string(),
+                    value: "http://example.com/p".to_string()
+                },
+                DocVar {
+                    name: "key".to_string(),
+                    value: "s3cr3t".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated_doc_vars() {
+        let ole =
+            OleFile::parse_sync(&word_with_doc_vars(&[0xFF, 0xFF, 1, 0, 0, 0, 9])[..]).unwrap();
+        assert!(ole.doc_vars().is_err());
+        let ole =
+            OleFile::parse_sync(&CfbBuilder::new().stream("Other", b"x").build()[..]).unwrap();
+        assert!(ole.doc_vars().unwrap().is_empty());
+    }
+}
@@ -1,13 +1,57 @@
-use ole::OleFile;
+use log::error;
+use ole_cli_common::{
+    command, exit_code, init_logging, stream_filter_args, CommonArgs, Report, StreamFilter,
+    EXIT_USAGE,
+};
+use std::process::exit;
 
-#[tokio::main]
-async fn main() {
-    let file = "data/oledoc1.doc_";
-    let res = OleFile::from_file_blocking(file).expect("file not found");
-    println!("{:#?}", &res);
-    println!("entries: {:#?}", res.list_streams());
+fn main() {
+    let cli = stream_filter_args(command("OleCli", "List the streams stored in OLE files."))
+        .version(env!("CARGO_PKG_VERSION"));
+    #[cfg(feature = "schema")]
+    let cli = ole_cli_common::schema_arg(cli);
+    let args_matches = cli.get_matches();
+    #[cfg(feature = "schema")]
+    if ole_cli_common::print_schema::<Vec<String>>(
+        &args_matches,
+        "olecli",
+        env!("CARGO_PKG_VERSION"),
+    ) {
+        return;
+    }
+    let args = CommonArgs::from_matches(&args_matches);
+    let filter = StreamFilter::from_matches(&args_matches);
+    init_logging(args.log_level);
 
-    let res = OleFile::from_file(file).await.expect("file not found");
-    println!("{:#?}", &res);
-    println!("entries: {:#?}", res.list_streams());
+    let files = args.input_files();
+    if files.is_empty() {
+        error!("File path is required.");
+        exit(EXIT_USAGE);
+    }
+
+    let mut statuses = vec![];
+    for file in files {
+        let mut warnings = vec![];
+        let result = args.open(&file, &mut warnings).map(|input| input.ole);
+        match &result {
+            Ok(res) if !args.json => {
+                println!("{:#?}", res);
+                println!("entries: {:#?}", filter.stream_names(res));
+            }
+            Err(err) => error!("Could not parse {}: {}", file.display(), err),
+            _ => {}
+        }
+        let report = Report::new(
+            "olecli",
+            env!("CARGO_PKG_VERSION"),
+            &file,
+            result.map(|res| filter.stream_names(&res)),
+        )
+        .with_warnings(warnings);
+        if args.json {
+            report.print_json();
+        }
+        statuses.push(report.status);
+    }
+    exit(exit_code(&statuses));
 }
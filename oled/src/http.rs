@@ -0,0 +1,267 @@
+//! Just enough HTTP/1.1 for the service: one request per connection, bodies sized by
+//! `Content-Length`, responses closing the connection.
+use std::{fmt, io};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// How long the request line and headers may be, together.
+pub const MAX_HEAD_SIZE: u64 = 16 * 1024;
+
+/// A request as read off the connection.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// the path of the target, without the query
+    pub path: String,
+    pub query: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// The decoded value of the query parameter `name`, if the target has it.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query.as_deref()?.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key) == name).then(|| percent_decode(value))
+        })
+    }
+}
+
+/// Why a request couldn't be read. All but `Io` are answered with their status.
+#[derive(Debug)]
+pub enum HttpError {
+    BadRequest(&'static str),
+    LengthRequired,
+    PayloadTooLarge(usize),
+    Io(io::Error),
+}
+
+impl HttpError {
+    pub fn status(&self) -> u16 {
+        match self {
+            HttpError::BadRequest(_) | HttpError::Io(_) => 400,
+            HttpError::LengthRequired => 411,
+            HttpError::PayloadTooLarge(_) => 413,
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            HttpError::LengthRequired => write!(f, "a Content-Length is required"),
+            HttpError::PayloadTooLarge(max) => write!(f, "the body is larger than {} bytes", max),
+            HttpError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for HttpError {
+    fn from(err: io::Error) -> Self {
+        HttpError::Io(err)
+    }
+}
+
+/// Read a request from `stream`, refusing bodies over `max_body` bytes before reading them.
+/// A client that sent `Expect: 100-continue` is told to go on once the length is known to fit.
+pub async fn read_request<S>(stream: &mut S, max_body: usize) -> Result<Request, HttpError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut reader = BufReader::new(stream);
+    let mut head = (&mut reader).take(MAX_HEAD_SIZE);
+
+    let mut line = String::new();
+    head.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+            (method.to_string(), target.to_string())
+        }
+        _ => return Err(HttpError::BadRequest("malformed request line")),
+    };
+
+    let mut content_length = None;
+    let mut expect_continue = false;
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await? == 0 {
+            return Err(HttpError::BadRequest("headers are cut short or too long"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or(HttpError::BadRequest("malformed header"))?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| HttpError::BadRequest("malformed Content-Length"))?,
+            );
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(HttpError::LengthRequired);
+        } else if name.eq_ignore_ascii_case("expect") {
+            expect_continue = value.eq_ignore_ascii_case("100-continue");
+        }
+    }
+
+    let length = match (content_length, method.as_str()) {
+        (Some(length), _) => length,
+        (None, "POST" | "PUT") => return Err(HttpError::LengthRequired),
+        (None, _) => 0,
+    };
+    if length > max_body {
+        return Err(HttpError::PayloadTooLarge(max_body));
+    }
+    if expect_continue && length > 0 {
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .await?;
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target, None),
+    };
+    Ok(Request {
+        method,
+        path,
+        query,
+        body,
+    })
+}
+
+/// Write a JSON response and flush it. The connection is closed after it.
+pub async fn write_json<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    status: u16,
+    body: &str,
+) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(body.as_bytes()).await?;
+    writer.flush().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Decode `%XX` escapes and `+` in a query component, leaving malformed escapes as they are.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' => bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (None, b'+') => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (None, byte) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    async fn read(raw: &[u8], max_body: usize) -> Result<Request, HttpError> {
+        let (mut client, mut server) = duplex(64 * 1024);
+        client.write_all(raw).await.unwrap();
+        read_request(&mut server, max_body).await
+    }
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let request = read(
+            b"POST /scan?name=a%20b.doc&x HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\n\r\nabcd",
+            4,
+        )
+        .await
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/scan");
+        assert_eq!(request.body, b"abcd");
+        assert_eq!(request.query_param("name").as_deref(), Some("a b.doc"));
+        assert_eq!(request.query_param("x").as_deref(), Some(""));
+        assert_eq!(request.query_param("y"), None);
+
+        let request = read(b"GET /health HTTP/1.1\r\n\r\n", 0).await.unwrap();
+        assert_eq!((request.path.as_str(), request.query), ("/health", None));
+
+        let status = |result: Result<Request, HttpError>| result.unwrap_err().status();
+        assert_eq!(status(read(b"POST /scan HTTP/1.1\r\n\r\n", 4).await), 411);
+        assert_eq!(
+            status(read(b"POST /scan HTTP/1.1\r\nContent-Length: 5\r\n\r\nabcde", 4).await),
+            413
+        );
+        assert_eq!(
+            status(
+                read(
+                    b"POST /scan HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+                    4
+                )
+                .await
+            ),
+            411
+        );
+        assert_eq!(status(read(b"GET /\r\n\r\n", 4).await), 400);
+        let long_header = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(20_000));
+        assert_eq!(status(read(long_header.as_bytes(), 4).await), 400);
+    }
+
+    #[tokio::test]
+    async fn test_expect_continue() {
+        let (mut client, mut server) = duplex(1024);
+        client
+            .write_all(b"POST /scan HTTP/1.1\r\nContent-Length: 2\r\nExpect: 100-continue\r\n\r\n")
+            .await
+            .unwrap();
+        let reading = tokio::spawn(async move { read_request(&mut server, 2).await });
+        let mut interim = [0; 25];
+        client.read_exact(&mut interim).await.unwrap();
+        assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+        client.write_all(b"ok").await.unwrap();
+        assert_eq!(reading.await.unwrap().unwrap().body, b"ok");
+    }
+}
@@ -0,0 +1,408 @@
+//! A long-running service putting [`ole::analyze`] behind HTTP, for integrations that would
+//! rather post a file and read JSON back than link the library.
+//!
+//! - `POST /scan?name=<file name>` with the file as the body answers with the report the
+//!   command line tools print under `--json`: 200 when the file could be analyzed, 422 when it
+//!   couldn't. The name is optional and only labels the report.
+//! - `GET /health` answers `{"status":"ok"}`.
+//! - `GET /capabilities` answers with the version of the library and what it was built with,
+//!   see [`ole::capabilities`], for routing files to instances that can handle them.
+//!
+//! Bodies over `--max-size` are refused before they are read, at most `--connections`
+//! requests are read at once and at most `--scans` files analyzed at once, the rest wait.
+//! Files are parsed leniently, like the tools do, and may take no more memory for their
+//! allocation tables, directory and mini stream than `--max-size`; an analysis running longer
+//! than `--timeout` is answered with an error.
+pub mod http;
+pub mod scan;
+
+use crate::http::{read_request, write_json, HttpError, Request};
+use clap::{Arg, Command};
+use log::{error, info, warn, Level};
+use ole::OleParseOptions;
+use ole_cli_common::{init_logging, Report, Status, DEFAULT_LOG_LEVEL};
+use serde_json::json;
+use std::{
+    fs, net::SocketAddr, path::Path, process::exit, str::FromStr, sync::Arc,
+    thread::available_parallelism, time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::Semaphore,
+    time::timeout,
+};
+
+/// Where the service listens unless told otherwise.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+/// How large a submitted file may be unless told otherwise, in bytes.
+pub const DEFAULT_MAX_SIZE: usize = 64 * 1024 * 1024;
+/// How many requests are read at once unless told otherwise.
+pub const DEFAULT_CONNECTIONS: usize = 64;
+/// How long a client has to send its request, and a file may be analyzed for, unless told
+/// otherwise, in seconds.
+pub const DEFAULT_TIMEOUT: u64 = 30;
+
+/// How the service runs, as given on the command line.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub listen: SocketAddr,
+    pub max_size: usize,
+    pub connections: usize,
+    pub scans: usize,
+    /// how long a client has to send the whole request, and a file may be analyzed for
+    pub timeout: Duration,
+    /// evaluated against every report when given
+    pub policy: Option<ole::Policy>,
+}
+
+/// What the connections share.
+struct State {
+    config: Config,
+    scans: Arc<Semaphore>,
+}
+
+#[tokio::main]
+pub async fn main() {
+    let command = Command::new("OleD")
+        .about("A service analyzing the OLE files posted to it and answering with JSON reports.")
+        .version(env!("CARGO_PKG_VERSION"))
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .help("The address and port to listen on.")
+                .takes_value(true)
+                .default_value(DEFAULT_LISTEN)
+                .validator(|value| value.parse::<SocketAddr>().map(|_| ())),
+        )
+        .arg(
+            Arg::new("max-size")
+                .long("max-size")
+                .help("Refuse files larger than this many bytes.")
+                .takes_value(true)
+                .validator(|value| value.parse::<usize>().map(|_| ())),
+        )
+        .arg(
+            Arg::new("connections")
+                .long("connections")
+                .help("How many requests to read at once.")
+                .takes_value(true)
+                .validator(|value| value.parse::<usize>().map(|_| ())),
+        )
+        .arg(
+            Arg::new("scans")
+                .long("scans")
+                .help("How many files to analyze at once, by default one per CPU.")
+                .takes_value(true)
+                .validator(|value| value.parse::<usize>().map(|_| ())),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .help("How many seconds a client has to send its request, and a file may be analyzed for.")
+                .takes_value(true)
+                .validator(|value| value.parse::<u64>().map(|_| ())),
+        )
+        .arg(
+            Arg::new("policy")
+                .long("policy")
+                .help("A policy file to evaluate every report against.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("log-level")
+                .long("log-level")
+                .help("One of error, warn, info, debug or trace.")
+                .takes_value(true)
+                .default_value(DEFAULT_LOG_LEVEL)
+                .validator(|value| Level::from_str(value).map(|_| ())),
+        );
+    #[cfg(feature = "schema")]
+    let command = ole_cli_common::schema_arg(command);
+    let matches = command.get_matches();
+    #[cfg(feature = "schema")]
+    if ole_cli_common::print_schema::<scan::Findings>(&matches, "oled", env!("CARGO_PKG_VERSION")) {
+        return;
+    }
+
+    // the unwraps are safe, the values were checked by the validators
+    init_logging(Level::from_str(matches.value_of("log-level").unwrap()).unwrap());
+    let policy = matches.value_of("policy").map(|path| {
+        match fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| {
+                text.parse()
+                    .map_err(|err: ole::error::Error| err.to_string())
+            }) {
+            Ok(policy) => policy,
+            Err(err) => {
+                error!("Could not load the policy {}: {}", path, err);
+                exit(1);
+            }
+        }
+    });
+    let parsed = |name: &str| matches.value_of(name).map(|value| value.parse().unwrap());
+    let config = Config {
+        listen: matches.value_of("listen").unwrap().parse().unwrap(),
+        max_size: parsed("max-size").unwrap_or(DEFAULT_MAX_SIZE),
+        connections: parsed("connections").unwrap_or(DEFAULT_CONNECTIONS),
+        scans: parsed("scans")
+            .unwrap_or_else(|| available_parallelism().map_or(1, |cpus| cpus.get())),
+        timeout: Duration::from_secs(
+            matches
+                .value_of("timeout")
+                .map_or(DEFAULT_TIMEOUT, |value| value.parse().unwrap()),
+        ),
+        policy,
+    };
+
+    let listener = match TcpListener::bind(config.listen).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Could not listen on {}: {}", config.listen, err);
+            exit(1);
+        }
+    };
+    info!("Listening on {}", config.listen);
+    tokio::select! {
+        _ = serve(listener, config) => {}
+        _ = tokio::signal::ctrl_c() => info!("Shutting down"),
+    }
+}
+
+/// Accept connections on `listener`, each handled on a task of its own.
+pub async fn serve(listener: TcpListener, config: Config) {
+    let connections = Arc::new(Semaphore::new(config.connections.max(1)));
+    let state = Arc::new(State {
+        scans: Arc::new(Semaphore::new(config.scans.max(1))),
+        config,
+    });
+    loop {
+        // waiting for a permit first leaves clients in the backlog rather than in memory
+        let permit = connections.clone().acquire_owned().await.unwrap();
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!("Could not accept a connection: {}", err);
+                continue;
+            }
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle(stream, &state).await {
+                warn!("{}: {}", peer, err);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Read one request from `stream`, answer it and close the connection.
+async fn handle(mut stream: TcpStream, state: &State) -> std::io::Result<()> {
+    let request = match timeout(
+        state.config.timeout,
+        read_request(&mut stream, state.config.max_size),
+    )
+    .await
+    {
+        Ok(Ok(request)) => request,
+        Ok(Err(HttpError::Io(err))) => return Err(err),
+        Ok(Err(err)) => return respond_error(&mut stream, err.status(), &err.to_string()).await,
+        Err(_) => return respond_error(&mut stream, 408, "the request took too long").await,
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => {
+            write_json(&mut stream, 200, &json!({"status": "ok"}).to_string()).await
+        }
+        ("GET", "/capabilities") => {
+            write_json(&mut stream, 200, &capabilities_json().to_string()).await
+        }
+        ("POST", "/scan") => {
+            let (status, body) = scan(request, state).await;
+            write_json(&mut stream, status, &body).await
+        }
+        (_, "/health" | "/capabilities" | "/scan") => {
+            respond_error(&mut stream, 405, "method not allowed").await
+        }
+        _ => respond_error(&mut stream, 404, "not found").await,
+    }
+}
+
+/// The capabilities of the library the service is built with.
+fn capabilities_json() -> serde_json::Value {
+    let capabilities = ole::capabilities();
+    json!({
+        "version": capabilities.version,
+        "features": capabilities.features,
+        "decryption": capabilities
+            .decryption
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// How uploads are parsed: leniently, as the tools do, and with the allocation tables, the
+/// directory and the mini stream held to `max_size`. They are all read from the file, so
+/// only a file whose chains loop or overlap asks for more.
+fn parse_options(config: &Config) -> OleParseOptions {
+    OleParseOptions {
+        max_memory: Some(config.max_size),
+        ..OleParseOptions::lenient()
+    }
+}
+
+/// Analyze the body of `request` once a scan slot is free, giving the status and the report.
+async fn scan(request: Request, state: &State) -> (u16, String) {
+    let name = request
+        .query_param("name")
+        .unwrap_or_else(|| "upload".to_string());
+    let policy = state.config.policy.clone();
+    let options = parse_options(&state.config);
+    let permit = state.scans.clone().acquire_owned().await.unwrap();
+    let data = request.body;
+    // the slot is given back when the analysis ends, not when it is given up on: a runaway
+    // one keeps holding it rather than making room for more
+    let analysis = tokio::task::spawn_blocking(move || {
+        let result = scan::scan(&data, &options, policy.as_ref());
+        drop(permit);
+        result
+    });
+    let result = match timeout(state.config.timeout, analysis).await {
+        Ok(joined) => joined.unwrap_or(Err(ole::error::Error::GenericError(
+            "the analysis panicked",
+        ))),
+        Err(_) => Err(ole::error::Error::GenericError(
+            "the analysis took too long",
+        )),
+    };
+    if let Err(err) = &result {
+        info!("Could not analyze {}: {}", name, err);
+    }
+    let report = Report::new("oled", env!("CARGO_PKG_VERSION"), Path::new(&name), result);
+    let status = match report.status {
+        Status::Ok => 200,
+        Status::Error => 422,
+    };
+    (status, serde_json::to_string(&report).unwrap())
+}
+
+async fn respond_error(stream: &mut TcpStream, status: u16, message: &str) -> std::io::Result<()> {
+    write_json(stream, status, &json!({ "error": message }).to_string()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn start(config: Config) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, config));
+        addr
+    }
+
+    /// Send `head` and `body` and read the whole response.
+    async fn request(addr: SocketAddr, head: &str, body: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(body).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_service() {
+        let addr = start(Config {
+            listen: DEFAULT_LISTEN.parse().unwrap(),
+            max_size: 4 * 1024 * 1024,
+            connections: 4,
+            scans: 2,
+            timeout: Duration::from_secs(5),
+            policy: Some("# ole policy v1\nblock macros\nallow\n".parse().unwrap()),
+        })
+        .await;
+
+        let response = request(addr, "GET /health HTTP/1.1\r\n\r\n", b"").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(r#"{"status":"ok"}"#));
+
+        let response = request(addr, "GET /capabilities HTTP/1.1\r\n\r\n", b"").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let capabilities: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(capabilities["version"], ole::capabilities().version);
+        assert!(capabilities["features"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("blocking")));
+
+        let data = fs::read("../data/oledoc1.doc_").unwrap();
+        let head = format!(
+            "POST /scan?name=oledoc1.doc HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            data.len()
+        );
+        let response = request(addr, &head, &data).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let report: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(report["tool"], "oled");
+        assert_eq!(report["file"], "oledoc1.doc");
+        assert_eq!(report["status"], "ok");
+        let findings = &report["findings"];
+        assert!(findings["streams"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|stream| stream["path"] == "WordDocument"));
+        assert!(findings["verdict"]["action"].is_string());
+
+        let response = request(
+            addr,
+            "POST /scan HTTP/1.1\r\nContent-Length: 10\r\n\r\n",
+            b"not an ole",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 422 "));
+        assert!(response.contains(r#""status":"error""#));
+
+        let head = format!(
+            "POST /scan HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            8 * 1024 * 1024
+        );
+        let response = request(addr, &head, b"").await;
+        assert!(response.starts_with("HTTP/1.1 413 "));
+
+        // a class ID in the header is let through, as the tools do
+        let mut tampered = data.clone();
+        tampered[8] = 0x01;
+        let head = format!(
+            "POST /scan HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            tampered.len()
+        );
+        let response = request(addr, &head, &tampered).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        // a DIFAT sector naming itself as the next one is an error, not the end of the service
+        let mut looping = data.clone();
+        let difat = (looping.len() / 512 - 1) as u32;
+        looping.extend(std::iter::repeat(0).take(508));
+        looping.extend_from_slice(&difat.to_le_bytes());
+        looping[0x44..0x48].copy_from_slice(&difat.to_le_bytes());
+        looping[0x48..0x4C].copy_from_slice(&u32::MAX.to_le_bytes());
+        let head = format!(
+            "POST /scan HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            looping.len()
+        );
+        let response = request(addr, &head, &looping).await;
+        assert!(response.starts_with("HTTP/1.1 422 "));
+        let response = request(addr, "GET /health HTTP/1.1\r\n\r\n", b"").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let response = request(addr, "DELETE /scan HTTP/1.1\r\n\r\n", b"").await;
+        assert!(response.starts_with("HTTP/1.1 405 "));
+        let response = request(addr, "GET /nothing HTTP/1.1\r\n\r\n", b"").await;
+        assert!(response.starts_with("HTTP/1.1 404 "));
+    }
+}
@@ -0,0 +1,112 @@
+//! What the service answers a scan with: the parts of an [`AnalysisReport`] that say what a
+//! file can do, and the verdict of the policy if one was configured.
+use ole::{prelude::AnalysisReport, OleParseOptions, Policy};
+use serde::Serialize;
+
+/// The findings of `/scan`, inside the same envelope the command line tools print.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Findings {
+    pub file_type: String,
+    pub encrypted: bool,
+    pub encryption_scheme: Option<String>,
+    /// whether there is a VBA project with at least one module
+    pub macros: bool,
+    /// the VBA procedures that run by themselves, as `module.procedure`
+    pub autoexec: Vec<String>,
+    pub ole_objects: Vec<ObjectFinding>,
+    /// URLs and UNC paths of connections to external data
+    pub external_urls: Vec<String>,
+    /// how many compound files are nested in this one, at any depth
+    pub nested_files: usize,
+    pub streams: Vec<StreamFinding>,
+    /// present when the service was started with a policy
+    pub verdict: Option<VerdictFinding>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ObjectFinding {
+    pub path: String,
+    /// storage, packager or linked
+    pub kind: String,
+    pub prog_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct StreamFinding {
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct VerdictFinding {
+    /// allow, flag or block
+    pub action: String,
+    /// the rules that matched, in the policy's text form
+    pub rules: Vec<String>,
+}
+
+/// Analyze `data`, parsed with `options`, and, given a policy, evaluate it against the report.
+pub fn scan(
+    data: &[u8],
+    options: &OleParseOptions,
+    policy: Option<&Policy>,
+) -> ole::Result<Findings> {
+    let report = ole::analyze_with_options(data, options)?;
+    Ok(Findings::new(&report, policy))
+}
+
+impl Findings {
+    fn new(report: &AnalysisReport, policy: Option<&Policy>) -> Self {
+        Findings {
+            file_type: format!("{:?}", report.file_type),
+            encrypted: report.encrypted,
+            encryption_scheme: report.encryption_scheme.map(|scheme| scheme.to_string()),
+            macros: report.macros,
+            autoexec: report
+                .autoexec
+                .iter()
+                .map(|autoexec| format!("{}.{}", autoexec.module, autoexec.procedure))
+                .collect(),
+            ole_objects: report
+                .ole_objects
+                .iter()
+                .map(|object| ObjectFinding {
+                    path: object.path.to_string(),
+                    kind: format!("{:?}", object.kind).to_lowercase(),
+                    prog_id: object.prog_id.clone(),
+                })
+                .collect(),
+            external_urls: report
+                .external_connections
+                .iter()
+                .flat_map(|connection| connection.urls())
+                .map(str::to_string)
+                .collect(),
+            nested_files: report.nested_files,
+            streams: report
+                .streams
+                .iter()
+                .map(|stream| StreamFinding {
+                    path: stream.path.join("/"),
+                    size: stream.size,
+                })
+                .collect(),
+            verdict: policy.map(|policy| {
+                let verdict = policy.evaluate(report);
+                VerdictFinding {
+                    action: verdict.action.to_string(),
+                    rules: verdict
+                        .flagged
+                        .iter()
+                        .chain(verdict.decided_by.as_ref())
+                        .map(|rule| rule.to_string())
+                        .collect(),
+                }
+            }),
+        }
+    }
+}
@@ -3,41 +3,63 @@ extern crate core;
 pub mod oleid;
 
 use crate::oleid::OleId;
-use clap::{Arg, Command};
-use log::{error, Level};
-use simple_logger::init_with_level;
+use log::error;
+use ole_cli_common::{command, exit_code, init_logging, CommonArgs, Report, EXIT_USAGE};
 use std::process::exit;
 
 pub fn main() {
-    // Set up logging
-    init_with_level(Level::Debug).unwrap();
-
     // Get arguments.
-    let args_matches = Command::new("OleId")
-        .about(
-            "A tool to analyze OLE files such as MS Office documents (e.g. Word,
+    let cli = command(
+        "OleId",
+        "A tool to analyze OLE files such as MS Office documents (e.g. Word,
 Excel), to detect specific characteristics that could potentially indicate that
 the file is suspicious or malicious, in terms of security (e.g. malware).",
-        )
-        .version(env!("CARGO_PKG_VERSION"))
-        .arg(
-            Arg::new("file")
-                .long("file")
-                .short('f')
-                .help("The path to the file to be processed.")
-                .takes_value(true),
-        )
-        .get_matches();
+    )
+    .version(env!("CARGO_PKG_VERSION"));
+    #[cfg(feature = "schema")]
+    let cli = ole_cli_common::schema_arg(cli);
+    let args_matches = cli.get_matches();
+    #[cfg(feature = "schema")]
+    if ole_cli_common::print_schema::<Vec<oleid::Indicator>>(
+        &args_matches,
+        "oleid",
+        env!("CARGO_PKG_VERSION"),
+    ) {
+        return;
+    }
+    let args = CommonArgs::from_matches(&args_matches);
 
-    let file_path = match args_matches.value_of("file") {
-        Some(t) => t,
-        _ => {
-            error!("File path is required.");
-            exit(1);
-        }
-    };
+    // Set up logging
+    init_logging(args.log_level);
 
-    let mut oleid = OleId::new(file_path);
-    let indicators = oleid.check();
-    println!("{:#?}", indicators);
+    let files = args.input_files();
+    if files.is_empty() {
+        error!("File path is required.");
+        exit(EXIT_USAGE);
+    }
+
+    let mut statuses = vec![];
+    for file in files {
+        let file_path = file.to_string_lossy();
+        let mut warnings = vec![];
+        let result = args.open(&file, &mut warnings).map(|input| {
+            let mut oleid = OleId::from_ref(&input.ole);
+            if let Some(scheme) = input.decrypted_from {
+                oleid = oleid.decrypted_from(scheme);
+            }
+            oleid.check()
+        });
+        if let Err(err) = &result {
+            error!("Could not parse {} as an OLE file: {}", file_path, err);
+        }
+        let report =
+            Report::new("oleid", env!("CARGO_PKG_VERSION"), &file, result).with_warnings(warnings);
+        if args.json {
+            report.print_json();
+        } else if let Some(indicators) = &report.findings {
+            println!("{:#?}", indicators);
+        }
+        statuses.push(report.status);
+    }
+    exit(exit_code(&statuses));
 }
@@ -1,10 +1,16 @@
 use log::error;
-use ole::{ftype::OleFileType, OleFile};
+use ole::prelude::{
+    DocumentRole, EncryptionScheme, OleFile, OleFileType, OleObjectKind, Result, SignatureKind,
+    TreeViolationKind,
+};
+use serde::Serialize;
+use std::borrow::Cow;
 use std::fmt::{Debug, Formatter};
 use std::process::exit;
 
 /// Constants for risk values.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Risk {
     HIGH,
     MEDIUM,
@@ -18,11 +24,13 @@ pub enum Risk {
 
 /// Piece of information of an `OleID` object.
 /// Contains an ID, value, type, name and description. No other functionality.
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Indicator {
     id: String,
     value: Option<String>,
     // Not sure we need this
+    #[serde(rename = "type")]
     _type: String,
     name: Option<String>,
     description: Option<String>,
@@ -63,12 +71,17 @@ impl Debug for Indicator {
 /// Summary of information about an OLE file (and a few other MS Office formats)
 /// Call `OleID::check()` to gather all info on a given file or run one
 /// of the `check_` functions to just get a specific piece of info.
-pub struct OleId {
+///
+/// The file is either owned or borrowed, so a caller that goes on to use a parsed file after
+/// the checks doesn't have to copy it.
+pub struct OleId<'a> {
     indicators: Vec<Indicator>,
-    ole: Option<OleFile>,
+    ole: Option<Cow<'a, OleFile>>,
+    /// the scheme the file was encrypted with, when it is a decrypted copy
+    decrypted_from: Option<EncryptionScheme>,
 }
 
-impl OleId {
+impl<'a> OleId<'a> {
     /// Create an OleID object.  
     ///         This does not run any checks yet nor open the file.
     ///         Can either give just a filename (as str), so OleID will check whether
@@ -77,27 +90,58 @@ impl OleId {
     ///         If filename is given, only `OleID::check` opens the file. Other
     ///         functions will return None
     pub fn new(filename: &str) -> Self {
+        match Self::try_new(filename) {
+            Ok(t) => t,
+            _ => {
+                error!(
+                    "Could not parse the provided file as an OLE file. Filename: {}",
+                    filename
+                );
+                exit(1);
+            }
+        }
+    }
+
+    /// Like `OleId::new`, but hands back the parse error instead of exiting. The parse is
+    /// lenient, what it got past is in the file's warnings.
+    pub fn try_new(filename: &str) -> Result<Self> {
+        let file = std::fs::File::open(filename)?;
+        Ok(Self::from_ole(OleFile::parse_lenient_sync(
+            std::io::BufReader::new(file),
+        )?))
+    }
+
+    /// Create an OleID object for an already parsed file.
+    pub fn from_ole(ole: OleFile) -> Self {
+        OleId {
+            indicators: Vec::new(),
+            ole: Some(Cow::Owned(ole)),
+            decrypted_from: None,
+        }
+    }
+
+    /// Create an OleID object that checks a parsed file the caller keeps.
+    pub fn from_ref(ole: &'a OleFile) -> Self {
         OleId {
             indicators: Vec::new(),
-            ole: match OleFile::from_file_blocking(filename) {
-                Ok(t) => Some(t),
-                _ => {
-                    error!(
-                        "Could not parse the provided file as an OLE file. Filename: {}",
-                        filename
-                    );
-                    exit(1);
-                }
-            },
+            ole: Some(Cow::Borrowed(ole)),
+            decrypted_from: None,
         }
     }
 
+    /// Check a decrypted copy of a file encrypted with `scheme`: the Encrypted indicator
+    /// reports the original, the other checks look at the decrypted content.
+    pub fn decrypted_from(mut self, scheme: EncryptionScheme) -> Self {
+        self.decrypted_from = Some(scheme);
+        self
+    }
+
     /// Open file and run all checks on it.
     /// returns: list of all `Indicator`s created
     pub fn check(&mut self) -> Vec<Indicator> {
         // We have a value so far but check the value of the ole file object available just to be sure.
-        let file_type = match self.ole.as_ref().cloned() {
-            Some(t) => t.file_type,
+        let file_type = match self.ole.as_ref() {
+            Some(t) => t.file_type(),
             _ => {
                 panic!("The ole file is invalid.");
             }
@@ -115,15 +159,60 @@ impl OleId {
             Risk::INFO,
         );
         self.indicators.push(filetype_indicator);
+        self.check_document_role();
 
         self.check_encrypted();
         self.check_macros();
         self.check_external_relationships();
         self.check_object_pool();
+        self.check_ole_objects();
+        self.check_doc_vars();
+        self.check_external_connections();
+        self.check_signatures();
+        self.check_directory_tree();
+        self.check_anomalies();
         self.check_flash();
         self.indicators.clone()
     }
 
+    /// Check whether this file is a template or an add-in rather than a plain document.
+    /// Office loads their macros along with new documents or with the application itself.
+    pub fn check_document_role(&mut self) -> Indicator {
+        let mut role_indicator = Indicator::new(
+            "DocRole",
+            Some("Document"),
+            "String",
+            Some("Template / Add-in"),
+            Some("The file is a plain document, not a template or an add-in."),
+            Risk::NONE,
+        );
+        match self.ole.as_ref().unwrap().document_role() {
+            Ok(DocumentRole::Document) => {}
+            Ok(role) => {
+                role_indicator.value = Some(format!("{:?}", role));
+                role_indicator.risk = Risk::INFO;
+                role_indicator.description = Some(match role {
+                    DocumentRole::AddIn => {
+                        "The file is an add-in, its macros load with the application.".to_string()
+                    }
+                    _ => "The file is a template, its macros come along with every document \
+                        created from it."
+                        .to_string(),
+                });
+            }
+            Err(err) => {
+                role_indicator.value = None;
+                role_indicator.risk = Risk::ERROR;
+                role_indicator.description = Some(format!(
+                    "Could not tell whether the file is a template: {}",
+                    err
+                ));
+            }
+        }
+        self.indicators.push(role_indicator.clone());
+        role_indicator
+    }
+
     /// Check whether this file is encrypted.
     pub fn check_encrypted(&mut self) -> Indicator {
         let mut encrypted_indicator = Indicator::new(
@@ -134,20 +223,31 @@ impl OleId {
             Some("The file is not encrypted"),
             Risk::NONE,
         );
-        if self.ole.as_ref().cloned().unwrap().encrypted {
+        let ole = self.ole.as_ref().unwrap();
+        if let Some(scheme) = self.decrypted_from {
             encrypted_indicator.value = Some("True".to_string());
             encrypted_indicator.risk = Risk::LOW;
-            encrypted_indicator.description = Some(
-                "The file is encrypted. It may be decrypted with msoffcrypto-tool".to_string(),
-            );
+            encrypted_indicator.description = Some(format!(
+                "The file is encrypted ({}), it was decrypted with the password given",
+                scheme
+            ));
+        } else if ole.is_encrypted() {
+            encrypted_indicator.value = Some("True".to_string());
+            encrypted_indicator.risk = Risk::LOW;
+            encrypted_indicator.description = Some(format!(
+                "The file is encrypted ({}). It may be decrypted with msoffcrypto-tool",
+                ole.encryption_scheme().unwrap_or(EncryptionScheme::Unknown)
+            ));
         }
         self.indicators.push(encrypted_indicator.clone());
         encrypted_indicator
     }
 
     /// Check whether this file contains macros (VBA and XLM/Excel 4).
+    /// VBA macros that Office runs by itself, such as `AutoOpen` or `Workbook_Open`, raise the
+    /// risk and are named in the description.
     pub fn check_macros(&mut self) {
-        let macros_indicator = Indicator::new(
+        let mut macros_indicator = Indicator::new(
             "vba",
             Some("No"),
             "String",
@@ -155,7 +255,45 @@ impl OleId {
             Some("This file does not contain VBA macros."),
             Risk::NONE,
         );
-        self.indicators.push(macros_indicator.clone());
+        let ole = self.ole.as_ref().unwrap();
+        let macros = ole.vba_projects().and_then(|projects| {
+            let modules = projects
+                .iter()
+                .map(|project| project.modules.len())
+                .sum::<usize>();
+            Ok((modules, ole.vba_autoexec()?))
+        });
+        match macros {
+            Ok((0, _)) => {}
+            Ok((_, autoexec)) if autoexec.is_empty() => {
+                macros_indicator.value = Some("Yes".to_string());
+                macros_indicator.risk = Risk::MEDIUM;
+                macros_indicator.description = Some(
+                    "This file contains VBA macros. No auto-executable procedure was found. \
+                    Use olevba to analyze them."
+                        .to_string(),
+                );
+            }
+            Ok((_, autoexec)) => {
+                macros_indicator.value = Some("Yes".to_string());
+                macros_indicator.risk = Risk::HIGH;
+                macros_indicator.description = Some(format!(
+                    "This file contains VBA macros with auto-executable procedures: {}",
+                    autoexec
+                        .iter()
+                        .map(|autoexec| format!("{} in {}", autoexec.procedure, autoexec.module))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            Err(err) => {
+                macros_indicator.value = None;
+                macros_indicator.risk = Risk::ERROR;
+                macros_indicator.description =
+                    Some(format!("Could not read the VBA projects: {}", err));
+            }
+        }
+        self.indicators.push(macros_indicator);
         let xlm_indicator = Indicator::new(
             "xlm",
             Some("No"),
@@ -166,8 +304,8 @@ impl OleId {
         );
         self.indicators.push(xlm_indicator.clone());
         // Check XLM Macros only in excel files
-        if self.ole.as_ref().cloned().unwrap().is_excel() {
-            // TODO: Hook up with the VBA Parser of the VBA module
+        if self.ole.as_ref().unwrap().is_excel() {
+            // TODO: Look for Excel 4 macro sheets in the workbook stream
         }
     }
 
@@ -183,36 +321,285 @@ impl OleId {
         );
         self.indicators.push(external_relations_indicator.clone());
         // TODO: This check is only for openxml files.
-        // match self.ole.as_ref().cloned().unwrap().file_type {
+        // match self.ole.as_ref().unwrap().file_type() {
         //     OleFileType::
         // }
         external_relations_indicator
     }
 
-    /// Check whether this file contains an ObjectPool stream.
-    /// Such a stream would be a strong indicator for embedded objects or files.
+    /// Check whether this file contains an ObjectPool storage with objects in it.
+    /// Such a storage would be a strong indicator for embedded objects or files.
     pub fn check_object_pool(&mut self) -> Indicator {
-        let mut object_pool_indicator = Indicator::new("ObjectPool", None, "Int", Some("Object Pool"), Some("Contains an ObjectPool stream, very likely to contain embedded OLE objects or files. Use oleobj to check it."), Risk::NONE);
-        if self
+        let mut object_pool_indicator = Indicator::new("ObjectPool", None, "Int", Some("Object Pool"), Some("Contains an ObjectPool storage, very likely to contain embedded OLE objects or files. Use oleobj to check it."), Risk::NONE);
+        let pooled = self
             .ole
             .as_ref()
-            .cloned()
             .unwrap()
-            .list_streams()
-            .contains(&"ObjectPool".to_string())
-        {
-            object_pool_indicator.value = Some("True".to_string());
+            .embedded_object_entries()
+            .iter()
+            .filter(|entry| {
+                entry.path.components().first().map(String::as_str) == Some("ObjectPool")
+            })
+            .count();
+        if pooled > 0 {
+            object_pool_indicator.value = Some(pooled.to_string());
             object_pool_indicator.risk = Risk::LOW;
         }
         self.indicators.push(object_pool_indicator.clone());
         object_pool_indicator
     }
 
+    /// Check what kinds of OLE objects this file holds.
+    /// Packager objects wrap arbitrary files and linked ones fetch their data from elsewhere,
+    /// while OLE 2 objects such as pictures and charts need a vulnerable server to do harm.
+    pub fn check_ole_objects(&mut self) -> Indicator {
+        let mut objects_indicator = Indicator::new(
+            "OleObjects",
+            Some("0"),
+            "Int",
+            Some("OLE Objects"),
+            Some("Number of embedded or linked OLE objects."),
+            Risk::NONE,
+        );
+        match self.ole.as_ref().unwrap().ole_objects() {
+            Ok(objects) if !objects.is_empty() => {
+                objects_indicator.value = Some(objects.len().to_string());
+                objects_indicator.risk = if objects
+                    .iter()
+                    .any(|object| object.kind != OleObjectKind::Storage)
+                {
+                    Risk::MEDIUM
+                } else {
+                    Risk::LOW
+                };
+                objects_indicator.description = Some(format!(
+                    "Contains OLE objects: {}",
+                    objects
+                        .iter()
+                        .map(|object| format!(
+                            "{} ({:?}{})",
+                            if object.path.components().is_empty() {
+                                "the whole file".to_string()
+                            } else {
+                                object.path.to_string()
+                            },
+                            object.kind,
+                            object
+                                .prog_id
+                                .as_ref()
+                                .map(|prog_id| format!(", {}", prog_id))
+                                .unwrap_or_default()
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            Ok(_) => {}
+            Err(err) => {
+                objects_indicator.value = None;
+                objects_indicator.risk = Risk::ERROR;
+                objects_indicator.description =
+                    Some(format!("Could not read the OLE objects: {}", err));
+            }
+        }
+        self.indicators.push(objects_indicator.clone());
+        objects_indicator
+    }
+
+    /// Check whether this Word document has document variables.
+    /// Droppers use them to hide payload strings from tools that only look at the VBA code.
+    pub fn check_doc_vars(&mut self) -> Indicator {
+        let mut doc_vars_indicator = Indicator::new(
+            "DocVars",
+            Some("0"),
+            "Int",
+            Some("Document Variables"),
+            Some("Number of document variables, which macros can read payloads from."),
+            Risk::NONE,
+        );
+        match self.ole.as_ref().unwrap().doc_vars() {
+            Ok(vars) if !vars.is_empty() => {
+                doc_vars_indicator.value = Some(vars.len().to_string());
+                doc_vars_indicator.risk = Risk::MEDIUM;
+                doc_vars_indicator.description = Some(format!(
+                    "Contains document variables, which macros can read payloads from: {}",
+                    vars.iter()
+                        .map(|var| var.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            Ok(_) => {}
+            Err(err) => {
+                doc_vars_indicator.value = None;
+                doc_vars_indicator.risk = Risk::ERROR;
+                doc_vars_indicator.description =
+                    Some(format!("Could not read the document variables: {}", err));
+            }
+        }
+        self.indicators.push(doc_vars_indicator.clone());
+        doc_vars_indicator
+    }
+
+    /// Check whether this workbook connects to external data.
+    /// Refreshing a query table, data connection or pivot cache fetches from wherever it points.
+    pub fn check_external_connections(&mut self) -> Indicator {
+        let mut connections_indicator = Indicator::new(
+            "ExternalConnections",
+            Some("0"),
+            "Int",
+            Some("External Data Connections"),
+            Some("Number of connections of the workbook to external data."),
+            Risk::NONE,
+        );
+        match self.ole.as_ref().unwrap().external_connections() {
+            Ok(connections) if !connections.is_empty() => {
+                let urls = connections
+                    .iter()
+                    .flat_map(|connection| connection.urls())
+                    .collect::<Vec<_>>();
+                connections_indicator.value = Some(connections.len().to_string());
+                connections_indicator.risk = if urls.is_empty() {
+                    Risk::LOW
+                } else {
+                    Risk::MEDIUM
+                };
+                connections_indicator.description = Some(if urls.is_empty() {
+                    "Contains connections to external data, which are fetched on refresh."
+                        .to_string()
+                } else {
+                    format!(
+                        "Contains connections to external data, which are fetched on refresh: {}",
+                        urls.join(", ")
+                    )
+                });
+            }
+            Ok(_) => {}
+            Err(err) => {
+                connections_indicator.value = None;
+                connections_indicator.risk = Risk::ERROR;
+                connections_indicator.description =
+                    Some(format!("Could not read the external connections: {}", err));
+            }
+        }
+        self.indicators.push(connections_indicator.clone());
+        connections_indicator
+    }
+
+    /// Check whether the VBA project, if there is one, is signed, and by whom.
+    pub fn check_signatures(&mut self) -> Indicator {
+        let mut signature_indicator = Indicator::new(
+            "VbaSignature",
+            Some("None"),
+            "String",
+            Some("VBA Signature"),
+            Some("This file does not contain a VBA project."),
+            Risk::NONE,
+        );
+        let ole = self.ole.as_ref().unwrap();
+        let has_vba = ole
+            .list_storage()
+            .iter()
+            .any(|name| name == "VBA" || name == "_VBA_PROJECT_CUR");
+        let signatures = ole.signatures();
+        let vba_signature = signatures
+            .iter()
+            .find(|signature| signature.kind == SignatureKind::VbaProject);
+        match (has_vba, vba_signature) {
+            (_, Some(signature)) => {
+                signature_indicator.value = Some("Signed".to_string());
+                signature_indicator.risk = Risk::INFO;
+                signature_indicator.description = Some(format!(
+                    "The VBA project is signed by {}{}. The signature was not verified.",
+                    signature
+                        .signer
+                        .as_deref()
+                        .unwrap_or("an unreadable certificate"),
+                    signature
+                        .signing_time
+                        .map(|time| format!(" at {}", time))
+                        .unwrap_or_default()
+                ));
+            }
+            (true, None) => {
+                signature_indicator.value = Some("Unsigned".to_string());
+                signature_indicator.risk = Risk::LOW;
+                signature_indicator.description =
+                    Some("The VBA project is not signed.".to_string());
+            }
+            (false, None) => {}
+        }
+        self.indicators.push(signature_indicator.clone());
+        signature_indicator
+    }
+
+    /// Check whether the directory trees follow the rules of MS-CFB.
+    /// Office writes valid trees, so entries out of name order point to a crafted file that
+    /// other parsers may read differently. Broken colors alone are common and weaker evidence.
+    pub fn check_directory_tree(&mut self) -> Indicator {
+        let mut tree_indicator = Indicator::new(
+            "DirectoryTree",
+            Some("0"),
+            "Int",
+            Some("Directory Tree Violations"),
+            Some("Number of places where the directory trees break the rules of MS-CFB."),
+            Risk::NONE,
+        );
+        let violations = self.ole.as_ref().unwrap().tree_violations();
+        if !violations.is_empty() {
+            let misordered = violations
+                .iter()
+                .filter(|violation| matches!(violation.kind, TreeViolationKind::NameOrder(_)))
+                .count();
+            tree_indicator.value = Some(violations.len().to_string());
+            tree_indicator.risk = if misordered > 0 {
+                Risk::MEDIUM
+            } else {
+                Risk::INFO
+            };
+            tree_indicator.description = Some(if misordered > 0 {
+                format!(
+                    "{} entries are out of name order, the directory was likely crafted by hand.",
+                    misordered
+                )
+            } else {
+                "The red-black tree colors are inconsistent, as some writers leave them."
+                    .to_string()
+            });
+        }
+        self.indicators.push(tree_indicator.clone());
+        tree_indicator
+    }
+
+    /// Check for structure that parses but that writers don't produce, such as FAT entries
+    /// leading out of the file or stream chains that don't fit the stream size.
+    pub fn check_anomalies(&mut self) -> Indicator {
+        let mut anomaly_indicator = Indicator::new(
+            "Anomalies",
+            Some("0"),
+            "Int",
+            Some("Structural Anomalies"),
+            Some("Number of places where the structure of the file is suspicious."),
+            Risk::NONE,
+        );
+        let anomalies = self.ole.as_ref().unwrap().anomalies();
+        if let Some(first) = anomalies.first() {
+            anomaly_indicator.value = Some(anomalies.len().to_string());
+            anomaly_indicator.risk = Risk::MEDIUM;
+            anomaly_indicator.description = Some(format!(
+                "The structure was likely tampered with, first: {}{}.",
+                first, first.location
+            ));
+        }
+        self.indicators.push(anomaly_indicator.clone());
+        anomaly_indicator
+    }
+
     /// Check whether this file contains flash objects
     pub fn check_flash(&mut self) -> Indicator {
         let mut flash_indicator = Indicator::new("Flash", Some("0"), "Int", Some("Flash Objects"), Some("Number of embedded Flash objects (SWF files) detected in OLE streams. Not 100% accurate, there may be false positives."), Risk::NONE);
         self.indicators.push(flash_indicator.clone());
-        let found = detect_flash(self.ole.as_ref().cloned().unwrap().directory_stream_data);
+        let found = detect_flash(self.ole.as_ref().unwrap().raw_directory_bytes());
         let val = flash_indicator
             .value
             .as_ref()
@@ -227,17 +614,13 @@ impl OleId {
 
     /// Helper function: returns an indicator if present (or None)
     pub fn get_indicator(&self, indicator_id: &str) -> Option<Indicator> {
-        return match self
-            .indicators
+        self.indicators
             .iter()
             .find(|indicator| indicator.id == indicator_id)
-        {
-            Some(t) => Some(t.clone()),
-            _ => None,
-        };
+            .cloned()
     }
 }
 
-pub fn detect_flash(stream_data: Vec<u8>) -> Vec<String> {
+pub fn detect_flash(_stream_data: &[u8]) -> Vec<String> {
     vec![]
 }
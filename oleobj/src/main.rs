@@ -1,34 +1,111 @@
 pub mod ole_object;
 
-use crate::ole_object::process_file;
-use clap::{Arg, Command};
-use log::{error, Level};
-use simple_logger::init_with_level;
+use crate::ole_object::{process_file, EmbeddedObject};
+use log::error;
+use ole_cli_common::{
+    command, exit_code, init_logging, stream_filter_args, CommonArgs, Report, StreamFilter,
+    EXIT_USAGE,
+};
 use std::process::exit;
 
 pub fn main() {
+    // Get arguments.
+    let cli = stream_filter_args(command(
+        "OleObj",
+        "A tool to parse OLE objects and files stored into various MS Office file formats (doc, xls, ppt, docx, xlsx, pptx, etc).",
+    ))
+    .version(env!("CARGO_PKG_VERSION"));
+    #[cfg(feature = "schema")]
+    let cli = ole_cli_common::schema_arg(cli);
+    let args_matches = cli.get_matches();
+    #[cfg(feature = "schema")]
+    if ole_cli_common::print_schema::<Vec<EmbeddedObject>>(
+        &args_matches,
+        "oleobj",
+        env!("CARGO_PKG_VERSION"),
+    ) {
+        return;
+    }
+    let args = CommonArgs::from_matches(&args_matches);
+    let filter = StreamFilter::from_matches(&args_matches);
+
     // Set up logger
-    init_with_level(Level::Debug).unwrap();
+    init_logging(args.log_level);
 
-    // Get arguments.
-    let args_matches = Command::new("OleObj")
-        .about("A tool to parse OLE objects and files stored into various MS Office file formats (doc, xls, ppt, docx, xlsx, pptx, etc).")
-        .version(env!("CARGO_PKG_VERSION"))
-        .arg(
-            Arg::new("file")
-                .long("file")
-                .short('f')
-                .help("The path to the file to be processed.")
-                .takes_value(true)
-        ).get_matches();
+    let files = args.input_files();
+    if files.is_empty() {
+        error!("File path is required.");
+        exit(EXIT_USAGE);
+    }
 
-    let file_path = match args_matches.value_of("file") {
-        Some(t) => t,
-        _ => {
-            error!("File path is required.");
-            exit(1);
+    let mut statuses = vec![];
+    for file in files {
+        let file_path = file.to_string_lossy();
+        let mut warnings = vec![];
+        let result = args.open(&file, &mut warnings).and_then(|input| {
+            process_file(
+                input.ole,
+                &file_path,
+                &args.unwrap_limits,
+                &filter,
+                &mut warnings,
+            )
+        });
+        let report =
+            Report::new("oleobj", env!("CARGO_PKG_VERSION"), &file, result).with_warnings(warnings);
+        if args.json {
+            report.print_json();
+        } else if let Some(objects) = &report.findings {
+            print_objects(&file_path, objects);
         }
-    };
+        statuses.push(report.status);
+    }
+    exit(exit_code(&statuses));
+}
 
-    process_file(file_path);
+fn print_objects(file_path: &str, objects: &[EmbeddedObject]) {
+    println!("{}", vec!["-"; 79].join(""));
+    println!("File: {}", file_path);
+    for object in objects {
+        println!(
+            "Extract file embedded in OLE object from stream {}",
+            object.stream
+        );
+        println!("Filename = {}", object.filename);
+        println!("Source path = {}", object.src_path);
+        println!("Temp path = {}", object.temp_path);
+        println!("Type = {:#06x}", object.header.type_short);
+        if let Some(timestamp) = &object.header.timestamp {
+            println!("Timestamp = {}", timestamp);
+        }
+        println!(
+            "Sizes = {} declared for the file, {} present in a stream of {} bytes{}",
+            object.header.actual_size,
+            object.header.data_size,
+            object.header.stream_size,
+            if object.header.size_mismatch {
+                " (MISMATCH)"
+            } else {
+                ""
+            }
+        );
+        if let Some(archive) = &object.archive {
+            match &archive.members {
+                Some(members) => {
+                    println!("Archive = {}, {} members", archive.format, members.len());
+                    for member in members {
+                        if member.is_dir {
+                            println!("    {}", member.name);
+                        } else {
+                            println!("    {} ({} bytes)", member.name, member.size);
+                        }
+                    }
+                }
+                None => println!("Archive = {}, members not listed", archive.format),
+            }
+        }
+        if let Some(saved_to) = &object.saved_to {
+            println!("Saving to file {}", saved_to);
+        }
+    }
 }
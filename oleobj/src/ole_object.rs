@@ -1,10 +1,15 @@
 use log::{debug, error, info};
-use ole::ftype::OleFileType;
-use ole::util::StringUtils;
-use ole::OleFile;
+use ole::error::Location;
+use ole::prelude::{
+    archive_format, Error, OleFile, OleFileType, Result, StringUtils, UnwrapLimits,
+};
+use ole::util::filetime;
+use ole_cli_common::StreamFilter;
+use serde::Serialize;
 use std::cmp::max;
 use std::fs;
 use std::io::{BufRead, Cursor, Read};
+use std::ops::Range;
 use std::path::Path;
 
 /// OLE object contained into an OLENativeStream structure.
@@ -21,15 +26,19 @@ pub struct OleNativeStream {
     actual_size: Option<u32>,
     data: Vec<u8>,
     package: bool,
+    stream_size: usize,
 }
 
+/// FILETIMEs from 1980 up to 2100, the range in which a timestamp is believable.
+const PLAUSIBLE_FILETIME: Range<u64> = 119_600_064_000_000_000..157_469_184_000_000_000;
+
 impl OleNativeStream {
     /// Constructor for OleNativeStream.
     /// If bindata is provided, it will be parsed using the parse() method.
     /// :param bindata: forwarded to parse, see docu there
     /// :param package: bool, set to True when extracting from an OLE Package
     /// object
-    pub fn new(bin_data: Option<Vec<u8>>, package: bool) -> Self {
+    pub fn new(bin_data: Option<Vec<u8>>, package: bool) -> Result<Self> {
         let mut instance = OleNativeStream {
             filename: None,
             src_path: None,
@@ -41,11 +50,12 @@ impl OleNativeStream {
             actual_size: None,
             data: Vec::new(),
             package,
+            stream_size: 0,
         };
         if let Some(data) = bin_data {
-            instance.parse(data);
+            instance.parse(data)?;
         }
-        instance
+        Ok(instance)
     }
 
     /// Parse binary data containing an OLENativeStream structure,
@@ -53,87 +63,268 @@ impl OleNativeStream {
     /// (see MS-OLEDS 2.3.6 OLENativeStream)  
     /// **Params**
     /// - data: bytes array or stream, containing OLENativeStream
-    /// structure containing an OLE object  
+    ///   structure containing an OLE object  
     ///
-    /// **returns** None
-    pub fn parse(&mut self, data: Vec<u8>) {
+    /// **returns** `OleUnexpectedEof` when the header is cut short
+    pub fn parse(&mut self, data: Vec<u8>) -> Result<()> {
+        self.stream_size = data.len();
         let mut cursor = Cursor::new(data);
         // An Ole package does not have the native data size field.
         if !self.package {
-            self.native_data_size = read_u32(&mut cursor);
+            self.native_data_size = read_u32(&mut cursor, "native data size")?;
             debug!(
                 "OLE native data size = {0:08X} ({} bytes)",
                 self.native_data_size
             );
         }
         // Probably an ole type specifier.
-        self.unknown_short = Some(read_u16(&mut cursor));
-        let mut filename_buf: Vec<u8> = Vec::new();
-        cursor.read_until(0x00, &mut filename_buf).unwrap();
+        self.unknown_short = Some(read_u16(&mut cursor, "type")?);
         // The filename.
-        self.filename = Some(String::from_utf8(filename_buf).unwrap());
+        self.filename = Some(read_zstring(&mut cursor));
         // The source path
-        let mut source_path_buf: Vec<u8> = Vec::new();
-        cursor.read_until(0x00, &mut source_path_buf).unwrap();
-        self.src_path = Some(String::from_utf8(source_path_buf).unwrap());
+        self.src_path = Some(read_zstring(&mut cursor));
         // Most probably time stamps.
-        self.unknown_long_1 = Some(read_u32(&mut cursor));
-        self.unknown_long_2 = Some(read_u32(&mut cursor));
+        self.unknown_long_1 = Some(read_u32(&mut cursor, "time stamp")?);
+        self.unknown_long_2 = Some(read_u32(&mut cursor, "time stamp")?);
         // The temp path
-        let mut temp_path_buf: Vec<u8> = Vec::new();
-        cursor.read_until(0x00, &mut temp_path_buf).unwrap();
-        self.temp_path = Some(String::from_utf8(temp_path_buf).unwrap());
+        self.temp_path = Some(read_zstring(&mut cursor));
         // Size the rest of the data.
-        self.actual_size = Some(read_u32(&mut cursor));
-        cursor.read(&mut self.data).unwrap();
+        self.actual_size = Some(read_u32(&mut cursor, "file size")?);
+        cursor.read_to_end(&mut self.data)?;
+        Ok(())
+    }
+
+    /// The header fields, and whether its sizes agree with the stream.
+    pub fn header(&self) -> NativeHeader {
+        let unknown_long_1 = self.unknown_long_1.unwrap_or_default();
+        let unknown_long_2 = self.unknown_long_2.unwrap_or_default();
+        let time = (unknown_long_2 as u64) << 32 | unknown_long_1 as u64;
+        let actual_size = self.actual_size.unwrap_or_default();
+        // the declared size counts everything after the size field itself
+        let declared_size_mismatch =
+            !self.package && self.native_data_size as usize != self.stream_size.saturating_sub(4);
+        NativeHeader {
+            type_short: self.unknown_short.unwrap_or_default(),
+            unknown_long_1,
+            unknown_long_2,
+            timestamp: PLAUSIBLE_FILETIME
+                .contains(&time)
+                .then(|| filetime(time))
+                .flatten()
+                .map(|time| time.to_string()),
+            native_data_size: self.native_data_size,
+            actual_size,
+            data_size: self.data.len(),
+            stream_size: self.stream_size,
+            size_mismatch: declared_size_mismatch || actual_size as usize > self.data.len(),
+        }
     }
 }
 
-/// find embedded objects in given file
-pub fn process_file(filepath: &str) {
-    let sane_filename = sanitize_filepath(filepath);
-    // let base_dir = Path::new(filepath).parent().unwrap();
-    // let filename_prefix = base_dir.join(sane_filename.clone());
+/// The fields of an OLENativeStream header, as found in the stream.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct NativeHeader {
+    /// the short after the size, probably the type of the object
+    pub type_short: u16,
+    pub unknown_long_1: u32,
+    pub unknown_long_2: u32,
+    /// the two longs read together as a FILETIME, when that falls between 1980 and 2100
+    pub timestamp: Option<String>,
+    /// the size the header declares for the whole native data
+    pub native_data_size: u32,
+    /// the size the header declares for the embedded file
+    pub actual_size: u32,
+    /// how much follows the size field: the embedded file, and in newer packages the paths
+    /// again in Unicode
+    pub data_size: usize,
+    pub stream_size: usize,
+    /// whether the stream isn't as long as the native data size says, or holds less than the
+    /// declared size of the file, a sign of tampering
+    pub size_mismatch: bool,
+}
+
+/// An object found in an OLE package stream, and the file it was saved to.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct EmbeddedObject {
+    pub stream: String,
+    pub filename: String,
+    pub src_path: String,
+    pub temp_path: String,
+    pub saved_to: Option<String>,
+    pub header: NativeHeader,
+    /// set when the embedded file is an archive
+    pub archive: Option<ArchiveListing>,
+}
+
+/// What an embedded archive holds, as far as can be told without extracting it.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ArchiveListing {
+    /// `Zip`, `SevenZip` or `Rar`
+    pub format: String,
+    /// `None` when the members can't be listed: a 7z archive, encrypted RAR headers, or a build
+    /// without the `archive` feature
+    pub members: Option<Vec<ArchiveEntry>>,
+}
 
-    println!("{}", vec!["-"; 79].join(""));
-    println!("File: {}", filepath);
-    // let index = 1;
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+impl ArchiveListing {
+    /// The listing of `data` if it is an archive. A listing that fails is told in `warnings`.
+    pub fn of(data: &[u8], stream: &str, warnings: &mut Vec<String>) -> Option<Self> {
+        let format = archive_format(data)?;
+        Some(ArchiveListing {
+            format: format!("{:?}", format),
+            members: Self::members(data, stream, warnings),
+        })
+    }
+
+    #[cfg(feature = "archive")]
+    fn members(data: &[u8], stream: &str, warnings: &mut Vec<String>) -> Option<Vec<ArchiveEntry>> {
+        match ole::archive_members(data) {
+            Ok(members) => members.map(|members| {
+                members
+                    .into_iter()
+                    .map(|member| ArchiveEntry {
+                        name: member.name,
+                        size: member.size,
+                        is_dir: member.is_dir,
+                    })
+                    .collect()
+            }),
+            Err(err) => {
+                warnings.push(format!(
+                    "could not list the archive embedded in {}: {}",
+                    stream, err
+                ));
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "archive"))]
+    fn members(_: &[u8], _: &str, _: &mut Vec<String>) -> Option<Vec<ArchiveEntry>> {
+        None
+    }
+}
+
+/// find embedded objects in given file, which was parsed from `filepath`, and in the files
+/// nested in it, looking only at the streams `filter` allows. Anything worth telling about an
+/// incomplete result goes to `warnings`.
+pub fn process_file(
+    olefile: OleFile,
+    filepath: &str,
+    limits: &UnwrapLimits,
+    filter: &StreamFilter,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<EmbeddedObject>> {
+    // there is no directory to save next to when reading from stdin
+    let sane_filename = if filepath == "-" {
+        "stdin".to_string()
+    } else {
+        sanitize_filepath(filepath)
+    };
+    let mut objects = vec![];
 
     // Look for ole files inside file.
-    for ole in find_ole(filepath) {
-        for parts_path in ole.list_streams() {
-            let stream_path = Path::new("/").join(parts_path.clone());
+    for ole in find_ole(olefile, filepath, limits, warnings)? {
+        // only streams in the storages the library counts as objects, as oleid does
+        let object_storages = ole
+            .embedded_object_entries()
+            .into_iter()
+            .map(|entry| entry.path.components().to_vec())
+            .collect::<Vec<_>>();
+        for (parts_path, entry) in filter.streams(&ole) {
+            let stream_path = Path::new("/").join(parts_path.to_string());
             debug!("Checking stream {}", stream_path.display());
-            if parts_path.to_lowercase() == "\x01ole10native".to_string() {
-                println!(
+            let storage = &parts_path.components()[..parts_path.depth()];
+            if entry.name().to_lowercase() == "\x01ole10native"
+                && object_storages.iter().any(|path| path == storage)
+            {
+                info!(
                     "Extract file embedded in OLE object from stream {}",
                     stream_path.display()
                 );
-                println!("Parsing OLE Package");
-                let stream = ole.open_stream(&vec![parts_path.as_str()]).unwrap();
-                let opkg = OleNativeStream::new(Some(stream.clone()), false);
+                let stream = match ole.open_stream(&parts_path.as_strs()) {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warnings.push(format!("could not read {}: {}", stream_path.display(), err));
+                        continue;
+                    }
+                };
+                let opkg = match OleNativeStream::new(Some(stream), false) {
+                    Ok(opkg) => opkg,
+                    Err(err) => {
+                        warnings.push(format!(
+                            "could not parse the header of {}: {}",
+                            stream_path.display(),
+                            err
+                        ));
+                        continue;
+                    }
+                };
+                let filename = opkg.filename.clone().unwrap_or_default();
+                let src_path = opkg.src_path.clone().unwrap_or_default();
+                let temp_path = opkg.temp_path.clone().unwrap_or_default();
 
-                println!("Filename = {}", opkg.filename.as_ref().cloned().unwrap());
-                println!("Source path = {}", opkg.src_path.as_ref().cloned().unwrap());
-                println!("Temp path = {}", opkg.temp_path.as_ref().cloned().unwrap());
                 let mut fname = String::new();
                 for embedded_fname in get_sane_embedded_filenames(
-                    opkg.filename.as_ref().cloned().unwrap(),
-                    opkg.src_path.as_ref().cloned().unwrap(),
-                    opkg.temp_path.as_ref().cloned().unwrap(),
+                    filename.clone(),
+                    src_path.clone(),
+                    temp_path.clone(),
                 ) {
                     fname = format!("{}_{}", sane_filename, embedded_fname);
-                    println!("{}", fname);
                     if !Path::new(fname.as_str()).is_file() {
                         break;
                     }
                 }
                 // Dump
-                println!("Saving to file {}", fname.clone());
-                fs::write(fname, stream).unwrap();
+                let saved_to = match fs::File::create(&fname)
+                    .map_err(Error::from)
+                    .and_then(|mut file| ole.copy_stream_to(&parts_path.as_strs(), &mut file))
+                {
+                    Ok(_) => Some(fname),
+                    Err(err) => {
+                        error!("Could not save to file {}: {}", fname, err);
+                        None
+                    }
+                };
+                let header = opkg.header();
+                if header.size_mismatch {
+                    warnings.push(format!(
+                        "sizes in the header of {} disagree with its content: {} bytes declared for the native data in a stream of {}, {} declared for the file with {} present",
+                        stream_path.display(),
+                        header.native_data_size,
+                        header.stream_size,
+                        header.actual_size,
+                        header.data_size
+                    ));
+                }
+                // newer packages put the paths again in Unicode after the file
+                let file_data = &opkg.data[..(header.actual_size as usize).min(opkg.data.len())];
+                let archive =
+                    ArchiveListing::of(file_data, &stream_path.display().to_string(), warnings);
+                objects.push(EmbeddedObject {
+                    stream: stream_path.display().to_string(),
+                    filename,
+                    src_path,
+                    temp_path,
+                    saved_to,
+                    header,
+                    archive,
+                });
             }
         }
     }
+    Ok(objects)
 }
 
 /// Get some sane filenames out of path information, preserving file suffix.
@@ -155,29 +346,25 @@ pub fn get_sane_embedded_filenames(
     let mut candidates_without_suffixes = Vec::new();
     for mut candidate in [filename, source_path, temp_path] {
         let mut index = max(
-            match candidate.rfind("/") {
-                Some(t) => t,
-                _ => 0,
-            },
-            match candidate.rfind("\\") {
-                Some(t) => t,
-                _ => 0,
-            },
+            candidate.rfind('/').unwrap_or_default(),
+            candidate.rfind('\\').unwrap_or_default(),
         );
 
         candidate = candidate.substring(index + 1..).trim().to_string();
 
         candidate = sanitize_filepath(candidate.as_str());
         // Skip whitespace only.
-        if candidate.len() == 0 {
-            continue;
-        }
-        if candidate.rfind(".").is_none() {
-            candidates_without_suffixes.push(candidate);
+        if candidate.is_empty() {
             continue;
         }
-        index = candidate.rfind(".").unwrap();
-        if index < candidate.len() - 5 {
+        index = match candidate.rfind('.') {
+            Some(index) => index,
+            None => {
+                candidates_without_suffixes.push(candidate);
+                continue;
+            }
+        };
+        if index + 5 < candidate.len() {
             candidates_without_suffixes.push(candidate);
             continue;
         }
@@ -187,29 +374,40 @@ pub fn get_sane_embedded_filenames(
     filenames
 }
 
-/// try to open somehow as zip/ole/rtf/... ; yield None if fail
 /// yields embedded ole streams in form of OleFileIO.
-fn find_ole(filename: &str) -> Vec<OleFile> {
-    return match OleFile::from_file_blocking(filename) {
-        Ok(t) => {
-            return match t.file_type {
-                OleFileType::Powerpoint97 => {
-                    info!("Is a powerpoint file {}", filename);
-                    find_ole_in_ppt(t)
-                }
-                _ => {
-                    // An OLE file of another format.
-                    info!("Is an OLE file {}", filename);
-                    vec![t.clone()]
-                }
-            };
+fn find_ole(
+    t: OleFile,
+    filename: &str,
+    limits: &UnwrapLimits,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<OleFile>> {
+    let unwrapped = t.unwrap_nested(limits);
+    warnings.extend(
+        unwrapped
+            .truncated
+            .iter()
+            .map(|truncation| format!("unwrapping truncated: {}", truncation)),
+    );
+    let mut oles = match t.file_type {
+        OleFileType::Powerpoint97 => {
+            info!("Is a powerpoint file {}", filename);
+            find_ole_in_ppt(t)
         }
         _ => {
-            // TODO: Try loading the file as a zip file
-            error!("Open failed: {} (or its data) is not an OLE.", filename);
-            vec![]
+            // An OLE file of another format.
+            info!("Is an OLE file {}", filename);
+            vec![t]
         }
     };
+    for nested in unwrapped.files {
+        info!(
+            "Found an OLE file nested in {} at {}",
+            filename,
+            nested.path.join(" > ")
+        );
+        oles.push(nested.file);
+    }
+    Ok(oles)
 }
 
 /// find ole streams in ppt
@@ -240,34 +438,60 @@ fn sanitize_filepath(filepath: &str) -> String {
     sane_filepath.clone()
 }
 
-fn read_u32(cursor: &mut Cursor<Vec<u8>>) -> u32 {
-    let mut data_size_buf = vec![0; 4];
-    cursor.read(&mut data_size_buf).unwrap();
-    let response = u32::from_ne_bytes(if cfg!(target_endian = "big") {
-        [
-            data_size_buf[0],
-            data_size_buf[1],
-            data_size_buf[2],
-            data_size_buf[3],
-        ]
-    } else {
-        [
-            data_size_buf[3],
-            data_size_buf[2],
-            data_size_buf[1],
-            data_size_buf[0],
-        ]
-    });
-    response
+/// A NUL terminated string, the NUL included; bytes that aren't UTF-8 are replaced.
+fn read_zstring(cursor: &mut Cursor<Vec<u8>>) -> String {
+    let mut buf = Vec::new();
+    cursor.read_until(0x00, &mut buf).ok();
+    String::from_utf8_lossy(&buf).into_owned()
 }
 
-fn read_u16(cursor: &mut Cursor<Vec<u8>>) -> u16 {
-    let mut unknown_short_buf = vec![0; 2];
-    cursor.read(&mut unknown_short_buf).unwrap();
-    let short = u16::from_ne_bytes(if cfg!(target_endian = "big") {
-        [unknown_short_buf[0], unknown_short_buf[1]]
-    } else {
-        [unknown_short_buf[1], unknown_short_buf[0]]
-    });
-    short
+/// The next `N` bytes of the header, which should hold its `field`.
+fn read_bytes<const N: usize>(cursor: &mut Cursor<Vec<u8>>, field: &str) -> Result<[u8; N]> {
+    let mut buf = [0; N];
+    cursor
+        .read_exact(&mut buf)
+        .map_err(|_| Error::OleUnexpectedEof {
+            message: format!(
+                "the OLENativeStream header ends at byte {} before its {}",
+                cursor.get_ref().len(),
+                field
+            ),
+            location: Location::default(),
+        })?;
+    Ok(buf)
+}
+
+fn read_u32(cursor: &mut Cursor<Vec<u8>>, field: &str) -> Result<u32> {
+    read_bytes(cursor, field).map(u32::from_le_bytes)
+}
+
+fn read_u16(cursor: &mut Cursor<Vec<u8>>, field: &str) -> Result<u16> {
+    read_bytes(cursor, field).map(u16::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncated_header() {
+        let mut data = 30u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(b"a.txt\0C:\\a.txt\0");
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(b"C:\\tmp\\a.txt\0");
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(b"abc");
+        let opkg = OleNativeStream::new(Some(data.clone()), false).unwrap();
+        assert_eq!(opkg.filename.as_deref(), Some("a.txt\0"));
+        assert_eq!(opkg.data, b"abc");
+
+        // cut inside the file size, which used to read as zero
+        let err = OleNativeStream::new(Some(data[..data.len() - 5].to_vec()), false)
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::OleUnexpectedEof { .. }));
+        assert!(err.to_string().contains("before its file size"));
+        assert!(OleNativeStream::new(Some(data[..3].to_vec()), false).is_err());
+    }
 }